@@ -2,71 +2,7 @@
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use open_g_hub_core::transport::HidTransport;
-
-const DEVICE_INDEX_CANDIDATES: [u8; 2] = [0xFF, 0x01];
-
-struct CliHidTransport {
-    device: hidapi::HidDevice,
-}
-
-impl CliHidTransport {
-    fn open_first_supported() -> Result<Self> {
-        let devices = open_g_hub_core::device::discover_devices()?;
-        let first = devices
-            .first()
-            .ok_or_else(|| anyhow::anyhow!("No supported Logitech G device found"))?;
-
-        let api = hidapi::HidApi::new().map_err(|e| anyhow::anyhow!("hidapi init: {e}"))?;
-        let device = api.open(first.vid, first.pid).map_err(|e| {
-            anyhow::anyhow!(
-                "open HID device (VID=0x{:04X} PID=0x{:04X}): {e}",
-                first.vid,
-                first.pid
-            )
-        })?;
-
-        Ok(Self { device })
-    }
-}
-
-impl HidTransport for CliHidTransport {
-    fn send_report(&self, data: &[u8]) -> open_g_hub_core::error::Result<Vec<u8>> {
-        self.device
-            .write(data)
-            .map_err(|e| open_g_hub_core::error::Error::Hid(format!("write: {e}")))?;
-
-        let mut response = [0u8; 64];
-        let n = self
-            .device
-            .read_timeout(&mut response, 1000)
-            .map_err(|e| open_g_hub_core::error::Error::Hid(format!("read_timeout: {e}")))?;
-
-        if n == 0 {
-            return Err(open_g_hub_core::error::Error::Timeout(
-                "hid_read timed out after 1000ms".to_string(),
-            ));
-        }
-
-        Ok(response[..n].to_vec())
-    }
-}
-
-fn with_device_index<T>(
-    mut op: impl FnMut(u8) -> open_g_hub_core::error::Result<T>,
-) -> open_g_hub_core::error::Result<T> {
-    let mut last_error = None;
-    for idx in DEVICE_INDEX_CANDIDATES {
-        match op(idx) {
-            Ok(v) => return Ok(v),
-            Err(e) => last_error = Some(e),
-        }
-    }
-
-    Err(last_error.unwrap_or_else(|| {
-        open_g_hub_core::error::Error::DeviceNotFound("no usable device index".to_string())
-    }))
-}
+use open_g_hub_core::client::DeviceClient;
 
 #[derive(Parser)]
 #[command(
@@ -75,6 +11,9 @@ fn with_device_index<T>(
     about = "Open-source Logitech mouse configuration"
 )]
 struct Cli {
+    /// Talk to the mouse directly even if open-g-hubd is running.
+    #[arg(long, global = true)]
+    no_daemon: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -106,10 +45,222 @@ enum Commands {
         /// Action: left, right, middle, back, forward, dpi-up, dpi-down, none.
         action: String,
     },
-    /// Save current settings to a profile.
-    SaveProfile,
+    /// Print everything readable from the mouse as profile JSON.
+    Snapshot,
+    /// Apply a profile JSON file (e.g. saved from `snapshot`, or a library
+    /// file of any version), writing only the settings that differ.
+    Apply {
+        /// Path to the profile JSON.
+        file: std::path::PathBuf,
+        /// Print the planned writes without making them.
+        #[arg(long)]
+        dry_run: bool,
+        /// Refuse files with fields this version does not know.
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Save the mouse's current settings into Logitech G Hub's settings.db.
+    ///
+    /// The database is backed up first; other profiles and settings are kept.
+    SaveProfile {
+        /// G Hub profile to create or update.
+        #[arg(long, default_value = "Open G Hub")]
+        name: String,
+        /// Path to settings.db (default: G Hub's own location).
+        #[arg(long)]
+        path: Option<std::path::PathBuf>,
+        /// G Hub device ID, e.g. g502_lightspeed (default: the mouse G Hub knows).
+        #[arg(long)]
+        device: Option<String>,
+    },
     /// Load and apply a saved profile.
     LoadProfile,
+    /// Manage the native profile library in ~/.config/open-g-hub/profiles.
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
+    /// Apply a profile from a Logitech G Hub settings.db (a copy from
+    /// Windows works too). Through the daemon it becomes the active profile.
+    ImportGhub {
+        /// Path to settings.db (default: G Hub's own location).
+        path: Option<std::path::PathBuf>,
+        /// G Hub profile name (default: the active one).
+        #[arg(long)]
+        profile: Option<String>,
+        /// Only list the profiles found.
+        #[arg(long)]
+        list: bool,
+        /// Print the planned writes without making them.
+        #[arg(long)]
+        dry_run: bool,
+        /// Store the profile, with its macros, in the profile library under
+        /// this name instead of applying it.
+        #[arg(long, conflicts_with = "dry_run")]
+        save: Option<String>,
+    },
+    /// Apply the Solaar settings (config.yaml) recorded for the connected
+    /// mouse, matched by serial number or unit ID.
+    ImportSolaar {
+        /// Path to config.yaml (default: ~/.config/solaar/config.yaml).
+        path: Option<std::path::PathBuf>,
+        /// Only list the devices found.
+        #[arg(long)]
+        list: bool,
+        /// Print the planned writes without making them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List Logitech G Hub installs found in Wine, Lutris, Bottles and Proton
+    /// prefixes, and choose which settings.db to use.
+    GhubPrefixes {
+        /// Use install N from the list from now on.
+        #[arg(long, conflicts_with = "clear")]
+        select: Option<usize>,
+        /// Forget the chosen install and search again each time.
+        #[arg(long)]
+        clear: bool,
+    },
+    /// Show the battery level and the runtime left at the current settings,
+    /// estimated from the battery history the daemon records.
+    Battery {
+        /// Also list recent readings and the drain measured per report rate
+        /// and lighting setting.
+        #[arg(long)]
+        history: bool,
+        /// Show the history of this mouse (unit ID) instead of the connected one.
+        #[arg(long)]
+        serial: Option<String>,
+    },
+    /// Show the focused application as the daemon's --auto-switch sees it,
+    /// and the profile the switching rules pick for it.
+    Focus {
+        /// Focus provider: x11, sway, wlroots, kde or gnome (default:
+        /// detected from the session).
+        #[arg(long)]
+        provider: Option<String>,
+        /// Rules file (default: ~/.config/open-g-hub/autoswitch.json).
+        #[arg(long)]
+        rules: Option<std::path::PathBuf>,
+    },
+    /// Filter switch bounce (double-click failure) on the mouse's evdev node.
+    ///
+    /// Grabs the mouse and re-emits cleaned events through uinput until Ctrl-C.
+    /// Starts from the profile's debounce settings; when the profile turns
+    /// debouncing off, only `--window` or `--button` turn it on.
+    #[cfg(target_os = "linux")]
+    Debounce {
+        /// evdev node to grab (default: first Logitech pointer found).
+        #[arg(long)]
+        device: Option<std::path::PathBuf>,
+        /// Library profile to take the settings from (default: the daemon's
+        /// active profile).
+        #[arg(long)]
+        profile: Option<String>,
+        /// Debounce window in ms for every button (0-100), overriding the
+        /// profile's.
+        #[arg(long)]
+        window: Option<u32>,
+        /// Per-button window override, e.g. `--button left=30`.
+        #[arg(long = "button", value_parser = parse_button_window)]
+        buttons: Vec<(open_g_hub_core::input::MouseButton, u32)>,
+        /// Seconds between statistics lines.
+        #[arg(long, default_value_t = 30)]
+        stats_interval: u64,
+    },
+    /// Run a G Hub Lua script, driven by diverted button presses.
+    #[cfg(feature = "scripting")]
+    RunScript {
+        /// Path to the Lua script.
+        path: std::path::PathBuf,
+        /// Button indices (0-5) whose presses are sent to the script.
+        #[arg(long, value_delimiter = ',', default_value = "3,4")]
+        divert: Vec<usize>,
+        /// Print synthesized input instead of injecting it through uinput.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// List stored profiles.
+    List,
+    /// Print a stored profile as JSON.
+    Show { name: String },
+    /// Store the connected mouse's current settings (or a copy of another
+    /// profile) under a new name.
+    Create {
+        name: String,
+        /// Copy this stored profile instead of reading the mouse.
+        #[arg(long)]
+        copy: Option<String>,
+        /// Only for this model: g502-lightspeed or g502-hero.
+        #[arg(long, value_parser = parse_model, conflicts_with = "serial")]
+        model: Option<open_g_hub_core::device::MouseModel>,
+        /// Only for the mouse with this serial number.
+        #[arg(long)]
+        serial: Option<String>,
+    },
+    /// Delete a stored profile.
+    Delete { name: String },
+    /// Rename a stored profile.
+    Rename { from: String, to: String },
+    /// Add or replace a battery rule: below a charge level, while not
+    /// charging, cap the report rate and LED brightness.
+    SetPower {
+        name: String,
+        /// Battery level in percent the rule applies below (1-100).
+        #[arg(long, required_unless_present = "clear")]
+        below: Option<u8>,
+        /// Highest polling rate in Hz (125, 250, 500 or 1000).
+        #[arg(long)]
+        rate: Option<u16>,
+        /// Highest LED brightness in percent; 0 turns the LEDs off.
+        #[arg(long)]
+        brightness: Option<u8>,
+        /// Remove every battery rule instead.
+        #[arg(long, conflicts_with_all = ["below", "rate", "brightness"])]
+        clear: bool,
+    },
+    /// Store a profile from a libratbag/Piper settings dump (JSON in the
+    /// ratbagd object model), listing what it cannot hold.
+    ImportRatbag {
+        /// Path to the ratbag JSON.
+        file: std::path::PathBuf,
+        /// Store under this name (default: the ratbag profile's name).
+        #[arg(long)]
+        name: Option<String>,
+        /// ratbag profile index (default: the active one).
+        #[arg(long)]
+        index: Option<u32>,
+    },
+    /// Store a profile from a Logitech Gaming Software XML export: DPI
+    /// levels, report rate, button assignments and simple macros.
+    ImportLgs {
+        /// Path to the LGS XML export.
+        file: std::path::PathBuf,
+        /// Store under this name (default: the LGS profile's name).
+        #[arg(long)]
+        name: Option<String>,
+        /// LGS profile to import (default: the first one).
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Print a stored profile in the libratbag/Piper data model.
+    ExportRatbag {
+        name: String,
+        /// Write to this file instead of stdout.
+        #[arg(long, short)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Apply a stored profile to the mouse.
+    Apply {
+        name: String,
+        /// Print the planned writes without making them.
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 fn main() -> Result<()> {
@@ -118,6 +269,13 @@ fn main() -> Result<()> {
         .init();
 
     let cli = Cli::parse();
+    let connect = || -> Result<DeviceClient> {
+        Ok(if cli.no_daemon {
+            DeviceClient::direct()?
+        } else {
+            DeviceClient::connect()?
+        })
+    };
 
     match cli.command {
         Commands::ListDevices => {
@@ -138,37 +296,26 @@ fn main() -> Result<()> {
             }
         }
         Commands::GetDpi => {
-            let transport = CliHidTransport::open_first_supported()?;
-            let dpi = with_device_index(|idx| open_g_hub_core::dpi::read_dpi(&transport, idx))?;
+            let dpi = connect()?.read_dpi()?;
             println!("Current DPI: {dpi}");
         }
         Commands::SetDpi { value } => {
-            let transport = CliHidTransport::open_first_supported()?;
-            let validated =
-                with_device_index(|idx| open_g_hub_core::dpi::write_dpi(&transport, idx, value))?;
+            let validated = connect()?.write_dpi(value)?;
             println!("DPI set to {validated}");
         }
         Commands::GetRate => {
-            let transport = CliHidTransport::open_first_supported()?;
-            let rate = with_device_index(|idx| {
-                open_g_hub_core::report_rate::read_report_rate(&transport, idx)
-            })?;
+            let rate = connect()?.read_report_rate()?;
             println!("Current polling rate: {} Hz", rate.as_hz());
         }
         Commands::SetRate { value } => {
             let validated = open_g_hub_core::safety::validate_polling_rate(value)?;
-            let transport = CliHidTransport::open_first_supported()?;
-            with_device_index(|idx| {
-                open_g_hub_core::report_rate::write_report_rate(&transport, idx, validated)
-            })?;
+            connect()?.write_report_rate(validated)?;
             println!("Polling rate set to {} Hz", validated.as_hz());
         }
         Commands::GetButtons => {
-            let transport = CliHidTransport::open_first_supported()?;
+            let mut client = connect()?;
             for index in 0..open_g_hub_core::device::G502_BUTTON_COUNT {
-                let action = with_device_index(|idx| {
-                    open_g_hub_core::buttons::read_button_mapping(&transport, idx, index)
-                })?;
+                let action = client.read_button_mapping(index)?;
                 println!("Button {index}: {}", action.label());
             }
         }
@@ -181,24 +328,44 @@ fn main() -> Result<()> {
                         action
                     )
                 })?;
-            let transport = CliHidTransport::open_first_supported()?;
-            with_device_index(|idx| {
-                open_g_hub_core::buttons::write_button_mapping(
-                    &transport,
-                    idx,
-                    index,
-                    parsed_action,
-                )
-            })?;
+            connect()?.write_button_mapping(index, parsed_action)?;
             println!("Set button {index} to '{}'", parsed_action.label());
         }
-        Commands::SaveProfile => {
-            let profile = open_g_hub_core::profile::Profile::default();
-            open_g_hub_core::profile::save_profile(&profile)?;
-            println!(
-                "Profile saved to {:?}",
-                open_g_hub_core::profile::profile_path()?
-            );
+        Commands::Snapshot => {
+            let profile = connect()?.snapshot()?;
+            println!("{}", serde_json::to_string_pretty(&profile)?);
+        }
+        Commands::Apply {
+            file,
+            dry_run,
+            strict,
+        } => {
+            use open_g_hub_core::schema::{self, ParseMode};
+            let text = std::fs::read_to_string(&file)?;
+            let mode = if strict {
+                ParseMode::Strict
+            } else {
+                ParseMode::Lenient
+            };
+            let parsed = schema::parse(&text, mode)
+                .map_err(|e| anyhow::anyhow!("{}: {e}", file.display()))?;
+            for field in &parsed.unknown_fields {
+                eprintln!("Ignoring unknown field {field}");
+            }
+            let profile = parsed.stored.profile;
+            if apply_planned(&mut connect()?, &profile, dry_run)? {
+                println!("Applied '{}'", profile.name);
+            }
+        }
+        Commands::SaveProfile { name, path, device } => {
+            let profile = read_current_profile(&mut connect()?, name)?;
+            let path = match path {
+                Some(path) => path,
+                None => open_g_hub_core::profile::profile_path()?,
+            };
+            let backup = open_g_hub_core::ghub::save_profile(&path, &profile, device.as_deref())?;
+            println!("Profile '{}' saved to {}", profile.name, path.display());
+            println!("Backup: {}", backup.display());
         }
         Commands::LoadProfile => {
             let profile = open_g_hub_core::profile::load_profile()?;
@@ -209,7 +376,782 @@ fn main() -> Result<()> {
                 println!("  Button {i}: {}", btn.label());
             }
         }
+        Commands::Profile { command } => {
+            use open_g_hub_core::store::{DeviceBinding, ProfileStore, StoredProfile};
+            let store = ProfileStore::open_default()?;
+            match command {
+                ProfileCommand::List => {
+                    let profiles = store.list()?;
+                    if profiles.is_empty() {
+                        println!("No profiles in {}", store.dir().display());
+                    }
+                    for stored in &profiles {
+                        let p = &stored.profile;
+                        println!(
+                            "{} [{}] DPI {}, {} Hz",
+                            p.name,
+                            stored
+                                .device
+                                .as_ref()
+                                .map_or("any mouse".to_string(), |d| d.to_string()),
+                            p.dpi,
+                            p.polling_rate.as_hz()
+                        );
+                    }
+                }
+                ProfileCommand::Show { name } => {
+                    println!("{}", serde_json::to_string_pretty(&store.get(&name)?)?);
+                }
+                ProfileCommand::Create {
+                    name,
+                    copy,
+                    model,
+                    serial,
+                } => {
+                    let profile = match copy {
+                        Some(source) => open_g_hub_core::profile::Profile {
+                            name: name.clone(),
+                            ..store.get(&source)?.profile
+                        },
+                        None => read_current_profile(&mut connect()?, name.clone())?,
+                    };
+                    let device = model
+                        .map(DeviceBinding::Model)
+                        .or(serial.map(DeviceBinding::Serial));
+                    store.create(&StoredProfile::new(profile, device))?;
+                    println!("Created profile '{name}'");
+                }
+                ProfileCommand::Delete { name } => {
+                    store.delete(&name)?;
+                    println!("Deleted profile '{name}'");
+                }
+                ProfileCommand::Rename { from, to } => {
+                    store.rename(&from, &to)?;
+                    println!("Renamed '{from}' to '{to}'");
+                }
+                ProfileCommand::SetPower {
+                    name,
+                    below,
+                    rate,
+                    brightness,
+                    clear,
+                } => {
+                    use open_g_hub_core::power::PowerRule;
+                    let mut stored = store.get(&name)?;
+                    let rules = &mut stored.profile.power;
+                    if clear {
+                        rules.clear();
+                    } else if let Some(below) = below {
+                        if rate.is_none() && brightness.is_none() {
+                            anyhow::bail!("Give --rate, --brightness or both");
+                        }
+                        let rule = PowerRule {
+                            below,
+                            polling_rate: rate
+                                .map(open_g_hub_core::safety::validate_polling_rate)
+                                .transpose()?,
+                            led_brightness: brightness,
+                        };
+                        open_g_hub_core::safety::validate_power_rule(&rule)?;
+                        rules.retain(|r| r.below != below);
+                        rules.push(rule);
+                        rules.sort_by_key(|r| std::cmp::Reverse(r.below));
+                    }
+                    for rule in rules.iter() {
+                        let mut limits = Vec::new();
+                        if let Some(rate) = rule.polling_rate {
+                            limits.push(format!("{} Hz", rate.as_hz()));
+                        }
+                        match rule.led_brightness {
+                            Some(0) => limits.push("LEDs off".to_string()),
+                            Some(b) => limits.push(format!("LEDs at {b}%")),
+                            None => {}
+                        }
+                        println!("Below {}%: {}", rule.below, limits.join(", "));
+                    }
+                    if rules.is_empty() {
+                        println!("No battery rules");
+                    }
+                    store.save(&stored)?;
+                }
+                ProfileCommand::ImportRatbag { file, name, index } => {
+                    use open_g_hub_core::ratbag;
+                    let text = std::fs::read_to_string(&file)?;
+                    let device: ratbag::RatbagDevice = serde_json::from_str(&text)
+                        .map_err(|e| anyhow::anyhow!("{}: {e}", file.display()))?;
+                    let mut imported = ratbag::import(&device, index)?;
+                    if let Some(name) = name {
+                        imported.profile.name = name;
+                    }
+                    let name = imported.profile.name.clone();
+                    store.create(&StoredProfile::new(imported.profile, None))?;
+                    println!("Created profile '{name}'");
+                    for setting in &imported.unsupported {
+                        println!("  Not imported: {setting}");
+                    }
+                }
+                ProfileCommand::ImportLgs {
+                    file,
+                    name,
+                    profile,
+                } => {
+                    let text = std::fs::read_to_string(&file)?;
+                    let mut imported = open_g_hub_core::lgs::import(&text, profile.as_deref())?;
+                    if let Some(name) = name {
+                        imported.profile.name = name;
+                    }
+                    let name = imported.profile.name.clone();
+                    let macros = imported.profile.button_macros.len();
+                    store.create(&StoredProfile::new(imported.profile, None))?;
+                    println!("Created profile '{name}' with {macros} host macro(s)");
+                    for setting in &imported.unsupported {
+                        println!("  Not imported: {setting}");
+                    }
+                }
+                ProfileCommand::ExportRatbag { name, output } => {
+                    let exported = open_g_hub_core::ratbag::export(&store.get(&name)?.profile);
+                    let json = serde_json::to_string_pretty(&exported.device)?;
+                    match output {
+                        Some(path) => std::fs::write(&path, json + "\n")?,
+                        None => println!("{json}"),
+                    }
+                    for setting in &exported.unsupported {
+                        eprintln!("Not exported: {setting}");
+                    }
+                }
+                ProfileCommand::Apply { name, dry_run } => {
+                    let stored = store.get(&name)?;
+                    if !apply_planned(&mut connect()?, &stored.profile, dry_run)? {
+                        return Ok(());
+                    }
+                    println!(
+                        "Applied '{}': {} DPI, {} Hz",
+                        stored.profile.name,
+                        stored.profile.dpi,
+                        stored.profile.polling_rate.as_hz()
+                    );
+                }
+            }
+        }
+        Commands::ImportGhub {
+            path,
+            profile,
+            list,
+            dry_run,
+            save,
+        } => {
+            use open_g_hub_core::ghub;
+            let path = match path {
+                Some(path) => path,
+                None => open_g_hub_core::profile::profile_path()?,
+            };
+            let profiles = ghub::load_profiles(&path)?;
+            if list {
+                for p in &profiles {
+                    let stages: Vec<String> = p.dpi_stages.iter().map(u16::to_string).collect();
+                    println!(
+                        "{}{} [{}] app: {}, DPI stages: {}, rate: {}, buttons: {}, macros: {}, unsupported: {}",
+                        p.name,
+                        if p.active { " (active)" } else { "" },
+                        p.device,
+                        p.application.as_deref().unwrap_or("-"),
+                        if stages.is_empty() {
+                            "-".to_string()
+                        } else {
+                            stages.join("/")
+                        },
+                        p.report_rate
+                            .map_or("-".to_string(), |hz| format!("{hz} Hz")),
+                        p.buttons.len(),
+                        p.macros.len(),
+                        p.skipped.len()
+                    );
+                }
+                return Ok(());
+            }
+            let chosen = ghub::select(&profiles, profile.as_deref()).ok_or_else(|| {
+                anyhow::anyhow!("No matching G Hub profile in {}", path.display())
+            })?;
+            let mut imported = chosen.to_profile()?;
+            if let Some(name) = save {
+                imported.name = name.clone();
+                use open_g_hub_core::store::{ProfileStore, StoredProfile};
+                ProfileStore::open_default()?.create(&StoredProfile::new(imported, None))?;
+                println!(
+                    "Created profile '{name}' with {} host macro(s)",
+                    chosen.macros.len()
+                );
+                for slot in &chosen.skipped {
+                    println!("  Skipped unsupported assignment {slot}");
+                }
+                return Ok(());
+            }
+            if !apply_planned(&mut connect()?, &imported, dry_run)? {
+                return Ok(());
+            }
+            println!(
+                "Applied G Hub profile '{}': {} DPI, {} Hz",
+                imported.name,
+                imported.dpi,
+                imported.polling_rate.as_hz()
+            );
+            for slot in &chosen.skipped {
+                println!("  Skipped unsupported assignment {slot}");
+            }
+        }
+        Commands::ImportSolaar {
+            path,
+            list,
+            dry_run,
+        } => {
+            use open_g_hub_core::solaar;
+            let path = match path {
+                Some(path) => path,
+                None => solaar::config_path()
+                    .ok_or_else(|| anyhow::anyhow!("No config directory: set HOME"))?,
+            };
+            let config = solaar::load(&path)?;
+            if list {
+                for d in &config.devices {
+                    println!(
+                        "{} serial: {}, unit ID: {}, settings: {}",
+                        d.name.as_deref().unwrap_or("(unnamed)"),
+                        d.serial.as_deref().unwrap_or("-"),
+                        d.unit_id.as_deref().unwrap_or("-"),
+                        d.settings.keys().cloned().collect::<Vec<_>>().join(", ")
+                    );
+                }
+                return Ok(());
+            }
+            let mut client = connect()?;
+            // Through a receiver the HID serial is the receiver's, so the
+            // unit ID alone identifies the mouse. Mice without
+            // DEVICE_INFORMATION can still match by serial.
+            let unit_id = client.read_unit_id().ok();
+            let serial = match unit_id {
+                Some(_) => None,
+                None => connected_serial(&mut client)?,
+            };
+            let device = config
+                .find(serial.as_deref(), unit_id.as_deref())
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "No entry for this mouse (serial {}, unit ID {}) in {}",
+                        serial.as_deref().unwrap_or("unknown"),
+                        unit_id.as_deref().unwrap_or("unknown"),
+                        path.display()
+                    )
+                })?;
+            let imported = device.to_profile();
+            for setting in &imported.skipped {
+                println!("Skipped {setting}");
+            }
+            if !apply_planned(&mut client, &imported.profile, dry_run)? {
+                return Ok(());
+            }
+            println!(
+                "Applied Solaar settings for '{}': {} DPI, {} Hz",
+                imported.profile.name,
+                imported.profile.dpi,
+                imported.profile.polling_rate.as_hz()
+            );
+        }
+        Commands::GhubPrefixes { select, clear } => {
+            use open_g_hub_core::wine;
+            let installs = wine::discover();
+            if clear {
+                wine::select(None)?;
+                println!("Cleared the chosen G Hub install.");
+                return Ok(());
+            }
+            if let Some(n) = select {
+                let install = n
+                    .checked_sub(1)
+                    .and_then(|i| installs.get(i))
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("No install {n}; run ghub-prefixes to list them")
+                    })?;
+                wine::select(Some(&install.settings))?;
+                println!("Using {}", install.settings.display());
+                return Ok(());
+            }
+            if installs.is_empty() {
+                println!("No Logitech G Hub installs found.");
+                return Ok(());
+            }
+            let in_use = open_g_hub_core::profile::profile_path().ok();
+            for (i, install) in installs.iter().enumerate() {
+                println!(
+                    "{}{}. {install}",
+                    if in_use.as_ref() == Some(&install.settings) {
+                        "* "
+                    } else {
+                        "  "
+                    },
+                    i + 1
+                );
+                println!("     {}", install.settings.display());
+            }
+        }
+        Commands::Battery { history, serial } => show_battery(history, serial)?,
+        Commands::Focus { provider, rules } => {
+            use open_g_hub_core::autoswitch;
+            let mut provider = match provider.as_deref() {
+                Some(name) => autoswitch::provider(name).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Unknown focus provider {name} (one of {})",
+                        autoswitch::PROVIDERS.join(", ")
+                    )
+                })?,
+                None => autoswitch::detect().ok_or_else(|| {
+                    anyhow::anyhow!("No focus provider for this session; use --provider")
+                })?,
+            };
+            let focus = provider.focused()?;
+            println!("Provider: {}", provider.name());
+            match &focus {
+                Some(focus) => {
+                    let show = |v: &Option<String>| v.clone().unwrap_or_else(|| "-".into());
+                    println!("Executable: {}", show(&focus.exe));
+                    println!("Class: {}", show(&focus.class));
+                    println!("Title: {}", show(&focus.title));
+                }
+                None => println!("No focused window"),
+            }
+            let path = rules.or_else(autoswitch::rules_path);
+            match path.filter(|p| p.is_file()) {
+                Some(path) => {
+                    let rules = autoswitch::Rules::load(&path)?;
+                    match rules.select(focus.as_ref()) {
+                        Some(profile) => println!("Profile: {profile}"),
+                        None => println!("Profile: unchanged (no rule or default applies)"),
+                    }
+                }
+                None => println!("No switching rules"),
+            }
+        }
+        #[cfg(target_os = "linux")]
+        Commands::Debounce {
+            device,
+            profile,
+            window,
+            buttons,
+            stats_interval,
+        } => {
+            let base = debounce_profile(profile.as_deref())?;
+            let mut settings = match &base {
+                Some(profile) => profile.debounce.clone(),
+                None => open_g_hub_core::debounce::DebounceSettings {
+                    enabled: true,
+                    ..Default::default()
+                },
+            };
+            if window.is_some() || !buttons.is_empty() {
+                settings.enabled = true;
+            }
+            if let Some(window) = window {
+                settings.window_ms = window;
+            }
+            settings.per_button.extend(buttons);
+            match base {
+                Some(profile) if !settings.enabled => println!(
+                    "Debouncing is off in profile '{}'; pass --window or --button to run it anyway",
+                    profile.name
+                ),
+                _ => run_debounce(device, settings, stats_interval)?,
+            }
+        }
+        #[cfg(feature = "scripting")]
+        Commands::RunScript {
+            path,
+            divert,
+            dry_run,
+        } => run_script(&path, &divert, dry_run)?,
+    }
+
+    Ok(())
+}
+
+/// The mouse's live settings as a profile named `name`.
+fn read_current_profile(
+    client: &mut DeviceClient,
+    name: String,
+) -> Result<open_g_hub_core::profile::Profile> {
+    Ok(open_g_hub_core::profile::Profile {
+        name,
+        ..client.snapshot()?
+    })
+}
+
+/// Readings listed by `battery --history`.
+const HISTORY_LINES: usize = 20;
+
+fn show_battery(history: bool, serial: Option<String>) -> Result<()> {
+    use open_g_hub_core::battery_history::{self, BatteryHistory, BatterySample, Usage};
+    let store = BatteryHistory::open_default()?;
+
+    // (unit ID, level, settings) of the mouse to estimate for.
+    let (unit_id, level, usage) = match serial {
+        Some(serial) => {
+            let last = store
+                .last(&serial)?
+                .ok_or_else(|| anyhow::anyhow!("No battery history for {serial}"))?;
+            println!(
+                "Battery: {}%, {:?} (last reading, {})",
+                last.level,
+                last.state,
+                store.local_time(last.time)
+            );
+            (serial, last.level, last.usage)
+        }
+        None => {
+            let mut client = DeviceClient::connect()?;
+            let battery = client.read_battery()?;
+            let unit_id = client.read_unit_id()?;
+            let usage = Usage::of(&client.snapshot()?);
+            // The daemon records readings itself.
+            if !client.is_daemon() {
+                store.record(&unit_id, &BatterySample::now(&battery, usage))?;
+            }
+            println!("Battery: {}%, {:?}", battery.level, battery.state);
+            (unit_id, battery.level, usage)
+        }
+    };
+
+    let samples = store.samples(&unit_id)?;
+    match battery_history::estimate_runtime(&samples, level, usage) {
+        Some(estimate) => println!("Runtime left at {usage}: {estimate}"),
+        None => println!("Runtime left: not enough discharge history yet"),
+    }
+    if !history {
+        return Ok(());
+    }
+
+    println!();
+    println!(
+        "Readings of {unit_id} ({} of {}):",
+        samples.len().min(HISTORY_LINES),
+        samples.len()
+    );
+    for sample in samples
+        .iter()
+        .skip(samples.len().saturating_sub(HISTORY_LINES))
+    {
+        println!(
+            "  {}  {:>3}%  {:<12}  {}",
+            store.local_time(sample.time),
+            sample.level,
+            format!("{:?}", sample.state),
+            sample.usage
+        );
+    }
+    let rates = battery_history::drain_rates(&samples);
+    if !rates.is_empty() {
+        println!();
+        println!("Drain by settings:");
+        for rate in rates {
+            println!(
+                "  {:<24}  {:>5.1} %/h  ~{:.0} h per charge  (over {:.1} h)",
+                rate.usage.to_string(),
+                rate.percent_per_hour,
+                rate.full_charge_hours(),
+                rate.hours
+            );
+        }
+    }
+    Ok(())
+}
+
+/// HID serial number of the mouse `client` talks to: the daemon's connected
+/// mouse, else the first supported device, which is the one
+/// [`DeviceClient::direct`] opens.
+fn connected_serial(client: &mut DeviceClient) -> Result<Option<String>> {
+    #[cfg(unix)]
+    if let DeviceClient::Daemon(daemon) = client {
+        let devices = daemon.list_devices()?;
+        return Ok(devices
+            .into_iter()
+            .find(|d| d.connected)
+            .and_then(|d| d.info.serial));
+    }
+    Ok(open_g_hub_core::device::discover_devices()?
+        .into_iter()
+        .next()
+        .and_then(|d| d.serial))
+}
+
+/// Print the writes `profile` needs and, unless `dry_run`, make them.
+/// Returns whether the profile was applied.
+fn apply_planned(
+    client: &mut DeviceClient,
+    profile: &open_g_hub_core::profile::Profile,
+    dry_run: bool,
+) -> Result<bool> {
+    let plan = client.plan(profile)?;
+    println!("{plan}");
+    if dry_run {
+        return Ok(false);
+    }
+    client.apply_plan(profile, &plan)?;
+    Ok(true)
+}
+
+/// Parse a model name for `profile create --model`.
+fn parse_model(arg: &str) -> std::result::Result<open_g_hub_core::device::MouseModel, String> {
+    use open_g_hub_core::device::MouseModel;
+    match arg.to_lowercase().as_str() {
+        "g502-lightspeed" => Ok(MouseModel::G502Lightspeed),
+        "g502-hero" => Ok(MouseModel::G502Hero),
+        _ => Err(format!(
+            "unknown model '{arg}' (g502-lightspeed, g502-hero)"
+        )),
+    }
+}
+
+/// Parse `name=ms` for `debounce --button`.
+#[cfg(target_os = "linux")]
+fn parse_button_window(
+    arg: &str,
+) -> std::result::Result<(open_g_hub_core::input::MouseButton, u32), String> {
+    use open_g_hub_core::input::MouseButton;
+
+    let (name, ms) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected BUTTON=MS, got '{arg}'"))?;
+    let button = match name.to_lowercase().as_str() {
+        "left" => MouseButton::Left,
+        "right" => MouseButton::Right,
+        "middle" => MouseButton::Middle,
+        "back" => MouseButton::Back,
+        "forward" => MouseButton::Forward,
+        other => {
+            return Err(format!(
+                "unknown button '{other}' (left, right, middle, back, forward)"
+            ))
+        }
+    };
+    let ms = ms
+        .parse()
+        .map_err(|e| format!("invalid window '{ms}': {e}"))?;
+    Ok((button, ms))
+}
+
+/// The profile `debounce` starts from: `name` from the library, else the
+/// daemon's active profile when a daemon is running.
+#[cfg(target_os = "linux")]
+fn debounce_profile(name: Option<&str>) -> Result<Option<open_g_hub_core::profile::Profile>> {
+    use open_g_hub_core::ipc::DaemonClient;
+    use open_g_hub_core::store::ProfileStore;
+
+    if let Some(name) = name {
+        return Ok(Some(ProfileStore::open_default()?.get(name)?.profile));
+    }
+    Ok(match DaemonClient::connect_default() {
+        Ok(mut daemon) => daemon.active_profile()?,
+        Err(_) => None,
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn run_debounce(
+    device: Option<std::path::PathBuf>,
+    settings: open_g_hub_core::debounce::DebounceSettings,
+    stats_interval: u64,
+) -> Result<()> {
+    use open_g_hub_core::evdev;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    let device = match device {
+        Some(path) => path,
+        None => evdev::find_logitech_pointers()
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("No Logitech evdev pointer found; pass --device"))?,
+    };
+
+    let mut forwarder = evdev::DebounceForwarder::open(&device, settings)?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&stop))?;
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&stop))?;
+
+    println!("Debouncing {} (Ctrl-C to stop)", device.display());
+    let interval = Duration::from_secs(stats_interval.max(1));
+    let mut last_report = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        forwarder.pump()?;
+        if last_report.elapsed() >= interval {
+            println!("{}", forwarder.stats());
+            last_report = Instant::now();
+        }
+    }
+
+    println!("Final: {}", forwarder.stats());
+    Ok(())
+}
+
+/// Input backend for `run-script --dry-run`: prints events instead of injecting.
+#[cfg(feature = "scripting")]
+struct PrintBackend;
+
+#[cfg(feature = "scripting")]
+impl open_g_hub_core::input::InputBackend for PrintBackend {
+    fn emit(
+        &mut self,
+        event: open_g_hub_core::input::InputEvent,
+    ) -> open_g_hub_core::error::Result<()> {
+        println!("  {event:?}");
+        Ok(())
+    }
+
+    fn delay(&mut self, ms: u32) {
+        println!("  Sleep({ms})");
+        std::thread::sleep(std::time::Duration::from_millis(ms as u64));
+    }
+}
+
+/// Buttons `run-script` diverted. Dropping the guard un-diverts whatever
+/// [`restore`](Self::restore) did not, so an error on any path still gives
+/// the buttons back to the mouse.
+#[cfg(feature = "scripting")]
+struct DivertGuard<'a> {
+    transport: &'a dyn open_g_hub_core::transport::HidTransport,
+    device_index: u8,
+    diverted: Vec<usize>,
+}
+
+#[cfg(feature = "scripting")]
+impl DivertGuard<'_> {
+    /// Divert a button; returns its control ID.
+    fn divert(&mut self, index: usize) -> open_g_hub_core::error::Result<u16> {
+        let cid = open_g_hub_core::buttons::set_button_divert(
+            self.transport,
+            self.device_index,
+            index,
+            true,
+        )?;
+        self.diverted.push(index);
+        Ok(cid)
+    }
+
+    /// Un-divert every button, reporting the first failure.
+    fn restore(mut self) -> open_g_hub_core::error::Result<()> {
+        while let Some(&index) = self.diverted.last() {
+            open_g_hub_core::buttons::set_button_divert(
+                self.transport,
+                self.device_index,
+                index,
+                false,
+            )?;
+            self.diverted.pop();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "scripting")]
+impl Drop for DivertGuard<'_> {
+    fn drop(&mut self) {
+        for &index in &self.diverted {
+            if let Err(e) = open_g_hub_core::buttons::set_button_divert(
+                self.transport,
+                self.device_index,
+                index,
+                false,
+            ) {
+                eprintln!("Could not restore button {index}: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(feature = "scripting")]
+fn run_script(path: &std::path::Path, divert: &[usize], dry_run: bool) -> Result<()> {
+    use open_g_hub_core::comm::RetrySettings;
+    use open_g_hub_core::hid::HidapiTransport;
+    use open_g_hub_core::transport::{HidTransport, TransportStack};
+    use open_g_hub_core::{buttons, comm, hidpp, scripting::ScriptEngine, transport};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    for &index in divert {
+        open_g_hub_core::safety::validate_button_index(index)?;
+    }
+
+    let backend: Box<dyn open_g_hub_core::input::InputBackend> = if dry_run {
+        Box::new(PrintBackend)
+    } else {
+        #[cfg(target_os = "linux")]
+        {
+            Box::new(open_g_hub_core::uinput::UinputBackend::new()?)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            anyhow::bail!("input injection is only available on Linux; use --dry-run");
+        }
+    };
+
+    let source = std::fs::read_to_string(path)?;
+    let mut engine = ScriptEngine::new(backend)?;
+    engine.load(&path.display().to_string(), &source)?;
+
+    // Diverted-button events arrive as notifications, so this needs its own
+    // HID handle rather than the daemon.
+    let hid = HidapiTransport::open_first_supported()?;
+    let retry = RetrySettings::load_default()?
+        .policy_for(hid.info())
+        .clone();
+    let transport = TransportStack::standard(retry).build(hid);
+    let dev_idx = comm::find_device_index(&transport)?;
+    let feature_idx =
+        transport::lookup_feature_index(&transport, dev_idx, hidpp::features::REPROG_CONTROLS_V4)?;
+
+    let mut diverts = DivertGuard {
+        transport: &transport,
+        device_index: dev_idx,
+        diverted: Vec::new(),
+    };
+    for &index in divert {
+        let cid = diverts.divert(index)?;
+        engine.map_control(cid, index as u8 + 1);
     }
 
+    let stop = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&stop))?;
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&stop))?;
+
+    println!("Running {} (Ctrl-C to stop)", path.display());
+    engine.activate()?;
+
+    let mut run = || -> Result<()> {
+        while !stop.load(Ordering::Relaxed) {
+            let Some(raw) = transport.read_report(100)? else {
+                continue;
+            };
+            let Ok(report) = hidpp::HidppResponse::decode(&raw) else {
+                continue;
+            };
+            if let Some(held) = buttons::parse_diverted_buttons(&report, feature_idx) {
+                if let Err(e) = engine.handle_diverted(&held) {
+                    eprintln!("Script error: {e}");
+                }
+            }
+        }
+        Ok(())
+    };
+    let ran = run();
+
+    // Clean up all the way before reporting whichever step failed first.
+    let deactivated = engine.deactivate();
+    let released = engine.release_all();
+    let restored = diverts.restore();
+    ran?;
+    deactivated?;
+    released?;
+    restored?;
+    println!("Script stopped; buttons restored.");
+
     Ok(())
 }
@@ -0,0 +1,734 @@
+//! Automatic profile switching by focused application.
+//!
+//! Rules live in `<config dir>/autoswitch.json` (see
+//! [`crate::store::config_dir`]) and name a profile from the library for an
+//! executable, a window class or a window title; the first matching rule
+//! wins, and `default` applies when none does:
+//!
+//! ```json
+//! {
+//!   "default": "Desktop",
+//!   "rules": [
+//!     {"exe": "cs2", "profile": "Shooter"},
+//!     {"exe": "eldenring.exe", "profile": "Souls"},
+//!     {"class": "blender", "title": "sculpt", "profile": "Sculpting"}
+//!   ]
+//! }
+//! ```
+//!
+//! `exe` and `class` compare whole names, `title` looks for a substring;
+//! none of them cares about case, and every field a rule gives must match.
+//!
+//! The focused window comes from a [`FocusProvider`]: X11 (`xprop`), Sway's
+//! IPC (`swaymsg`), other wlroots compositors through the foreign-toplevel
+//! protocol (`lswt`), KWin scripting (`kdotool`), GNOME Shell's Window Calls
+//! extension (`gdbus`), or [`TestFocus`] for tests. [`AutoSwitcher`] turns
+//! focus changes into profile switches; the caller applies them with
+//! [`crate::profile::apply_changes`], so only the settings that differ are
+//! written, and confirms each one that succeeded.
+
+use crate::error::{Error, Result};
+use crate::store;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+/// Rules file name under the config dir.
+const RULES_FILE: &str = "autoswitch.json";
+
+/// Focus provider names accepted by [`provider`].
+pub const PROVIDERS: &[&str] = &["x11", "sway", "wlroots", "kde", "gnome"];
+
+/// The application owning the focused window, as far as the provider knows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FocusInfo {
+    /// Executable file name, e.g. `cs2` or `eldenring.exe` for Wine games.
+    pub exe: Option<String>,
+    /// X11 `WM_CLASS` class or Wayland app ID.
+    pub class: Option<String>,
+    pub title: Option<String>,
+}
+
+/// Switch to `profile` when the focused application matches.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exe: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    /// Part of the window title.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Library profile name.
+    pub profile: String,
+}
+
+impl Rule {
+    /// Whether every field this rule gives matches `focus`. A rule without
+    /// any field matches nothing.
+    pub fn matches(&self, focus: &FocusInfo) -> bool {
+        if self.exe.is_none() && self.class.is_none() && self.title.is_none() {
+            return false;
+        }
+        let name = |want: &Option<String>, have: &Option<String>| match want {
+            Some(want) => have
+                .as_deref()
+                .is_some_and(|h| h.eq_ignore_ascii_case(want)),
+            None => true,
+        };
+        let title = match &self.title {
+            Some(want) => focus
+                .title
+                .as_deref()
+                .is_some_and(|t| t.to_lowercase().contains(&want.to_lowercase())),
+            None => true,
+        };
+        name(&self.exe, &focus.exe) && name(&self.class, &focus.class) && title
+    }
+}
+
+/// Ordered switching rules with a fallback profile.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rules {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// Profile for applications no rule matches, and for no focused window.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+impl Rules {
+    /// The profile for `focus`: the first matching rule's, else the default.
+    pub fn select(&self, focus: Option<&FocusInfo>) -> Option<&str> {
+        focus
+            .and_then(|f| self.rules.iter().find(|r| r.matches(f)))
+            .map(|r| r.profile.as_str())
+            .or(self.default.as_deref())
+    }
+
+    /// Read a rules file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| Error::Profile(format!("cannot read {}: {e}", path.display())))?;
+        serde_json::from_str(&json)
+            .map_err(|e| Error::Profile(format!("invalid rules in {}: {e}", path.display())))
+    }
+}
+
+/// `<config dir>/autoswitch.json`.
+pub fn rules_path() -> Option<PathBuf> {
+    store::config_dir().map(|dir| dir.join(RULES_FILE))
+}
+
+/// Tracks the profile chosen for the focused application.
+#[derive(Debug, Clone)]
+pub struct AutoSwitcher {
+    rules: Rules,
+    current: Option<String>,
+}
+
+impl AutoSwitcher {
+    pub fn new(rules: Rules) -> Self {
+        Self {
+            rules,
+            current: None,
+        }
+    }
+
+    /// The profile of the last confirmed switch.
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// The profile to switch to now that `focus` has the focus, or `None`
+    /// when it is already the current one or nothing applies (no matching
+    /// rule and no default), in which case the current profile stays.
+    ///
+    /// The switch counts once [confirmed](Self::confirm); until then every
+    /// update asks for it again.
+    pub fn update(&self, focus: Option<&FocusInfo>) -> Option<String> {
+        let wanted = self.rules.select(focus)?;
+        if self.current.as_deref() == Some(wanted) {
+            return None;
+        }
+        Some(wanted.to_string())
+    }
+
+    /// Record that the switch to `name` was applied.
+    pub fn confirm(&mut self, name: &str) {
+        self.current = Some(name.to_string());
+    }
+}
+
+/// Source of the focused window.
+pub trait FocusProvider: Send {
+    /// Short name, one of [`PROVIDERS`] for the built-in providers.
+    fn name(&self) -> &'static str;
+
+    /// The focused application, or `None` when no window has the focus.
+    fn focused(&mut self) -> Result<Option<FocusInfo>>;
+}
+
+/// Focus provider driven by the test: reports whatever was last [`set`].
+///
+/// Clones share the focus, so a test can keep one and hand another to the
+/// code under test.
+///
+/// [`set`]: TestFocus::set
+#[derive(Debug, Clone, Default)]
+pub struct TestFocus {
+    focus: Arc<Mutex<Option<FocusInfo>>>,
+}
+
+impl TestFocus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, focus: Option<FocusInfo>) {
+        *self.focus.lock().unwrap() = focus;
+    }
+}
+
+impl FocusProvider for TestFocus {
+    fn name(&self) -> &'static str {
+        "test"
+    }
+
+    fn focused(&mut self) -> Result<Option<FocusInfo>> {
+        Ok(self.focus.lock().unwrap().clone())
+    }
+}
+
+/// The provider for the running session: Sway when `SWAYSOCK` is set, then
+/// KDE, GNOME or another wlroots compositor under Wayland (by
+/// `XDG_CURRENT_DESKTOP`), then X11 when `DISPLAY` is set.
+pub fn detect() -> Option<Box<dyn FocusProvider>> {
+    let set = |var: &str| std::env::var_os(var).is_some_and(|v| !v.is_empty());
+    let desktop = std::env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .to_uppercase();
+    let name = if set("SWAYSOCK") {
+        "sway"
+    } else if set("WAYLAND_DISPLAY") {
+        if desktop.contains("KDE") {
+            "kde"
+        } else if desktop.contains("GNOME") {
+            "gnome"
+        } else {
+            "wlroots"
+        }
+    } else if set("DISPLAY") {
+        "x11"
+    } else {
+        return None;
+    };
+    provider(name)
+}
+
+/// The built-in provider called `name` (see [`PROVIDERS`]).
+pub fn provider(name: &str) -> Option<Box<dyn FocusProvider>> {
+    Some(match name {
+        "x11" => Box::new(X11Focus),
+        "sway" => Box::new(SwayFocus),
+        "wlroots" => Box::new(WlrootsFocus),
+        "kde" => Box::new(KdeFocus),
+        "gnome" => Box::new(GnomeFocus),
+        _ => return None,
+    })
+}
+
+/// X11 `_NET_ACTIVE_WINDOW`, read with `xprop`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct X11Focus;
+
+impl FocusProvider for X11Focus {
+    fn name(&self) -> &'static str {
+        "x11"
+    }
+
+    fn focused(&mut self) -> Result<Option<FocusInfo>> {
+        let root = run("xprop", &["-root", "_NET_ACTIVE_WINDOW"])?;
+        let Some(id) = parse_xprop_active(&root) else {
+            return Ok(None);
+        };
+        let props = run(
+            "xprop",
+            &["-id", id, "WM_CLASS", "_NET_WM_NAME", "_NET_WM_PID"],
+        )?;
+        Ok(Some(parse_xprop_window(&props).into_focus()))
+    }
+}
+
+/// Sway's focused container, from `swaymsg -t get_tree`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SwayFocus;
+
+impl FocusProvider for SwayFocus {
+    fn name(&self) -> &'static str {
+        "sway"
+    }
+
+    fn focused(&mut self) -> Result<Option<FocusInfo>> {
+        let tree = parse_json("swaymsg", &run("swaymsg", &["-t", "get_tree", "-r"])?)?;
+        Ok(find_sway_focused(&tree).map(Window::into_focus))
+    }
+}
+
+/// The activated toplevel of a wlroots compositor (zwlr_foreign_toplevel
+/// protocol), listed by `lswt -j`. The protocol carries no process ID, so
+/// `exe` is never known; match on `class` (the app ID) instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WlrootsFocus;
+
+impl FocusProvider for WlrootsFocus {
+    fn name(&self) -> &'static str {
+        "wlroots"
+    }
+
+    fn focused(&mut self) -> Result<Option<FocusInfo>> {
+        let list = parse_json("lswt", &run("lswt", &["-j"])?)?;
+        Ok(find_lswt_activated(&list).map(Window::into_focus))
+    }
+}
+
+/// KWin's active window through KWin scripting, using `kdotool`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KdeFocus;
+
+impl FocusProvider for KdeFocus {
+    fn name(&self) -> &'static str {
+        "kde"
+    }
+
+    fn focused(&mut self) -> Result<Option<FocusInfo>> {
+        let out = run(
+            "kdotool",
+            &[
+                "getactivewindow",
+                "getwindowclassname",
+                "getwindowname",
+                "getwindowpid",
+            ],
+        )?;
+        Ok(parse_kdotool(&out).map(Window::into_focus))
+    }
+}
+
+/// GNOME Shell's focused window from the Window Calls extension's D-Bus
+/// interface, called with `gdbus`. GNOME offers no focus query without an
+/// extension.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GnomeFocus;
+
+/// Window Calls' object path and interface.
+const WINDOW_CALLS_PATH: &str = "/org/gnome/Shell/Extensions/Windows";
+const WINDOW_CALLS_IFACE: &str = "org.gnome.Shell.Extensions.Windows";
+
+impl GnomeFocus {
+    fn call(method: &str, args: &[&str]) -> Result<String> {
+        let method = format!("{WINDOW_CALLS_IFACE}.{method}");
+        let mut argv = vec![
+            "call",
+            "--session",
+            "--dest",
+            "org.gnome.Shell",
+            "--object-path",
+            WINDOW_CALLS_PATH,
+            "--method",
+            &method,
+        ];
+        argv.extend_from_slice(args);
+        let out = run("gdbus", &argv)?;
+        parse_gvariant_string(&out)
+            .ok_or_else(|| Error::Focus(format!("unexpected reply from {method}: {out}")))
+    }
+}
+
+impl FocusProvider for GnomeFocus {
+    fn name(&self) -> &'static str {
+        "gnome"
+    }
+
+    fn focused(&mut self) -> Result<Option<FocusInfo>> {
+        let list = parse_json("Window Calls", &Self::call("List", &[])?)?;
+        let Some((id, mut window)) = find_gnome_focused(&list) else {
+            return Ok(None);
+        };
+        // Newer versions leave the title out of List.
+        if window.title.is_none() {
+            window.title = Some(Self::call("GetTitle", &[&id.to_string()])?);
+        }
+        Ok(Some(window.into_focus()))
+    }
+}
+
+/// What the providers read about a window, before resolving the process.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Window {
+    class: Option<String>,
+    title: Option<String>,
+    pid: Option<u32>,
+}
+
+impl Window {
+    fn into_focus(self) -> FocusInfo {
+        FocusInfo {
+            exe: self.pid.and_then(exe_name),
+            class: self.class,
+            title: self.title,
+        }
+    }
+}
+
+/// Run a helper and return its output; a missing helper or a failed run is
+/// an [`Error::Focus`].
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| Error::Focus(format!("cannot run {program}: {e}")))?;
+    if !output.status.success() {
+        return Err(Error::Focus(format!(
+            "{program} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn parse_json(source: &str, text: &str) -> Result<Value> {
+    serde_json::from_str(text).map_err(|e| Error::Focus(format!("invalid {source} output: {e}")))
+}
+
+/// Executable name of process `pid`: the last component of `argv[0]`, which
+/// for Wine games is the Windows path (`C:\Games\game.exe`), else the
+/// kernel's command name.
+fn exe_name(pid: u32) -> Option<String> {
+    let proc = PathBuf::from(format!("/proc/{pid}"));
+    let cmdline = std::fs::read(proc.join("cmdline")).unwrap_or_default();
+    let argv0 = cmdline.split(|&b| b == 0).next().unwrap_or_default();
+    file_name(&String::from_utf8_lossy(argv0))
+        .map(str::to_string)
+        .or_else(|| {
+            let comm = std::fs::read_to_string(proc.join("comm")).ok()?;
+            Some(comm.trim_end().to_string()).filter(|c| !c.is_empty())
+        })
+}
+
+/// Last component of a Unix or Windows path.
+fn file_name(path: &str) -> Option<&str> {
+    path.rsplit(['/', '\\'])
+        .next()
+        .filter(|name| !name.is_empty())
+}
+
+/// The window ID in `_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007`;
+/// `None` for `0x0` (nothing focused).
+fn parse_xprop_active(out: &str) -> Option<&str> {
+    let id = out.split_once('#')?.1.split(',').next()?.trim();
+    Some(id).filter(|id| !id.is_empty() && *id != "0x0")
+}
+
+/// `WM_CLASS`, `_NET_WM_NAME` and `_NET_WM_PID` from `xprop -id` output.
+fn parse_xprop_window(out: &str) -> Window {
+    let mut window = Window::default();
+    for line in out.lines() {
+        let Some((name, value)) = line.split_once(" = ") else {
+            continue;
+        };
+        match name.split('(').next().unwrap_or_default() {
+            // Instance name first, then the class.
+            "WM_CLASS" => window.class = xprop_strings(value).pop(),
+            "_NET_WM_NAME" => window.title = xprop_strings(value).pop(),
+            "_NET_WM_PID" => window.pid = value.trim().parse().ok(),
+            _ => {}
+        }
+    }
+    window
+}
+
+/// The quoted strings in an xprop value: `"Navigator", "firefox"`.
+fn xprop_strings(value: &str) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut current: Option<String> = None;
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (&mut current, c) {
+            (None, '"') => current = Some(String::new()),
+            (Some(s), '\\') => s.extend(chars.next()),
+            (Some(_), '"') => strings.extend(current.take()),
+            (Some(s), c) => s.push(c),
+            (None, _) => {}
+        }
+    }
+    strings
+}
+
+/// The focused view in a Sway tree: `app_id` for Wayland clients, the X11
+/// class for XWayland ones.
+fn find_sway_focused(node: &Value) -> Option<Window> {
+    if node["focused"].as_bool() == Some(true) && node["pid"].is_u64() {
+        let class = node["app_id"]
+            .as_str()
+            .or_else(|| node["window_properties"]["class"].as_str());
+        return Some(Window {
+            class: class.map(str::to_string),
+            title: node["name"].as_str().map(str::to_string),
+            pid: node["pid"].as_u64().and_then(|pid| u32::try_from(pid).ok()),
+        });
+    }
+    let children = node["nodes"].as_array().into_iter().flatten();
+    let floating = node["floating_nodes"].as_array().into_iter().flatten();
+    children.chain(floating).find_map(find_sway_focused)
+}
+
+/// The activated toplevel in `lswt -j` output, either a bare list or
+/// `{"toplevels": [...]}`.
+fn find_lswt_activated(list: &Value) -> Option<Window> {
+    let toplevels = list["toplevels"].as_array().or_else(|| list.as_array())?;
+    let active = toplevels
+        .iter()
+        .find(|t| t["activated"].as_bool() == Some(true))?;
+    Some(Window {
+        class: active["app-id"].as_str().map(str::to_string),
+        title: active["title"].as_str().map(str::to_string),
+        pid: None,
+    })
+}
+
+/// `kdotool` chained output: class, title and PID on one line each. No
+/// output means no active window.
+fn parse_kdotool(out: &str) -> Option<Window> {
+    let lines: Vec<&str> = out.lines().collect();
+    let [class, title @ .., pid] = lines.as_slice() else {
+        return None;
+    };
+    let non_empty = |s: &str| Some(s.to_string()).filter(|s| !s.is_empty());
+    Some(Window {
+        class: non_empty(class),
+        title: non_empty(&title.join("\n")),
+        pid: pid.trim().parse().ok(),
+    })
+}
+
+/// The focused window and its ID in a Window Calls `List` reply.
+fn find_gnome_focused(list: &Value) -> Option<(u64, Window)> {
+    let window = list
+        .as_array()?
+        .iter()
+        .find(|w| w["focus"].as_bool() == Some(true))?;
+    Some((
+        window["id"].as_u64()?,
+        Window {
+            class: window["wm_class"].as_str().map(str::to_string),
+            title: window["title"].as_str().map(str::to_string),
+            pid: window["pid"]
+                .as_u64()
+                .and_then(|pid| u32::try_from(pid).ok()),
+        },
+    ))
+}
+
+/// The string in a one-string `gdbus call` reply, `('...',)`, unescaped.
+fn parse_gvariant_string(out: &str) -> Option<String> {
+    let quoted = out
+        .trim()
+        .strip_prefix("('")?
+        .strip_suffix(",)")?
+        .strip_suffix('\'')?;
+    let mut text = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            c => text.push(c),
+        }
+    }
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::Write;
+    use crate::profile::{self, Profile};
+    use crate::sim::SimulatedG502;
+
+    fn app(exe: &str, class: &str, title: &str) -> FocusInfo {
+        FocusInfo {
+            exe: Some(exe.into()),
+            class: Some(class.into()),
+            title: Some(title.into()),
+        }
+    }
+
+    fn rules() -> Rules {
+        serde_json::from_str(
+            r#"{
+                "default": "Desktop",
+                "rules": [
+                    {"exe": "eldenring.exe", "profile": "Souls"},
+                    {"class": "blender", "title": "Sculpt", "profile": "Sculpting"},
+                    {"class": "steam_app_730", "profile": "Shooter"},
+                    {"profile": "Never"}
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn first_matching_rule_wins_else_default() {
+        let rules = rules();
+        let pick = |focus: FocusInfo| rules.select(Some(&focus)).map(str::to_string);
+        assert_eq!(
+            pick(app("ELDENRING.EXE", "steam_app_1245620", "ELDEN RING")).as_deref(),
+            Some("Souls")
+        );
+        assert_eq!(
+            pick(app("blender", "Blender", "scene.blend - sculpting")).as_deref(),
+            Some("Sculpting")
+        );
+        assert_eq!(
+            pick(app("blender", "Blender", "scene.blend")).as_deref(),
+            Some("Desktop")
+        );
+        let shooter = FocusInfo {
+            class: Some("steam_app_730".into()),
+            ..Default::default()
+        };
+        assert_eq!(pick(shooter).as_deref(), Some("Shooter"));
+        assert_eq!(rules.select(None), Some("Desktop"));
+        assert_eq!(Rules::default().select(Some(&app("cs2", "cs2", ""))), None);
+    }
+
+    #[test]
+    fn switches_apply_only_the_changed_settings() {
+        let sim = SimulatedG502::new(0x01);
+        let desktop = profile::snapshot(&sim, 0x01).unwrap();
+        let souls = Profile {
+            name: "Souls".into(),
+            dpi: 1600,
+            ..desktop.clone()
+        };
+        let library = [("Desktop", &desktop), ("Souls", &souls)];
+
+        let focus = TestFocus::new();
+        let mut provider: Box<dyn FocusProvider> = Box::new(focus.clone());
+        let mut switcher = AutoSwitcher::new(rules());
+        let mut switch = || {
+            let name = switcher.update(provider.focused().unwrap().as_ref())?;
+            let (_, target) = library.iter().find(|(n, _)| *n == name)?;
+            let report = profile::apply_changes(&sim, 0x01, target).unwrap();
+            switcher.confirm(&name);
+            Some(report)
+        };
+
+        focus.set(Some(app(
+            "eldenring.exe",
+            "steam_app_1245620",
+            "ELDEN RING",
+        )));
+        let report = switch().unwrap();
+        assert_eq!(
+            report.applied,
+            [Write::Dpi {
+                from: 800,
+                to: 1600
+            }]
+        );
+        assert_eq!(sim.dpi(), 1600);
+        // Same application, new title: nothing to switch.
+        focus.set(Some(app("eldenring.exe", "steam_app_1245620", "Loading")));
+        assert!(switch().is_none());
+
+        focus.set(None);
+        let report = switch().unwrap();
+        assert_eq!(
+            report.applied,
+            [Write::Dpi {
+                from: 1600,
+                to: 800
+            }]
+        );
+        assert_eq!(switcher.current(), Some("Desktop"));
+    }
+
+    #[test]
+    fn xprop_output_is_parsed() {
+        assert_eq!(
+            parse_xprop_active("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007, 0x0\n"),
+            Some("0x3a00007")
+        );
+        assert_eq!(
+            parse_xprop_active("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x0\n"),
+            None
+        );
+        let window = parse_xprop_window(
+            "WM_CLASS(STRING) = \"Navigator\", \"firefox\"\n\
+             _NET_WM_NAME(UTF8_STRING) = \"Say \\\"gg\\\" - Mozilla Firefox\"\n\
+             _NET_WM_PID(CARDINAL) = 4242\n",
+        );
+        assert_eq!(
+            window,
+            Window {
+                class: Some("firefox".into()),
+                title: Some("Say \"gg\" - Mozilla Firefox".into()),
+                pid: Some(4242),
+            }
+        );
+        let bare = parse_xprop_window("WM_CLASS:  not found.\n_NET_WM_PID:  not found.\n");
+        assert_eq!(bare, Window::default());
+    }
+
+    #[test]
+    fn compositor_replies_are_parsed() {
+        let tree: Value = serde_json::from_str(
+            r#"{"type": "root", "nodes": [{"type": "workspace", "focused": false,
+                "nodes": [{"app_id": "foot", "name": "~", "pid": 10, "focused": false}],
+                "floating_nodes": [{"app_id": null, "name": "Counter-Strike 2",
+                    "pid": 11, "focused": true,
+                    "window_properties": {"class": "cs2", "instance": "cs2"}}]}]}"#,
+        )
+        .unwrap();
+        let sway = find_sway_focused(&tree).unwrap();
+        assert_eq!(sway.class.as_deref(), Some("cs2"));
+        assert_eq!(sway.pid, Some(11));
+
+        let lswt: Value = serde_json::from_str(
+            r#"{"toplevels": [{"app-id": "foot", "title": "~", "activated": false},
+                {"app-id": "org.gnome.Nautilus", "title": "Home", "activated": true}]}"#,
+        )
+        .unwrap();
+        let active = find_lswt_activated(&lswt).unwrap();
+        assert_eq!(active.class.as_deref(), Some("org.gnome.Nautilus"));
+        assert_eq!(active.pid, None);
+
+        let kde = parse_kdotool("steam_app_730\nCounter-Strike 2\n5150\n").unwrap();
+        assert_eq!(kde.title.as_deref(), Some("Counter-Strike 2"));
+        assert_eq!(kde.pid, Some(5150));
+        assert_eq!(parse_kdotool(""), None);
+
+        let reply = r#"('[{"id":3862,"wm_class":"steam_app_730","pid":77,"focus":true,"title":"It\'s \\"CS\\""},{"id":1,"focus":false}]',)"#;
+        let list: Value = serde_json::from_str(&parse_gvariant_string(reply).unwrap()).unwrap();
+        let (id, gnome) = find_gnome_focused(&list).unwrap();
+        assert_eq!(id, 3862);
+        assert_eq!(gnome.title.as_deref(), Some("It's \"CS\""));
+        assert_eq!(gnome.pid, Some(77));
+    }
+
+    #[test]
+    fn exe_names_come_from_unix_and_windows_paths() {
+        assert_eq!(file_name("/usr/bin/blender"), Some("blender"));
+        assert_eq!(
+            file_name(r"C:\Program Files\ELDEN RING\eldenring.exe"),
+            Some("eldenring.exe")
+        );
+        assert_eq!(file_name(""), None);
+        assert!(provider("x11").is_some());
+        assert!(provider("test").is_none());
+    }
+}
@@ -0,0 +1,171 @@
+//! Battery level via HID++ 2.0 BATTERY_STATUS feature (0x1000).
+//!
+//! HID++ 2.0 BATTERY_STATUS functions:
+//!   - Function 0: getBatteryLevelStatus → [level %, next level %, status]
+//!   - Function 1: getBatteryCapability (not used)
+//!
+//! The device also broadcasts event 0 with the same layout whenever the level
+//! or charging status changes.
+
+use crate::error::{Error, Result};
+use crate::hidpp::{self, HidppRequest, HidppResponse};
+use crate::transport::{hidpp_request, lookup_feature_index, HidTransport};
+use serde::{Deserialize, Serialize};
+
+/// Charging status byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChargingState {
+    Discharging,
+    Recharging,
+    AlmostFull,
+    Full,
+    SlowRecharge,
+    InvalidBattery,
+    ThermalError,
+}
+
+impl ChargingState {
+    pub fn from_byte(b: u8) -> Option<Self> {
+        match b {
+            0 => Some(Self::Discharging),
+            1 => Some(Self::Recharging),
+            2 => Some(Self::AlmostFull),
+            3 => Some(Self::Full),
+            4 => Some(Self::SlowRecharge),
+            5 => Some(Self::InvalidBattery),
+            6 => Some(Self::ThermalError),
+            _ => None,
+        }
+    }
+
+    /// Whether external power is connected.
+    pub fn is_charging(&self) -> bool {
+        matches!(
+            self,
+            Self::Recharging | Self::AlmostFull | Self::Full | Self::SlowRecharge
+        )
+    }
+}
+
+/// A battery reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatteryStatus {
+    /// Charge in percent.
+    pub level: u8,
+    /// Next reportable level in percent (devices report in steps).
+    pub next_level: u8,
+    pub state: ChargingState,
+}
+
+impl BatteryStatus {
+    fn decode(params: &[u8]) -> Option<Self> {
+        match params {
+            [level, next_level, status, ..] => Some(Self {
+                level: *level,
+                next_level: *next_level,
+                state: ChargingState::from_byte(*status)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Read the battery level and charging state.
+pub fn read_battery(transport: &dyn HidTransport, device_index: u8) -> Result<BatteryStatus> {
+    let feature_idx =
+        lookup_feature_index(transport, device_index, hidpp::features::BATTERY_STATUS)?;
+
+    // getBatteryLevelStatus: function 0, no params
+    let req = HidppRequest::new(device_index, feature_idx, 0x00, vec![]);
+    let resp = hidpp_request(transport, &req)?;
+
+    BatteryStatus::decode(&resp.params).ok_or(Error::MalformedReply {
+        feature: hidpp::features::BATTERY_STATUS,
+    })
+}
+
+/// Decode a battery broadcast (BATTERY_STATUS event 0).
+///
+/// Returns `None` if the report is not a battery event for `feature_idx`.
+/// Broadcasts carry software ID 0, which tells them apart from replies.
+pub fn parse_battery_event(report: &HidppResponse, feature_idx: u8) -> Option<BatteryStatus> {
+    if report.feature_index != feature_idx
+        || report.function() != 0x00
+        || report.function_sw & 0x0F != 0
+    {
+        return None;
+    }
+    BatteryStatus::decode(&report.params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimulatedG502;
+    use crate::transport::mock::MockTransport;
+
+    const DEV_IDX: u8 = 0x01;
+    const BATTERY_FEATURE_IDX: u8 = 0x03;
+
+    #[test]
+    fn read_battery_decodes_level_and_state() {
+        let mock = MockTransport::new();
+        mock.on_short_request(
+            DEV_IDX,
+            0x00,
+            0x01,
+            &[0x10, 0x00],
+            &[BATTERY_FEATURE_IDX, 0x00, 0x00],
+        );
+        mock.on_short_request(DEV_IDX, BATTERY_FEATURE_IDX, 0x01, &[], &[0x32, 0x14, 0x01]);
+
+        let status = read_battery(&mock, DEV_IDX).unwrap();
+        assert_eq!(status.level, 50);
+        assert_eq!(status.next_level, 20);
+        assert_eq!(status.state, ChargingState::Recharging);
+        assert!(status.state.is_charging());
+    }
+
+    #[test]
+    fn unknown_status_byte_is_a_protocol_error() {
+        let mock = MockTransport::new();
+        mock.on_short_request(
+            DEV_IDX,
+            0x00,
+            0x01,
+            &[0x10, 0x00],
+            &[BATTERY_FEATURE_IDX, 0x00, 0x00],
+        );
+        mock.on_short_request(DEV_IDX, BATTERY_FEATURE_IDX, 0x01, &[], &[0x32, 0x14, 0x09]);
+        assert!(read_battery(&mock, DEV_IDX).is_err());
+    }
+
+    #[test]
+    fn broadcasts_parse_but_replies_do_not() {
+        let event =
+            HidppResponse::decode(&[0x10, DEV_IDX, BATTERY_FEATURE_IDX, 0x00, 0x0A, 0x05, 0x00])
+                .unwrap();
+        let status = parse_battery_event(&event, BATTERY_FEATURE_IDX).unwrap();
+        assert_eq!(status.level, 10);
+        assert_eq!(status.state, ChargingState::Discharging);
+
+        let reply =
+            HidppResponse::decode(&[0x10, DEV_IDX, BATTERY_FEATURE_IDX, 0x01, 0x0A, 0x05, 0x00])
+                .unwrap();
+        assert!(parse_battery_event(&reply, BATTERY_FEATURE_IDX).is_none());
+        assert!(parse_battery_event(&event, 0x07).is_none());
+    }
+
+    #[test]
+    fn simulated_device_broadcasts_changes() {
+        let sim = SimulatedG502::new(DEV_IDX);
+        sim.set_battery(15, 0);
+        assert_eq!(read_battery(&sim, DEV_IDX).unwrap().level, 15);
+
+        let idx = SimulatedG502::feature_index(hidpp::features::BATTERY_STATUS).unwrap();
+        let raw = sim.read_report(0).unwrap().unwrap();
+        let event = HidppResponse::decode(&raw).unwrap();
+        assert_eq!(parse_battery_event(&event, idx).unwrap().level, 15);
+    }
+}
@@ -0,0 +1,501 @@
+//! Battery history and runtime estimation.
+//!
+//! Battery readings and charging changes are kept per mouse in a small
+//! SQLite database, `$XDG_STATE_HOME/open-g-hub/battery.db` (else
+//! `~/.local/state/open-g-hub/battery.db`), along with the report rate and
+//! whether the LEDs were lit at the time. Mice are keyed by unit ID (see
+//! [`crate::device::read_unit_id`]), so a mouse keeps its history across
+//! receivers and cables. Readings older than [`RETENTION_DAYS`] are dropped.
+//!
+//! [`drain_rates`] measures how fast the battery falls under each
+//! combination of settings, from consecutive discharging readings taken
+//! with the same settings; [`estimate_runtime`] turns the rate for the
+//! current settings into hours left.
+
+use crate::battery::{BatteryStatus, ChargingState};
+use crate::device::PollingRate;
+use crate::error::{Error, Result};
+use crate::led::LedMode;
+use crate::profile::Profile;
+use rusqlite::{params, Connection};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Database file name under the state dir.
+const HISTORY_FILE: &str = "battery.db";
+
+/// How long readings are kept.
+pub const RETENTION_DAYS: u64 = 180;
+
+/// Longest gap between two readings still counted as one stretch of use.
+/// Longer gaps mean nobody was watching (daemon stopped, mouse switched off).
+const MAX_GAP_SECS: u64 = 6 * 3600;
+
+/// `$XDG_STATE_HOME/open-g-hub/battery.db`, else
+/// `~/.local/state/open-g-hub/battery.db`.
+pub fn history_path() -> Option<PathBuf> {
+    let non_empty = |var: &str| std::env::var_os(var).filter(|v| !v.is_empty());
+    let base = non_empty("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| non_empty("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    Some(base.join("open-g-hub").join(HISTORY_FILE))
+}
+
+/// The settings that drive battery drain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Usage {
+    pub polling_rate: Option<PollingRate>,
+    /// Whether any LED zone is lit; `None` when unknown.
+    pub lighting: Option<bool>,
+}
+
+impl Usage {
+    /// The usage of a mouse running `profile`. A profile without LED
+    /// effects leaves the lighting unknown.
+    pub fn of(profile: &Profile) -> Self {
+        Self {
+            polling_rate: Some(profile.polling_rate),
+            lighting: (!profile.leds.is_empty())
+                .then(|| profile.leds.iter().any(|e| e.mode != LedMode::Off)),
+        }
+    }
+}
+
+impl std::fmt::Display for Usage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.polling_rate {
+            Some(rate) => write!(f, "{} Hz", rate.as_hz())?,
+            None => write!(f, "unknown rate")?,
+        }
+        match self.lighting {
+            Some(true) => write!(f, ", lighting on"),
+            Some(false) => write!(f, ", lighting off"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// One recorded reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatterySample {
+    /// Unix time in seconds.
+    pub time: u64,
+    pub level: u8,
+    pub state: ChargingState,
+    pub usage: Usage,
+}
+
+impl BatterySample {
+    /// A reading taken now.
+    pub fn now(battery: &BatteryStatus, usage: Usage) -> Self {
+        Self {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            level: battery.level,
+            state: battery.state,
+            usage,
+        }
+    }
+}
+
+/// Measured drain under one combination of settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrainRate {
+    pub usage: Usage,
+    /// Battery used per hour, in percent.
+    pub percent_per_hour: f64,
+    /// Hours of discharging the rate was measured over.
+    pub hours: f64,
+}
+
+impl DrainRate {
+    /// Hours a full charge lasts at this rate.
+    pub fn full_charge_hours(&self) -> f64 {
+        100.0 / self.percent_per_hour
+    }
+}
+
+/// Remaining runtime at some battery level.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RuntimeEstimate {
+    pub hours: f64,
+    pub percent_per_hour: f64,
+    /// Whether the rate was measured with the same settings; otherwise it is
+    /// the average over everything recorded.
+    pub same_usage: bool,
+}
+
+impl std::fmt::Display for RuntimeEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "about {:.1} h ({:.1} %/h",
+            self.hours, self.percent_per_hour
+        )?;
+        if !self.same_usage {
+            write!(f, ", averaged over other settings")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Drain per combination of settings, most measured first. Settings whose
+/// readings never showed a drop are left out.
+pub fn drain_rates(samples: &[BatterySample]) -> Vec<DrainRate> {
+    // (usage, percent used, seconds)
+    let mut totals: Vec<(Usage, u64, u64)> = Vec::new();
+    for pair in samples.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let gap = b.time.saturating_sub(a.time);
+        if a.state != ChargingState::Discharging
+            || b.state != ChargingState::Discharging
+            || a.usage != b.usage
+            || gap == 0
+            || gap > MAX_GAP_SECS
+            || b.level > a.level
+        {
+            continue;
+        }
+        let drop = u64::from(a.level - b.level);
+        match totals.iter_mut().find(|(usage, ..)| *usage == a.usage) {
+            Some((_, used, secs)) => {
+                *used += drop;
+                *secs += gap;
+            }
+            None => totals.push((a.usage, drop, gap)),
+        }
+    }
+    let mut rates: Vec<DrainRate> = totals
+        .into_iter()
+        .filter(|&(_, used, _)| used > 0)
+        .map(|(usage, used, secs)| {
+            let hours = secs as f64 / 3600.0;
+            DrainRate {
+                usage,
+                percent_per_hour: used as f64 / hours,
+                hours,
+            }
+        })
+        .collect();
+    rates.sort_by(|a, b| b.hours.total_cmp(&a.hours));
+    rates
+}
+
+/// Hours left at `level` percent with `usage`, from the rate measured with
+/// the same settings, else the average over all settings. `None` until the
+/// history shows some drain.
+pub fn estimate_runtime(
+    samples: &[BatterySample],
+    level: u8,
+    usage: Usage,
+) -> Option<RuntimeEstimate> {
+    let rates = drain_rates(samples);
+    let (percent_per_hour, same_usage) = match rates.iter().find(|r| r.usage == usage) {
+        Some(rate) => (rate.percent_per_hour, true),
+        None => {
+            let hours: f64 = rates.iter().map(|r| r.hours).sum();
+            let used: f64 = rates.iter().map(|r| r.percent_per_hour * r.hours).sum();
+            if hours == 0.0 {
+                return None;
+            }
+            (used / hours, false)
+        }
+    };
+    Some(RuntimeEstimate {
+        hours: f64::from(level) / percent_per_hour,
+        percent_per_hour,
+        same_usage,
+    })
+}
+
+/// The battery history database.
+pub struct BatteryHistory {
+    conn: Connection,
+    path: PathBuf,
+}
+
+fn db_error(path: &Path) -> impl Fn(rusqlite::Error) -> Error + '_ {
+    move |e| Error::Profile(format!("{}: {e}", path.display()))
+}
+
+fn state_name(state: ChargingState) -> String {
+    serde_json::to_value(state)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+impl BatteryHistory {
+    /// Open the history at [`history_path`], creating it if needed.
+    pub fn open_default() -> Result<Self> {
+        let path = history_path().ok_or_else(|| {
+            Error::Profile("cannot locate the state directory (set HOME)".to_string())
+        })?;
+        Self::open(&path)
+    }
+
+    /// Open or create a history database at `path`.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| Error::Profile(format!("cannot create {}: {e}", dir.display())))?;
+        }
+        let conn = Connection::open(path).map_err(db_error(path))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS readings (
+                 device TEXT NOT NULL,
+                 time INTEGER NOT NULL,
+                 level INTEGER NOT NULL,
+                 state TEXT NOT NULL,
+                 polling_rate INTEGER,
+                 lighting INTEGER
+             );
+             CREATE INDEX IF NOT EXISTS readings_by_device ON readings (device, time);",
+        )
+        .map_err(db_error(path))?;
+        Ok(Self {
+            conn,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Store a reading for `device` unless it repeats the last one (same
+    /// level, state and settings), and drop readings past retention.
+    ///
+    /// Returns whether the reading was stored.
+    pub fn record(&self, device: &str, sample: &BatterySample) -> Result<bool> {
+        let err = db_error(&self.path);
+        if self.last(device)?.is_some_and(|last| {
+            last.level == sample.level && last.state == sample.state && last.usage == sample.usage
+        }) {
+            return Ok(false);
+        }
+        self.conn
+            .execute(
+                "INSERT INTO readings (device, time, level, state, polling_rate, lighting)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    device,
+                    sample.time as i64,
+                    sample.level,
+                    state_name(sample.state),
+                    sample.usage.polling_rate.map(|r| r.as_hz()),
+                    sample.usage.lighting,
+                ],
+            )
+            .map_err(&err)?;
+        let cutoff = sample.time.saturating_sub(RETENTION_DAYS * 86_400);
+        self.conn
+            .execute(
+                "DELETE FROM readings WHERE device = ?1 AND time < ?2",
+                params![device, cutoff as i64],
+            )
+            .map_err(&err)?;
+        Ok(true)
+    }
+
+    /// Every reading of `device`, oldest first.
+    pub fn samples(&self, device: &str) -> Result<Vec<BatterySample>> {
+        self.query(
+            "SELECT time, level, state, polling_rate, lighting FROM readings
+             WHERE device = ?1 ORDER BY time, rowid",
+            device,
+        )
+    }
+
+    /// The newest reading of `device`.
+    pub fn last(&self, device: &str) -> Result<Option<BatterySample>> {
+        Ok(self
+            .query(
+                "SELECT time, level, state, polling_rate, lighting FROM readings
+                 WHERE device = ?1 ORDER BY time DESC, rowid DESC LIMIT 1",
+                device,
+            )?
+            .pop())
+    }
+
+    fn query(&self, sql: &str, device: &str) -> Result<Vec<BatterySample>> {
+        let err = db_error(&self.path);
+        let mut stmt = self.conn.prepare(sql).map_err(&err)?;
+        let rows = stmt
+            .query_map([device], |row| {
+                let state: String = row.get(2)?;
+                let rate: Option<u16> = row.get(3)?;
+                Ok(BatterySample {
+                    time: row.get::<_, i64>(0)?.max(0) as u64,
+                    level: row.get(1)?,
+                    state: serde_json::from_value(state.into())
+                        .unwrap_or(ChargingState::InvalidBattery),
+                    usage: Usage {
+                        polling_rate: rate.and_then(PollingRate::from_hz),
+                        lighting: row.get(4)?,
+                    },
+                })
+            })
+            .map_err(&err)?;
+        rows.collect::<rusqlite::Result<_>>().map_err(&err)
+    }
+
+    /// Mice with recorded readings.
+    pub fn devices(&self) -> Result<Vec<String>> {
+        let err = db_error(&self.path);
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT device FROM readings ORDER BY device")
+            .map_err(&err)?;
+        let rows = stmt.query_map([], |row| row.get(0)).map_err(&err)?;
+        rows.collect::<rusqlite::Result<_>>().map_err(&err)
+    }
+
+    /// `time` as local date and time, `2026-10-18 14:02`.
+    pub fn local_time(&self, time: u64) -> String {
+        self.conn
+            .query_row(
+                "SELECT strftime('%Y-%m-%d %H:%M', ?1, 'unixepoch', 'localtime')",
+                [time as i64],
+                |row| row.get(0),
+            )
+            .unwrap_or_else(|_| time.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::led::LedEffect;
+
+    const HOUR: u64 = 3600;
+
+    fn usage(hz: u16, lighting: bool) -> Usage {
+        Usage {
+            polling_rate: PollingRate::from_hz(hz),
+            lighting: Some(lighting),
+        }
+    }
+
+    fn sample(time: u64, level: u8, state: ChargingState, usage: Usage) -> BatterySample {
+        BatterySample {
+            time,
+            level,
+            state,
+            usage,
+        }
+    }
+
+    /// Ten hours at 1000 Hz with lights (5 %/h), a charge, then ten hours at
+    /// 500 Hz without (2 %/h).
+    fn week() -> Vec<BatterySample> {
+        let fast = usage(1000, true);
+        let slow = usage(500, false);
+        let mut samples: Vec<BatterySample> = (0..=10)
+            .map(|h| {
+                sample(
+                    h * HOUR,
+                    100 - 5 * h as u8,
+                    ChargingState::Discharging,
+                    fast,
+                )
+            })
+            .collect();
+        samples.push(sample(11 * HOUR, 50, ChargingState::Recharging, fast));
+        samples.push(sample(14 * HOUR, 100, ChargingState::Full, slow));
+        samples.extend((0..=10).map(|h| {
+            sample(
+                (15 + h) * HOUR,
+                100 - 2 * h as u8,
+                ChargingState::Discharging,
+                slow,
+            )
+        }));
+        samples
+    }
+
+    #[test]
+    fn drain_is_measured_per_setting() {
+        let rates = drain_rates(&week());
+        assert_eq!(rates.len(), 2);
+        let fast = rates.iter().find(|r| r.usage == usage(1000, true)).unwrap();
+        assert_eq!(fast.percent_per_hour, 5.0);
+        assert_eq!(fast.hours, 10.0);
+        assert_eq!(fast.full_charge_hours(), 20.0);
+        let slow = rates.iter().find(|r| r.usage == usage(500, false)).unwrap();
+        assert_eq!(slow.percent_per_hour, 2.0);
+
+        let estimate = estimate_runtime(&week(), 40, usage(500, false)).unwrap();
+        assert_eq!(estimate.hours, 20.0);
+        assert!(estimate.same_usage);
+        let unseen = estimate_runtime(&week(), 35, usage(250, true)).unwrap();
+        assert!(!unseen.same_usage);
+        assert_eq!(unseen.percent_per_hour, 3.5);
+        assert_eq!(estimate_runtime(&[], 50, usage(1000, true)), None);
+    }
+
+    #[test]
+    fn gaps_and_setting_changes_split_the_history() {
+        let fast = usage(1000, true);
+        let samples = [
+            sample(0, 90, ChargingState::Discharging, fast),
+            // Overnight with the daemon stopped: not counted.
+            sample(10 * HOUR, 60, ChargingState::Discharging, fast),
+            sample(11 * HOUR, 55, ChargingState::Discharging, fast),
+            // Settings changed between readings: not counted either.
+            sample(12 * HOUR, 50, ChargingState::Discharging, usage(125, false)),
+        ];
+        let rates = drain_rates(&samples);
+        assert_eq!(rates.len(), 1);
+        assert_eq!(rates[0].percent_per_hour, 5.0);
+        assert_eq!(rates[0].hours, 1.0);
+    }
+
+    #[test]
+    fn readings_are_stored_per_device() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = BatteryHistory::open(&dir.path().join("state/battery.db")).unwrap();
+        for s in week() {
+            assert!(history.record("1A2B3C4D", &s).unwrap());
+        }
+        // A repeat of the last reading adds nothing.
+        let mut repeat = *week().last().unwrap();
+        repeat.time += HOUR;
+        assert!(!history.record("1A2B3C4D", &repeat).unwrap());
+        history
+            .record(
+                "9F8E7D6C",
+                &sample(0, 80, ChargingState::Discharging, Usage::default()),
+            )
+            .unwrap();
+
+        assert_eq!(history.devices().unwrap(), ["1A2B3C4D", "9F8E7D6C"]);
+        assert_eq!(history.samples("1A2B3C4D").unwrap(), week());
+        let last = history.last("9F8E7D6C").unwrap().unwrap();
+        assert_eq!(last.usage, Usage::default());
+        assert_eq!(history.samples("00000000").unwrap(), []);
+
+        // Readings past retention go when a new one comes in.
+        let later = sample(
+            (RETENTION_DAYS + 1) * 86_400,
+            70,
+            ChargingState::Discharging,
+            Usage::default(),
+        );
+        history.record("9F8E7D6C", &later).unwrap();
+        assert_eq!(history.samples("9F8E7D6C").unwrap(), [later]);
+    }
+
+    #[test]
+    fn usage_comes_from_the_profile() {
+        let dark = Profile {
+            polling_rate: PollingRate::Hz500,
+            leds: vec![LedEffect {
+                mode: LedMode::Off,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(Usage::of(&dark), usage(500, false));
+        assert_eq!(Usage::of(&dark).to_string(), "500 Hz, lighting off");
+        assert_eq!(Usage::of(&Profile::default()).lighting, None);
+    }
+}
@@ -11,7 +11,7 @@
 
 use crate::device::ButtonAction;
 use crate::error::{Error, Result};
-use crate::hidpp::{self, HidppRequest};
+use crate::hidpp::{self, HidppRequest, HidppResponse};
 use crate::safety;
 use crate::transport::{hidpp_request, lookup_feature_index, HidTransport};
 
@@ -101,9 +101,8 @@ pub fn read_control_info(
 
     // Response: params[0..1] = CID, params[2..3] = task_id, params[4] = flags
     if resp.params.len() < 5 {
-        return Err(Error::HidppProtocol {
+        return Err(Error::MalformedReply {
             feature: hidpp::features::REPROG_CONTROLS_V4,
-            code: 0xFE,
         });
     }
 
@@ -123,13 +122,21 @@ pub fn read_button_mapping(
     button_index: usize,
 ) -> Result<ButtonAction> {
     safety::validate_button_index(button_index)?;
+    read_control_mapping(transport, device_index, button_index as u8)
+}
 
+/// Read the current remapping of the control at `index`, including the
+/// controls past the remappable buttons (DPI buttons, G-shift).
+pub fn read_control_mapping(
+    transport: &dyn HidTransport,
+    device_index: u8,
+    index: u8,
+) -> Result<ButtonAction> {
     let feature_idx =
         lookup_feature_index(transport, device_index, hidpp::features::REPROG_CONTROLS_V4)?;
 
-    // First get the CID for this button index
-    let info =
-        read_control_info_with_feature(transport, device_index, feature_idx, button_index as u8)?;
+    // First get the CID for this control index
+    let info = read_control_info_with_feature(transport, device_index, feature_idx, index)?;
 
     // getControlReporting: function 2, params[0..1]=CID
     let req = HidppRequest::new(
@@ -228,6 +235,58 @@ fn write_button_mapping_cid_with_feature(
     Ok(())
 }
 
+/// Divert (or restore) a button so its presses are reported to the host.
+///
+/// A diverted control no longer performs its native action; instead the device
+/// sends divertedButtonsEvent notifications (see [`parse_diverted_buttons`]).
+/// Returns the control's CID so callers can match it in those notifications.
+pub fn set_button_divert(
+    transport: &dyn HidTransport,
+    device_index: u8,
+    button_index: usize,
+    divert: bool,
+) -> Result<u16> {
+    safety::validate_button_index(button_index)?;
+
+    let feature_idx =
+        lookup_feature_index(transport, device_index, hidpp::features::REPROG_CONTROLS_V4)?;
+    let info =
+        read_control_info_with_feature(transport, device_index, feature_idx, button_index as u8)?;
+
+    // setControlReporting: function 3
+    // params: CID[0..1], flags (bit1 = divert valid, bit0 = divert)
+    let flags = if divert { 0x03 } else { 0x02 };
+    let req = HidppRequest::new(
+        device_index,
+        feature_idx,
+        0x03,
+        vec![(info.cid >> 8) as u8, (info.cid & 0xFF) as u8, flags],
+    );
+    let _resp = hidpp_request(transport, &req)?;
+
+    Ok(info.cid)
+}
+
+/// Decode a divertedButtonsEvent notification (REPROG_CONTROLS_V4 event 0).
+///
+/// Returns the CIDs currently held down (up to four; empty means all released),
+/// or `None` if the report is not a diverted-buttons event for `feature_idx`.
+pub fn parse_diverted_buttons(report: &HidppResponse, feature_idx: u8) -> Option<Vec<u16>> {
+    if report.feature_index != feature_idx || report.function() != 0x00 {
+        return None;
+    }
+
+    Some(
+        report
+            .params
+            .chunks_exact(2)
+            .take(4)
+            .map(|pair| ((pair[0] as u16) << 8) | (pair[1] as u16))
+            .filter(|&cid| cid != 0)
+            .collect(),
+    )
+}
+
 /// Internal: read control info when feature index is already known.
 fn read_control_info_with_feature(
     transport: &dyn HidTransport,
@@ -239,9 +298,8 @@ fn read_control_info_with_feature(
     let resp = hidpp_request(transport, &req)?;
 
     if resp.params.len() < 5 {
-        return Err(Error::HidppProtocol {
+        return Err(Error::MalformedReply {
             feature: hidpp::features::REPROG_CONTROLS_V4,
-            code: 0xFE,
         });
     }
 
@@ -437,4 +495,53 @@ mod tests {
 
         write_button_mapping_cid(&mock, DEV_IDX, 2, 0x0065).unwrap();
     }
+
+    #[test]
+    fn set_button_divert_sends_divert_flags() {
+        let mock = MockTransport::new();
+        setup_button_feature_lookup(&mock);
+
+        // getControlInfo for index 3: CID=0x0053 (back)
+        mock.on_long_request(
+            DEV_IDX,
+            BTN_FEATURE_IDX,
+            0x11,
+            &[0x03],
+            &[
+                0x00, 0x53, 0x00, 0x3B, 0x21, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00,
+            ],
+        );
+
+        // setControlReporting: CID 0x0053, flags = divert valid | divert
+        mock.on_short_request(
+            DEV_IDX,
+            BTN_FEATURE_IDX,
+            0x31,
+            &[0x00, 0x53, 0x03],
+            &[0x00, 0x53, 0x03],
+        );
+
+        let cid = set_button_divert(&mock, DEV_IDX, 3, true).unwrap();
+        assert_eq!(cid, 0x0053);
+    }
+
+    #[test]
+    fn parse_diverted_buttons_lists_held_cids() {
+        let mut data = vec![0x11, DEV_IDX, BTN_FEATURE_IDX, 0x00, 0x00, 0x53, 0x00, 0x56];
+        data.resize(hidpp::LONG_REPORT_LEN, 0);
+        let report = HidppResponse::decode(&data).unwrap();
+
+        let held = parse_diverted_buttons(&report, BTN_FEATURE_IDX).unwrap();
+        assert_eq!(held, vec![0x0053, 0x0056]);
+    }
+
+    #[test]
+    fn parse_diverted_buttons_ignores_other_reports() {
+        let mut data = vec![0x11, DEV_IDX, 0x07, 0x00, 0x00, 0x53];
+        data.resize(hidpp::LONG_REPORT_LEN, 0);
+        let report = HidppResponse::decode(&data).unwrap();
+
+        assert!(parse_diverted_buttons(&report, BTN_FEATURE_IDX).is_none());
+    }
 }
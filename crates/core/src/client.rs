@@ -0,0 +1,272 @@
+//! Device access for the GUI and CLI.
+//!
+//! When `open-g-hubd` is running it owns the mouse, so requests go through
+//! its socket. The daemon then also remembers applied settings and restores
+//! them after a reconnect. Without a daemon, the client opens the mouse
+//! directly over HID as before.
+
+use crate::battery::{self, BatteryStatus};
+use crate::comm::{self, RetrySettings};
+use crate::device::{self, ButtonAction, PollingRate};
+use crate::error::Result;
+use crate::hid::HidapiTransport;
+#[cfg(unix)]
+use crate::ipc::DaemonClient;
+use crate::plan::{self, Plan};
+use crate::profile::{self, Profile};
+use crate::transaction::{self, ApplyReport};
+use crate::transport::{HidTransport, TransportStack};
+use crate::{buttons, dpi, report_rate};
+use tracing::debug;
+
+/// A connection to the mouse, through the daemon or direct.
+pub enum DeviceClient {
+    /// Requests are forwarded to `open-g-hubd`.
+    #[cfg(unix)]
+    Daemon(DaemonClient),
+    /// Requests go straight to the device.
+    Direct {
+        transport: Box<dyn HidTransport>,
+        device_index: u8,
+    },
+}
+
+impl DeviceClient {
+    /// Use the daemon if it is running, otherwise open the first supported mouse.
+    pub fn connect() -> Result<Self> {
+        #[cfg(unix)]
+        if let Ok(client) = DaemonClient::connect_default() {
+            debug!("Using open-g-hubd");
+            return Ok(Self::Daemon(client));
+        }
+        Self::direct()
+    }
+
+    /// Open the first supported mouse directly, bypassing any daemon,
+    /// through the [standard stack](TransportStack::standard) with its
+    /// retry policy from `retry.json`.
+    pub fn direct() -> Result<Self> {
+        let hid = HidapiTransport::open_first_supported()?;
+        let retry = RetrySettings::load_default()?
+            .policy_for(hid.info())
+            .clone();
+        Self::with_transport(TransportStack::standard(retry).build(hid))
+    }
+
+    /// Talk directly to a device over `transport`.
+    pub fn with_transport(transport: Box<dyn HidTransport>) -> Result<Self> {
+        let device_index = comm::find_device_index(transport.as_ref())?;
+        Ok(Self::Direct {
+            transport,
+            device_index,
+        })
+    }
+
+    /// Whether requests go through the daemon.
+    pub fn is_daemon(&self) -> bool {
+        !matches!(self, Self::Direct { .. })
+    }
+
+    pub fn read_dpi(&mut self) -> Result<u16> {
+        match self {
+            #[cfg(unix)]
+            Self::Daemon(client) => client.read_dpi(),
+            Self::Direct {
+                transport,
+                device_index,
+            } => dpi::read_dpi(transport.as_ref(), *device_index),
+        }
+    }
+
+    pub fn write_dpi(&mut self, value: u16) -> Result<u16> {
+        match self {
+            #[cfg(unix)]
+            Self::Daemon(client) => client.write_dpi(value),
+            Self::Direct {
+                transport,
+                device_index,
+            } => dpi::write_dpi(transport.as_ref(), *device_index, value),
+        }
+    }
+
+    pub fn read_report_rate(&mut self) -> Result<PollingRate> {
+        match self {
+            #[cfg(unix)]
+            Self::Daemon(client) => client.read_report_rate(),
+            Self::Direct {
+                transport,
+                device_index,
+            } => report_rate::read_report_rate(transport.as_ref(), *device_index),
+        }
+    }
+
+    pub fn write_report_rate(&mut self, rate: PollingRate) -> Result<()> {
+        match self {
+            #[cfg(unix)]
+            Self::Daemon(client) => client.write_report_rate(rate),
+            Self::Direct {
+                transport,
+                device_index,
+            } => report_rate::write_report_rate(transport.as_ref(), *device_index, rate),
+        }
+    }
+
+    pub fn read_button_mapping(&mut self, index: usize) -> Result<ButtonAction> {
+        match self {
+            #[cfg(unix)]
+            Self::Daemon(client) => client.read_button_mapping(index),
+            Self::Direct {
+                transport,
+                device_index,
+            } => buttons::read_button_mapping(transport.as_ref(), *device_index, index),
+        }
+    }
+
+    pub fn write_button_mapping(&mut self, index: usize, action: ButtonAction) -> Result<()> {
+        match self {
+            #[cfg(unix)]
+            Self::Daemon(client) => client.write_button_mapping(index, action),
+            Self::Direct {
+                transport,
+                device_index,
+            } => buttons::write_button_mapping(transport.as_ref(), *device_index, index, action),
+        }
+    }
+
+    pub fn write_button_mapping_cid(&mut self, index: usize, cid: u16) -> Result<()> {
+        match self {
+            #[cfg(unix)]
+            Self::Daemon(client) => client.write_button_mapping_cid(index, cid),
+            Self::Direct {
+                transport,
+                device_index,
+            } => buttons::write_button_mapping_cid(transport.as_ref(), *device_index, index, cid),
+        }
+    }
+
+    pub fn read_battery(&mut self) -> Result<BatteryStatus> {
+        match self {
+            #[cfg(unix)]
+            Self::Daemon(client) => client.read_battery(),
+            Self::Direct {
+                transport,
+                device_index,
+            } => battery::read_battery(transport.as_ref(), *device_index),
+        }
+    }
+
+    /// The mouse's unit ID (see [`device::read_unit_id`]).
+    pub fn read_unit_id(&mut self) -> Result<String> {
+        match self {
+            #[cfg(unix)]
+            Self::Daemon(client) => client.read_unit_id(),
+            Self::Direct {
+                transport,
+                device_index,
+            } => device::read_unit_id(transport.as_ref(), *device_index),
+        }
+    }
+
+    /// Capture the mouse's live settings (see [`profile::snapshot`]).
+    pub fn snapshot(&mut self) -> Result<Profile> {
+        match self {
+            #[cfg(unix)]
+            Self::Daemon(client) => client.snapshot(),
+            Self::Direct {
+                transport,
+                device_index,
+            } => profile::snapshot(transport.as_ref(), *device_index),
+        }
+    }
+
+    /// The writes [`Self::apply_profile`] would make, without making them.
+    pub fn plan(&mut self, profile: &Profile) -> Result<Plan> {
+        plan::plan(&self.snapshot()?, profile)
+    }
+
+    /// Apply `plan`, made by [`Self::plan`] for `profile`, without reading
+    /// the whole mouse again; failed writes are rolled back as in
+    /// [`Self::apply_profile`]. Through the daemon, `profile` becomes the
+    /// active profile and the daemon plans each of its mice itself.
+    pub fn apply_plan(&mut self, profile: &Profile, plan: &Plan) -> Result<()> {
+        match self {
+            #[cfg(unix)]
+            Self::Daemon(client) => client.set_active_profile(profile),
+            Self::Direct {
+                transport,
+                device_index,
+            } => transaction::apply(transport.as_ref(), *device_index, plan)
+                .into_result()
+                .map(|_| ()),
+        }
+    }
+
+    /// Apply a profile, writing only the settings that differ. If a write
+    /// fails, the earlier ones are rolled back and the error says what was
+    /// applied, rolled back and left unrestored. Through the daemon it also
+    /// becomes the profile restored after every reconnect.
+    pub fn apply_profile(&mut self, profile: &Profile) -> Result<()> {
+        match self {
+            #[cfg(unix)]
+            Self::Daemon(client) => client.set_active_profile(profile),
+            Self::Direct {
+                transport,
+                device_index,
+            } => profile::apply_changes(transport.as_ref(), *device_index, profile)
+                .and_then(ApplyReport::into_result)
+                .map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hidpp::features;
+    use crate::sim::SimulatedG502;
+    use crate::transport::{Record, RecordLayer, Recording};
+
+    #[test]
+    fn direct_client_resolves_receiver_index() {
+        let mut client = DeviceClient::with_transport(Box::new(SimulatedG502::new(0x01))).unwrap();
+        assert!(!client.is_daemon());
+        assert!(matches!(
+            client,
+            DeviceClient::Direct {
+                device_index: 0x01,
+                ..
+            }
+        ));
+        assert_eq!(client.write_dpi(1234).unwrap(), 1250);
+        assert_eq!(client.read_dpi().unwrap(), 1250);
+        assert_eq!(client.snapshot().unwrap().dpi, 1250);
+    }
+
+    #[test]
+    fn shown_plan_is_applied_without_a_second_snapshot() {
+        let recording = Recording::new();
+        let transport = TransportStack::new()
+            .layer(RecordLayer::new(recording.clone()))
+            .build(SimulatedG502::new(0x01));
+        let mut client = DeviceClient::with_transport(transport).unwrap();
+        let mut target = client.snapshot().unwrap();
+        target.dpi = 1600;
+
+        recording.clear();
+        let plan = client.plan(&target).unwrap();
+        client.apply_plan(&target, &plan).unwrap();
+        assert_eq!(client.read_dpi().unwrap(), 1600);
+
+        // The report rate is not in the plan, so only the snapshot reads it.
+        let rate = SimulatedG502::feature_index(features::REPORT_RATE).unwrap();
+        let rate_reads = recording
+            .records()
+            .iter()
+            .filter(|r| {
+                matches!(r, Record::Exchange { request, .. }
+                    if request[2] == rate && request[3] >> 4 == 0x01)
+            })
+            .count();
+        assert_eq!(rate_reads, 1);
+    }
+}
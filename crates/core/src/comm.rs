@@ -1,18 +1,27 @@
 //! Device communication layer with error handling and retry logic.
 //!
 //! Provides robust device communication by classifying errors and
-//! implementing retry strategies for transient failures.
+//! implementing retry strategies for transient failures. The
+//! [`RetryPolicy`] is applied to every HID++ request; probes such as
+//! [`find_device_index`] go out once.
 
+use crate::device::{DeviceInfo, DEVICE_INDEX_CANDIDATES};
 use crate::error::{Error, Result};
-use crate::hidpp::HidppRequest;
-use crate::transport::{hidpp_request, HidTransport};
+use crate::hidpp::{HidppRequest, HidppResponse};
+use crate::store;
+use crate::transport::{hidpp_request_once, HidTransport};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tracing::{debug, warn};
 
-/// Maximum retry attempts for transient errors.
-pub const MAX_RETRIES: u32 = 3;
+/// File name of the retry settings in the config directory.
+pub const RETRY_SETTINGS_FILE: &str = "retry.json";
 
 /// Classification of communication errors.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ErrorClass {
     /// Transient errors that may succeed on retry (timeout, busy).
     Transient,
@@ -28,31 +37,28 @@ pub enum ErrorClass {
 
 impl ErrorClass {
     /// Classify an error for retry decisions.
+    ///
+    /// A busy device is transient; a receiver that cannot reach its mouse
+    /// (HID++ 1.0 resource error or unknown device) counts as disconnected.
     pub fn classify(err: &Error) -> Self {
         match err {
             Error::Timeout(_) => Self::Transient,
             Error::PermissionDenied(_) => Self::PermissionDenied,
-            Error::DeviceNotFound(_) => Self::Disconnected,
-            Error::HidppProtocol { .. } => Self::Protocol,
-            Error::Hid(msg) => {
-                let lower = msg.to_lowercase();
-                if lower.contains("disconnect")
-                    || lower.contains("not found")
-                    || lower.contains("no such device")
-                {
-                    Self::Disconnected
-                } else if lower.contains("permission")
-                    || lower.contains("access denied")
-                    || lower.contains("access is denied")
-                {
-                    Self::PermissionDenied
-                } else if lower.contains("timeout") || lower.contains("timed out") {
-                    Self::Transient
-                } else {
-                    Self::InvalidResponse
-                }
-            }
-            Error::OutOfRange { .. } | Error::Profile(_) => Self::InvalidResponse,
+            Error::DeviceNotFound(_) | Error::Disconnected { .. } => Self::Disconnected,
+            Error::HidppProtocol { code, .. } if code.is_busy() => Self::Transient,
+            Error::HidppProtocol { code, .. } if code.is_unreachable() => Self::Disconnected,
+            Error::HidppProtocol { .. } | Error::UnsupportedFeature(_) => Self::Protocol,
+            Error::Hid { .. }
+            | Error::MalformedReply { .. }
+            | Error::MalformedReport(_)
+            | Error::FeatureNotAllowed(_)
+            | Error::UnknownFeatureIndex { .. }
+            | Error::OutOfRange { .. }
+            | Error::Profile(_)
+            | Error::Input(_)
+            | Error::Script(_)
+            | Error::Focus(_)
+            | Error::Ipc(_) => Self::InvalidResponse,
         }
     }
 
@@ -62,50 +68,238 @@ impl ErrorClass {
     }
 }
 
-/// Send a HID++ request with automatic retry for transient errors.
+/// How HID++ requests are retried.
 ///
-/// Returns the response on success, or the last error after exhausting retries.
-pub fn send_with_retry(
-    transport: &dyn HidTransport,
-    req: &HidppRequest,
-    max_retries: u32,
-) -> Result<crate::hidpp::HidppResponse> {
-    let mut last_error = None;
+/// Every request from the feature modules goes through
+/// [`hidpp_request`](crate::transport::hidpp_request), which asks its
+/// transport for a policy ([`HidTransport::retry_policy`]). A failed attempt
+/// is retried when its [`ErrorClass`] is in `retry_on`, with exponential
+/// backoff and jitter, until `max_attempts` or the `deadline_ms` budget runs
+/// out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first one included.
+    pub max_attempts: u32,
+    /// Wait before the second attempt; doubles for each one after.
+    pub initial_backoff_ms: u32,
+    /// Cap on a single wait.
+    pub max_backoff_ms: u32,
+    /// Up to this share of each wait, in percent, is randomly cut off so
+    /// retries from several clients do not line up.
+    pub jitter_percent: u8,
+    /// Time budget for all attempts of a request together. No retry starts
+    /// once its wait would overrun it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deadline_ms: Option<u32>,
+    /// Error classes worth another attempt.
+    pub retry_on: Vec<ErrorClass>,
+}
 
-    for attempt in 0..=max_retries {
-        match hidpp_request(transport, req) {
-            Ok(resp) => {
-                if attempt > 0 {
-                    debug!("HID++ request succeeded on attempt {}", attempt + 1);
-                }
-                return Ok(resp);
-            }
-            Err(e) => {
-                let class = ErrorClass::classify(&e);
-
-                if !class.is_retryable() || attempt == max_retries {
-                    warn!(
-                        "HID++ request failed (class={:?}, attempt={}/{}): {}",
-                        class,
-                        attempt + 1,
-                        max_retries + 1,
-                        e
-                    );
-                    return Err(e);
-                }
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff_ms: 20,
+            max_backoff_ms: 500,
+            jitter_percent: 25,
+            deadline_ms: Some(3000),
+            retry_on: vec![ErrorClass::Transient],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A single attempt, never retried.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Fails on zero attempts or jitter above 100%.
+    pub fn validate(&self) -> Result<()> {
+        if self.max_attempts == 0 {
+            return Err(Error::OutOfRange {
+                field: "max_attempts",
+                value: 0,
+                min: 1,
+                max: u32::MAX,
+            });
+        }
+        if self.jitter_percent > 100 {
+            return Err(Error::OutOfRange {
+                field: "jitter_percent",
+                value: self.jitter_percent.into(),
+                min: 0,
+                max: 100,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether an error of `class` gets another attempt.
+    pub fn retries(&self, class: ErrorClass) -> bool {
+        self.retry_on.contains(&class)
+    }
+
+    /// Wait after failed attempt number `attempt` (1-based), before jitter.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u64 << attempt.saturating_sub(1).min(16);
+        let ms = (u64::from(self.initial_backoff_ms) * factor).min(self.max_backoff_ms.into());
+        Duration::from_millis(ms)
+    }
+
+    /// [`backoff`](Self::backoff) with up to `jitter_percent` of it cut off.
+    fn jittered_backoff(&self, attempt: u32) -> Duration {
+        let delay = self.backoff(attempt);
+        let max_cut = delay.as_micros() as u64 * u64::from(self.jitter_percent.min(100)) / 100;
+        if max_cut == 0 {
+            return delay;
+        }
+        delay - Duration::from_micros(random() % (max_cut + 1))
+    }
+
+    /// Send `req` over `transport`, retrying as this policy says. Returns
+    /// the last error once it gives up.
+    pub fn send(&self, transport: &dyn HidTransport, req: &HidppRequest) -> Result<HidppResponse> {
+        self.run(req, || hidpp_request_once(transport, req))
+    }
 
+    /// Call `attempt` for `req` until it succeeds or this policy gives up.
+    pub(crate) fn run(
+        &self,
+        req: &HidppRequest,
+        mut attempt_once: impl FnMut() -> Result<HidppResponse>,
+    ) -> Result<HidppResponse> {
+        let started = Instant::now();
+        let deadline = self.deadline_ms.map(|ms| Duration::from_millis(ms.into()));
+        let max_attempts = self.max_attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            let error = match attempt_once() {
+                Ok(resp) => {
+                    if attempt > 1 {
+                        debug!(
+                            feature_index = req.feature_index,
+                            function_sw = format_args!("0x{:02X}", req.function_sw),
+                            attempt,
+                            "HID++ request succeeded after retrying"
+                        );
+                    }
+                    return Ok(resp);
+                }
+                Err(e) => e,
+            };
+            let class = ErrorClass::classify(&error);
+            let delay = self.jittered_backoff(attempt);
+            let out_of_time = deadline.is_some_and(|d| started.elapsed() + delay >= d);
+            if !self.retries(class) {
                 debug!(
-                    "HID++ transient error (attempt {}/{}): {}, retrying...",
-                    attempt + 1,
-                    max_retries + 1,
-                    e
+                    feature_index = req.feature_index,
+                    function_sw = format_args!("0x{:02X}", req.function_sw),
+                    attempt,
+                    ?class,
+                    "HID++ request failed, not retrying: {error}"
+                );
+                return Err(error);
+            }
+            if attempt >= max_attempts || out_of_time {
+                warn!(
+                    feature_index = req.feature_index,
+                    function_sw = format_args!("0x{:02X}", req.function_sw),
+                    attempt,
+                    max_attempts,
+                    ?class,
+                    elapsed_ms = started.elapsed().as_millis() as u64,
+                    "HID++ request failed, giving up: {error}"
                 );
-                last_error = Some(e);
+                return Err(error);
+            }
+            debug!(
+                feature_index = req.feature_index,
+                function_sw = format_args!("0x{:02X}", req.function_sw),
+                attempt,
+                max_attempts,
+                ?class,
+                delay_ms = delay.as_millis() as u64,
+                "HID++ request failed, retrying: {error}"
+            );
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+}
+
+/// A pseudo-random number for jitter: every `RandomState` gets fresh keys.
+fn random() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish()
+}
+
+/// Retry policies for the daemon: a default and overrides per mouse model,
+/// read from `<config dir>/retry.json`:
+///
+/// ```json
+/// {
+///   "default": {"max_attempts": 4, "initial_backoff_ms": 20},
+///   "devices": {"Logitech G502 Lightspeed": {"max_attempts": 6}}
+/// }
+/// ```
+///
+/// Fields left out of a policy keep [`RetryPolicy::default`]'s values.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetrySettings {
+    pub default: RetryPolicy,
+    /// Keyed by model name, as in [`MouseModel::name`](crate::device::MouseModel::name).
+    pub devices: HashMap<String, RetryPolicy>,
+}
+
+impl RetrySettings {
+    /// Read settings from `path`. A missing file gives the defaults.
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(Error::Profile(format!(
+                    "cannot read {}: {e}",
+                    path.display()
+                )))
             }
+        };
+        let settings: Self = serde_json::from_str(&json).map_err(|e| {
+            Error::Profile(format!("invalid retry settings in {}: {e}", path.display()))
+        })?;
+        settings.default.validate()?;
+        for policy in settings.devices.values() {
+            policy.validate()?;
         }
+        Ok(settings)
+    }
+
+    /// Read settings from [`settings_path`], or the defaults without a
+    /// config directory.
+    pub fn load_default() -> Result<Self> {
+        match settings_path() {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// The policy for `info`'s mouse.
+    pub fn policy_for(&self, info: &DeviceInfo) -> &RetryPolicy {
+        self.devices.get(info.model.name()).unwrap_or(&self.default)
     }
+}
 
-    Err(last_error.unwrap_or_else(|| Error::Hid("retry loop completed without result".into())))
+/// `<config dir>/retry.json`.
+pub fn settings_path() -> Option<PathBuf> {
+    store::config_dir().map(|dir| dir.join(RETRY_SETTINGS_FILE))
 }
 
 /// Device connection status for UI display.
@@ -124,7 +318,7 @@ pub enum DeviceStatus {
 /// Check device connectivity by sending a ping via ROOT feature.
 pub fn check_device_status(transport: &dyn HidTransport, device_index: u8) -> DeviceStatus {
     let ping = HidppRequest::new(device_index, 0x00, 0x00, vec![0x00, 0x00]);
-    match hidpp_request(transport, &ping) {
+    match hidpp_request_once(transport, &ping) {
         Ok(_) => DeviceStatus::Connected,
         Err(ref e) => match ErrorClass::classify(e) {
             ErrorClass::Disconnected => DeviceStatus::Disconnected,
@@ -134,10 +328,31 @@ pub fn check_device_status(transport: &dyn HidTransport, device_index: u8) -> De
     }
 }
 
+/// Find the HID++ device index the mouse answers on.
+///
+/// Pings each of [`DEVICE_INDEX_CANDIDATES`] and returns the first that
+/// replies on the ROOT feature. A receiver answering for itself (HID++ 1.0
+/// error reports) does not count.
+pub fn find_device_index(transport: &dyn HidTransport) -> Result<u8> {
+    let mut last_error = None;
+    for idx in DEVICE_INDEX_CANDIDATES {
+        let ping = HidppRequest::new(idx, 0x00, 0x00, vec![0x00, 0x00]);
+        match hidpp_request_once(transport, &ping) {
+            Ok(resp) if resp.feature_index == 0x00 => return Ok(idx),
+            Ok(_) => {}
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| Error::DeviceNotFound("no usable device index".to_string())))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::device::MouseModel;
+    use crate::hidpp::{Hidpp10Error, Hidpp20Error, HidppError};
     use crate::transport::mock::MockTransport;
+    use crate::transport::FeatureTable;
 
     #[test]
     fn classify_timeout_as_transient() {
@@ -161,53 +376,201 @@ mod tests {
     }
 
     #[test]
-    fn classify_hid_disconnect_message() {
-        let err = Error::Hid("device disconnect detected".into());
-        assert_eq!(ErrorClass::classify(&err), ErrorClass::Disconnected);
+    fn classify_transport_variants() {
+        let gone = Error::Disconnected {
+            op: "read",
+            message: "No such device".into(),
+        };
+        assert_eq!(ErrorClass::classify(&gone), ErrorClass::Disconnected);
+        let other = Error::Hid {
+            op: "init",
+            message: "Failed to initialize hidapi".into(),
+        };
+        assert_eq!(ErrorClass::classify(&other), ErrorClass::InvalidResponse);
+        let garbled = Error::MalformedReport("unknown report ID: 0x99".into());
+        assert_eq!(ErrorClass::classify(&garbled), ErrorClass::InvalidResponse);
     }
 
     #[test]
-    fn classify_hid_permission_message() {
-        let err = Error::Hid("Access is denied".into());
-        assert_eq!(ErrorClass::classify(&err), ErrorClass::PermissionDenied);
+    fn classify_protocol_error() {
+        let err = Error::HidppProtocol {
+            feature_index: 0x07,
+            function: 2,
+            code: HidppError::V20(Hidpp20Error::InvalidArgument),
+        };
+        assert_eq!(ErrorClass::classify(&err), ErrorClass::Protocol);
+        assert!(!ErrorClass::classify(&err).is_retryable());
+        assert_eq!(
+            ErrorClass::classify(&Error::UnsupportedFeature(0x8070)),
+            ErrorClass::Protocol
+        );
     }
 
     #[test]
-    fn classify_hid_timeout_message() {
-        let err = Error::Hid("timed out waiting for response".into());
-        assert_eq!(ErrorClass::classify(&err), ErrorClass::Transient);
+    fn classify_busy_as_transient_and_unreachable_as_disconnected() {
+        let busy = Error::HidppProtocol {
+            feature_index: 0x07,
+            function: 2,
+            code: HidppError::V20(Hidpp20Error::Busy),
+        };
+        assert!(ErrorClass::classify(&busy).is_retryable());
+        assert!(busy.to_string().contains("try again"));
+        let asleep = Error::HidppProtocol {
+            feature_index: 0x00,
+            function: 0,
+            code: HidppError::V10(Hidpp10Error::ResourceError),
+        };
+        assert_eq!(ErrorClass::classify(&asleep), ErrorClass::Disconnected);
+    }
+
+    /// Fails every request with a timeout, counting them.
+    #[derive(Default)]
+    struct Unanswered {
+        attempts: std::sync::Mutex<u32>,
+        features: FeatureTable,
+    }
+
+    impl HidTransport for Unanswered {
+        fn send_report(&self, _data: &[u8]) -> Result<Vec<u8>> {
+            *self.attempts.lock().unwrap() += 1;
+            Err(Error::Timeout("no reply".into()))
+        }
+
+        fn feature_table(&self) -> &FeatureTable {
+            &self.features
+        }
+    }
+
+    fn ping() -> HidppRequest {
+        HidppRequest::new(0x01, 0x00, 0x00, vec![0x00, 0x00])
     }
 
     #[test]
-    fn classify_protocol_error() {
-        let err = Error::HidppProtocol {
-            feature: 0x2201,
-            code: 0x05,
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy {
+            initial_backoff_ms: 20,
+            max_backoff_ms: 100,
+            ..RetryPolicy::default()
         };
-        assert_eq!(ErrorClass::classify(&err), ErrorClass::Protocol);
-        assert!(!ErrorClass::classify(&err).is_retryable());
+        let waits: Vec<u64> = (1..=5)
+            .map(|n| policy.backoff(n).as_millis() as u64)
+            .collect();
+        assert_eq!(waits, [20, 40, 80, 100, 100]);
+        for _ in 0..20 {
+            let wait = policy.jittered_backoff(2);
+            assert!(wait <= Duration::from_millis(40) && wait >= Duration::from_millis(30));
+        }
     }
 
     #[test]
-    fn send_with_retry_succeeds_immediately() {
-        let mock = MockTransport::new();
+    fn feature_modules_retry_a_busy_device() {
+        let sim = crate::sim::SimulatedG502::new(0x01);
+        sim.set_busy(2);
+        assert_eq!(crate::dpi::read_dpi(&sim, 0x01).unwrap(), 800);
+        sim.set_busy(10);
+        assert!(matches!(
+            crate::dpi::read_dpi(&sim, 0x01),
+            Err(Error::HidppProtocol {
+                code: HidppError::V20(Hidpp20Error::Busy),
+                ..
+            })
+        ));
+        sim.set_busy(0);
+        sim.set_retry_policy(RetryPolicy::none());
+        sim.set_busy(1);
+        assert!(crate::dpi::read_dpi(&sim, 0x01).is_err());
+    }
 
-        // Register ROOT ping response
-        mock.on_short_request(0x01, 0x00, 0x01, &[0x00, 0x00], &[0x00, 0x00, 0x00]);
+    #[test]
+    fn policy_retries_listed_classes_within_the_deadline() {
+        let transport = Unanswered::default();
+        let quick = RetryPolicy {
+            initial_backoff_ms: 1,
+            ..RetryPolicy::default()
+        };
+        assert!(matches!(
+            quick.send(&transport, &ping()),
+            Err(Error::Timeout(_))
+        ));
+        assert_eq!(*transport.attempts.lock().unwrap(), 4);
+
+        // Timeouts are not retried unless the policy lists them.
+        *transport.attempts.lock().unwrap() = 0;
+        let protocol_only = RetryPolicy {
+            retry_on: vec![ErrorClass::Protocol],
+            ..quick.clone()
+        };
+        assert!(protocol_only.send(&transport, &ping()).is_err());
+        assert_eq!(*transport.attempts.lock().unwrap(), 1);
+
+        // The deadline stops retrying before the attempts run out.
+        *transport.attempts.lock().unwrap() = 0;
+        let bounded = RetryPolicy {
+            max_attempts: 100,
+            initial_backoff_ms: 10,
+            max_backoff_ms: 10,
+            jitter_percent: 0,
+            deadline_ms: Some(35),
+            ..RetryPolicy::default()
+        };
+        let started = Instant::now();
+        assert!(bounded.send(&transport, &ping()).is_err());
+        assert!(*transport.attempts.lock().unwrap() <= 4);
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn retry_settings_file_with_device_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(RETRY_SETTINGS_FILE);
+        assert_eq!(
+            RetrySettings::load(&path).unwrap(),
+            RetrySettings::default()
+        );
 
-        let req = HidppRequest::new(0x01, 0x00, 0x00, vec![0x00, 0x00]);
-        let result = send_with_retry(&mock, &req, 3);
-        assert!(result.is_ok());
+        std::fs::write(
+            &path,
+            r#"{"default": {"max_attempts": 2},
+                "devices": {"Logitech G502 Lightspeed": {"max_attempts": 6, "retry_on": ["transient", "disconnected"]}}}"#,
+        )
+        .unwrap();
+        let settings = RetrySettings::load(&path).unwrap();
+        let mut info = DeviceInfo {
+            model: MouseModel::G502Lightspeed,
+            vid: crate::LOGITECH_VID,
+            pid: crate::pids::G502_LIGHTSPEED,
+            path: "sim".into(),
+            serial: None,
+        };
+        let wireless = settings.policy_for(&info);
+        assert_eq!(wireless.max_attempts, 6);
+        assert!(wireless.retries(ErrorClass::Disconnected));
+        assert_eq!(wireless.initial_backoff_ms, 20);
+        info.model = MouseModel::G502Hero;
+        assert_eq!(settings.policy_for(&info).max_attempts, 2);
+
+        std::fs::write(&path, r#"{"default": {"max_attempts": 0}}"#).unwrap();
+        assert!(matches!(
+            RetrySettings::load(&path),
+            Err(Error::OutOfRange {
+                field: "max_attempts",
+                ..
+            })
+        ));
     }
 
     #[test]
-    fn send_with_retry_fails_non_retryable() {
+    fn policy_sends_once_on_success() {
         let mock = MockTransport::new();
-        // No response registered → mock returns error (treated as non-retryable)
+        mock.on_short_request(0x01, 0x00, 0x01, &[0x00, 0x00], &[0x00, 0x00, 0x00]);
+        assert!(RetryPolicy::default().send(&mock, &ping()).is_ok());
+    }
 
-        let req = HidppRequest::new(0x01, 0x00, 0x00, vec![0x00, 0x00]);
-        let result = send_with_retry(&mock, &req, 3);
-        assert!(result.is_err());
+    #[test]
+    fn policy_does_not_retry_invalid_responses() {
+        let mock = MockTransport::new();
+        // No response registered → mock returns error (treated as non-retryable)
+        assert!(RetryPolicy::default().send(&mock, &ping()).is_err());
     }
 
     #[test]
@@ -223,4 +586,22 @@ mod tests {
         // No response → error → classified as InvalidResponse → Error status
         assert_eq!(check_device_status(&mock, 0x01), DeviceStatus::Error);
     }
+
+    #[test]
+    fn find_device_index_skips_receiver_replies() {
+        let mock = MockTransport::new();
+        // Receiver answers index 0xFF with a HID++ 1.0 error (sub ID 0x8F).
+        mock.on_request(
+            vec![0x10, 0xFF, 0x00, 0x01, 0x00, 0x00, 0x00],
+            vec![0x10, 0xFF, 0x8F, 0x00, 0x01, 0x01, 0x00],
+        );
+        mock.on_short_request(0x01, 0x00, 0x01, &[0x00, 0x00], &[0x00, 0x00, 0x00]);
+        assert_eq!(find_device_index(&mock).unwrap(), 0x01);
+    }
+
+    #[test]
+    fn find_device_index_fails_without_device() {
+        let mock = MockTransport::new();
+        assert!(find_device_index(&mock).is_err());
+    }
 }
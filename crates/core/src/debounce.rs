@@ -0,0 +1,430 @@
+//! Host-side click debounce for worn mouse switches.
+//!
+//! Worn Omron-style switches chatter: one physical click arrives as
+//! press → release → press → release a few milliseconds apart, which the OS
+//! sees as a double click. [`DebounceFilter`] removes these bounces from an
+//! evdev event stream:
+//!
+//! - A button release is held back for the button's debounce window.
+//! - If the same button is pressed again inside the window, the held release
+//!   and the new press are both dropped (counted as one suppressed bounce).
+//! - Otherwise the release is forwarded once the window expires.
+//!
+//! Presses are never delayed, so click latency is unchanged; only releases are
+//! deferred by at most the window. On Linux, [`crate::evdev::DebounceForwarder`]
+//! grabs the mouse's evdev node and forwards the filtered stream via uinput.
+
+use crate::error::Result;
+use crate::input::MouseButton;
+use crate::safety;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::debug;
+
+/// evdev event type for keys and buttons.
+const EV_KEY: u16 = 0x01;
+/// evdev event type for synchronization frames.
+const EV_SYN: u16 = 0x00;
+
+/// Default debounce window when none is configured.
+pub const DEFAULT_WINDOW_MS: u32 = 20;
+
+/// Debounce configuration stored in a [`crate::profile::Profile`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebounceSettings {
+    /// Whether the host-side filter should run for this profile.
+    pub enabled: bool,
+    /// Window applied to every button without an override (0 = pass through).
+    pub window_ms: u32,
+    /// Per-button window overrides.
+    #[serde(default)]
+    pub per_button: BTreeMap<MouseButton, u32>,
+}
+
+impl Default for DebounceSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_ms: DEFAULT_WINDOW_MS,
+            per_button: BTreeMap::new(),
+        }
+    }
+}
+
+impl DebounceSettings {
+    /// Debounce window for a button.
+    pub fn window_for(&self, button: MouseButton) -> u32 {
+        self.per_button
+            .get(&button)
+            .copied()
+            .unwrap_or(self.window_ms)
+    }
+
+    /// Validate every configured window against the safety bounds.
+    pub fn validate(&self) -> Result<()> {
+        safety::validate_debounce_window(self.window_ms)?;
+        for &ms in self.per_button.values() {
+            safety::validate_debounce_window(ms)?;
+        }
+        Ok(())
+    }
+}
+
+/// A raw evdev event with its kernel timestamp in microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvdevEvent {
+    pub time_us: u64,
+    pub type_: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl EvdevEvent {
+    pub fn new(time_us: u64, type_: u16, code: u16, value: i32) -> Self {
+        Self {
+            time_us,
+            type_,
+            code,
+            value,
+        }
+    }
+
+    /// A SYN_REPORT frame terminator at `time_us`.
+    pub fn syn(time_us: u64) -> Self {
+        Self::new(time_us, EV_SYN, 0, 0)
+    }
+
+    /// A button press (`value` 1) or release (`value` 0).
+    pub fn button(time_us: u64, button: MouseButton, pressed: bool) -> Self {
+        Self::new(time_us, EV_KEY, button.evdev_code(), pressed as i32)
+    }
+}
+
+/// Per-button filter counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ButtonStats {
+    /// Presses forwarded to the OS.
+    pub presses: u64,
+    /// Bounces removed (each is one dropped release + press pair).
+    pub suppressed: u64,
+}
+
+/// Suppression statistics collected by a [`DebounceFilter`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebounceStats {
+    pub buttons: BTreeMap<MouseButton, ButtonStats>,
+}
+
+impl DebounceStats {
+    /// Total bounces suppressed across all buttons.
+    pub fn total_suppressed(&self) -> u64 {
+        self.buttons.values().map(|s| s.suppressed).sum()
+    }
+}
+
+impl std::fmt::Display for DebounceStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.buttons.is_empty() {
+            return write!(f, "no clicks seen yet");
+        }
+        let parts: Vec<String> = self
+            .buttons
+            .iter()
+            .map(|(button, s)| {
+                format!(
+                    "{button:?}: {} clicks, {} bounces suppressed",
+                    s.presses, s.suppressed
+                )
+            })
+            .collect();
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+/// Bounce filter over an evdev event stream.
+#[derive(Debug)]
+pub struct DebounceFilter {
+    settings: DebounceSettings,
+    /// Releases held back until their window expires, keyed by button.
+    pending: BTreeMap<MouseButton, EvdevEvent>,
+    stats: DebounceStats,
+}
+
+impl DebounceFilter {
+    pub fn new(settings: DebounceSettings) -> Self {
+        Self {
+            settings,
+            pending: BTreeMap::new(),
+            stats: DebounceStats::default(),
+        }
+    }
+
+    /// Statistics so far.
+    pub fn stats(&self) -> &DebounceStats {
+        &self.stats
+    }
+
+    /// Feed one event; returns the events to forward now, in order.
+    pub fn process(&mut self, event: EvdevEvent) -> Vec<EvdevEvent> {
+        let mut out = self.flush(event.time_us);
+
+        let button = match (event.type_, MouseButton::from_evdev_code(event.code)) {
+            (EV_KEY, Some(button)) if event.value != 2 => button,
+            _ => {
+                out.push(event);
+                return out;
+            }
+        };
+
+        let window_us = self.settings.window_for(button) as u64 * 1000;
+        if window_us == 0 {
+            if event.value == 1 {
+                self.stats.buttons.entry(button).or_default().presses += 1;
+            }
+            out.push(event);
+            return out;
+        }
+
+        if event.value == 0 {
+            // Hold the release; it is forwarded by `flush` once the window passes.
+            self.pending.insert(button, event);
+            return out;
+        }
+
+        let stats = self.stats.buttons.entry(button).or_default();
+        if self.pending.remove(&button).is_some() {
+            // Re-press inside the window: the switch bounced. Drop both halves
+            // so the OS keeps seeing one continuous press.
+            stats.suppressed += 1;
+            debug!(?button, time_us = event.time_us, "Suppressed switch bounce");
+            return out;
+        }
+
+        stats.presses += 1;
+        out.push(event);
+        out
+    }
+
+    /// Forward held releases whose window has expired at `now_us`.
+    ///
+    /// Each flushed release is followed by its own SYN_REPORT.
+    pub fn flush(&mut self, now_us: u64) -> Vec<EvdevEvent> {
+        let expired: Vec<MouseButton> = self
+            .pending
+            .iter()
+            .filter(|(button, release)| {
+                let window_us = self.settings.window_for(**button) as u64 * 1000;
+                now_us.saturating_sub(release.time_us) >= window_us
+            })
+            .map(|(button, _)| *button)
+            .collect();
+
+        let mut out = Vec::new();
+        for button in expired {
+            if let Some(release) = self.pending.remove(&button) {
+                out.push(release);
+                out.push(EvdevEvent::syn(release.time_us));
+            }
+        }
+        out
+    }
+
+    /// How long until the next held release expires, if any is pending.
+    pub fn next_deadline(&self, now_us: u64) -> Option<Duration> {
+        self.pending
+            .iter()
+            .map(|(button, release)| {
+                let window_us = self.settings.window_for(*button) as u64 * 1000;
+                (release.time_us + window_us).saturating_sub(now_us)
+            })
+            .min()
+            .map(Duration::from_micros)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(window_ms: u32) -> DebounceSettings {
+        DebounceSettings {
+            enabled: true,
+            window_ms,
+            per_button: BTreeMap::new(),
+        }
+    }
+
+    /// Feed a timeline through the filter, then flush far in the future.
+    fn run(filter: &mut DebounceFilter, timeline: &[EvdevEvent]) -> Vec<EvdevEvent> {
+        let mut out = Vec::new();
+        for &event in timeline {
+            out.extend(filter.process(event));
+        }
+        out.extend(filter.flush(u64::MAX));
+        out
+    }
+
+    fn buttons_only(events: &[EvdevEvent]) -> Vec<(u16, i32)> {
+        events
+            .iter()
+            .filter(|e| e.type_ == EV_KEY)
+            .map(|e| (e.code, e.value))
+            .collect()
+    }
+
+    const LEFT: u16 = 0x110;
+
+    #[test]
+    fn clean_click_passes_through() {
+        let mut filter = DebounceFilter::new(settings(20));
+        let out = run(
+            &mut filter,
+            &[
+                EvdevEvent::button(0, MouseButton::Left, true),
+                EvdevEvent::syn(0),
+                EvdevEvent::button(90_000, MouseButton::Left, false),
+                EvdevEvent::syn(90_000),
+            ],
+        );
+        assert_eq!(buttons_only(&out), vec![(LEFT, 1), (LEFT, 0)]);
+        assert_eq!(filter.stats().total_suppressed(), 0);
+    }
+
+    #[test]
+    fn chatter_inside_window_is_suppressed() {
+        let mut filter = DebounceFilter::new(settings(20));
+        // One physical click that chattered: release/press 3ms apart.
+        let out = run(
+            &mut filter,
+            &[
+                EvdevEvent::button(0, MouseButton::Left, true),
+                EvdevEvent::button(40_000, MouseButton::Left, false),
+                EvdevEvent::button(43_000, MouseButton::Left, true),
+                EvdevEvent::button(120_000, MouseButton::Left, false),
+            ],
+        );
+        assert_eq!(buttons_only(&out), vec![(LEFT, 1), (LEFT, 0)]);
+        let stats = filter.stats().buttons[&MouseButton::Left];
+        assert_eq!(stats.presses, 1);
+        assert_eq!(stats.suppressed, 1);
+    }
+
+    #[test]
+    fn intentional_double_click_survives() {
+        let mut filter = DebounceFilter::new(settings(20));
+        let out = run(
+            &mut filter,
+            &[
+                EvdevEvent::button(0, MouseButton::Left, true),
+                EvdevEvent::button(60_000, MouseButton::Left, false),
+                EvdevEvent::button(140_000, MouseButton::Left, true),
+                EvdevEvent::button(200_000, MouseButton::Left, false),
+            ],
+        );
+        assert_eq!(
+            buttons_only(&out),
+            vec![(LEFT, 1), (LEFT, 0), (LEFT, 1), (LEFT, 0)]
+        );
+        assert_eq!(filter.stats().buttons[&MouseButton::Left].presses, 2);
+    }
+
+    #[test]
+    fn release_is_forwarded_with_syn_after_window() {
+        let mut filter = DebounceFilter::new(settings(20));
+        filter.process(EvdevEvent::button(0, MouseButton::Left, true));
+        assert!(filter
+            .process(EvdevEvent::button(50_000, MouseButton::Left, false))
+            .is_empty());
+
+        assert!(filter.flush(60_000).is_empty());
+        assert_eq!(
+            filter.next_deadline(60_000),
+            Some(Duration::from_millis(10))
+        );
+
+        let flushed = filter.flush(70_000);
+        assert_eq!(
+            flushed,
+            vec![
+                EvdevEvent::button(50_000, MouseButton::Left, false),
+                EvdevEvent::syn(50_000),
+            ]
+        );
+        assert_eq!(filter.next_deadline(70_000), None);
+    }
+
+    #[test]
+    fn per_button_windows_apply_independently() {
+        let mut config = settings(0);
+        config.per_button.insert(MouseButton::Right, 30);
+        let mut filter = DebounceFilter::new(config);
+
+        let out = run(
+            &mut filter,
+            &[
+                // Left has no window: chatter passes straight through.
+                EvdevEvent::button(0, MouseButton::Left, true),
+                EvdevEvent::button(5_000, MouseButton::Left, false),
+                EvdevEvent::button(8_000, MouseButton::Left, true),
+                EvdevEvent::button(50_000, MouseButton::Left, false),
+                // Right has a 30ms window: 25ms gap is a bounce.
+                EvdevEvent::button(100_000, MouseButton::Right, true),
+                EvdevEvent::button(150_000, MouseButton::Right, false),
+                EvdevEvent::button(175_000, MouseButton::Right, true),
+                EvdevEvent::button(300_000, MouseButton::Right, false),
+            ],
+        );
+        let right = MouseButton::Right.evdev_code();
+        assert_eq!(
+            buttons_only(&out),
+            vec![
+                (LEFT, 1),
+                (LEFT, 0),
+                (LEFT, 1),
+                (LEFT, 0),
+                (right, 1),
+                (right, 0)
+            ]
+        );
+        assert_eq!(filter.stats().buttons[&MouseButton::Left].suppressed, 0);
+        assert_eq!(filter.stats().buttons[&MouseButton::Right].suppressed, 1);
+    }
+
+    #[test]
+    fn motion_and_other_keys_pass_through_untouched() {
+        let mut filter = DebounceFilter::new(settings(20));
+        let motion = EvdevEvent::new(1_000, 0x02, 0x00, 5);
+        let key = EvdevEvent::new(2_000, EV_KEY, 30, 1);
+        assert_eq!(filter.process(motion), vec![motion]);
+        assert_eq!(filter.process(key), vec![key]);
+    }
+
+    #[test]
+    fn settings_validation_rejects_huge_windows() {
+        assert!(settings(20).validate().is_ok());
+        assert!(settings(10_000).validate().is_err());
+
+        let mut config = settings(20);
+        config.per_button.insert(MouseButton::Left, 500);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn stats_display_lists_buttons() {
+        let mut filter = DebounceFilter::new(settings(20));
+        run(
+            &mut filter,
+            &[
+                EvdevEvent::button(0, MouseButton::Left, true),
+                EvdevEvent::button(40_000, MouseButton::Left, false),
+                EvdevEvent::button(42_000, MouseButton::Left, true),
+                EvdevEvent::button(90_000, MouseButton::Left, false),
+            ],
+        );
+        assert_eq!(
+            filter.stats().to_string(),
+            "Left: 1 clicks, 1 bounces suppressed"
+        );
+    }
+}
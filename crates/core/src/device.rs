@@ -1,11 +1,13 @@
 //! Device model: discovery, connection, and feature access.
 
 use crate::error::{Error, Result};
+use crate::hidpp::{self, HidppRequest};
+use crate::transport::{hidpp_request, lookup_feature_index, HidTransport};
 use crate::{pids, LOGITECH_VID};
 use tracing::{debug, info};
 
 /// Supported Logitech mouse models.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum MouseModel {
     G502Lightspeed,
     G502Hero,
@@ -39,7 +41,7 @@ impl MouseModel {
 }
 
 /// Information about a discovered Logitech device.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct DeviceInfo {
     pub model: MouseModel,
     pub vid: u16,
@@ -48,12 +50,16 @@ pub struct DeviceInfo {
     pub serial: Option<String>,
 }
 
+/// HID++ device indices to try: 0xFF for a wired mouse, 0x01 for the first
+/// device paired to a Lightspeed receiver.
+pub const DEVICE_INDEX_CANDIDATES: [u8; 2] = [0xFF, 0x01];
+
 /// Discover all connected Logitech G mice.
 ///
 /// Enumerates USB HID devices and returns info for any recognized models.
 pub fn discover_devices() -> Result<Vec<DeviceInfo>> {
     debug!("Starting HID device enumeration");
-    let api = hidapi::HidApi::new().map_err(|e| Error::Hid(e.to_string()))?;
+    let api = hidapi::HidApi::new().map_err(|e| crate::hid::hid_error("init", e))?;
 
     let mut devices = Vec::new();
     for info in api.device_list() {
@@ -83,6 +89,21 @@ pub fn discover_devices() -> Result<Vec<DeviceInfo>> {
     Ok(devices)
 }
 
+/// The device's unit ID from DEVICE_INFORMATION, as 8 uppercase hex digits
+/// (the form Solaar and G Hub show).
+pub fn read_unit_id(transport: &dyn HidTransport, device_index: u8) -> Result<String> {
+    let feature_idx =
+        lookup_feature_index(transport, device_index, hidpp::features::DEVICE_INFORMATION)?;
+
+    // getDeviceInfo: function 0 → [entityCount, unitId[4], transport, modelId[6], ...]
+    let req = HidppRequest::new(device_index, feature_idx, 0x00, vec![]);
+    let resp = hidpp_request(transport, &req)?;
+    let unit_id = resp.params.get(1..5).ok_or(Error::MalformedReply {
+        feature: hidpp::features::DEVICE_INFORMATION,
+    })?;
+    Ok(unit_id.iter().map(|b| format!("{b:02X}")).collect())
+}
+
 /// Polling rate options supported by G502 mice.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 #[repr(u16)]
@@ -279,6 +300,12 @@ mod tests {
         );
     }
 
+    #[test]
+    fn unit_id_is_read_as_hex() {
+        let sim = crate::sim::SimulatedG502::new(0x01);
+        assert_eq!(read_unit_id(&sim, 0x01).unwrap(), "1A2B3C4D");
+    }
+
     #[test]
     fn button_action_from_name_rejects_unknown() {
         assert_eq!(ButtonAction::from_name("shoot"), None);
@@ -56,6 +56,37 @@ pub fn write_dpi(transport: &dyn HidTransport, device_index: u8, dpi: u16) -> Re
     Ok(validated)
 }
 
+/// Read the current DPI of sensor `sensor` (multi-sensor mice number them from 0).
+pub fn read_sensor_dpi(transport: &dyn HidTransport, device_index: u8, sensor: u8) -> Result<u16> {
+    let feature_idx =
+        lookup_feature_index(transport, device_index, hidpp::features::ADJUSTABLE_DPI)?;
+
+    // getSensorDpi: function 1, params[0] = sensor index
+    let req = HidppRequest::new(device_index, feature_idx, 0x01, vec![sensor]);
+    let resp = hidpp_request(transport, &req)?;
+
+    Ok(u16::from_be_bytes([resp.params[0], resp.params[1]]))
+}
+
+/// Write a DPI value to sensor `sensor`, validated like [`write_dpi`].
+pub fn write_sensor_dpi(
+    transport: &dyn HidTransport,
+    device_index: u8,
+    sensor: u8,
+    dpi: u16,
+) -> Result<u16> {
+    let validated = safety::validate_dpi(dpi)?;
+    let feature_idx =
+        lookup_feature_index(transport, device_index, hidpp::features::ADJUSTABLE_DPI)?;
+
+    // setSensorDpi: function 2, params = [sensor_idx, dpi_hi, dpi_lo]
+    let [hi, lo] = validated.to_be_bytes();
+    let req = HidppRequest::new(device_index, feature_idx, 0x02, vec![sensor, hi, lo]);
+    let _resp = hidpp_request(transport, &req)?;
+
+    Ok(validated)
+}
+
 /// Read the sensor count from the device.
 pub fn read_sensor_count(transport: &dyn HidTransport, device_index: u8) -> Result<u8> {
     let feature_idx =
@@ -1,21 +1,52 @@
 //! Error types for open-g-hub-core.
 
+use crate::hidpp::HidppError;
 use thiserror::Error;
 
 /// Core library error type.
 #[derive(Debug, Error)]
 pub enum Error {
-    /// HID device communication failure.
-    #[error("HID error: {0}")]
-    Hid(String),
+    /// HID transport failure other than a disconnect, timeout or missing
+    /// permission. `op` is what failed: "init", "open", "write" or "read".
+    #[error("HID {op} failed: {message}")]
+    Hid { op: &'static str, message: String },
+
+    /// The device went away during `op`: unplugged, or its HID node gone.
+    #[error("device disconnected during {op}: {message}")]
+    Disconnected { op: &'static str, message: String },
 
     /// Device not found during enumeration.
     #[error("device not found: {0}")]
     DeviceNotFound(String),
 
-    /// HID++ protocol error (device returned error code).
-    #[error("HID++ error: feature 0x{feature:04X}, code {code}")]
-    HidppProtocol { feature: u16, code: u8 },
+    /// The device rejected a request with a HID++ error report.
+    #[error("HID++ request to feature index 0x{feature_index:02X}, function {function} failed: {code}; {}", code.hint())]
+    HidppProtocol {
+        feature_index: u8,
+        function: u8,
+        code: HidppError,
+    },
+
+    /// The device does not implement a HID++ 2.0 feature.
+    #[error("feature 0x{0:04X} is not supported by this device")]
+    UnsupportedFeature(u16),
+
+    /// A request to a feature outside the whitelist in `safety`.
+    #[error("feature 0x{0:04X} is not on the feature whitelist; request refused")]
+    FeatureNotAllowed(u16),
+
+    /// A request to a feature index that was never looked up, so which
+    /// feature it reaches is unknown.
+    #[error("feature index 0x{feature_index:02X} on device 0x{device_index:02X} was not looked up; request refused")]
+    UnknownFeatureIndex { device_index: u8, feature_index: u8 },
+
+    /// A reply too short or holding values its feature does not define.
+    #[error("malformed reply from feature 0x{feature:04X}")]
+    MalformedReply { feature: u16 },
+
+    /// Bytes that do not form a HID++ report.
+    #[error("malformed HID++ report: {0}")]
+    MalformedReport(String),
 
     /// Value out of safe range.
     #[error("value out of range: {field} = {value} (allowed {min}..={max})")]
@@ -37,6 +68,22 @@ pub enum Error {
     /// Operation timed out.
     #[error("timeout: {0}")]
     Timeout(String),
+
+    /// Host input device failure (uinput/evdev).
+    #[error("input device error: {0}")]
+    Input(String),
+
+    /// Script load or runtime failure.
+    #[error("script error: {0}")]
+    Script(String),
+
+    /// Focused-window lookup failure (automatic profile switching).
+    #[error("focus provider error: {0}")]
+    Focus(String),
+
+    /// Daemon IPC failure (socket I/O or an error returned by the daemon).
+    #[error("daemon error: {0}")]
+    Ipc(String),
 }
 
 /// Convenience Result alias.
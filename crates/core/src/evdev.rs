@@ -0,0 +1,250 @@
+//! Linux evdev access: locate the mouse's event node, grab it, and forward a
+//! filtered copy of its events through uinput.
+//!
+//! Used by the click debounce filter ([`crate::debounce`]). While grabbed, the
+//! real node's events reach only us; the cleaned stream is re-emitted on a
+//! virtual device so the desktop keeps working.
+
+use crate::debounce::{DebounceFilter, DebounceSettings, DebounceStats, EvdevEvent};
+use crate::error::{Error, Result};
+use crate::uinput::{self, UinputDevice};
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info};
+
+/// `EVIOCGRAB`: `_IOW('E', 0x90, int)`.
+const EVIOCGRAB: u32 =
+    (1 << 30) | ((std::mem::size_of::<libc::c_int>() as u32) << 16) | (0x45 << 8) | 0x90;
+
+/// Longest time `pump` waits for input when no release is pending.
+const IDLE_WAIT: Duration = Duration::from_millis(100);
+
+/// An opened evdev event node.
+pub struct EvdevDevice {
+    file: File,
+    path: PathBuf,
+    grabbed: bool,
+}
+
+impl EvdevDevice {
+    /// Open an event node (e.g. `/dev/input/event7`) for non-blocking reads.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .map_err(|e| Error::Input(format!("open {}: {e}", path.display())))?;
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            grabbed: false,
+        })
+    }
+
+    /// Take exclusive access so events stop reaching other readers.
+    pub fn grab(&mut self) -> Result<()> {
+        self.set_grab(true)
+    }
+
+    fn set_grab(&mut self, grab: bool) -> Result<()> {
+        // SAFETY: valid fd; EVIOCGRAB takes an int by value.
+        let rc = unsafe { libc::ioctl(self.file.as_raw_fd(), EVIOCGRAB as _, grab as libc::c_int) };
+        if rc < 0 {
+            return Err(Error::Input(format!(
+                "EVIOCGRAB {}: {}",
+                self.path.display(),
+                std::io::Error::last_os_error()
+            )));
+        }
+        self.grabbed = grab;
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for events and return everything available.
+    pub fn read_events(&mut self, timeout: Duration) -> Result<Vec<EvdevEvent>> {
+        let mut pfd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `pfd` is a valid pollfd array of length 1.
+        let rc = unsafe { libc::poll(&mut pfd, 1, timeout.as_millis() as libc::c_int) };
+        if rc < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                return Ok(Vec::new());
+            }
+            return Err(Error::Input(format!("poll: {err}")));
+        }
+        if pfd.revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0 {
+            return Err(Error::DeviceNotFound(format!(
+                "{} disconnected",
+                self.path.display()
+            )));
+        }
+
+        const EVENT_SIZE: usize = std::mem::size_of::<libc::input_event>();
+        let mut buf = [0u8; EVENT_SIZE * 64];
+        let mut events = Vec::new();
+        loop {
+            match self.file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    for chunk in buf[..n].chunks_exact(EVENT_SIZE) {
+                        // SAFETY: the kernel writes whole input_event structs;
+                        // read_unaligned copes with the byte buffer's alignment.
+                        let ev: libc::input_event = unsafe {
+                            std::ptr::read_unaligned(chunk.as_ptr() as *const libc::input_event)
+                        };
+                        let time_us = ev.time.tv_sec as u64 * 1_000_000 + ev.time.tv_usec as u64;
+                        events.push(EvdevEvent::new(time_us, ev.type_, ev.code, ev.value));
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) if e.raw_os_error() == Some(libc::ENODEV) => {
+                    return Err(Error::DeviceNotFound(format!(
+                        "{} disconnected",
+                        self.path.display()
+                    )))
+                }
+                Err(e) => return Err(Error::Input(format!("read evdev: {e}"))),
+            }
+        }
+        Ok(events)
+    }
+}
+
+impl Drop for EvdevDevice {
+    fn drop(&mut self) {
+        if self.grabbed {
+            let _ = self.set_grab(false);
+        }
+    }
+}
+
+/// Find evdev nodes belonging to Logitech pointing devices.
+///
+/// Scans `/sys/class/input/event*` for vendor 0x046D devices that report
+/// relative X/Y motion. Receivers expose several nodes; only pointers match.
+pub fn find_logitech_pointers() -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let Ok(entries) = std::fs::read_dir("/sys/class/input") else {
+        return found;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with("event") {
+            continue;
+        }
+        let device = entry.path().join("device");
+        let vendor = std::fs::read_to_string(device.join("id/vendor")).unwrap_or_default();
+        let rel = std::fs::read_to_string(device.join("capabilities/rel")).unwrap_or_default();
+        if u16::from_str_radix(vendor.trim(), 16).ok() == Some(crate::LOGITECH_VID)
+            && has_relative_xy(&rel)
+        {
+            found.push(PathBuf::from("/dev/input").join(&name));
+        }
+    }
+    found.sort();
+    debug!(count = found.len(), "Logitech evdev pointer scan complete");
+    found
+}
+
+/// Whether a sysfs `capabilities/rel` bitmask includes REL_X and REL_Y.
+fn has_relative_xy(mask: &str) -> bool {
+    // The lowest word is printed last; REL_X/REL_Y are bits 0 and 1.
+    mask.split_whitespace()
+        .last()
+        .and_then(|word| u64::from_str_radix(word, 16).ok())
+        .is_some_and(|bits| bits & 0b11 == 0b11)
+}
+
+fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+/// Grabs a mouse's evdev node and re-emits its debounced events via uinput.
+pub struct DebounceForwarder {
+    source: EvdevDevice,
+    sink: UinputDevice,
+    filter: DebounceFilter,
+}
+
+impl DebounceForwarder {
+    /// Validate settings, create the virtual mouse, then grab `device`.
+    pub fn open(device: &Path, settings: DebounceSettings) -> Result<Self> {
+        settings.validate()?;
+        let sink = UinputDevice::create_pointer_keyboard(
+            "Open G Hub debounced mouse",
+            &[
+                uinput::REL_HWHEEL,
+                uinput::REL_WHEEL_HI_RES,
+                uinput::REL_HWHEEL_HI_RES,
+            ],
+        )?;
+        let mut source = EvdevDevice::open(device)?;
+        source.grab()?;
+        info!(device = %device.display(), "Grabbed mouse for click debounce");
+        Ok(Self {
+            source,
+            sink,
+            filter: DebounceFilter::new(settings),
+        })
+    }
+
+    /// Run one read → filter → forward cycle.
+    ///
+    /// Waits at most until the next held release expires (or a short idle
+    /// period), so releases are never delayed longer than their window.
+    pub fn pump(&mut self) -> Result<()> {
+        let wait = self
+            .filter
+            .next_deadline(now_us())
+            .map_or(IDLE_WAIT, |d| d.min(IDLE_WAIT));
+        let events = self.source.read_events(wait)?;
+
+        let mut out = Vec::new();
+        for event in events {
+            out.extend(self.filter.process(event));
+        }
+        out.extend(self.filter.flush(now_us()));
+
+        for event in out {
+            self.sink
+                .write_event(event.type_, event.code, event.value)?;
+        }
+        Ok(())
+    }
+
+    /// Suppression statistics so far.
+    pub fn stats(&self) -> &DebounceStats {
+        self.filter.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eviocgrab_matches_kernel_header() {
+        assert_eq!(EVIOCGRAB, 0x4004_4590);
+    }
+
+    #[test]
+    fn relative_capability_mask_parsing() {
+        // Typical mouse: REL_X, REL_Y, REL_HWHEEL, REL_WHEEL, hi-res wheels.
+        assert!(has_relative_xy("1943"));
+        assert!(has_relative_xy("0 3"));
+        // Keyboard node on the same receiver: consumer wheel only.
+        assert!(!has_relative_xy("1040"));
+        assert!(!has_relative_xy(""));
+    }
+}
@@ -0,0 +1,1269 @@
+//! Logitech G Hub `settings.db` reader and writer.
+//!
+//! G Hub keeps its settings in an SQLite database with one table:
+//!
+//! ```sql
+//! CREATE TABLE DATA (_id INTEGER PRIMARY KEY, _date_created TEXT, FILE BLOB)
+//! ```
+//!
+//! Every save appends a row, and `FILE` of the newest row holds the whole
+//! settings document as JSON. The parts read here:
+//!
+//! - `profiles.profiles[]`: `{id, name, applicationId, activeForApplication,
+//!   assignments: [{slotId, cardId}]}`
+//! - `cards.cards[]`: `{id, attribute, ...}`. `MOUSE_SETTINGS` cards carry
+//!   `mouseSettings: {dpiStages: [{dpi, isDefault}], reportRate}`, and
+//!   `MOUSE_BUTTON` cards carry `mouseButton` (see [`button_name`]).
+//!   `KEYSTROKE` cards carry `keystroke: {code, modifiers}`, and
+//!   `MACRO_PLAYBACK` cards a `macro` of one of these `type`s:
+//!   - `KEYSTROKE`: `keystroke` as above
+//!   - `TEXT`: `textBlock: {text}`, typed on a US layout
+//!   - `SEQUENCE`: `sequence: {components: [...]}` of `{keyboard: {code,
+//!     isDown}}`, `{mouse: {button, isDown}}` and `{delay: {durationMs}}`
+//!   - `SYSTEM`: `system: {action}`; only media and volume keys convert
+//!
+//!   Key codes are G Hub key names (`"C"`, `"LCTRL"`) or set-1 scancodes.
+//!   Keystroke and macro cards become host macros (see [`crate::macros`]),
+//!   which open-g-hubd plays in place of the button's action.
+//! - `applications.applications[]`: `{applicationId, name}`
+//!
+//! Slot IDs name the device and the control: `<device>_settings` holds the
+//! settings card, `<device>_c<N>` or `<device>_c<N>_m<M>` button N (1-based)
+//! in G-shift mode M. Only mode 1 maps onto [`Profile`].
+//!
+//! Reading opens the database read-only, so a copy taken from a Windows
+//! install works on any platform.
+//!
+//! Writing ([`save_profile`]) only touches the profile being saved and the
+//! cards it owns. It refuses documents whose top-level `version` is not in
+//! [`SUPPORTED_SCHEMA_VERSIONS`], backs the database up next to itself, and
+//! appends the merged document in a single transaction.
+
+use crate::device::{ButtonAction, G502_BUTTON_COUNT};
+use crate::error::{Error, Result};
+use crate::input::{self, MouseButton};
+use crate::macros::{Macro, MacroStep};
+use crate::profile::Profile;
+use crate::safety;
+use rusqlite::{Connection, OpenFlags, TransactionBehavior};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// File name of the settings database inside the `LGHUB` directory.
+pub const SETTINGS_FILE: &str = "settings.db";
+
+/// Settings document versions whose layout [`save_profile`] understands.
+pub const SUPPORTED_SCHEMA_VERSIONS: &[u64] = &[8, 9];
+
+/// How long to wait for G Hub to release a write lock.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Card attribute for DPI stages and report rate.
+pub const MOUSE_SETTINGS_CARD: &str = "MOUSE_SETTINGS";
+/// Card attribute for a plain mouse button assignment.
+pub const MOUSE_BUTTON_CARD: &str = "MOUSE_BUTTON";
+
+/// Card attribute for a single key combination.
+pub const KEYSTROKE_CARD: &str = "KEYSTROKE";
+/// Card attribute for a macro.
+pub const MACRO_CARD: &str = "MACRO_PLAYBACK";
+
+/// G Hub system actions that are a single key press.
+const SYSTEM_KEYS: &[(&str, &str)] = &[
+    ("MEDIA_PLAY_PAUSE", "media_play_pause"),
+    ("MEDIA_NEXT", "media_next"),
+    ("MEDIA_PREVIOUS", "media_prev"),
+    ("MEDIA_STOP", "media_stop"),
+    ("VOLUME_UP", "volume_up"),
+    ("VOLUME_DOWN", "volume_down"),
+    ("VOLUME_MUTE", "mute"),
+];
+
+/// G Hub names for the actions [`ButtonAction`] models.
+const BUTTON_NAMES: &[(&str, ButtonAction)] = &[
+    ("PRIMARY_CLICK", ButtonAction::LeftClick),
+    ("SECONDARY_CLICK", ButtonAction::RightClick),
+    ("MIDDLE_CLICK", ButtonAction::MiddleClick),
+    ("BACK", ButtonAction::Back),
+    ("FORWARD", ButtonAction::Forward),
+    ("DPI_UP", ButtonAction::DpiCycleUp),
+    ("DPI_DOWN", ButtonAction::DpiCycleDown),
+    ("DISABLED", ButtonAction::NoAction),
+];
+
+/// G Hub's `mouseButton` name for `action`.
+pub fn button_name(action: ButtonAction) -> &'static str {
+    BUTTON_NAMES
+        .iter()
+        .find(|(_, a)| *a == action)
+        .map(|(name, _)| *name)
+        .unwrap_or("DISABLED")
+}
+
+/// The action for a G Hub `mouseButton` name.
+pub fn button_from_name(name: &str) -> Option<ButtonAction> {
+    BUTTON_NAMES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, action)| *action)
+}
+
+/// One G Hub profile's settings for one device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GhubProfile {
+    /// Device part of the slot IDs, e.g. `g502_lightspeed`.
+    pub device: String,
+    pub name: String,
+    /// Name of the application the profile belongs to.
+    pub application: Option<String>,
+    /// Whether G Hub uses this profile when its application is focused.
+    pub active: bool,
+    pub dpi_stages: Vec<u16>,
+    /// Index into `dpi_stages` of the stage used at startup.
+    pub default_dpi_stage: Option<usize>,
+    pub report_rate: Option<u16>,
+    /// Button index (0-based) and its action.
+    pub buttons: Vec<(usize, ButtonAction)>,
+    /// Button index (0-based) and the host macro its card converts to.
+    pub macros: Vec<(usize, Macro)>,
+    /// Slot IDs assigned to cards that [`Profile`] cannot express
+    /// (macros that do not convert, G-shift layers).
+    pub skipped: Vec<String>,
+}
+
+impl GhubProfile {
+    /// DPI of the default stage, else the first stage.
+    pub fn default_dpi(&self) -> Option<u16> {
+        self.default_dpi_stage
+            .and_then(|i| self.dpi_stages.get(i))
+            .or_else(|| self.dpi_stages.first())
+            .copied()
+    }
+
+    /// Convert to a [`Profile`]. Settings the G Hub profile leaves out keep
+    /// their defaults; values outside the safe range are rejected.
+    pub fn to_profile(&self) -> Result<Profile> {
+        let mut profile = Profile {
+            name: self.name.clone(),
+            ..Default::default()
+        };
+        if let Some(dpi) = self.default_dpi() {
+            profile.dpi = safety::validate_dpi(dpi)?;
+        }
+        profile.dpi_stages = self
+            .dpi_stages
+            .iter()
+            .map(|&dpi| safety::validate_dpi(dpi))
+            .collect::<Result<_>>()?;
+        profile.default_dpi_stage = self
+            .default_dpi_stage
+            .filter(|&i| i < self.dpi_stages.len())
+            .unwrap_or(0);
+        if let Some(hz) = self.report_rate {
+            profile.polling_rate = safety::validate_polling_rate(hz)?;
+        }
+        for &(index, action) in &self.buttons {
+            if let Some(slot) = profile.buttons.get_mut(index) {
+                *slot = action;
+            }
+        }
+        profile.button_macros = self.macros.iter().cloned().collect();
+        Ok(profile)
+    }
+}
+
+fn db_error(path: &Path) -> impl Fn(rusqlite::Error) -> Error + '_ {
+    move |e| Error::Profile(format!("{}: {e}", path.display()))
+}
+
+/// Read the newest settings document from a `settings.db`.
+pub fn read_settings(path: &Path) -> Result<Value> {
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(db_error(path))?;
+    newest_document(&conn, path)
+}
+
+fn newest_document(conn: &Connection, path: &Path) -> Result<Value> {
+    let file: Vec<u8> = conn
+        .query_row(
+            "SELECT FILE FROM DATA ORDER BY _id DESC LIMIT 1",
+            [],
+            |row| Ok(row.get_ref(0)?.as_bytes().map(<[u8]>::to_vec)),
+        )
+        .map_err(db_error(path))?
+        .map_err(|e| Error::Profile(format!("{}: FILE column: {e}", path.display())))?;
+    serde_json::from_slice(&file)
+        .map_err(|e| Error::Profile(format!("{}: settings JSON: {e}", path.display())))
+}
+
+/// The document's top-level `version`, if any.
+pub fn schema_version(settings: &Value) -> Option<u64> {
+    settings.get("version")?.as_u64()
+}
+
+/// Fail unless the document's schema version is one we can write.
+pub fn check_schema_version(settings: &Value) -> Result<u64> {
+    match schema_version(settings) {
+        Some(version) if SUPPORTED_SCHEMA_VERSIONS.contains(&version) => Ok(version),
+        Some(version) => Err(Error::Profile(format!(
+            "G Hub settings schema version {version} is not supported (known: {SUPPORTED_SCHEMA_VERSIONS:?}); refusing to write"
+        ))),
+        None => Err(Error::Profile(
+            "G Hub settings have no schema version; refusing to write".to_string(),
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+struct Document {
+    #[serde(default)]
+    applications: Applications,
+    #[serde(default)]
+    cards: Cards,
+    profiles: Profiles,
+}
+
+#[derive(Default, Deserialize)]
+struct Applications {
+    #[serde(default)]
+    applications: Vec<Application>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Application {
+    application_id: String,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct Cards {
+    #[serde(default)]
+    cards: Vec<Card>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Card {
+    id: String,
+    #[serde(default)]
+    attribute: String,
+    #[serde(default)]
+    mouse_settings: Option<MouseSettings>,
+    #[serde(default)]
+    mouse_button: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    // Macro bodies are decoded per card, so one G Hub does not know yet
+    // cannot make the whole document unreadable.
+    #[serde(default)]
+    keystroke: Option<Value>,
+    #[serde(default, rename = "macro")]
+    macro_body: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct Keystroke {
+    code: Value,
+    #[serde(default)]
+    modifiers: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+enum MacroBody {
+    Keystroke {
+        keystroke: Keystroke,
+    },
+    #[serde(rename_all = "camelCase")]
+    Text {
+        text_block: TextBlock,
+    },
+    Sequence {
+        sequence: Sequence,
+    },
+    System {
+        system: SystemAction,
+    },
+}
+
+#[derive(Deserialize)]
+struct TextBlock {
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct Sequence {
+    components: Vec<Component>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Component {
+    #[serde(rename_all = "camelCase")]
+    Keyboard {
+        code: Value,
+        #[serde(default)]
+        is_down: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    Mouse {
+        button: String,
+        #[serde(default)]
+        is_down: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    Delay { duration_ms: u32 },
+}
+
+#[derive(Deserialize)]
+struct SystemAction {
+    action: String,
+}
+
+/// A G Hub key code: a key name or a set-1 scancode.
+fn key_code(code: &Value) -> std::result::Result<u16, String> {
+    match code {
+        Value::String(name) => input::key_from_name(name),
+        Value::Number(n) => n
+            .as_u64()
+            .and_then(|n| u16::try_from(n).ok())
+            .and_then(input::key_from_scancode),
+        _ => None,
+    }
+    .ok_or_else(|| format!("unknown key {code}"))
+}
+
+fn keystroke_macro(name: String, keystroke: &Keystroke) -> std::result::Result<Macro, String> {
+    let modifiers = keystroke
+        .modifiers
+        .iter()
+        .map(key_code)
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(Macro::keystroke(
+        name,
+        &modifiers,
+        key_code(&keystroke.code)?,
+    ))
+}
+
+fn mouse_button(name: &str) -> Option<MouseButton> {
+    match name {
+        "PRIMARY_CLICK" => Some(MouseButton::Left),
+        "SECONDARY_CLICK" => Some(MouseButton::Right),
+        "MIDDLE_CLICK" => Some(MouseButton::Middle),
+        "BACK" => Some(MouseButton::Back),
+        "FORWARD" => Some(MouseButton::Forward),
+        _ => None,
+    }
+}
+
+/// The host macro for a `KEYSTROKE` or `MACRO_PLAYBACK` card; the error
+/// says why there is none.
+fn card_macro(card: &Card, name: String) -> std::result::Result<Macro, String> {
+    fn body(value: &Option<Value>) -> std::result::Result<&Value, &'static str> {
+        value.as_ref().ok_or("the card holds no macro body")
+    }
+    let m = if card.attribute == KEYSTROKE_CARD {
+        let keystroke =
+            Keystroke::deserialize(body(&card.keystroke)?).map_err(|e| e.to_string())?;
+        keystroke_macro(name, &keystroke)?
+    } else {
+        match MacroBody::deserialize(body(&card.macro_body)?).map_err(|e| e.to_string())? {
+            MacroBody::Keystroke { keystroke } => keystroke_macro(name, &keystroke)?,
+            MacroBody::Text { text_block } => Macro::text(name, &text_block.text)
+                .map_err(|c| format!("{c:?} cannot be typed on a US layout"))?,
+            MacroBody::Sequence { sequence } => {
+                let mut steps = Vec::new();
+                for component in sequence.components {
+                    steps.push(match component {
+                        Component::Keyboard { code, is_down } => {
+                            let key = key_code(&code)?;
+                            if is_down {
+                                MacroStep::KeyDown(key)
+                            } else {
+                                MacroStep::KeyUp(key)
+                            }
+                        }
+                        Component::Mouse { button, is_down } => {
+                            let button = mouse_button(&button)
+                                .ok_or_else(|| format!("mouse button {button}"))?;
+                            if is_down {
+                                MacroStep::ButtonDown(button)
+                            } else {
+                                MacroStep::ButtonUp(button)
+                            }
+                        }
+                        Component::Delay { duration_ms } => MacroStep::Delay(duration_ms),
+                    });
+                }
+                Macro { name, steps }
+            }
+            MacroBody::System { system } => {
+                let key = SYSTEM_KEYS
+                    .iter()
+                    .find(|(action, _)| *action == system.action)
+                    .and_then(|&(_, key)| input::key_from_name(key))
+                    .ok_or_else(|| format!("system action {}", system.action))?;
+                Macro::keystroke(name, &[], key)
+            }
+        }
+    };
+    safety::validate_macro(&m).map_err(|e| e.to_string())?;
+    Ok(m)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MouseSettings {
+    #[serde(default)]
+    dpi_stages: Vec<DpiStage>,
+    #[serde(default)]
+    report_rate: Option<u16>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DpiStage {
+    dpi: u16,
+    #[serde(default)]
+    is_default: bool,
+}
+
+#[derive(Deserialize)]
+struct Profiles {
+    profiles: Vec<ProfileEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProfileEntry {
+    name: String,
+    #[serde(default)]
+    application_id: Option<String>,
+    #[serde(default)]
+    active_for_application: bool,
+    #[serde(default)]
+    assignments: Vec<Assignment>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Assignment {
+    slot_id: String,
+    card_id: String,
+}
+
+/// What a slot ID addresses.
+#[derive(Debug, PartialEq)]
+enum Slot<'a> {
+    Settings {
+        device: &'a str,
+    },
+    Button {
+        device: &'a str,
+        index: usize,
+        mode: u8,
+    },
+}
+
+fn parse_slot(slot_id: &str) -> Option<Slot<'_>> {
+    if let Some(device) = slot_id.strip_suffix("_settings") {
+        return Some(Slot::Settings { device });
+    }
+    let (rest, mode) = match slot_id.rsplit_once("_m") {
+        Some((rest, mode)) if mode.bytes().all(|b| b.is_ascii_digit()) => {
+            (rest, mode.parse().ok()?)
+        }
+        _ => (slot_id, 1),
+    };
+    let (device, number) = rest.rsplit_once("_c")?;
+    let number: usize = number.parse().ok()?;
+    Some(Slot::Button {
+        device,
+        index: number.checked_sub(1)?,
+        mode,
+    })
+}
+
+/// Every profile's settings, one entry per profile and device.
+pub fn parse_profiles(settings: &Value) -> Result<Vec<GhubProfile>> {
+    let doc = Document::deserialize(settings)
+        .map_err(|e| Error::Profile(format!("unexpected G Hub settings layout: {e}")))?;
+    let cards: HashMap<&str, &Card> = doc.cards.cards.iter().map(|c| (c.id.as_str(), c)).collect();
+    let apps: HashMap<&str, &str> = doc
+        .applications
+        .applications
+        .iter()
+        .filter_map(|a| Some((a.application_id.as_str(), a.name.as_deref()?)))
+        .collect();
+
+    let mut out = Vec::new();
+    for entry in &doc.profiles.profiles {
+        let mut by_device: Vec<GhubProfile> = Vec::new();
+        for assignment in &entry.assignments {
+            let Some(slot) = parse_slot(&assignment.slot_id) else {
+                continue;
+            };
+            let device = match slot {
+                Slot::Settings { device } | Slot::Button { device, .. } => device,
+            };
+            let target = match by_device.iter().position(|p| p.device == device) {
+                Some(i) => &mut by_device[i],
+                None => {
+                    by_device.push(GhubProfile {
+                        device: device.to_string(),
+                        name: entry.name.clone(),
+                        application: entry
+                            .application_id
+                            .as_deref()
+                            .and_then(|id| apps.get(id))
+                            .map(|name| name.to_string()),
+                        active: entry.active_for_application,
+                        dpi_stages: Vec::new(),
+                        default_dpi_stage: None,
+                        report_rate: None,
+                        buttons: Vec::new(),
+                        macros: Vec::new(),
+                        skipped: Vec::new(),
+                    });
+                    by_device.last_mut().unwrap()
+                }
+            };
+            let Some(card) = cards.get(assignment.card_id.as_str()) else {
+                debug!(slot = %assignment.slot_id, "Assignment to a missing card");
+                continue;
+            };
+            match (slot, card.attribute.as_str()) {
+                (Slot::Settings { .. }, MOUSE_SETTINGS_CARD) => {
+                    if let Some(settings) = &card.mouse_settings {
+                        target.dpi_stages = settings.dpi_stages.iter().map(|s| s.dpi).collect();
+                        target.default_dpi_stage =
+                            settings.dpi_stages.iter().position(|s| s.is_default);
+                        target.report_rate = settings.report_rate;
+                    }
+                }
+                (Slot::Button { index, mode: 1, .. }, MOUSE_BUTTON_CARD) => {
+                    match card.mouse_button.as_deref().and_then(button_from_name) {
+                        Some(action) if index < G502_BUTTON_COUNT => {
+                            target.buttons.push((index, action))
+                        }
+                        _ => target.skipped.push(assignment.slot_id.clone()),
+                    }
+                }
+                (Slot::Button { index, mode: 1, .. }, KEYSTROKE_CARD | MACRO_CARD)
+                    if index < G502_BUTTON_COUNT =>
+                {
+                    let name = card
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| assignment.slot_id.clone());
+                    match card_macro(card, name) {
+                        Ok(m) => target.macros.push((index, m)),
+                        Err(reason) => {
+                            debug!(slot = %assignment.slot_id, %reason, "Macro card not converted");
+                            target.skipped.push(assignment.slot_id.clone());
+                        }
+                    }
+                }
+                _ => target.skipped.push(assignment.slot_id.clone()),
+            }
+        }
+        out.append(&mut by_device);
+    }
+    Ok(out)
+}
+
+/// Read every profile from a `settings.db`.
+pub fn load_profiles(path: &Path) -> Result<Vec<GhubProfile>> {
+    parse_profiles(&read_settings(path)?)
+}
+
+/// Pick the profile named `name`, else the first active one, else the first.
+///
+/// When a profile covers several devices, the entry with mouse settings wins.
+pub fn select<'a>(profiles: &'a [GhubProfile], name: Option<&str>) -> Option<&'a GhubProfile> {
+    let wanted = |p: &&GhubProfile| name.map_or(p.active, |name| p.name == name);
+    let has_mouse_settings = |p: &&GhubProfile| !p.dpi_stages.is_empty() || !p.buttons.is_empty();
+    profiles
+        .iter()
+        .filter(wanted)
+        .find(has_mouse_settings)
+        .or_else(|| profiles.iter().find(wanted))
+        .or_else(|| name.map_or(profiles.first(), |_| None))
+}
+
+/// Load one profile from a `settings.db` as a [`Profile`] (see [`select`]).
+pub fn load_profile(path: &Path, name: Option<&str>) -> Result<Profile> {
+    let profiles = load_profiles(path)?;
+    let chosen = select(&profiles, name).ok_or_else(|| match name {
+        Some(name) => Error::Profile(format!("no G Hub profile named '{name}'")),
+        None => Error::Profile(format!("no profiles in {}", path.display())),
+    })?;
+    chosen.to_profile()
+}
+
+/// Merge `profile` into the G Hub database at `path`.
+///
+/// The G Hub profile with the same name is updated (or created) for
+/// `device`, a slot ID prefix such as `g502_lightspeed`; `None` picks the
+/// mouse already configured in the database. Only settings that differ from
+/// what G Hub has are written, so assignments `Profile` cannot express
+/// survive unless that button was changed. Buttons with a host macro keep
+/// whatever card G Hub has: host macros are played by open-g-hubd, not
+/// written back as cards.
+///
+/// Before writing, the database is copied to a timestamped backup next to
+/// it, whose path is returned.
+pub fn save_profile(path: &Path, profile: &Profile, device: Option<&str>) -> Result<PathBuf> {
+    let mut conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(db_error(path))?;
+    conn.busy_timeout(BUSY_TIMEOUT).map_err(db_error(path))?;
+
+    // Refuse before leaving anything behind.
+    check_schema_version(&newest_document(&conn, path)?)?;
+    let backup = backup(&conn, path)?;
+
+    let tx = conn
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(db_error(path))?;
+    let mut doc = newest_document(&tx, path)?;
+    check_schema_version(&doc)?;
+    let device = match device {
+        Some(device) => device.to_string(),
+        None => configured_mouse(&doc)?,
+    };
+    merge_profile(&mut doc, profile, &device)?;
+    let file = serde_json::to_vec(&doc).map_err(|e| Error::Profile(e.to_string()))?;
+    tx.execute(
+        "INSERT INTO DATA (_date_created, FILE) VALUES (datetime('now'), ?1)",
+        [file],
+    )
+    .map_err(db_error(path))?;
+    tx.commit().map_err(db_error(path))?;
+
+    info!(profile = %profile.name, %device, backup = %backup.display(), "Saved G Hub profile");
+    Ok(backup)
+}
+
+/// Copy the database to `<name>.<UTC timestamp>.bak` beside it.
+fn backup(conn: &Connection, path: &Path) -> Result<PathBuf> {
+    let stamp: String = conn
+        .query_row("SELECT strftime('%Y%m%d-%H%M%S', 'now')", [], |row| {
+            row.get(0)
+        })
+        .map_err(db_error(path))?;
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| SETTINGS_FILE.to_string());
+    let mut backup = path.with_file_name(format!("{name}.{stamp}.bak"));
+    let mut n = 1;
+    while backup.exists() {
+        backup = path.with_file_name(format!("{name}.{stamp}-{n}.bak"));
+        n += 1;
+    }
+    // Unlike a file copy, this includes pages still in the WAL.
+    conn.execute("VACUUM INTO ?1", [backup.to_string_lossy()])
+        .map_err(db_error(path))?;
+    Ok(backup)
+}
+
+/// The mouse G Hub already has settings for, preferring a G502.
+fn configured_mouse(doc: &Value) -> Result<String> {
+    let profiles = parse_profiles(doc)?;
+    let mice: Vec<&str> = profiles
+        .iter()
+        .filter(|p| !p.dpi_stages.is_empty() || !p.buttons.is_empty())
+        .map(|p| p.device.as_str())
+        .collect();
+    mice.iter()
+        .find(|d| d.starts_with("g502"))
+        .or_else(|| mice.first())
+        .map(|d| d.to_string())
+        .ok_or_else(|| {
+            Error::Profile("no mouse configured in G Hub; name the G Hub device to write".into())
+        })
+}
+
+/// A random UUID-style ID for new cards and profiles.
+fn new_id() -> String {
+    use std::hash::{BuildHasher, Hasher};
+    let mut bytes = [0u8; 16];
+    for chunk in bytes.chunks_mut(8) {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u128(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        );
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    bytes[6] = (bytes[6] & 0x0F) | 0x40;
+    bytes[8] = (bytes[8] & 0x3F) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// `doc[section][section]`, G Hub's layout for every list, created if missing.
+fn section_mut<'a>(doc: &'a mut Value, section: &str) -> Result<&'a mut Vec<Value>> {
+    let layout = || Error::Profile(format!("unexpected G Hub settings layout at '{section}'"));
+    doc.as_object_mut()
+        .ok_or_else(layout)?
+        .entry(section)
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+        .ok_or_else(layout)?
+        .entry(section)
+        .or_insert_with(|| json!([]))
+        .as_array_mut()
+        .ok_or_else(layout)
+}
+
+fn str_field<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key)?.as_str()
+}
+
+/// Merge `profile` into the settings document for `device`.
+fn merge_profile(doc: &mut Value, profile: &Profile, device: &str) -> Result<()> {
+    // What G Hub has now, to leave equal settings (and macros) alone.
+    let current = parse_profiles(doc)?
+        .into_iter()
+        .find(|p| p.name == profile.name && p.device == device)
+        .and_then(|p| p.to_profile().ok());
+
+    let desktop_app = section_mut(doc, "applications")?
+        .iter()
+        .find(|a| str_field(a, "name") == Some("Desktop"))
+        .and_then(|a| str_field(a, "applicationId"))
+        .map(str::to_string);
+
+    let mut card_uses: HashMap<String, usize> = HashMap::new();
+    for entry in section_mut(doc, "profiles")?.iter() {
+        for assignment in entry["assignments"].as_array().into_iter().flatten() {
+            if let Some(card) = str_field(assignment, "cardId") {
+                *card_uses.entry(card.to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    let mut new_cards: Vec<Value> = Vec::new();
+    let mut card_edits: Vec<(String, Value)> = Vec::new();
+    let cards_snapshot = section_mut(doc, "cards")?.clone();
+    let find_card = |id: &str| -> Option<&Value> {
+        cards_snapshot
+            .iter()
+            .find(|c| str_field(c, "id") == Some(id))
+    };
+
+    let profiles = section_mut(doc, "profiles")?;
+    let entry = match profiles
+        .iter()
+        .position(|p| str_field(p, "name") == Some(profile.name.as_str()))
+    {
+        Some(i) => &mut profiles[i],
+        None => {
+            let mut entry = Map::new();
+            entry.insert("id".into(), Value::from(new_id()));
+            entry.insert("name".into(), Value::from(profile.name.clone()));
+            if let Some(app) = &desktop_app {
+                entry.insert("applicationId".into(), Value::from(app.clone()));
+            }
+            entry.insert("activeForApplication".into(), Value::from(false));
+            entry.insert("assignments".into(), json!([]));
+            profiles.push(Value::Object(entry));
+            profiles.last_mut().unwrap()
+        }
+    };
+    let assignments = entry
+        .as_object_mut()
+        .ok_or_else(|| Error::Profile("unexpected G Hub profile entry".into()))?
+        .entry("assignments")
+        .or_insert_with(|| json!([]))
+        .as_array_mut()
+        .ok_or_else(|| Error::Profile("unexpected G Hub assignments list".into()))?;
+
+    let slot_position = |assignments: &[Value], wanted: &Slot| {
+        assignments
+            .iter()
+            .position(|a| str_field(a, "slotId").and_then(parse_slot).as_ref() == Some(wanted))
+    };
+
+    // DPI and report rate live on the settings card.
+    let settings_changed = current
+        .as_ref()
+        .is_none_or(|c| c.dpi != profile.dpi || c.polling_rate != profile.polling_rate);
+    if settings_changed {
+        let slot = Slot::Settings { device };
+        let existing = slot_position(assignments, &slot).and_then(|i| {
+            let card_id = str_field(&assignments[i], "cardId")?;
+            let card = find_card(card_id)?;
+            (str_field(card, "attribute") == Some(MOUSE_SETTINGS_CARD)).then_some((i, card))
+        });
+        let mut card = existing
+            .map(|(_, card)| card.clone())
+            .unwrap_or_else(|| json!({ "attribute": MOUSE_SETTINGS_CARD }));
+        update_mouse_settings(&mut card, profile);
+
+        match existing {
+            // Edit in place only if no other slot shares the card.
+            Some((i, _))
+                if card_uses
+                    .get(str_field(&assignments[i], "cardId").unwrap_or_default())
+                    .copied()
+                    .unwrap_or(0)
+                    <= 1 =>
+            {
+                let id = str_field(&assignments[i], "cardId").unwrap_or_default();
+                card_edits.push((id.to_string(), card));
+            }
+            _ => {
+                let id = new_id();
+                card["id"] = Value::from(id.clone());
+                new_cards.push(card);
+                set_assignment(
+                    assignments,
+                    existing.map(|(i, _)| i),
+                    &format!("{device}_settings"),
+                    &id,
+                );
+            }
+        }
+    }
+
+    for (index, &action) in profile.buttons.iter().enumerate().take(G502_BUTTON_COUNT) {
+        if current.as_ref().and_then(|c| c.buttons.get(index)) == Some(&action) {
+            continue;
+        }
+        // Host macros are not written back; G Hub keeps its own card there.
+        if profile.button_macros.contains_key(&index) {
+            continue;
+        }
+        let name = button_name(action);
+        // Button cards are never edited, so an identical one can be shared.
+        let id = match cards_snapshot.iter().chain(&new_cards).find(|c| {
+            str_field(c, "attribute") == Some(MOUSE_BUTTON_CARD)
+                && str_field(c, "mouseButton") == Some(name)
+        }) {
+            Some(card) => str_field(card, "id").unwrap_or_default().to_string(),
+            None => {
+                let id = new_id();
+                new_cards.push(json!({
+                    "id": id,
+                    "attribute": MOUSE_BUTTON_CARD,
+                    "mouseButton": name,
+                }));
+                id
+            }
+        };
+        let slot = Slot::Button {
+            device,
+            index,
+            mode: 1,
+        };
+        let position = slot_position(assignments, &slot);
+        set_assignment(
+            assignments,
+            position,
+            &format!("{device}_c{}", index + 1),
+            &id,
+        );
+    }
+
+    let cards = section_mut(doc, "cards")?;
+    for (id, edited) in card_edits {
+        if let Some(card) = cards
+            .iter_mut()
+            .find(|c| str_field(c, "id") == Some(id.as_str()))
+        {
+            *card = edited;
+        }
+    }
+    cards.extend(new_cards);
+    Ok(())
+}
+
+/// Point the assignment at `position` to `card_id`, or add one for `slot_id`.
+fn set_assignment(
+    assignments: &mut Vec<Value>,
+    position: Option<usize>,
+    slot_id: &str,
+    card_id: &str,
+) {
+    match position {
+        Some(i) => assignments[i]["cardId"] = Value::from(card_id),
+        None => assignments.push(json!({ "slotId": slot_id, "cardId": card_id })),
+    }
+}
+
+/// Set the default DPI stage and report rate on a `MOUSE_SETTINGS` card,
+/// keeping its other stages and fields.
+fn update_mouse_settings(card: &mut Value, profile: &Profile) {
+    let settings = &mut card["mouseSettings"];
+    if !settings.is_object() {
+        *settings = json!({});
+    }
+    settings["reportRate"] = Value::from(profile.polling_rate.as_hz());
+
+    let stages = &mut settings["dpiStages"];
+    if !stages.is_array() {
+        *stages = json!([]);
+    }
+    let stages = stages.as_array_mut().unwrap();
+    let chosen = match stages
+        .iter()
+        .position(|s| s["dpi"].as_u64() == Some(profile.dpi as u64))
+    {
+        Some(i) => i,
+        None => {
+            let i = stages
+                .iter()
+                .position(|s| s["isDefault"].as_bool() == Some(true))
+                .unwrap_or(0);
+            if stages.is_empty() {
+                stages.push(json!({}));
+            }
+            stages[i]["dpi"] = Value::from(profile.dpi);
+            i
+        }
+    };
+    for (i, stage) in stages.iter_mut().enumerate() {
+        if i == chosen {
+            stage["isDefault"] = Value::from(true);
+        } else if stage.get("isDefault").is_some() {
+            stage["isDefault"] = Value::from(false);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::device::PollingRate;
+
+    pub(crate) const FIXTURE: &str = include_str!("../tests/fixtures/ghub/settings.json");
+
+    /// Write `documents` as successive rows of a G Hub-style database.
+    pub(crate) fn fixture_db(dir: &Path, documents: &[&str]) -> std::path::PathBuf {
+        let path = dir.join(SETTINGS_FILE);
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE DATA (_id INTEGER PRIMARY KEY, _date_created TEXT, FILE BLOB);",
+        )
+        .unwrap();
+        for doc in documents {
+            conn.execute(
+                "INSERT INTO DATA (_date_created, FILE) VALUES (datetime('now'), ?1)",
+                [doc.as_bytes()],
+            )
+            .unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn slot_ids_parse() {
+        assert_eq!(
+            parse_slot("g502_lightspeed_settings"),
+            Some(Slot::Settings {
+                device: "g502_lightspeed"
+            })
+        );
+        assert_eq!(
+            parse_slot("g502_lightspeed_c4_m2"),
+            Some(Slot::Button {
+                device: "g502_lightspeed",
+                index: 3,
+                mode: 2
+            })
+        );
+        assert_eq!(
+            parse_slot("g502_hero_c1"),
+            Some(Slot::Button {
+                device: "g502_hero",
+                index: 0,
+                mode: 1
+            })
+        );
+        assert_eq!(parse_slot("g502_hero_c0"), None);
+        assert_eq!(parse_slot("keyboard_logo"), None);
+    }
+
+    #[test]
+    fn reads_newest_row_of_fixture_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let stale = r#"{"profiles": {"profiles": []}}"#;
+        let path = fixture_db(dir.path(), &[stale, FIXTURE]);
+
+        let profiles = load_profiles(&path).unwrap();
+        let names: Vec<(&str, &str)> = profiles
+            .iter()
+            .map(|p| (p.name.as_str(), p.device.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("Desktop", "g502_lightspeed"),
+                ("Shooter", "g502_lightspeed"),
+                ("Shooter", "g915")
+            ]
+        );
+
+        let shooter = &profiles[1];
+        assert_eq!(shooter.application.as_deref(), Some("Counter-Strike 2"));
+        assert_eq!(shooter.dpi_stages, [400, 800, 1600]);
+        assert_eq!(shooter.default_dpi(), Some(800));
+        assert_eq!(shooter.report_rate, Some(1000));
+        assert_eq!(
+            shooter.buttons,
+            [(3, ButtonAction::DpiCycleDown), (4, ButtonAction::NoAction)]
+        );
+        assert_eq!(
+            shooter.skipped,
+            ["g502_lightspeed_c6", "g502_lightspeed_c4_m2"]
+        );
+    }
+
+    #[test]
+    fn maps_onto_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = fixture_db(dir.path(), &[FIXTURE]);
+
+        // "Desktop" is the active profile.
+        let profile = load_profile(&path, None).unwrap();
+        assert_eq!(profile.name, "Desktop");
+        assert_eq!(profile.dpi, 1600);
+        assert_eq!(profile.dpi_stages, [800, 1600, 3200]);
+        assert_eq!(profile.default_dpi_stage, 1);
+        assert_eq!(profile.polling_rate, PollingRate::Hz500);
+        assert_eq!(profile.buttons[2], ButtonAction::MiddleClick);
+        assert_eq!(profile.buttons[3], ButtonAction::Forward);
+
+        let profiles = load_profiles(&path).unwrap();
+        let mut keyboard_first = profiles.clone();
+        keyboard_first.reverse();
+        assert_eq!(
+            select(&keyboard_first, Some("Shooter")).unwrap().device,
+            "g502_lightspeed"
+        );
+
+        let shooter = load_profile(&path, Some("Shooter")).unwrap();
+        assert_eq!(shooter.dpi, 800);
+        assert_eq!(shooter.buttons[3], ButtonAction::DpiCycleDown);
+        assert!(load_profile(&path, Some("Missing")).is_err());
+    }
+
+    #[test]
+    fn keystroke_and_macro_cards_become_host_macros() {
+        let doc: Value =
+            serde_json::from_str(include_str!("../tests/fixtures/ghub/macros.json")).unwrap();
+        let profiles = parse_profiles(&doc).unwrap();
+        let ghub = &profiles[0];
+        let key = |name| input::key_from_name(name).unwrap();
+
+        let macros: HashMap<usize, &Macro> = ghub.macros.iter().map(|(i, m)| (*i, m)).collect();
+        assert_eq!(
+            *macros[&2],
+            Macro::keystroke("Play/Pause", &[], key("media_play_pause"))
+        );
+        assert_eq!(
+            *macros[&3],
+            Macro::keystroke("Copy", &[key("lctrl")], key("c"))
+        );
+        assert_eq!(*macros[&4], Macro::text("Greeting", "GG wp!").unwrap());
+        assert_eq!(
+            macros[&5].steps,
+            [
+                MacroStep::KeyDown(key("r")),
+                MacroStep::Delay(40),
+                MacroStep::KeyUp(key("r")),
+                MacroStep::ButtonDown(MouseButton::Left),
+                MacroStep::ButtonUp(MouseButton::Left),
+            ]
+        );
+        // Non-US text, other system actions, unknown macro types, macros
+        // stored outside the card, buttons past G6 and G-shift layers.
+        assert_eq!(
+            ghub.skipped,
+            [
+                "g502_lightspeed_c1",
+                "g502_lightspeed_c2",
+                "g502_lightspeed_c7",
+                "g502_lightspeed_c4_m2"
+            ]
+        );
+
+        let profile = ghub.to_profile().unwrap();
+        assert_eq!(profile.button_macros.len(), 4);
+        assert_eq!(profile.buttons, Profile::default().buttons);
+    }
+
+    #[test]
+    fn host_macros_leave_ghub_cards_alone_on_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = include_str!("../tests/fixtures/ghub/macros.json");
+        let path = fixture_db(dir.path(), &[text]);
+        let mut profile = load_profile(&path, None).unwrap();
+        profile.buttons[3] = ButtonAction::DpiCycleUp;
+        profile.buttons[1] = ButtonAction::Back;
+        save_profile(&path, &profile, None).unwrap();
+
+        let saved = &load_profiles(&path).unwrap()[0];
+        assert!(saved
+            .macros
+            .iter()
+            .any(|(i, m)| *i == 3 && m.name == "Copy"));
+        assert!(saved.buttons.contains(&(1, ButtonAction::Back)));
+    }
+
+    #[test]
+    fn unsafe_values_and_bad_files_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let doc = FIXTURE.replace("\"reportRate\": 500", "\"reportRate\": 8000");
+        let path = fixture_db(dir.path(), &[&doc]);
+        assert!(matches!(
+            load_profile(&path, None),
+            Err(Error::OutOfRange {
+                field: "polling_rate",
+                ..
+            })
+        ));
+
+        assert!(read_settings(&dir.path().join("missing.db")).is_err());
+        let garbage = dir.path().join("garbage.db");
+        std::fs::write(&garbage, b"not a database").unwrap();
+        assert!(read_settings(&garbage).is_err());
+    }
+
+    fn row_count(path: &Path) -> i64 {
+        Connection::open(path)
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM DATA", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn save_round_trip_leaves_unrelated_settings_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = fixture_db(dir.path(), &[FIXTURE]);
+        let before = read_settings(&path).unwrap();
+        let shooter_before = load_profiles(&path).unwrap()[1].clone();
+
+        let mut desktop = load_profile(&path, Some("Desktop")).unwrap();
+        desktop.dpi = 3200;
+        desktop.polling_rate = PollingRate::Hz1000;
+        desktop.buttons[1] = ButtonAction::Back;
+        let backup = save_profile(&path, &desktop, None).unwrap();
+
+        assert_eq!(row_count(&path), 2);
+        let after = read_settings(&path).unwrap();
+        for key in ["version", "applications", "settings", "backlight_settings"] {
+            assert_eq!(after[key], before[key], "{key} changed");
+        }
+        assert_eq!(load_profile(&path, Some("Desktop")).unwrap().dpi, 3200);
+        let desktop_after = &load_profiles(&path).unwrap()[0];
+        assert_eq!(desktop_after.dpi_stages, [800, 1600, 3200]);
+        assert_eq!(desktop_after.default_dpi_stage, Some(2));
+        assert_eq!(desktop_after.report_rate, Some(1000));
+        assert!(desktop_after.buttons.contains(&(1, ButtonAction::Back)));
+        assert_eq!(load_profiles(&path).unwrap()[1], shooter_before);
+
+        // The backup holds the database as it was.
+        assert!(backup
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("settings.db."));
+        assert_eq!(read_settings(&backup).unwrap(), before);
+    }
+
+    #[test]
+    fn shared_cards_and_unsupported_assignments_survive() {
+        let dir = tempfile::tempdir().unwrap();
+        // Both profiles share one settings card.
+        let doc = FIXTURE.replace(
+            r#""slotId": "g502_lightspeed_settings", "cardId": "card-shooter-settings""#,
+            r#""slotId": "g502_lightspeed_settings", "cardId": "card-desktop-settings""#,
+        );
+        let path = fixture_db(dir.path(), &[&doc]);
+
+        let mut shooter = load_profile(&path, Some("Shooter")).unwrap();
+        shooter.dpi = 400;
+        save_profile(&path, &shooter, Some("g502_lightspeed")).unwrap();
+
+        let profiles = load_profiles(&path).unwrap();
+        assert_eq!(profiles[0].default_dpi(), Some(1600), "desktop card edited");
+        assert_eq!(profiles[1].default_dpi(), Some(400));
+        // The grenade macro on button 6 was not changed, so it stays.
+        assert!(profiles[1]
+            .skipped
+            .contains(&"g502_lightspeed_c6".to_string()));
+    }
+
+    #[test]
+    fn save_creates_missing_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = fixture_db(dir.path(), &[FIXTURE]);
+        let profile = Profile {
+            name: "Open G Hub".into(),
+            dpi: 2400,
+            ..Default::default()
+        };
+        save_profile(&path, &profile, None).unwrap();
+
+        let created = load_profiles(&path)
+            .unwrap()
+            .into_iter()
+            .find(|p| p.name == "Open G Hub")
+            .unwrap();
+        assert_eq!(created.device, "g502_lightspeed");
+        assert_eq!(created.application.as_deref(), Some("Desktop"));
+        assert!(!created.active);
+        let loaded = created.to_profile().unwrap();
+        assert_eq!(loaded.dpi, 2400);
+        assert_eq!(loaded.buttons, profile.buttons);
+    }
+
+    #[test]
+    fn unknown_schema_versions_are_refused_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        for doc in [
+            FIXTURE.replace("\"version\": 9", "\"version\": 99"),
+            FIXTURE.replace("\"version\": 9,", ""),
+        ] {
+            let path = fixture_db(dir.path(), &[&doc]);
+            let err = save_profile(&path, &Profile::default(), None).unwrap_err();
+            assert!(err.to_string().contains("refusing to write"), "{err}");
+            assert_eq!(row_count(&path), 1);
+            std::fs::remove_file(&path).unwrap();
+        }
+        let leftovers = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(leftovers, 0, "no backup for a refused write");
+    }
+
+    #[test]
+    fn button_names_round_trip() {
+        for &action in ButtonAction::ALL {
+            assert_eq!(button_from_name(button_name(action)), Some(action));
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Real HID transport backed by hidapi.
+//!
+//! A HID++ device sends notifications (button events, connection changes) on
+//! the same pipe as request responses. `send_report` only returns the report
+//! that answers its request. Anything else read while waiting is queued and
+//! handed out by `read_report`, so notifications are never dropped.
+//!
+//! hidapi failures are sorted into [`Error`] variants here, by OS error,
+//! so callers can tell a disconnect or a permission problem from other
+//! failures without looking at messages (see [`hid_error`]).
+
+use crate::device::{self, DeviceInfo};
+use crate::error::{Error, Result};
+use crate::transport::{FeatureTable, HidTransport};
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::trace;
+
+/// How long to wait for the response to a request.
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// A HID++ interface opened through hidapi.
+pub struct HidapiTransport {
+    device: Mutex<hidapi::HidDevice>,
+    pending: Mutex<VecDeque<Vec<u8>>>,
+    info: DeviceInfo,
+    features: FeatureTable,
+}
+
+impl HidapiTransport {
+    /// Open a discovered device.
+    pub fn open(info: &DeviceInfo) -> Result<Self> {
+        let api = hidapi::HidApi::new().map_err(|e| hid_error("init", e))?;
+        let device = api
+            .open(info.vid, info.pid)
+            .map_err(|e| hid_error("open", e))?;
+
+        Ok(Self {
+            device: Mutex::new(device),
+            pending: Mutex::new(VecDeque::new()),
+            info: info.clone(),
+            features: FeatureTable::new(),
+        })
+    }
+
+    /// Open the first supported mouse found by [`device::discover_devices`].
+    pub fn open_first_supported() -> Result<Self> {
+        let devices = device::discover_devices()?;
+        let first = devices.first().ok_or_else(|| {
+            Error::DeviceNotFound("no supported Logitech G device found".to_string())
+        })?;
+        Self::open(first)
+    }
+
+    /// The device this transport was opened for.
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+}
+
+/// Sort a hidapi failure during `op` into the variant callers act on.
+///
+/// The native backend reports OS errors as `io::Error`s: missing permission,
+/// a timeout, or a node that is gone (`ENODEV`, and `EIO` from hidraw after
+/// an unplug). Everything else is [`Error::Hid`].
+pub(crate) fn hid_error(op: &'static str, e: hidapi::HidError) -> Error {
+    let message = e.to_string();
+    let hidapi::HidError::IoError { error } = &e else {
+        return Error::Hid { op, message };
+    };
+    match error.kind() {
+        ErrorKind::PermissionDenied => Error::PermissionDenied(format!("{op}: {message}")),
+        ErrorKind::TimedOut => Error::Timeout(format!("{op}: {message}")),
+        ErrorKind::NotFound | ErrorKind::BrokenPipe | ErrorKind::NotConnected => {
+            Error::Disconnected { op, message }
+        }
+        _ if is_gone(error) => Error::Disconnected { op, message },
+        _ => Error::Hid { op, message },
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn is_gone(error: &std::io::Error) -> bool {
+    matches!(
+        error.raw_os_error(),
+        Some(libc::ENODEV | libc::EIO | libc::ESHUTDOWN)
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_gone(_error: &std::io::Error) -> bool {
+    false
+}
+
+/// Whether `report` answers `request` (a matching reply or a HID++ error for it).
+fn is_response_to(request: &[u8], report: &[u8]) -> bool {
+    if report.len() < 5 || request.len() < 4 || report[1] != request[1] {
+        return false;
+    }
+    match report[2] {
+        // HID++ 2.0 error: [0xFF, feature index, function/sw, code]
+        0xFF => report[3] == request[2] && report[4] == request[3],
+        // HID++ 1.0 error: [0x8F, sub id, address, code]
+        0x8F => report[3] == request[2],
+        feature_index => feature_index == request[2] && report[3] == request[3],
+    }
+}
+
+impl HidTransport for HidapiTransport {
+    fn send_report(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let device = self.device.lock().unwrap();
+        device.write(data).map_err(|e| hid_error("write", e))?;
+
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+        let mut buf = [0u8; 64];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::Timeout(format!(
+                    "hid_read timed out after {}ms",
+                    RESPONSE_TIMEOUT.as_millis()
+                )));
+            }
+            let n = device
+                .read_timeout(&mut buf, remaining.as_millis() as i32)
+                .map_err(|e| hid_error("read", e))?;
+            if n == 0 {
+                continue;
+            }
+            let report = buf[..n].to_vec();
+            if is_response_to(data, &report) {
+                return Ok(report);
+            }
+            trace!(
+                report_hex = format_args!("{:02X?}", report),
+                "Queued notification"
+            );
+            self.pending.lock().unwrap().push_back(report);
+        }
+    }
+
+    fn read_report(&self, timeout_ms: i32) -> Result<Option<Vec<u8>>> {
+        if let Some(report) = self.pending.lock().unwrap().pop_front() {
+            return Ok(Some(report));
+        }
+
+        let device = self.device.lock().unwrap();
+        let mut buf = [0u8; 64];
+        let n = device
+            .read_timeout(&mut buf, timeout_ms)
+            .map_err(|e| hid_error("read", e))?;
+        Ok((n > 0).then(|| buf[..n].to_vec()))
+    }
+
+    fn feature_table(&self) -> &FeatureTable {
+        &self.features
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_reply_is_a_response() {
+        let request = [0x10, 0x01, 0x07, 0x11, 0x00, 0x00, 0x00];
+        let reply = [0x11, 0x01, 0x07, 0x11, 0x03, 0x20, 0x03, 0x20];
+        assert!(is_response_to(&request, &reply));
+    }
+
+    #[test]
+    fn hidpp_errors_for_the_request_are_responses() {
+        let request = [0x10, 0x01, 0x07, 0x21, 0x00, 0x06, 0x40];
+        assert!(is_response_to(
+            &request,
+            &[0x10, 0x01, 0xFF, 0x07, 0x21, 0x02, 0x00]
+        ));
+        assert!(is_response_to(
+            &request,
+            &[0x10, 0x01, 0x8F, 0x07, 0x21, 0x09, 0x00]
+        ));
+    }
+
+    #[test]
+    fn os_errors_become_typed_variants() {
+        let io = |error: std::io::Error| hid_error("read", hidapi::HidError::IoError { error });
+        assert!(matches!(
+            io(std::io::Error::from(ErrorKind::PermissionDenied)),
+            Error::PermissionDenied(_)
+        ));
+        assert!(matches!(
+            io(std::io::Error::from(ErrorKind::TimedOut)),
+            Error::Timeout(_)
+        ));
+        #[cfg(target_os = "linux")]
+        assert!(matches!(
+            io(std::io::Error::from_raw_os_error(libc::ENODEV)),
+            Error::Disconnected { op: "read", .. }
+        ));
+        assert!(matches!(
+            hid_error("init", hidapi::HidError::InitializationError),
+            Error::Hid { op: "init", .. }
+        ));
+    }
+
+    #[test]
+    fn notifications_are_not_responses() {
+        let request = [0x10, 0x01, 0x07, 0x11, 0x00, 0x00, 0x00];
+        // Diverted button event on another feature.
+        assert!(!is_response_to(
+            &request,
+            &[0x11, 0x01, 0x09, 0x00, 0x00, 0x53, 0x00, 0x00]
+        ));
+        // Receiver connection notification.
+        assert!(!is_response_to(
+            &request,
+            &[0x10, 0x01, 0x41, 0x04, 0x02, 0x8D, 0xC0]
+        ));
+        // Same feature, different device index.
+        assert!(!is_response_to(
+            &request,
+            &[0x11, 0x02, 0x07, 0x11, 0x03, 0x20, 0x03, 0x20]
+        ));
+    }
+}
@@ -4,9 +4,15 @@
 //! - Short reports: 7 bytes (report ID 0x10)
 //! - Long reports: 20 bytes (report ID 0x11)
 //!
+//! A rejected request is answered with an error report in place of the
+//! feature index: 0xFF from HID++ 2.0 devices, 0x8F from receivers and
+//! HID++ 1.0 devices. [`HidppResponse::error`] decodes their codes into
+//! [`HidppError`].
+//!
 //! Protocol reference: libratbag (MIT) and Solaar (GPLv2, protocol knowledge only).
 
 use crate::error::{Error, Result};
+use std::fmt;
 
 /// HID++ report ID for short messages (7 bytes total).
 pub const SHORT_REPORT_ID: u8 = 0x10;
@@ -24,18 +30,266 @@ pub mod features {
     pub const ROOT: u16 = 0x0000;
     /// Feature set — enumerate all supported features.
     pub const FEATURE_SET: u16 = 0x0001;
+    /// Device information — unit ID, model ID, firmware entity count.
+    pub const DEVICE_INFORMATION: u16 = 0x0003;
     /// Device name and type.
     pub const DEVICE_NAME: u16 = 0x0005;
     /// Adjustable DPI setting.
     pub const ADJUSTABLE_DPI: u16 = 0x2201;
     /// USB report rate (polling rate).
     pub const REPORT_RATE: u16 = 0x8060;
+    /// RGB LED zone effects.
+    pub const COLOR_LED_EFFECTS: u16 = 0x8070;
     /// Programmable button remapping.
     pub const REPROG_CONTROLS_V4: u16 = 0x1B04;
     /// Onboard profiles.
     pub const ONBOARD_PROFILES: u16 = 0x8100;
     /// Battery status.
     pub const BATTERY_STATUS: u16 = 0x1000;
+    /// Wireless device status (reconnection notifications).
+    pub const WIRELESS_DEVICE_STATUS: u16 = 0x1D4B;
+}
+
+/// Feature index byte of a HID++ 2.0 error report.
+pub const ERROR_REPORT_20: u8 = 0xFF;
+/// Sub ID byte of a HID++ 1.0 error report.
+pub const ERROR_REPORT_10: u8 = 0x8F;
+
+/// Error codes of HID++ 2.0 error reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hidpp20Error {
+    NoError,
+    Unknown,
+    InvalidArgument,
+    OutOfRange,
+    HwError,
+    LogitechInternal,
+    InvalidFeatureIndex,
+    InvalidFunctionId,
+    Busy,
+    Unsupported,
+    /// A code the specification does not define.
+    Other(u8),
+}
+
+impl Hidpp20Error {
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x00 => Self::NoError,
+            0x01 => Self::Unknown,
+            0x02 => Self::InvalidArgument,
+            0x03 => Self::OutOfRange,
+            0x04 => Self::HwError,
+            0x05 => Self::LogitechInternal,
+            0x06 => Self::InvalidFeatureIndex,
+            0x07 => Self::InvalidFunctionId,
+            0x08 => Self::Busy,
+            0x09 => Self::Unsupported,
+            other => Self::Other(other),
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            Self::NoError => 0x00,
+            Self::Unknown => 0x01,
+            Self::InvalidArgument => 0x02,
+            Self::OutOfRange => 0x03,
+            Self::HwError => 0x04,
+            Self::LogitechInternal => 0x05,
+            Self::InvalidFeatureIndex => 0x06,
+            Self::InvalidFunctionId => 0x07,
+            Self::Busy => 0x08,
+            Self::Unsupported => 0x09,
+            Self::Other(code) => code,
+        }
+    }
+}
+
+/// Error codes of HID++ 1.0 error reports, sent by receivers and older
+/// devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hidpp10Error {
+    Success,
+    InvalidSubId,
+    InvalidAddress,
+    InvalidValue,
+    ConnectFail,
+    TooManyDevices,
+    AlreadyExists,
+    Busy,
+    UnknownDevice,
+    ResourceError,
+    RequestUnavailable,
+    InvalidParamValue,
+    WrongPinCode,
+    /// A code the specification does not define.
+    Other(u8),
+}
+
+impl Hidpp10Error {
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x00 => Self::Success,
+            0x01 => Self::InvalidSubId,
+            0x02 => Self::InvalidAddress,
+            0x03 => Self::InvalidValue,
+            0x04 => Self::ConnectFail,
+            0x05 => Self::TooManyDevices,
+            0x06 => Self::AlreadyExists,
+            0x07 => Self::Busy,
+            0x08 => Self::UnknownDevice,
+            0x09 => Self::ResourceError,
+            0x0A => Self::RequestUnavailable,
+            0x0B => Self::InvalidParamValue,
+            0x0C => Self::WrongPinCode,
+            other => Self::Other(other),
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            Self::Success => 0x00,
+            Self::InvalidSubId => 0x01,
+            Self::InvalidAddress => 0x02,
+            Self::InvalidValue => 0x03,
+            Self::ConnectFail => 0x04,
+            Self::TooManyDevices => 0x05,
+            Self::AlreadyExists => 0x06,
+            Self::Busy => 0x07,
+            Self::UnknownDevice => 0x08,
+            Self::ResourceError => 0x09,
+            Self::RequestUnavailable => 0x0A,
+            Self::InvalidParamValue => 0x0B,
+            Self::WrongPinCode => 0x0C,
+            Self::Other(code) => code,
+        }
+    }
+}
+
+/// The error code of a HID++ error report, by protocol version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HidppError {
+    V20(Hidpp20Error),
+    V10(Hidpp10Error),
+}
+
+impl HidppError {
+    /// Rebuild an error from its protocol version ("2.0" or "1.0") and code
+    /// byte, as [`version`](Self::version) and [`code`](Self::code) give them.
+    pub fn from_version(version: &str, code: u8) -> Option<Self> {
+        match version {
+            "2.0" => Some(Self::V20(Hidpp20Error::from_code(code))),
+            "1.0" => Some(Self::V10(Hidpp10Error::from_code(code))),
+            _ => None,
+        }
+    }
+
+    /// The HID++ version of the report: "2.0" or "1.0".
+    pub fn version(self) -> &'static str {
+        match self {
+            Self::V20(_) => "2.0",
+            Self::V10(_) => "1.0",
+        }
+    }
+
+    /// The raw code byte.
+    pub fn code(self) -> u8 {
+        match self {
+            Self::V20(e) => e.code(),
+            Self::V10(e) => e.code(),
+        }
+    }
+
+    /// Stable snake_case name, for logs and JSON-RPC error data.
+    pub fn name(self) -> &'static str {
+        use Hidpp10Error as V10;
+        use Hidpp20Error as V20;
+        match self {
+            Self::V20(V20::NoError) | Self::V10(V10::Success) => "no_error",
+            Self::V20(V20::Unknown) => "unknown",
+            Self::V20(V20::InvalidArgument) => "invalid_argument",
+            Self::V20(V20::OutOfRange) => "out_of_range",
+            Self::V20(V20::HwError) => "hw_error",
+            Self::V20(V20::LogitechInternal) => "logitech_internal",
+            Self::V20(V20::InvalidFeatureIndex) => "invalid_feature_index",
+            Self::V20(V20::InvalidFunctionId) => "invalid_function_id",
+            Self::V20(V20::Busy) | Self::V10(V10::Busy) => "busy",
+            Self::V20(V20::Unsupported) => "unsupported",
+            Self::V10(V10::InvalidSubId) => "invalid_sub_id",
+            Self::V10(V10::InvalidAddress) => "invalid_address",
+            Self::V10(V10::InvalidValue) => "invalid_value",
+            Self::V10(V10::ConnectFail) => "connect_fail",
+            Self::V10(V10::TooManyDevices) => "too_many_devices",
+            Self::V10(V10::AlreadyExists) => "already_exists",
+            Self::V10(V10::UnknownDevice) => "unknown_device",
+            Self::V10(V10::ResourceError) => "resource_error",
+            Self::V10(V10::RequestUnavailable) => "request_unavailable",
+            Self::V10(V10::InvalidParamValue) => "invalid_param_value",
+            Self::V10(V10::WrongPinCode) => "wrong_pin_code",
+            Self::V20(V20::Other(_)) | Self::V10(V10::Other(_)) => "other",
+        }
+    }
+
+    /// The device is busy with something else; the same request may
+    /// succeed shortly.
+    pub fn is_busy(self) -> bool {
+        matches!(
+            self,
+            Self::V20(Hidpp20Error::Busy) | Self::V10(Hidpp10Error::Busy)
+        )
+    }
+
+    /// The receiver cannot reach the device at that index: asleep,
+    /// switched off, or not paired.
+    pub fn is_unreachable(self) -> bool {
+        matches!(
+            self,
+            Self::V10(
+                Hidpp10Error::ResourceError
+                    | Hidpp10Error::UnknownDevice
+                    | Hidpp10Error::ConnectFail
+            )
+        )
+    }
+
+    /// What the user can do about it.
+    pub fn hint(self) -> &'static str {
+        use Hidpp10Error as V10;
+        use Hidpp20Error as V20;
+        match self {
+            Self::V20(V20::Busy) | Self::V10(V10::Busy) => {
+                "the mouse is busy; try again in a moment"
+            }
+            Self::V20(V20::InvalidArgument | V20::OutOfRange)
+            | Self::V10(V10::InvalidValue | V10::InvalidParamValue) => {
+                "the mouse rejected the value; check it is supported by this model"
+            }
+            Self::V20(V20::Unsupported | V20::InvalidFeatureIndex | V20::InvalidFunctionId)
+            | Self::V10(V10::InvalidSubId | V10::InvalidAddress | V10::RequestUnavailable) => {
+                "this mouse or firmware does not support the request"
+            }
+            Self::V20(V20::HwError) => {
+                "the mouse reported a hardware error; replug it or power-cycle it"
+            }
+            Self::V10(V10::ResourceError | V10::UnknownDevice | V10::ConnectFail) => {
+                "the receiver cannot reach the mouse; wake it or switch it on"
+            }
+            _ => "unexpected device error; replug the mouse and try again",
+        }
+    }
+}
+
+impl fmt::Display for HidppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (HID++ {} code 0x{:02X})",
+            self.name(),
+            self.version(),
+            self.code()
+        )
+    }
 }
 
 /// A HID++ 2.0 request message.
@@ -92,10 +346,9 @@ impl HidppRequest {
             }
             Ok(buf)
         } else {
-            Err(Error::HidppProtocol {
-                feature: 0,
-                code: 0xFF,
-            })
+            Err(Error::MalformedReport(format!(
+                "request has {param_len} parameter bytes, at most 16 fit"
+            )))
         }
     }
 }
@@ -119,7 +372,7 @@ impl HidppResponse {
     /// Decode a raw HID report into a structured response.
     pub fn decode(data: &[u8]) -> Result<Self> {
         if data.len() < SHORT_REPORT_LEN {
-            return Err(Error::Hid(format!(
+            return Err(Error::MalformedReport(format!(
                 "response too short: {} bytes (minimum {})",
                 data.len(),
                 SHORT_REPORT_LEN
@@ -131,12 +384,14 @@ impl HidppResponse {
             SHORT_REPORT_ID => (false, SHORT_REPORT_LEN),
             LONG_REPORT_ID => (true, LONG_REPORT_LEN),
             other => {
-                return Err(Error::Hid(format!("unknown report ID: 0x{other:02X}")));
+                return Err(Error::MalformedReport(format!(
+                    "unknown report ID: 0x{other:02X}"
+                )));
             }
         };
 
         if data.len() < expected_len {
-            return Err(Error::Hid(format!(
+            return Err(Error::MalformedReport(format!(
                 "incomplete report: got {} bytes, expected {}",
                 data.len(),
                 expected_len
@@ -160,9 +415,26 @@ impl HidppResponse {
     }
 
     /// Check if this response is an error report.
-    /// HID++ 2.0 errors have feature_index == 0xFF.
+    /// HID++ 2.0 errors have feature_index == 0xFF, HID++ 1.0 errors 0x8F.
     pub fn is_error(&self) -> bool {
-        self.feature_index == 0xFF
+        matches!(self.feature_index, ERROR_REPORT_20 | ERROR_REPORT_10)
+    }
+
+    /// Decode an error report: the feature index (or sub ID) and function
+    /// of the rejected request, and the error code.
+    ///
+    /// Layout: `[0xFF, feature index, function/sw, code]` for HID++ 2.0,
+    /// `[0x8F, sub ID, address, code]` for HID++ 1.0.
+    pub fn error(&self) -> Option<(u8, u8, HidppError)> {
+        // Decoded as a reply, the rejected feature index sits in
+        // `function_sw` and the rejected function byte in `params[0]`.
+        let code = *self.params.get(1)?;
+        let code = match self.feature_index {
+            ERROR_REPORT_20 => HidppError::V20(Hidpp20Error::from_code(code)),
+            ERROR_REPORT_10 => HidppError::V10(Hidpp10Error::from_code(code)),
+            _ => return None,
+        };
+        Some((self.function_sw, self.params[0] >> 4, code))
     }
 }
 
@@ -242,6 +514,31 @@ mod tests {
         let data = [SHORT_REPORT_ID, 0x01, 0xFF, 0x01, 0x05, 0x02, 0x00];
         let resp = HidppResponse::decode(&data).unwrap();
         assert!(resp.is_error());
+        assert_eq!(
+            resp.error(),
+            Some((0x01, 0x00, HidppError::V20(Hidpp20Error::InvalidArgument)))
+        );
+    }
+
+    #[test]
+    fn error_codes_decode_by_protocol_version() {
+        let busy = [SHORT_REPORT_ID, 0x01, 0xFF, 0x07, 0x21, 0x08, 0x00];
+        let (_, function, code) = HidppResponse::decode(&busy).unwrap().error().unwrap();
+        assert_eq!(function, 0x02);
+        assert!(code.is_busy() && !code.is_unreachable());
+        assert_eq!(code.to_string(), "busy (HID++ 2.0 code 0x08)");
+
+        // Receiver: no mouse reachable at index 1.
+        let asleep = [SHORT_REPORT_ID, 0x01, 0x8F, 0x00, 0x01, 0x09, 0x00];
+        let (_, _, code) = HidppResponse::decode(&asleep).unwrap().error().unwrap();
+        assert_eq!(code, HidppError::V10(Hidpp10Error::ResourceError));
+        assert!(code.is_unreachable());
+
+        for byte in 0..=0x0F {
+            assert_eq!(Hidpp20Error::from_code(byte).code(), byte);
+            assert_eq!(Hidpp10Error::from_code(byte).code(), byte);
+        }
+        assert_eq!(HidppError::V20(Hidpp20Error::Other(0x42)).name(), "other");
     }
 
     #[test]
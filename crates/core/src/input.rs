@@ -0,0 +1,390 @@
+//! Host input synthesis: keystrokes, mouse buttons, and pointer motion.
+//!
+//! Host-side features (scripts, macros) describe what to type or click as
+//! [`InputEvent`]s and hand them to an [`InputBackend`]. Keys are identified by
+//! Linux evdev key codes. For the base key block these equal PC/AT set-1
+//! scancodes, which is what G Hub and LGS scripts pass to `PressKey`.
+
+use crate::error::Result;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Mouse buttons that can be synthesized on the host.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+}
+
+impl MouseButton {
+    /// Linux evdev button code (BTN_LEFT and friends).
+    pub fn evdev_code(&self) -> u16 {
+        match self {
+            Self::Left => 0x110,
+            Self::Right => 0x111,
+            Self::Middle => 0x112,
+            Self::Back => 0x113,
+            Self::Forward => 0x114,
+        }
+    }
+
+    /// Look up a button from its evdev code.
+    pub fn from_evdev_code(code: u16) -> Option<Self> {
+        match code {
+            0x110 => Some(Self::Left),
+            0x111 => Some(Self::Right),
+            0x112 => Some(Self::Middle),
+            0x113 => Some(Self::Back),
+            0x114 => Some(Self::Forward),
+            _ => None,
+        }
+    }
+}
+
+/// A single synthesized input action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    /// Key pressed (evdev key code).
+    KeyDown(u16),
+    /// Key released (evdev key code).
+    KeyUp(u16),
+    /// Mouse button pressed.
+    ButtonDown(MouseButton),
+    /// Mouse button released.
+    ButtonUp(MouseButton),
+    /// Relative pointer motion in device units.
+    MoveRelative { dx: i32, dy: i32 },
+    /// Absolute pointer position, normalized to 0..=65535 on each axis.
+    MoveAbsolute { x: i32, y: i32 },
+    /// Vertical wheel clicks (positive = away from the user).
+    Wheel(i32),
+}
+
+/// Destination for synthesized input.
+pub trait InputBackend: Send {
+    /// Emit one input event.
+    fn emit(&mut self, event: InputEvent) -> Result<()>;
+
+    /// Wait between events. Headless backends may record instead of sleeping.
+    fn delay(&mut self, ms: u32) {
+        std::thread::sleep(Duration::from_millis(ms as u64));
+    }
+}
+
+/// An entry captured by [`RecordingBackend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recorded {
+    /// An emitted input event.
+    Event(InputEvent),
+    /// A requested delay in milliseconds (not actually slept).
+    Delay(u32),
+}
+
+/// Backend that records events instead of injecting them.
+///
+/// Clones share the same log, so a test can keep one handle and give the other
+/// to a script engine or macro player.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingBackend {
+    log: Arc<Mutex<Vec<Recorded>>>,
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything recorded so far, in order.
+    pub fn recorded(&self) -> Vec<Recorded> {
+        self.log.lock().unwrap().clone()
+    }
+
+    /// Only the input events, without delays.
+    pub fn events(&self) -> Vec<InputEvent> {
+        self.recorded()
+            .into_iter()
+            .filter_map(|r| match r {
+                Recorded::Event(e) => Some(e),
+                Recorded::Delay(_) => None,
+            })
+            .collect()
+    }
+
+    /// Forget everything recorded so far.
+    pub fn clear(&self) {
+        self.log.lock().unwrap().clear();
+    }
+}
+
+impl InputBackend for RecordingBackend {
+    fn emit(&mut self, event: InputEvent) -> Result<()> {
+        self.log.lock().unwrap().push(Recorded::Event(event));
+        Ok(())
+    }
+
+    fn delay(&mut self, ms: u32) {
+        self.log.lock().unwrap().push(Recorded::Delay(ms));
+    }
+}
+
+/// Key names accepted by G Hub's `PressKey` family, with their evdev codes.
+const KEY_NAMES: &[(&str, u16)] = &[
+    ("escape", 1),
+    ("1", 2),
+    ("2", 3),
+    ("3", 4),
+    ("4", 5),
+    ("5", 6),
+    ("6", 7),
+    ("7", 8),
+    ("8", 9),
+    ("9", 10),
+    ("0", 11),
+    ("minus", 12),
+    ("equal", 13),
+    ("backspace", 14),
+    ("tab", 15),
+    ("q", 16),
+    ("w", 17),
+    ("e", 18),
+    ("r", 19),
+    ("t", 20),
+    ("y", 21),
+    ("u", 22),
+    ("i", 23),
+    ("o", 24),
+    ("p", 25),
+    ("lbracket", 26),
+    ("rbracket", 27),
+    ("enter", 28),
+    ("lctrl", 29),
+    ("a", 30),
+    ("s", 31),
+    ("d", 32),
+    ("f", 33),
+    ("g", 34),
+    ("h", 35),
+    ("j", 36),
+    ("k", 37),
+    ("l", 38),
+    ("semicolon", 39),
+    ("quote", 40),
+    ("tilde", 41),
+    ("lshift", 42),
+    ("backslash", 43),
+    ("z", 44),
+    ("x", 45),
+    ("c", 46),
+    ("v", 47),
+    ("b", 48),
+    ("n", 49),
+    ("m", 50),
+    ("comma", 51),
+    ("period", 52),
+    ("slash", 53),
+    ("rshift", 54),
+    ("numstar", 55),
+    ("lalt", 56),
+    ("spacebar", 57),
+    ("capslock", 58),
+    ("f1", 59),
+    ("f2", 60),
+    ("f3", 61),
+    ("f4", 62),
+    ("f5", 63),
+    ("f6", 64),
+    ("f7", 65),
+    ("f8", 66),
+    ("f9", 67),
+    ("f10", 68),
+    ("numlock", 69),
+    ("scrolllock", 70),
+    ("num7", 71),
+    ("num8", 72),
+    ("num9", 73),
+    ("numminus", 74),
+    ("num4", 75),
+    ("num5", 76),
+    ("num6", 77),
+    ("numplus", 78),
+    ("num1", 79),
+    ("num2", 80),
+    ("num3", 81),
+    ("num0", 82),
+    ("numperiod", 83),
+    ("non_us_slash", 86),
+    ("f11", 87),
+    ("f12", 88),
+    ("numenter", 96),
+    ("rctrl", 97),
+    ("numslash", 98),
+    ("printscreen", 99),
+    ("ralt", 100),
+    ("home", 102),
+    ("up", 103),
+    ("pageup", 104),
+    ("left", 105),
+    ("right", 106),
+    ("end", 107),
+    ("down", 108),
+    ("pagedown", 109),
+    ("insert", 110),
+    ("delete", 111),
+    ("mute", 113),
+    ("volume_down", 114),
+    ("volume_up", 115),
+    ("pause", 119),
+    ("lgui", 125),
+    ("rgui", 126),
+    ("appkey", 127),
+    ("media_next", 163),
+    ("media_play_pause", 164),
+    ("media_prev", 165),
+    ("media_stop", 166),
+    ("f13", 183),
+    ("f14", 184),
+    ("f15", 185),
+    ("f16", 186),
+    ("f17", 187),
+    ("f18", 188),
+    ("f19", 189),
+    ("f20", 190),
+    ("f21", 191),
+    ("f22", 192),
+    ("f23", 193),
+    ("f24", 194),
+];
+
+/// Extended (0xE0-prefixed) set-1 scancodes as written by G Hub/LGS (`0x100 | code`).
+const EXTENDED_SCANCODES: &[(u16, u16)] = &[
+    (0x11C, 96),  // numenter
+    (0x11D, 97),  // rctrl
+    (0x135, 98),  // numslash
+    (0x137, 99),  // printscreen
+    (0x138, 100), // ralt
+    (0x147, 102), // home
+    (0x148, 103), // up
+    (0x149, 104), // pageup
+    (0x14B, 105), // left
+    (0x14D, 106), // right
+    (0x14F, 107), // end
+    (0x150, 108), // down
+    (0x151, 109), // pagedown
+    (0x152, 110), // insert
+    (0x153, 111), // delete
+    (0x15B, 125), // lgui
+    (0x15C, 126), // rgui
+    (0x15D, 127), // appkey
+];
+
+/// Look up a key by its G Hub name (case-insensitive).
+pub fn key_from_name(name: &str) -> Option<u16> {
+    let lower = name.to_lowercase();
+    let lookup = match lower.as_str() {
+        "esc" => "escape",
+        "space" => "spacebar",
+        "return" => "enter",
+        "grave" => "tilde",
+        "ctrl" => "lctrl",
+        "shift" => "lshift",
+        "alt" => "lalt",
+        "win" | "gui" => "lgui",
+        other => other,
+    };
+    KEY_NAMES
+        .iter()
+        .find(|(n, _)| *n == lookup)
+        .map(|&(_, code)| code)
+}
+
+/// Name of a key code, if it has one in the G Hub key table.
+pub fn key_name(code: u16) -> Option<&'static str> {
+    KEY_NAMES.iter().find(|(_, c)| *c == code).map(|&(n, _)| n)
+}
+
+/// Convert a G Hub/LGS scancode into an evdev key code.
+pub fn key_from_scancode(scancode: u16) -> Option<u16> {
+    match scancode {
+        0x01..=0x58 => Some(scancode),
+        _ => EXTENDED_SCANCODES
+            .iter()
+            .find(|(sc, _)| *sc == scancode)
+            .map(|&(_, code)| code),
+    }
+}
+
+/// Every key code in the name table (used to register uinput key bits).
+pub fn all_key_codes() -> impl Iterator<Item = u16> {
+    KEY_NAMES.iter().map(|&(_, code)| code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_names_resolve_to_evdev_codes() {
+        assert_eq!(key_from_name("a"), Some(30));
+        assert_eq!(key_from_name("LShift"), Some(42));
+        assert_eq!(key_from_name("spacebar"), Some(57));
+        assert_eq!(key_from_name("space"), Some(57));
+        assert_eq!(key_from_name("Ctrl"), Some(29));
+        assert_eq!(key_from_name("f24"), Some(194));
+        assert_eq!(key_from_name("nope"), None);
+    }
+
+    #[test]
+    fn key_name_reverse_lookup() {
+        assert_eq!(key_name(30), Some("a"));
+        assert_eq!(key_name(0), None);
+    }
+
+    #[test]
+    fn scancodes_map_base_and_extended_keys() {
+        assert_eq!(key_from_scancode(0x1E), Some(30)); // a
+        assert_eq!(key_from_scancode(0x11D), Some(97)); // rctrl
+        assert_eq!(key_from_scancode(0x148), Some(103)); // up
+        assert_eq!(key_from_scancode(0x00), None);
+        assert_eq!(key_from_scancode(0x1FF), None);
+    }
+
+    #[test]
+    fn mouse_button_evdev_roundtrip() {
+        for button in [
+            MouseButton::Left,
+            MouseButton::Right,
+            MouseButton::Middle,
+            MouseButton::Back,
+            MouseButton::Forward,
+        ] {
+            assert_eq!(
+                MouseButton::from_evdev_code(button.evdev_code()),
+                Some(button)
+            );
+        }
+    }
+
+    #[test]
+    fn recording_backend_shares_log_between_clones() {
+        let recorder = RecordingBackend::new();
+        let mut handle = recorder.clone();
+        handle.emit(InputEvent::KeyDown(30)).unwrap();
+        handle.delay(25);
+        handle.emit(InputEvent::KeyUp(30)).unwrap();
+
+        assert_eq!(
+            recorder.recorded(),
+            vec![
+                Recorded::Event(InputEvent::KeyDown(30)),
+                Recorded::Delay(25),
+                Recorded::Event(InputEvent::KeyUp(30)),
+            ]
+        );
+        assert_eq!(recorder.events().len(), 2);
+    }
+}
@@ -166,7 +166,7 @@ mod tests {
         onboard::set_onboard_mode(&mock, DEV_IDX, OnboardMode::Host).unwrap();
 
         // Get current profile
-        mock.on_short_request(DEV_IDX, PROFILE_IDX, 0x21, &[], &[0x00, 0x00, 0x00]);
+        mock.on_short_request(DEV_IDX, PROFILE_IDX, 0x41, &[], &[0x00, 0x00, 0x00]);
         let (page, offset) = onboard::get_current_profile(&mock, DEV_IDX).unwrap();
         assert_eq!(page, 0);
         assert_eq!(offset, 0);
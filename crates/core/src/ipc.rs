@@ -0,0 +1,820 @@
+//! Daemon IPC: JSON-RPC 2.0 over a Unix domain socket.
+//!
+//! `open-g-hubd` owns the HID connections. The GUI and CLI talk to it by
+//! sending one JSON-RPC request per line and reading one response per line.
+//! The message types live here so both ends share them.
+//!
+//! Clients that subscribe to events also receive `event` notifications
+//! (requests without an `id`) between responses. See `docs/JSON-RPC.md`.
+
+use crate::battery::BatteryStatus;
+use crate::comm::ErrorClass;
+use crate::device::{ButtonAction, DeviceInfo, PollingRate};
+use crate::error::{Error, Result};
+use crate::hidpp::HidppError;
+use crate::onboard::OnboardMode;
+use crate::profile::Profile;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+
+/// Environment variable overriding the socket path.
+pub const SOCKET_ENV: &str = "OPEN_G_HUB_SOCKET";
+
+/// JSON-RPC protocol version string.
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// Method name of event notifications.
+pub const EVENT_METHOD: &str = "event";
+
+/// Standard JSON-RPC error codes plus the daemon's device errors.
+///
+/// Device errors get one code per [`ErrorClass`], so clients can decide
+/// whether to retry without parsing messages.
+pub mod codes {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+    /// A device operation failed with an unexpected reply or local error.
+    pub const DEVICE_ERROR: i64 = -32000;
+    /// Timeout or busy device; retrying may succeed.
+    pub const TRANSIENT: i64 = -32001;
+    /// No connected mouse, or it went away mid-request.
+    pub const DISCONNECTED: i64 = -32002;
+    /// The daemon may not open the device.
+    pub const PERMISSION_DENIED: i64 = -32003;
+    /// The device rejected the request with a HID++ error code.
+    pub const PROTOCOL: i64 = -32004;
+}
+
+/// Where the daemon listens.
+///
+/// `$OPEN_G_HUB_SOCKET` if set, else `$XDG_RUNTIME_DIR/open-g-hub.sock`, else
+/// a per-user path in the temp directory.
+pub fn socket_path() -> PathBuf {
+    if let Some(path) = std::env::var_os(SOCKET_ENV) {
+        return PathBuf::from(path);
+    }
+    if let Some(runtime) = std::env::var_os("XDG_RUNTIME_DIR") {
+        return PathBuf::from(runtime).join("open-g-hub.sock");
+    }
+    let user = std::env::var("USER").unwrap_or_else(|_| "default".to_string());
+    std::env::temp_dir().join(format!("open-g-hub-{user}.sock"))
+}
+
+/// A JSON-RPC request (a notification when `id` is absent).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcRequest {
+    pub jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+impl RpcRequest {
+    pub fn new(id: u64, method: &str, params: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id: Some(Value::from(id)),
+            method: method.to_string(),
+            params,
+        }
+    }
+
+    /// A request that expects no reply.
+    pub fn notification(method: &str, params: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id: None,
+            method: method.to_string(),
+            params,
+        }
+    }
+}
+
+/// A JSON-RPC error object.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// Error object for a failed device operation.
+    ///
+    /// The code follows the error's [`ErrorClass`] (range violations are
+    /// invalid params), and `data` carries the class, whether a retry may
+    /// help, and the fields of structured errors.
+    pub fn from_error(err: &Error) -> Self {
+        let class = ErrorClass::classify(err);
+        let code = match (err, class) {
+            (
+                Error::OutOfRange { .. }
+                | Error::FeatureNotAllowed(_)
+                | Error::UnknownFeatureIndex { .. },
+                _,
+            ) => codes::INVALID_PARAMS,
+            (_, ErrorClass::Transient) => codes::TRANSIENT,
+            (_, ErrorClass::Disconnected) => codes::DISCONNECTED,
+            (_, ErrorClass::PermissionDenied) => codes::PERMISSION_DENIED,
+            (_, ErrorClass::Protocol) => codes::PROTOCOL,
+            (_, ErrorClass::InvalidResponse) => codes::DEVICE_ERROR,
+        };
+        let mut data = serde_json::json!({
+            "class": class,
+            "retryable": class.is_retryable(),
+        });
+        match err {
+            Error::OutOfRange {
+                field,
+                value,
+                min,
+                max,
+            } => {
+                data["field"] = Value::from(*field);
+                data["value"] = Value::from(*value);
+                data["min"] = Value::from(*min);
+                data["max"] = Value::from(*max);
+            }
+            Error::HidppProtocol {
+                feature_index,
+                function,
+                code,
+            } => {
+                data["feature_index"] = Value::from(*feature_index);
+                data["function"] = Value::from(*function);
+                data["hidpp_code"] = Value::from(code.code());
+                data["hidpp_error"] = Value::from(code.name());
+                data["hidpp_version"] = Value::from(code.version());
+            }
+            Error::UnsupportedFeature(feature)
+            | Error::FeatureNotAllowed(feature)
+            | Error::MalformedReply { feature } => {
+                data["feature"] = Value::from(*feature);
+            }
+            Error::UnknownFeatureIndex {
+                device_index,
+                feature_index,
+            } => {
+                data["device_index"] = Value::from(*device_index);
+                data["feature_index"] = Value::from(*feature_index);
+            }
+            _ => {}
+        }
+        Self {
+            code,
+            message: err.to_string(),
+            data: Some(data),
+        }
+    }
+
+    /// The core error a client reports for this object.
+    ///
+    /// Structured errors are rebuilt from their `data` fields; the rest map
+    /// by `data.class` (or, without data, by code) to an error of the same
+    /// [`ErrorClass`]. Anything unclassified becomes [`Error::Ipc`].
+    pub fn into_error(self) -> Error {
+        let data = self.data.unwrap_or(Value::Null);
+        let u8_field = |key: &str| data[key].as_u64().and_then(|v| u8::try_from(v).ok());
+        let u16_field = |key: &str| data[key].as_u64().and_then(|v| u16::try_from(v).ok());
+        let u32_field = |key: &str| data[key].as_u64().and_then(|v| u32::try_from(v).ok());
+
+        if let (Some(field), Some(value), Some(min), Some(max)) = (
+            data["field"].as_str(),
+            u32_field("value"),
+            u32_field("min"),
+            u32_field("max"),
+        ) {
+            return Error::OutOfRange {
+                field: range_field(field),
+                value,
+                min,
+                max,
+            };
+        }
+        if let (Some(feature_index), Some(function), Some(code)) = (
+            u8_field("feature_index"),
+            u8_field("function"),
+            data["hidpp_version"]
+                .as_str()
+                .zip(u8_field("hidpp_code"))
+                .and_then(|(version, code)| HidppError::from_version(version, code)),
+        ) {
+            return Error::HidppProtocol {
+                feature_index,
+                function,
+                code,
+            };
+        }
+        if let (Some(device_index), Some(feature_index)) =
+            (u8_field("device_index"), u8_field("feature_index"))
+        {
+            return Error::UnknownFeatureIndex {
+                device_index,
+                feature_index,
+            };
+        }
+        if let Some(feature) = u16_field("feature") {
+            match self.code {
+                codes::INVALID_PARAMS => return Error::FeatureNotAllowed(feature),
+                codes::PROTOCOL => return Error::UnsupportedFeature(feature),
+                codes::DEVICE_ERROR => return Error::MalformedReply { feature },
+                _ => {}
+            }
+        }
+
+        let class = serde_json::from_value(data["class"].clone())
+            .ok()
+            .or(match self.code {
+                codes::TRANSIENT => Some(ErrorClass::Transient),
+                codes::DISCONNECTED => Some(ErrorClass::Disconnected),
+                codes::PERMISSION_DENIED => Some(ErrorClass::PermissionDenied),
+                _ => None,
+            });
+        match class {
+            Some(ErrorClass::Transient) => Error::Timeout(self.message),
+            Some(ErrorClass::Disconnected) => Error::Disconnected {
+                op: "request",
+                message: self.message,
+            },
+            Some(ErrorClass::PermissionDenied) => Error::PermissionDenied(self.message),
+            Some(ErrorClass::Protocol | ErrorClass::InvalidResponse) | None => {
+                Error::Ipc(self.message)
+            }
+        }
+    }
+}
+
+/// Range-checked fields, so a client can name them in [`Error::OutOfRange`].
+const RANGE_FIELDS: &[&str] = &[
+    "button_index",
+    "debounce_ms",
+    "dpi",
+    "function_id",
+    "jitter_percent",
+    "led_brightness",
+    "led_duration_ms",
+    "led_zone",
+    "low_battery",
+    "macro_delay_ms",
+    "macro_steps",
+    "max_attempts",
+    "polling_rate",
+    "power_below",
+    "power_led_brightness",
+];
+
+/// The static name of a range-checked field, or "value" for one this
+/// build does not know.
+fn range_field(name: &str) -> &'static str {
+    RANGE_FIELDS
+        .iter()
+        .find(|field| **field == name)
+        .copied()
+        .unwrap_or("value")
+}
+
+/// A JSON-RPC response: exactly one of `result` and `error` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl RpcResponse {
+    pub fn success(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn failure(id: Value, error: RpcError) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// A device as tracked by the daemon.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceState {
+    pub info: DeviceInfo,
+    /// HID++ device index in use (0xFF wired, 0x01.. paired), once known.
+    pub device_index: Option<u8>,
+    /// Whether the mouse currently answers (a receiver stays present while
+    /// its mouse sleeps).
+    pub connected: bool,
+}
+
+/// Event topics a client can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// A mouse connected, woke up, slept, or was unplugged.
+    Connection,
+    /// A battery level or charging change.
+    Battery,
+    /// Diverted buttons pressed or released.
+    Button,
+    /// The DPI buttons switched stages.
+    Dpi,
+    /// Another profile became the active one.
+    Profile,
+}
+
+impl EventKind {
+    pub const ALL: &'static [EventKind] = &[
+        Self::Connection,
+        Self::Battery,
+        Self::Button,
+        Self::Dpi,
+        Self::Profile,
+    ];
+}
+
+/// Params of an `event` notification. `device` is the HID path from
+/// [`DeviceInfo::path`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DaemonEvent {
+    Connection {
+        device: String,
+        connected: bool,
+    },
+    Battery {
+        device: String,
+        battery: BatteryStatus,
+    },
+    Button {
+        device: String,
+        /// CIDs held down; empty when all diverted buttons are released.
+        pressed: Vec<u16>,
+    },
+    Dpi {
+        device: String,
+        /// Onboard DPI stage index.
+        stage: u8,
+        /// DPI read back after the change, when the mouse answered.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        dpi: Option<u16>,
+    },
+    /// Not tied to a device: the active profile applies to every mouse.
+    Profile {
+        name: String,
+    },
+}
+
+impl DaemonEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::Connection { .. } => EventKind::Connection,
+            Self::Battery { .. } => EventKind::Battery,
+            Self::Button { .. } => EventKind::Button,
+            Self::Dpi { .. } => EventKind::Dpi,
+            Self::Profile { .. } => EventKind::Profile,
+        }
+    }
+}
+
+/// Reply to the `status` method.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub devices: Vec<DeviceState>,
+    /// Name of the profile re-applied on reconnect, if any.
+    pub active_profile: Option<String>,
+}
+
+#[cfg(unix)]
+pub use client::DaemonClient;
+
+#[cfg(unix)]
+mod client {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::io::{BufRead, BufReader, ErrorKind, Write};
+    use std::os::unix::net::UnixStream;
+    use std::path::Path;
+    use std::time::Duration;
+
+    /// Replies can take a while when the daemon writes a whole profile.
+    const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// A connection to a running `open-g-hubd`.
+    pub struct DaemonClient {
+        reader: BufReader<UnixStream>,
+        writer: UnixStream,
+        next_id: u64,
+        /// Events that arrived while waiting for a response.
+        events: VecDeque<DaemonEvent>,
+    }
+
+    impl DaemonClient {
+        /// Connect to the daemon at `path`.
+        pub fn connect(path: &Path) -> Result<Self> {
+            let stream = UnixStream::connect(path)
+                .map_err(|e| Error::Ipc(format!("connect {}: {e}", path.display())))?;
+            stream
+                .set_read_timeout(Some(CALL_TIMEOUT))
+                .map_err(|e| Error::Ipc(e.to_string()))?;
+            let writer = stream.try_clone().map_err(|e| Error::Ipc(e.to_string()))?;
+            Ok(Self {
+                reader: BufReader::new(stream),
+                writer,
+                next_id: 1,
+                events: VecDeque::new(),
+            })
+        }
+
+        /// Connect to the daemon at [`socket_path`].
+        pub fn connect_default() -> Result<Self> {
+            Self::connect(&socket_path())
+        }
+
+        /// Call `method` and return its result.
+        pub fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+            let id = self.next_id;
+            self.next_id += 1;
+
+            let mut line = serde_json::to_string(&RpcRequest::new(id, method, params))
+                .map_err(|e| Error::Ipc(e.to_string()))?;
+            line.push('\n');
+            self.writer
+                .write_all(line.as_bytes())
+                .map_err(|e| Error::Ipc(format!("send: {e}")))?;
+
+            let response = loop {
+                match self.read_message()? {
+                    Message::Response(response) => break response,
+                    Message::Event(event) => self.events.push_back(event),
+                }
+            };
+            if response.id != id {
+                return Err(Error::Ipc(format!(
+                    "reply id {} does not match request {id}",
+                    response.id
+                )));
+            }
+            match (response.result, response.error) {
+                (_, Some(err)) => Err(err.into_error()),
+                (Some(result), None) => Ok(result),
+                (None, None) => Ok(Value::Null),
+            }
+        }
+
+        /// Wait up to `timeout` for the next subscribed event.
+        ///
+        /// Returns `Ok(None)` if none arrived in time.
+        pub fn next_event(&mut self, timeout: Duration) -> Result<Option<DaemonEvent>> {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+            let set_timeout = |stream: &UnixStream, t| {
+                stream
+                    .set_read_timeout(Some(t))
+                    .map_err(|e| Error::Ipc(e.to_string()))
+            };
+            set_timeout(self.reader.get_ref(), timeout.max(Duration::from_millis(1)))?;
+            let message = self.read_message();
+            set_timeout(self.reader.get_ref(), CALL_TIMEOUT)?;
+            match message {
+                Ok(Message::Event(event)) => Ok(Some(event)),
+                Ok(Message::Response(response)) => Err(Error::Ipc(format!(
+                    "unexpected reply {} while waiting for events",
+                    response.id
+                ))),
+                Err(Error::Timeout(_)) => Ok(None),
+                Err(e) => Err(e),
+            }
+        }
+
+        /// Read one line: a response or an event notification.
+        fn read_message(&mut self) -> Result<Message> {
+            let mut line = String::new();
+            let n = self.reader.read_line(&mut line).map_err(|e| {
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) {
+                    Error::Timeout("no message from daemon".to_string())
+                } else {
+                    Error::Ipc(format!("receive: {e}"))
+                }
+            })?;
+            if n == 0 {
+                return Err(Error::Ipc("daemon closed the connection".to_string()));
+            }
+            let value: Value = serde_json::from_str(&line)
+                .map_err(|e| Error::Ipc(format!("malformed reply: {e}")))?;
+            if value.get("method").is_some() {
+                let note: RpcRequest = serde_json::from_value(value)
+                    .map_err(|e| Error::Ipc(format!("malformed notification: {e}")))?;
+                if note.method != EVENT_METHOD {
+                    return Err(Error::Ipc(format!(
+                        "unexpected notification '{}'",
+                        note.method
+                    )));
+                }
+                return serde_json::from_value(note.params)
+                    .map(Message::Event)
+                    .map_err(|e| Error::Ipc(format!("malformed event: {e}")));
+            }
+            serde_json::from_value(value)
+                .map(Message::Response)
+                .map_err(|e| Error::Ipc(format!("malformed reply: {e}")))
+        }
+
+        fn call_as<T: serde::de::DeserializeOwned>(
+            &mut self,
+            method: &str,
+            params: Value,
+        ) -> Result<T> {
+            let value = self.call(method, params)?;
+            serde_json::from_value(value)
+                .map_err(|e| Error::Ipc(format!("unexpected {method} result: {e}")))
+        }
+
+        pub fn status(&mut self) -> Result<DaemonStatus> {
+            self.call_as("status", Value::Null)
+        }
+
+        pub fn list_devices(&mut self) -> Result<Vec<DeviceState>> {
+            self.call_as("list_devices", Value::Null)
+        }
+
+        /// Receive `kinds` events from now on (see [`Self::next_event`]).
+        pub fn subscribe(&mut self, kinds: &[EventKind]) -> Result<()> {
+            self.call("subscribe", serde_json::json!({ "events": kinds }))?;
+            Ok(())
+        }
+
+        pub fn read_unit_id(&mut self) -> Result<String> {
+            self.call_as("get_unit_id", Value::Null)
+        }
+
+        pub fn read_battery(&mut self) -> Result<BatteryStatus> {
+            self.call_as("get_battery", Value::Null)
+        }
+
+        pub fn set_onboard_mode(&mut self, mode: OnboardMode) -> Result<()> {
+            self.call("set_onboard_mode", serde_json::json!({ "mode": mode }))?;
+            Ok(())
+        }
+
+        pub fn read_dpi(&mut self) -> Result<u16> {
+            self.call_as("get_dpi", Value::Null)
+        }
+
+        pub fn write_dpi(&mut self, dpi: u16) -> Result<u16> {
+            self.call_as("set_dpi", serde_json::json!({ "dpi": dpi }))
+        }
+
+        pub fn read_report_rate(&mut self) -> Result<PollingRate> {
+            let hz: u16 = self.call_as("get_report_rate", Value::Null)?;
+            PollingRate::from_hz(hz)
+                .ok_or_else(|| Error::Ipc(format!("daemon reported unsupported rate {hz} Hz")))
+        }
+
+        pub fn write_report_rate(&mut self, rate: PollingRate) -> Result<()> {
+            self.call("set_report_rate", serde_json::json!({ "hz": rate.as_hz() }))?;
+            Ok(())
+        }
+
+        pub fn read_button_mapping(&mut self, index: usize) -> Result<ButtonAction> {
+            self.call_as("get_button", serde_json::json!({ "index": index }))
+        }
+
+        pub fn write_button_mapping(&mut self, index: usize, action: ButtonAction) -> Result<()> {
+            self.call(
+                "set_button",
+                serde_json::json!({ "index": index, "action": action }),
+            )?;
+            Ok(())
+        }
+
+        pub fn write_button_mapping_cid(&mut self, index: usize, cid: u16) -> Result<()> {
+            self.call(
+                "set_button_cid",
+                serde_json::json!({ "index": index, "cid": cid }),
+            )?;
+            Ok(())
+        }
+
+        /// Everything the daemon can read from the mouse (see [`crate::profile::snapshot`]).
+        pub fn snapshot(&mut self) -> Result<Profile> {
+            self.call_as("read_profile", Value::Null)
+        }
+
+        pub fn active_profile(&mut self) -> Result<Option<Profile>> {
+            self.call_as("get_active_profile", Value::Null)
+        }
+
+        /// Make `profile` active: the daemon applies it now and on every reconnect.
+        pub fn set_active_profile(&mut self, profile: &Profile) -> Result<()> {
+            self.call(
+                "set_active_profile",
+                serde_json::json!({ "profile": profile }),
+            )?;
+            Ok(())
+        }
+    }
+
+    enum Message {
+        Response(RpcResponse),
+        Event(DaemonEvent),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hidpp::{Hidpp10Error, Hidpp20Error};
+
+    #[test]
+    fn request_roundtrip() {
+        let req = RpcRequest::new(7, "set_dpi", serde_json::json!({ "dpi": 1600 }));
+        let json = serde_json::to_string(&req).unwrap();
+        assert_eq!(
+            json,
+            r#"{"jsonrpc":"2.0","id":7,"method":"set_dpi","params":{"dpi":1600}}"#
+        );
+        let back: RpcRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.method, "set_dpi");
+    }
+
+    #[test]
+    fn notification_has_no_id_and_params_default_to_null() {
+        let req: RpcRequest =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","method":"status"}"#).unwrap();
+        assert!(req.id.is_none());
+        assert!(req.params.is_null());
+    }
+
+    #[test]
+    fn responses_carry_either_result_or_error() {
+        let ok = RpcResponse::success(Value::from(1), Value::from(800));
+        assert_eq!(
+            serde_json::to_string(&ok).unwrap(),
+            r#"{"jsonrpc":"2.0","id":1,"result":800}"#
+        );
+        let err = RpcResponse::failure(
+            Value::from(2),
+            RpcError::new(codes::METHOD_NOT_FOUND, "unknown method"),
+        );
+        assert_eq!(
+            serde_json::to_string(&err).unwrap(),
+            r#"{"jsonrpc":"2.0","id":2,"error":{"code":-32601,"message":"unknown method"}}"#
+        );
+    }
+
+    #[test]
+    fn device_errors_carry_their_class() {
+        let err = RpcError::from_error(&Error::Timeout("no reply".into()));
+        assert_eq!(err.code, codes::TRANSIENT);
+        assert_eq!(
+            err.data,
+            Some(serde_json::json!({ "class": "transient", "retryable": true }))
+        );
+        assert!(matches!(err.into_error(), Error::Timeout(_)));
+
+        let err = RpcError::from_error(&Error::OutOfRange {
+            field: "dpi",
+            value: 50_000,
+            min: 100,
+            max: 25_600,
+        });
+        assert_eq!(err.code, codes::INVALID_PARAMS);
+        let data = err.data.unwrap();
+        assert_eq!(data["class"], "invalid_response");
+        assert_eq!(data["max"], 25_600);
+
+        let err = RpcError::from_error(&Error::HidppProtocol {
+            feature_index: 0x05,
+            function: 2,
+            code: HidppError::V20(Hidpp20Error::InvalidArgument),
+        });
+        assert_eq!(err.code, codes::PROTOCOL);
+        let data = err.data.unwrap();
+        assert_eq!(data["hidpp_code"], 2);
+        assert_eq!(data["hidpp_error"], "invalid_argument");
+
+        let err = RpcError::from_error(&Error::HidppProtocol {
+            feature_index: 0x05,
+            function: 2,
+            code: HidppError::V20(Hidpp20Error::Busy),
+        });
+        assert_eq!(err.code, codes::TRANSIENT);
+        assert_eq!(err.data.unwrap()["retryable"], true);
+    }
+
+    #[test]
+    fn device_errors_round_trip() {
+        let errors = [
+            Error::Timeout("no reply".into()),
+            Error::Disconnected {
+                op: "write",
+                message: "gone".into(),
+            },
+            Error::DeviceNotFound("no mouse".into()),
+            Error::PermissionDenied("hidraw3".into()),
+            Error::HidppProtocol {
+                feature_index: 0x05,
+                function: 2,
+                code: HidppError::V20(Hidpp20Error::InvalidArgument),
+            },
+            Error::HidppProtocol {
+                feature_index: 0x05,
+                function: 2,
+                code: HidppError::V20(Hidpp20Error::Busy),
+            },
+            Error::HidppProtocol {
+                feature_index: 0x00,
+                function: 0,
+                code: HidppError::V10(Hidpp10Error::ResourceError),
+            },
+            Error::UnsupportedFeature(0x8100),
+            Error::FeatureNotAllowed(0x00C2),
+            Error::UnknownFeatureIndex {
+                device_index: 0xFF,
+                feature_index: 0x0A,
+            },
+            Error::MalformedReply { feature: 0x2201 },
+            Error::OutOfRange {
+                field: "dpi",
+                value: 50_000,
+                min: 100,
+                max: 25_600,
+            },
+        ];
+        for err in errors {
+            let back = RpcError::from_error(&err).into_error();
+            assert_eq!(
+                ErrorClass::classify(&back),
+                ErrorClass::classify(&err),
+                "{err}"
+            );
+            // Structured errors come back whole; the rest keep their class.
+            if !matches!(
+                err,
+                Error::Timeout(_)
+                    | Error::Disconnected { .. }
+                    | Error::DeviceNotFound(_)
+                    | Error::PermissionDenied(_)
+            ) {
+                assert_eq!(back.to_string(), err.to_string());
+            }
+        }
+
+        let err = RpcError::from_error(&Error::DeviceNotFound("no mouse".into()));
+        assert!(matches!(err.into_error(), Error::Disconnected { .. }));
+        let err = RpcError::from_error(&Error::OutOfRange {
+            field: "polling_rate",
+            value: 300,
+            min: 125,
+            max: 1000,
+        });
+        assert!(matches!(
+            err.into_error(),
+            Error::OutOfRange {
+                field: "polling_rate",
+                value: 300,
+                min: 125,
+                max: 1000,
+            }
+        ));
+    }
+
+    #[test]
+    fn events_are_tagged_notifications() {
+        let event = DaemonEvent::Button {
+            device: "/dev/hidraw3".into(),
+            pressed: vec![0x00C4],
+        };
+        assert_eq!(event.kind(), EventKind::Button);
+        let note = RpcRequest::notification(EVENT_METHOD, serde_json::to_value(&event).unwrap());
+        assert_eq!(
+            serde_json::to_string(&note).unwrap(),
+            r#"{"jsonrpc":"2.0","method":"event","params":{"device":"/dev/hidraw3","pressed":[196],"type":"button"}}"#
+        );
+    }
+}
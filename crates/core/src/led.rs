@@ -0,0 +1,296 @@
+//! LED effects via HID++ 2.0 COLOR_LED_EFFECTS feature (0x8070).
+//!
+//! HID++ 2.0 COLOR_LED_EFFECTS functions:
+//!   - Function 0: getInfo → params[0] = zone count
+//!   - Function 3: setZoneEffect(zone, effect, params[10], persist)
+//!   - Function 14: getZoneEffect(zone) → params = [zone, effect, params[10]]
+//!
+//! Effect parameter layout (10 bytes, depends on the effect):
+//!   - Fixed:     [r, g, b, ...]
+//!   - Cycle:     [_, _, _, _, _, period_hi, period_lo, brightness, ...]
+//!   - Breathing: [r, g, b, period_hi, period_lo, waveform, brightness, ...]
+//!
+//! Protocol reference: libratbag (MIT), Solaar (GPL, used for layout only).
+
+use crate::error::{Error, Result};
+use crate::hidpp::{self, HidppRequest};
+use crate::safety;
+use crate::transport::{hidpp_request, lookup_feature_index, HidTransport};
+use serde::{Deserialize, Serialize};
+
+/// Number of effect parameter bytes in set/get zone effect.
+const EFFECT_PARAMS_LEN: usize = 10;
+
+/// Reply we cannot decode (too short or an effect we do not model).
+const MALFORMED: Error = Error::MalformedReply {
+    feature: hidpp::features::COLOR_LED_EFFECTS,
+};
+
+/// HID++ effect IDs used by the G502 zones.
+pub mod effects {
+    pub const DISABLED: u8 = 0x00;
+    pub const FIXED: u8 = 0x01;
+    pub const CYCLE: u8 = 0x03;
+    pub const BREATHING: u8 = 0x0A;
+}
+
+/// LED behaviour, matching the modes front-ends such as Piper offer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LedMode {
+    Off,
+    Solid,
+    Cycle,
+    Breathing,
+}
+
+impl LedMode {
+    /// All available modes.
+    pub const ALL: &'static [LedMode] = &[
+        LedMode::Off,
+        LedMode::Solid,
+        LedMode::Cycle,
+        LedMode::Breathing,
+    ];
+
+    /// HID++ effect ID for this mode.
+    pub fn effect_id(self) -> u8 {
+        match self {
+            Self::Off => effects::DISABLED,
+            Self::Solid => effects::FIXED,
+            Self::Cycle => effects::CYCLE,
+            Self::Breathing => effects::BREATHING,
+        }
+    }
+
+    /// Mode for a HID++ effect ID, if it is one we support.
+    pub fn from_effect_id(id: u8) -> Option<Self> {
+        match id {
+            effects::DISABLED => Some(Self::Off),
+            effects::FIXED => Some(Self::Solid),
+            effects::CYCLE => Some(Self::Cycle),
+            effects::BREATHING => Some(Self::Breathing),
+            _ => None,
+        }
+    }
+
+    /// Whether the mode animates (and so uses duration and brightness).
+    pub fn is_animated(self) -> bool {
+        matches!(self, Self::Cycle | Self::Breathing)
+    }
+}
+
+/// Effect shown by one LED zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedEffect {
+    pub mode: LedMode,
+    /// RGB color (ignored by Off and Cycle).
+    pub color: [u8; 3],
+    /// Period of one cycle/breath in milliseconds.
+    pub duration_ms: u16,
+    /// Brightness in percent.
+    pub brightness: u8,
+}
+
+impl Default for LedEffect {
+    fn default() -> Self {
+        Self {
+            mode: LedMode::Cycle,
+            color: [0x00, 0xA0, 0xFF],
+            duration_ms: 10_000,
+            brightness: 100,
+        }
+    }
+}
+
+impl LedEffect {
+    /// Encode the 10 effect parameter bytes.
+    fn encode_params(&self) -> [u8; EFFECT_PARAMS_LEN] {
+        let [r, g, b] = self.color;
+        let [period_hi, period_lo] = self.duration_ms.to_be_bytes();
+        let mut p = [0u8; EFFECT_PARAMS_LEN];
+        match self.mode {
+            LedMode::Off => {}
+            LedMode::Solid => p[..3].copy_from_slice(&[r, g, b]),
+            LedMode::Cycle => p[5..8].copy_from_slice(&[period_hi, period_lo, self.brightness]),
+            LedMode::Breathing => {
+                p[..7].copy_from_slice(&[r, g, b, period_hi, period_lo, 0x00, self.brightness])
+            }
+        }
+        p
+    }
+
+    /// The effect as the device would report it back: fields the mode does
+    /// not use are reset to their defaults.
+    pub fn normalized(&self) -> Self {
+        Self::decode(self.mode.effect_id(), &self.encode_params()).unwrap_or(*self)
+    }
+
+    /// Decode an effect ID and its parameter bytes.
+    fn decode(effect_id: u8, p: &[u8]) -> Result<Self> {
+        let mode = LedMode::from_effect_id(effect_id).ok_or(MALFORMED)?;
+        if p.len() < EFFECT_PARAMS_LEN {
+            return Err(MALFORMED);
+        }
+        let defaults = Self::default();
+        let effect = match mode {
+            LedMode::Off => Self { mode, ..defaults },
+            LedMode::Solid => Self {
+                mode,
+                color: [p[0], p[1], p[2]],
+                ..defaults
+            },
+            LedMode::Cycle => Self {
+                mode,
+                duration_ms: u16::from_be_bytes([p[5], p[6]]),
+                brightness: p[7],
+                ..defaults
+            },
+            LedMode::Breathing => Self {
+                mode,
+                color: [p[0], p[1], p[2]],
+                duration_ms: u16::from_be_bytes([p[3], p[4]]),
+                brightness: p[6],
+            },
+        };
+        Ok(effect)
+    }
+}
+
+/// Read the number of LED zones.
+pub fn read_zone_count(transport: &dyn HidTransport, device_index: u8) -> Result<u8> {
+    let feature_idx =
+        lookup_feature_index(transport, device_index, hidpp::features::COLOR_LED_EFFECTS)?;
+
+    // getInfo: function 0, no params
+    let req = HidppRequest::new(device_index, feature_idx, 0x00, vec![]);
+    let resp = hidpp_request(transport, &req)?;
+    Ok(resp.params[0])
+}
+
+/// Read the effect currently shown by `zone`.
+pub fn read_led_effect(
+    transport: &dyn HidTransport,
+    device_index: u8,
+    zone: u8,
+) -> Result<LedEffect> {
+    safety::validate_led_zone(zone)?;
+    let feature_idx =
+        lookup_feature_index(transport, device_index, hidpp::features::COLOR_LED_EFFECTS)?;
+
+    // getZoneEffect: function 14, params[0] = zone
+    let req = HidppRequest::new(device_index, feature_idx, 0x0E, vec![zone]);
+    let resp = hidpp_request(transport, &req)?;
+    if resp.params.len() < 2 {
+        return Err(MALFORMED);
+    }
+    LedEffect::decode(resp.params[1], &resp.params[2..])
+}
+
+/// Show `effect` on `zone` and store it in the device.
+///
+/// The effect is validated against safe bounds before sending.
+pub fn write_led_effect(
+    transport: &dyn HidTransport,
+    device_index: u8,
+    zone: u8,
+    effect: &LedEffect,
+) -> Result<()> {
+    safety::validate_led_zone(zone)?;
+    let effect = safety::validate_led_effect(effect)?;
+    let feature_idx =
+        lookup_feature_index(transport, device_index, hidpp::features::COLOR_LED_EFFECTS)?;
+
+    // setZoneEffect: function 3, params = [zone, effect, params[10], persist]
+    let mut params = vec![zone, effect.mode.effect_id()];
+    params.extend_from_slice(&effect.encode_params());
+    params.push(0x01);
+    let req = HidppRequest::new(device_index, feature_idx, 0x03, params);
+    let _resp = hidpp_request(transport, &req)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimulatedG502;
+    use crate::transport::mock::MockTransport;
+
+    const DEV_IDX: u8 = 0x01;
+    const LED_FEATURE_IDX: u8 = 0x09;
+
+    fn setup_led_feature_lookup(mock: &MockTransport) {
+        mock.on_short_request(
+            DEV_IDX,
+            0x00,
+            0x01,
+            &[0x80, 0x70],
+            &[LED_FEATURE_IDX, 0x00, 0x00],
+        );
+    }
+
+    #[test]
+    fn write_solid_sends_color() {
+        let mock = MockTransport::new();
+        setup_led_feature_lookup(&mock);
+        mock.on_long_request(
+            DEV_IDX,
+            LED_FEATURE_IDX,
+            0x31,
+            &[0x00, 0x01, 0xFF, 0x40, 0x00, 0, 0, 0, 0, 0, 0, 0, 0x01],
+            &[0x00],
+        );
+
+        let effect = LedEffect {
+            mode: LedMode::Solid,
+            color: [0xFF, 0x40, 0x00],
+            ..LedEffect::default()
+        };
+        write_led_effect(&mock, DEV_IDX, 0, &effect).unwrap();
+    }
+
+    #[test]
+    fn read_breathing_decodes_params() {
+        let mock = MockTransport::new();
+        setup_led_feature_lookup(&mock);
+        mock.on_long_request(
+            DEV_IDX,
+            LED_FEATURE_IDX,
+            0xE1,
+            &[0x01],
+            &[
+                0x01, 0x0A, 0x10, 0x20, 0x30, 0x0F, 0xA0, 0x00, 0x32, 0, 0, 0,
+            ],
+        );
+
+        let effect = read_led_effect(&mock, DEV_IDX, 1).unwrap();
+        assert_eq!(
+            effect,
+            LedEffect {
+                mode: LedMode::Breathing,
+                color: [0x10, 0x20, 0x30],
+                duration_ms: 4000,
+                brightness: 50,
+            }
+        );
+    }
+
+    #[test]
+    fn write_rejects_bad_zone_before_io() {
+        let mock = MockTransport::new();
+        assert!(write_led_effect(&mock, DEV_IDX, 7, &LedEffect::default()).is_err());
+    }
+
+    #[test]
+    fn round_trip_through_simulated_device() {
+        let sim = SimulatedG502::new(DEV_IDX);
+        assert_eq!(read_zone_count(&sim, DEV_IDX).unwrap(), 2);
+        let effect = LedEffect {
+            mode: LedMode::Cycle,
+            duration_ms: 5000,
+            brightness: 40,
+            ..LedEffect::default()
+        };
+        write_led_effect(&sim, DEV_IDX, 1, &effect).unwrap();
+        assert_eq!(read_led_effect(&sim, DEV_IDX, 1).unwrap(), effect);
+    }
+}
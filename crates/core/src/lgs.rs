@@ -0,0 +1,385 @@
+//! Logitech Gaming Software (LGS) profile export import.
+//!
+//! LGS exports profiles as XML. Each `<profile>` has a `<macros>` list, an
+//! `<assignments>` list binding macros to buttons, and per-device
+//! `<settings>` with the DPI levels and report rate:
+//!
+//! ```xml
+//! <profiles>
+//!   <profile name="Shooter">
+//!     <macros>
+//!       <macro guid="{A1}" name="Copy">
+//!         <keystroke><modifier name="ctrl"/><key value="c"/></keystroke>
+//!       </macro>
+//!     </macros>
+//!     <assignments>
+//!       <assignment contextid="4" shiftstate="0" macroguid="{A1}"/>
+//!     </assignments>
+//!     <settings>
+//!       <dpi reportrate="500"><level value="800"/><level value="1600" default="1"/></dpi>
+//!     </settings>
+//!   </profile>
+//! </profiles>
+//! ```
+//!
+//! LGS calls the buttons G1-G11 (`contextid`); G1-G6 are the six
+//! programmable buttons. A `<mousefunction>` macro becomes the button's
+//! action. `<keystroke>` and `<multikey>` macros (keys, mouse buttons and
+//! delays) become host macros, played by open-g-hubd in place of the
+//! button's action. Text blocks, shortcuts, scripts, G-shift assignments
+//! and the DPI shift value are listed in the report instead.
+
+use crate::device::{ButtonAction, PollingRate, G502_BUTTON_COUNT};
+use crate::error::{Error, Result};
+use crate::input::{self, MouseButton};
+use crate::macros::{Macro, MacroStep};
+use crate::profile::Profile;
+use crate::safety;
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+
+/// LGS mouse function names and the actions they map to.
+const MOUSE_FUNCTIONS: &[(&str, ButtonAction)] = &[
+    ("left click", ButtonAction::LeftClick),
+    ("right click", ButtonAction::RightClick),
+    ("middle click", ButtonAction::MiddleClick),
+    ("back", ButtonAction::Back),
+    ("forward", ButtonAction::Forward),
+    ("dpi up", ButtonAction::DpiCycleUp),
+    ("dpi down", ButtonAction::DpiCycleDown),
+    ("dpi cycling", ButtonAction::DpiCycleUp),
+    ("disable", ButtonAction::NoAction),
+];
+
+/// A profile converted from LGS, and what did not fit.
+#[derive(Debug, Clone)]
+pub struct Imported {
+    pub profile: Profile,
+    pub unsupported: Vec<String>,
+}
+
+fn parse(xml: &str) -> Result<Document<'_>> {
+    Document::parse(xml).map_err(|e| Error::Profile(format!("invalid LGS profile: {e}")))
+}
+
+fn profiles<'a, 'input>(doc: &'a Document<'input>) -> Vec<Node<'a, 'input>> {
+    doc.descendants()
+        .filter(|n| n.has_tag_name("profile"))
+        .collect()
+}
+
+fn profile_name<'a>(node: Node<'a, '_>) -> &'a str {
+    node.attribute("name").unwrap_or("LGS profile")
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, tag: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(tag))
+}
+
+fn elements<'a, 'input>(node: Node<'a, 'input>) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(Node::is_element)
+}
+
+/// Names of the profiles in an LGS export.
+pub fn profile_names(xml: &str) -> Result<Vec<String>> {
+    let doc = parse(xml)?;
+    Ok(profiles(&doc)
+        .into_iter()
+        .map(|p| profile_name(p).to_string())
+        .collect())
+}
+
+/// A key by LGS name (`c`, `f5`, `lshift`) or set-1 scancode (`0x2E`, `46`).
+fn key(value: &str) -> Option<u16> {
+    let scancode = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None if value.len() > 1 => value.parse().ok(),
+        None => None,
+    };
+    match scancode {
+        Some(scancode) => input::key_from_scancode(scancode),
+        None => input::key_from_name(value),
+    }
+}
+
+fn mouse_button(name: &str) -> Option<MouseButton> {
+    match name.to_lowercase().as_str() {
+        "left" => Some(MouseButton::Left),
+        "right" => Some(MouseButton::Right),
+        "middle" => Some(MouseButton::Middle),
+        "back" => Some(MouseButton::Back),
+        "forward" => Some(MouseButton::Forward),
+        _ => None,
+    }
+}
+
+/// What an LGS macro turns into.
+enum Converted {
+    Action(ButtonAction),
+    Macro(Macro),
+}
+
+/// Convert one `<macro>`; the error says why it cannot be.
+fn convert_macro(node: Node) -> std::result::Result<Converted, String> {
+    let name = node.attribute("name").unwrap_or("unnamed").to_string();
+    let Some(body) = elements(node).next() else {
+        return Err("empty macro".to_string());
+    };
+    let attr = |n: Node, a: &str| n.attribute(a).unwrap_or_default().to_string();
+
+    match body.tag_name().name() {
+        "mousefunction" => {
+            let function = attr(body, "value");
+            MOUSE_FUNCTIONS
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(&function))
+                .map(|&(_, action)| Converted::Action(action))
+                .ok_or(format!("mouse function '{function}'"))
+        }
+        "keystroke" => {
+            let mut modifiers = Vec::new();
+            let mut tapped = None;
+            for part in elements(body) {
+                match part.tag_name().name() {
+                    "modifier" => {
+                        let name = attr(part, "name");
+                        let key = input::key_from_name(&name);
+                        modifiers.push(key.ok_or(format!("unknown modifier '{name}'"))?);
+                    }
+                    "key" => {
+                        let value = attr(part, "value");
+                        tapped = Some(key(&value).ok_or(format!("unknown key '{value}'"))?);
+                    }
+                    other => return Err(format!("{other} in a keystroke")),
+                }
+            }
+            let key = tapped.ok_or("keystroke without a key")?;
+            Ok(Converted::Macro(Macro::keystroke(name, &modifiers, key)))
+        }
+        "multikey" => {
+            let mut steps = Vec::new();
+            for part in elements(body) {
+                let down = attr(part, "direction") != "up";
+                let step = match part.tag_name().name() {
+                    "key" => key(&attr(part, "value"))
+                        .map(|k| {
+                            if down {
+                                MacroStep::KeyDown(k)
+                            } else {
+                                MacroStep::KeyUp(k)
+                            }
+                        })
+                        .ok_or(format!("unknown key '{}'", attr(part, "value")))?,
+                    "mouse" => mouse_button(&attr(part, "button"))
+                        .map(|b| {
+                            if down {
+                                MacroStep::ButtonDown(b)
+                            } else {
+                                MacroStep::ButtonUp(b)
+                            }
+                        })
+                        .ok_or(format!("mouse button '{}'", attr(part, "button")))?,
+                    "delay" => attr(part, "milliseconds")
+                        .parse()
+                        .map(MacroStep::Delay)
+                        .map_err(|_| format!("delay '{}'", attr(part, "milliseconds")))?,
+                    other => return Err(format!("{other} step")),
+                };
+                steps.push(step);
+            }
+            let m = Macro { name, steps };
+            safety::validate_macro(&m).map_err(|e| e.to_string())?;
+            Ok(Converted::Macro(m))
+        }
+        other => Err(format!("{other} macros are not supported")),
+    }
+}
+
+/// Convert the LGS profile called `name`, or the first one, into a
+/// [`Profile`].
+pub fn import(xml: &str, name: Option<&str>) -> Result<Imported> {
+    let doc = parse(xml)?;
+    let all = profiles(&doc);
+    let chosen = match name {
+        Some(name) => all.iter().find(|p| profile_name(**p) == name),
+        None => all.first(),
+    }
+    .copied()
+    .ok_or_else(|| Error::Profile("no matching LGS profile".to_string()))?;
+
+    let mut unsupported = Vec::new();
+    for other in &all {
+        if *other != chosen {
+            unsupported.push(format!(
+                "profile '{}': only one profile is imported at a time",
+                profile_name(*other)
+            ));
+        }
+    }
+
+    let mut profile = Profile {
+        name: profile_name(chosen).to_string(),
+        ..Default::default()
+    };
+
+    if let Some(dpi) = chosen.descendants().find(|n| n.has_tag_name("dpi")) {
+        let mut default = None;
+        for level in dpi.children().filter(|n| n.has_tag_name("level")) {
+            let value = level.attribute("value").unwrap_or_default();
+            let Some(dpi) = value
+                .parse()
+                .ok()
+                .and_then(|v| safety::validate_dpi(v).ok())
+            else {
+                unsupported.push(format!("DPI level {value}: outside the safe range"));
+                continue;
+            };
+            if matches!(level.attribute("default"), Some("1" | "true")) {
+                default = Some(profile.dpi_stages.len());
+            }
+            profile.dpi_stages.push(dpi);
+        }
+        profile.default_dpi_stage = default.unwrap_or(0);
+        if let Some(&dpi) = profile.dpi_stages.get(profile.default_dpi_stage) {
+            profile.dpi = dpi;
+        }
+        if let Some(shift) = dpi.attribute("shift") {
+            unsupported.push(format!("DPI shift {shift}"));
+        }
+        if let Some(rate) = dpi.attribute("reportrate") {
+            match rate.parse().ok().and_then(PollingRate::from_hz) {
+                Some(rate) => profile.polling_rate = rate,
+                None => unsupported.push(format!(
+                    "report rate {rate} Hz (supported: 125, 250, 500, 1000)"
+                )),
+            }
+        }
+    }
+
+    let macros: HashMap<&str, Node> = child(chosen, "macros")
+        .into_iter()
+        .flat_map(|m| m.children().filter(|n| n.has_tag_name("macro")))
+        .filter_map(|m| Some((m.attribute("guid")?, m)))
+        .collect();
+    let assignments = child(chosen, "assignments")
+        .into_iter()
+        .flat_map(|a| a.children().filter(|n| n.has_tag_name("assignment")));
+    for assignment in assignments {
+        let context = assignment.attribute("contextid").unwrap_or_default();
+        let button = format!("G{context}");
+        if assignment.attribute("shiftstate").is_some_and(|s| s != "0") {
+            unsupported.push(format!("{button} (G-shift): G-shift layers"));
+            continue;
+        }
+        let Some(index) = context
+            .parse::<usize>()
+            .ok()
+            .and_then(|c| c.checked_sub(1))
+            .filter(|&i| i < G502_BUTTON_COUNT)
+        else {
+            unsupported.push(format!(
+                "{button}: only G1-G{G502_BUTTON_COUNT} can be assigned"
+            ));
+            continue;
+        };
+        let guid = assignment.attribute("macroguid").unwrap_or_default();
+        let Some(&node) = macros.get(guid) else {
+            unsupported.push(format!("{button}: macro {guid} is missing"));
+            continue;
+        };
+        match convert_macro(node) {
+            Ok(Converted::Action(action)) => profile.buttons[index] = action,
+            Ok(Converted::Macro(m)) => {
+                profile.button_macros.insert(index, m);
+            }
+            Err(why) => unsupported.push(format!(
+                "{button}: macro '{}': {why}",
+                node.attribute("name").unwrap_or(guid)
+            )),
+        }
+    }
+
+    Ok(Imported {
+        profile,
+        unsupported,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = include_str!("../tests/fixtures/lgs/profiles.xml");
+
+    #[test]
+    fn settings_and_assignments_are_imported() {
+        assert_eq!(profile_names(EXPORT).unwrap(), ["Shooter", "Desktop"]);
+        let imported = import(EXPORT, None).unwrap();
+        let profile = &imported.profile;
+        assert_eq!(profile.name, "Shooter");
+        assert_eq!(profile.dpi_stages, [800, 1600, 3200]);
+        assert_eq!(profile.default_dpi_stage, 1);
+        assert_eq!(profile.dpi, 1600);
+        assert_eq!(profile.polling_rate, PollingRate::Hz500);
+        assert_eq!(
+            profile.buttons,
+            [
+                ButtonAction::LeftClick,
+                ButtonAction::RightClick,
+                ButtonAction::MiddleClick,
+                ButtonAction::Back,
+                ButtonAction::Forward,
+                ButtonAction::DpiCycleUp,
+            ]
+        );
+
+        let ctrl = input::key_from_name("lctrl").unwrap();
+        let c = input::key_from_name("c").unwrap();
+        assert_eq!(
+            profile.button_macros[&3],
+            Macro::keystroke("Copy", &[ctrl], c)
+        );
+        let r = input::key_from_name("r").unwrap();
+        assert_eq!(
+            profile.button_macros[&0].steps,
+            [
+                MacroStep::KeyDown(r),
+                MacroStep::Delay(40),
+                MacroStep::KeyUp(r),
+                MacroStep::ButtonDown(MouseButton::Left),
+                MacroStep::ButtonUp(MouseButton::Left),
+            ]
+        );
+    }
+
+    #[test]
+    fn everything_else_is_reported() {
+        let imported = import(EXPORT, None).unwrap();
+        assert_eq!(
+            imported.unsupported,
+            [
+                "profile 'Desktop': only one profile is imported at a time",
+                "DPI level 40000: outside the safe range",
+                "DPI shift 400",
+                "G6: macro 'Sniper': mouse function 'DPI Shift'",
+                "G4 (G-shift): G-shift layers",
+                "G9: only G1-G6 can be assigned",
+                "G3: macro 'Grenade': value out of range: macro_delay_ms = 60000 (allowed 0..=10000)",
+                "G2: macro {FF} is missing",
+            ]
+        );
+    }
+
+    #[test]
+    fn profiles_are_chosen_by_name() {
+        let desktop = import(EXPORT, Some("Desktop")).unwrap();
+        assert_eq!(desktop.profile.dpi, 1200);
+        assert_eq!(desktop.profile.polling_rate, PollingRate::Hz1000);
+        assert!(desktop.profile.button_macros.is_empty());
+        assert!(import(EXPORT, Some("Racing")).is_err());
+        assert!(import("<profiles>", None).is_err());
+    }
+}
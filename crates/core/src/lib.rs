@@ -3,19 +3,48 @@
 //! This crate provides the cross-platform core logic for communicating with
 //! Logitech G mice via the HID++ 2.0 protocol over USB HID.
 
+pub mod autoswitch;
+pub mod battery;
+pub mod battery_history;
 pub mod buttons;
+pub mod client;
 pub mod comm;
+pub mod debounce;
 pub mod device;
 pub mod dpi;
 pub mod error;
+#[cfg(target_os = "linux")]
+pub mod evdev;
+pub mod ghub;
+pub mod hid;
 pub mod hidpp;
+pub mod input;
 #[cfg(test)]
 mod integration_tests;
+pub mod ipc;
+pub mod led;
+pub mod lgs;
+pub mod macros;
+pub mod notify;
 pub mod onboard;
+pub mod plan;
+pub mod power;
 pub mod profile;
+pub mod ratbag;
 pub mod report_rate;
 pub mod safety;
+pub mod schema;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod sim;
+pub mod solaar;
+pub mod store;
+pub mod transaction;
 pub mod transport;
+#[cfg(target_os = "linux")]
+pub mod uinput;
+pub mod wine;
+pub mod wireless;
 
 /// Logitech USB Vendor ID.
 pub const LOGITECH_VID: u16 = 0x046D;
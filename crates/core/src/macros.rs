@@ -0,0 +1,253 @@
+//! Host-side macros: key and mouse button sequences with delays.
+//!
+//! A [`Macro`] is played on the host through an [`InputBackend`]; it is never
+//! written to the mouse (onboard macros stay unsupported, see [`safety`]).
+//! Profiles bind macros to buttons in `Profile::button_macros`, and the
+//! LGS and G Hub importers produce them. open-g-hubd diverts those buttons
+//! and plays their macros through a [`MacroPlayer`].
+//!
+//! [`safety`]: crate::safety
+
+use crate::error::Result;
+use crate::input::{self, InputBackend, InputEvent, MouseButton};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc;
+use tracing::warn;
+
+/// Characters typed with Shift on a US layout, and the key's plain character.
+const SHIFTED: &[(char, char)] = &[
+    ('!', '1'),
+    ('@', '2'),
+    ('#', '3'),
+    ('$', '4'),
+    ('%', '5'),
+    ('^', '6'),
+    ('&', '7'),
+    ('*', '8'),
+    ('(', '9'),
+    (')', '0'),
+    ('_', '-'),
+    ('+', '='),
+    ('{', '['),
+    ('}', ']'),
+    (':', ';'),
+    ('"', '\''),
+    ('~', '`'),
+    ('|', '\\'),
+    ('<', ','),
+    ('>', '.'),
+    ('?', '/'),
+];
+
+/// Key names for the plain characters that are not letters or digits.
+const PUNCTUATION: &[(char, &str)] = &[
+    (' ', "spacebar"),
+    ('\n', "enter"),
+    ('\t', "tab"),
+    ('-', "minus"),
+    ('=', "equal"),
+    ('[', "lbracket"),
+    (']', "rbracket"),
+    (';', "semicolon"),
+    ('\'', "quote"),
+    ('`', "tilde"),
+    ('\\', "backslash"),
+    (',', "comma"),
+    ('.', "period"),
+    ('/', "slash"),
+];
+
+/// The key that types `c` on a US layout, and whether it needs Shift.
+fn typed_key(c: char) -> Option<(u16, bool)> {
+    let (plain, shifted) = match SHIFTED.iter().find(|&&(s, _)| s == c) {
+        Some(&(_, plain)) => (plain, true),
+        None if c.is_ascii_uppercase() => (c.to_ascii_lowercase(), true),
+        None => (c, false),
+    };
+    let key = match PUNCTUATION.iter().find(|&&(p, _)| p == plain) {
+        Some(&(_, name)) => input::key_from_name(name),
+        None if plain.is_ascii_alphanumeric() => {
+            input::key_from_name(plain.encode_utf8(&mut [0; 4]))
+        }
+        None => None,
+    }?;
+    Some((key, shifted))
+}
+
+/// One step of a macro.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MacroStep {
+    /// Key pressed (evdev key code).
+    KeyDown(u16),
+    /// Key released (evdev key code).
+    KeyUp(u16),
+    ButtonDown(MouseButton),
+    ButtonUp(MouseButton),
+    /// Pause in milliseconds.
+    Delay(u32),
+}
+
+/// A named sequence of input steps.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Macro {
+    pub name: String,
+    pub steps: Vec<MacroStep>,
+}
+
+impl Macro {
+    /// Press `modifiers` in order, tap `key`, then release the modifiers in
+    /// reverse order.
+    pub fn keystroke(name: impl Into<String>, modifiers: &[u16], key: u16) -> Self {
+        let mut steps: Vec<MacroStep> = modifiers.iter().map(|&m| MacroStep::KeyDown(m)).collect();
+        steps.push(MacroStep::KeyDown(key));
+        steps.push(MacroStep::KeyUp(key));
+        steps.extend(modifiers.iter().rev().map(|&m| MacroStep::KeyUp(m)));
+        Self {
+            name: name.into(),
+            steps,
+        }
+    }
+
+    /// Type `text` on a US keyboard layout. Fails with the first character
+    /// that has no key there.
+    pub fn text(name: impl Into<String>, text: &str) -> std::result::Result<Self, char> {
+        let shift = input::key_from_name("lshift").expect("lshift is in the key table");
+        let mut steps = Vec::new();
+        for c in text.chars() {
+            let (key, shifted) = typed_key(c).ok_or(c)?;
+            if shifted {
+                steps.push(MacroStep::KeyDown(shift));
+            }
+            steps.push(MacroStep::KeyDown(key));
+            steps.push(MacroStep::KeyUp(key));
+            if shifted {
+                steps.push(MacroStep::KeyUp(shift));
+            }
+        }
+        Ok(Self {
+            name: name.into(),
+            steps,
+        })
+    }
+
+    /// Emit every step through `backend`.
+    pub fn play(&self, backend: &mut dyn InputBackend) -> Result<()> {
+        for step in &self.steps {
+            match *step {
+                MacroStep::KeyDown(key) => backend.emit(InputEvent::KeyDown(key))?,
+                MacroStep::KeyUp(key) => backend.emit(InputEvent::KeyUp(key))?,
+                MacroStep::ButtonDown(button) => backend.emit(InputEvent::ButtonDown(button))?,
+                MacroStep::ButtonUp(button) => backend.emit(InputEvent::ButtonUp(button))?,
+                MacroStep::Delay(ms) => backend.delay(ms),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Plays macros one after another on a background thread, so their delays
+/// do not hold up the caller.
+pub struct MacroPlayer {
+    queue: mpsc::Sender<Macro>,
+}
+
+impl MacroPlayer {
+    /// Start the playback thread. It stops when the player is dropped.
+    pub fn spawn(mut backend: Box<dyn InputBackend>) -> Self {
+        let (queue, macros) = mpsc::channel::<Macro>();
+        std::thread::spawn(move || {
+            for m in macros {
+                if let Err(e) = m.play(backend.as_mut()) {
+                    warn!(name = %m.name, "Playing macro failed: {e}");
+                }
+            }
+        });
+        Self { queue }
+    }
+
+    /// Queue `m` to play after the macros already queued.
+    pub fn play(&self, m: Macro) {
+        // The thread only exits once `queue` is dropped.
+        let _ = self.queue.send(m);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{key_from_name, Recorded, RecordingBackend};
+
+    #[test]
+    fn keystroke_wraps_the_key_in_its_modifiers() {
+        let ctrl = key_from_name("lctrl").unwrap();
+        let shift = key_from_name("lshift").unwrap();
+        let t = key_from_name("t").unwrap();
+        let undo_close = Macro::keystroke("Reopen tab", &[ctrl, shift], t);
+        assert_eq!(
+            undo_close.steps,
+            [
+                MacroStep::KeyDown(ctrl),
+                MacroStep::KeyDown(shift),
+                MacroStep::KeyDown(t),
+                MacroStep::KeyUp(t),
+                MacroStep::KeyUp(shift),
+                MacroStep::KeyUp(ctrl),
+            ]
+        );
+    }
+
+    #[test]
+    fn text_is_typed_with_shift_where_needed() {
+        let shift = key_from_name("lshift").unwrap();
+        let g = key_from_name("g").unwrap();
+        let one = key_from_name("1").unwrap();
+        let typed = Macro::text("Cheer", "Gg!").unwrap();
+        assert_eq!(
+            typed.steps,
+            [
+                MacroStep::KeyDown(shift),
+                MacroStep::KeyDown(g),
+                MacroStep::KeyUp(g),
+                MacroStep::KeyUp(shift),
+                MacroStep::KeyDown(g),
+                MacroStep::KeyUp(g),
+                MacroStep::KeyDown(shift),
+                MacroStep::KeyDown(one),
+                MacroStep::KeyUp(one),
+                MacroStep::KeyUp(shift),
+            ]
+        );
+        assert_eq!(Macro::text("Greeting", "gg wp\n").unwrap().steps.len(), 12);
+        assert_eq!(Macro::text("Accent", "café"), Err('é'));
+    }
+
+    #[test]
+    fn play_emits_events_and_delays_in_order() {
+        let double_click = Macro {
+            name: "Double click".into(),
+            steps: vec![
+                MacroStep::ButtonDown(MouseButton::Left),
+                MacroStep::ButtonUp(MouseButton::Left),
+                MacroStep::Delay(30),
+                MacroStep::ButtonDown(MouseButton::Left),
+                MacroStep::ButtonUp(MouseButton::Left),
+            ],
+        };
+        let backend = RecordingBackend::new();
+        double_click.play(&mut backend.clone()).unwrap();
+        let recorded = backend.recorded();
+        assert_eq!(recorded.len(), 5);
+        assert_eq!(recorded[2], Recorded::Delay(30));
+        assert_eq!(
+            recorded[4],
+            Recorded::Event(InputEvent::ButtonUp(MouseButton::Left))
+        );
+
+        let json = serde_json::to_string(&double_click.steps[..3]).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"button_down":"Left"},{"button_up":"Left"},{"delay":30}]"#
+        );
+    }
+}
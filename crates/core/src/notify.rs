@@ -0,0 +1,309 @@
+//! Desktop notifications: which daemon events pop up an on-screen message,
+//! and what it says.
+//!
+//! The daemon sends them through the freedesktop notification service; this
+//! module only decides. Settings live in `<config dir>/notifications.json`,
+//! every field optional:
+//!
+//! ```json
+//! {
+//!   "dpi": true,
+//!   "profile": true,
+//!   "disconnect": true,
+//!   "low_battery": [15, 5],
+//!   "timeout_ms": 2000
+//! }
+//! ```
+//!
+//! `low_battery` lists levels in percent: a warning pops up when a
+//! discharging mouse drops below one of them, once per crossing. Charging
+//! re-arms them. An empty list turns battery warnings off.
+
+use crate::error::{Error, Result};
+use crate::ipc::DaemonEvent;
+use crate::store;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// File name of the settings in the config directory.
+pub const SETTINGS_FILE: &str = "notifications.json";
+
+/// Which notifications to show.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifySettings {
+    /// DPI stage changes.
+    pub dpi: bool,
+    /// Switches of the active profile.
+    pub profile: bool,
+    /// A mouse going to sleep, switched off, or unplugged.
+    pub disconnect: bool,
+    /// Battery levels in percent to warn below.
+    pub low_battery: Vec<u8>,
+    /// How long a notification stays up; the notification server decides
+    /// when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u32>,
+}
+
+impl Default for NotifySettings {
+    fn default() -> Self {
+        Self {
+            dpi: true,
+            profile: true,
+            disconnect: true,
+            low_battery: vec![15, 5],
+            timeout_ms: None,
+        }
+    }
+}
+
+impl NotifySettings {
+    /// Read settings from `path`. A missing file gives the defaults.
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(Error::Profile(format!(
+                    "cannot read {}: {e}",
+                    path.display()
+                )))
+            }
+        };
+        let settings: Self = serde_json::from_str(&json).map_err(|e| {
+            Error::Profile(format!(
+                "invalid notification settings in {}: {e}",
+                path.display()
+            ))
+        })?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Fails on a battery threshold outside 1-100.
+    pub fn validate(&self) -> Result<()> {
+        match self.low_battery.iter().find(|t| !(1..=100).contains(*t)) {
+            Some(&threshold) => Err(Error::OutOfRange {
+                field: "low_battery",
+                value: threshold as u32,
+                min: 1,
+                max: 100,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `<config dir>/notifications.json`.
+pub fn settings_path() -> Option<PathBuf> {
+    store::config_dir().map(|dir| dir.join(SETTINGS_FILE))
+}
+
+/// What a notification is about. A new notification replaces the one of
+/// the same topic still on screen, so pressing the DPI button repeatedly
+/// does not stack them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Topic {
+    Dpi,
+    Profile,
+    Battery,
+    Disconnect,
+}
+
+/// freedesktop urgency levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Low = 0,
+    Normal = 1,
+    Critical = 2,
+}
+
+/// One message to show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub topic: Topic,
+    pub summary: String,
+    pub body: String,
+    /// freedesktop icon name.
+    pub icon: &'static str,
+    pub urgency: Urgency,
+}
+
+/// Turns daemon events into notifications, remembering battery levels to
+/// spot threshold crossings.
+#[derive(Debug, Clone)]
+pub struct Notifications {
+    settings: NotifySettings,
+    /// Last battery level per device path.
+    battery: HashMap<String, u8>,
+}
+
+impl Notifications {
+    pub fn new(settings: NotifySettings) -> Self {
+        Self {
+            settings,
+            battery: HashMap::new(),
+        }
+    }
+
+    pub fn settings(&self) -> &NotifySettings {
+        &self.settings
+    }
+
+    /// The notification `event` calls for, if any. `device_name` names the
+    /// mouse the event is about.
+    pub fn notification(&mut self, event: &DaemonEvent, device_name: &str) -> Option<Notification> {
+        match event {
+            DaemonEvent::Connection {
+                connected: false, ..
+            } if self.settings.disconnect => Some(Notification {
+                topic: Topic::Disconnect,
+                summary: format!("{device_name} disconnected"),
+                body: "Settings are restored when it comes back.".into(),
+                icon: "input-mouse",
+                urgency: Urgency::Normal,
+            }),
+            DaemonEvent::Battery { device, battery } => {
+                let previous = self.battery.insert(device.clone(), battery.level);
+                if battery.state.is_charging() {
+                    return None;
+                }
+                let previous = previous.unwrap_or(u8::MAX);
+                let crossed = self
+                    .settings
+                    .low_battery
+                    .iter()
+                    .copied()
+                    .filter(|&t| battery.level < t && previous >= t)
+                    .min()?;
+                let lowest = self.settings.low_battery.iter().copied().min();
+                let critical = lowest == Some(crossed);
+                Some(Notification {
+                    topic: Topic::Battery,
+                    summary: format!("{device_name} battery low"),
+                    body: format!("{}% remaining", battery.level),
+                    icon: if critical {
+                        "battery-caution"
+                    } else {
+                        "battery-low"
+                    },
+                    urgency: if critical {
+                        Urgency::Critical
+                    } else {
+                        Urgency::Normal
+                    },
+                })
+            }
+            DaemonEvent::Dpi { stage, dpi, .. } if self.settings.dpi => Some(Notification {
+                topic: Topic::Dpi,
+                summary: match dpi {
+                    Some(dpi) => format!("{dpi} DPI"),
+                    None => format!("DPI stage {}", stage + 1),
+                },
+                body: format!("{device_name}, stage {}", stage + 1),
+                icon: "input-mouse",
+                urgency: Urgency::Low,
+            }),
+            DaemonEvent::Profile { name } if self.settings.profile => Some(Notification {
+                topic: Topic::Profile,
+                summary: format!("Profile: {name}"),
+                body: String::new(),
+                icon: "input-mouse",
+                urgency: Urgency::Low,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::{BatteryStatus, ChargingState};
+
+    fn battery(level: u8, state: ChargingState) -> DaemonEvent {
+        DaemonEvent::Battery {
+            device: "sim".into(),
+            battery: BatteryStatus {
+                level,
+                next_level: level.saturating_sub(10),
+                state,
+            },
+        }
+    }
+
+    #[test]
+    fn battery_warnings_fire_once_per_threshold_crossing() {
+        let mut notes = Notifications::new(NotifySettings {
+            low_battery: vec![20, 10],
+            ..Default::default()
+        });
+        let mut at = |level, state| {
+            notes
+                .notification(&battery(level, state), "G502")
+                .map(|n| n.urgency)
+        };
+        assert_eq!(at(50, ChargingState::Discharging), None);
+        assert_eq!(at(19, ChargingState::Discharging), Some(Urgency::Normal));
+        assert_eq!(at(15, ChargingState::Discharging), None);
+        assert_eq!(at(5, ChargingState::Discharging), Some(Urgency::Critical));
+        assert_eq!(at(4, ChargingState::Discharging), None);
+        // Charging re-arms the thresholds.
+        assert_eq!(at(30, ChargingState::Recharging), None);
+        assert_eq!(at(18, ChargingState::Discharging), Some(Urgency::Normal));
+
+        // A first reading already below warns straight away.
+        let mut fresh = Notifications::new(NotifySettings::default());
+        let note = fresh
+            .notification(&battery(3, ChargingState::Discharging), "G502")
+            .unwrap();
+        assert_eq!(note.summary, "G502 battery low");
+        assert_eq!(note.body, "3% remaining");
+    }
+
+    #[test]
+    fn toggles_and_settings_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SETTINGS_FILE);
+        assert_eq!(
+            NotifySettings::load(&path).unwrap(),
+            NotifySettings::default()
+        );
+
+        std::fs::write(&path, r#"{"dpi": false, "low_battery": []}"#).unwrap();
+        let settings = NotifySettings::load(&path).unwrap();
+        assert!(!settings.dpi && settings.profile);
+        let mut notes = Notifications::new(settings);
+        let dpi = DaemonEvent::Dpi {
+            device: "sim".into(),
+            stage: 1,
+            dpi: Some(800),
+        };
+        assert_eq!(notes.notification(&dpi, "G502"), None);
+        assert_eq!(
+            notes.notification(&battery(1, ChargingState::Discharging), "G502"),
+            None
+        );
+        let profile = notes
+            .notification(
+                &DaemonEvent::Profile {
+                    name: "Gaming".into(),
+                },
+                "G502",
+            )
+            .unwrap();
+        assert_eq!(profile.topic, Topic::Profile);
+        assert_eq!(profile.summary, "Profile: Gaming");
+
+        std::fs::write(&path, r#"{"low_battery": [0]}"#).unwrap();
+        assert!(matches!(
+            NotifySettings::load(&path),
+            Err(Error::OutOfRange {
+                field: "low_battery",
+                ..
+            })
+        ));
+    }
+}
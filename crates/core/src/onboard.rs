@@ -3,19 +3,29 @@
 //! HID++ 2.0 ONBOARD_PROFILES functions:
 //!   - Function 0: getDescription → memory model, profile count, button count, etc.
 //!   - Function 1: setOnboardMode(mode) → 1=host-mode, 2=onboard-mode
-//!   - Function 2: getCurrentProfile → currently active profile page/offset
+//!   - Function 2: getOnboardMode → params[0] = mode
 //!   - Function 3: setCurrentProfile(page, offset) → switch active profile
+//!   - Function 4: getCurrentProfile → currently active profile page/offset
+//!   - Function 5: memoryRead(sector, offset) → 16 bytes of profile memory
 //!
-//! For G502: typically 1 onboard profile at page 0, offset 0.
+//! For G502: typically 1 onboard profile at page 0, offset 1. Page and offset
+//! together form the big-endian sector number of the profile in memory.
 //! "Host mode" means the computer controls settings; "onboard mode" means
 //! the mouse uses its stored profile.
+//!
+//! Profile sector layout (first 16 bytes, as in libratbag):
+//!   - [0] report interval in ms
+//!   - [1] default DPI stage, [2] DPI-shift stage
+//!   - [3..13] five DPI stages, little-endian; 0 or 0xFFFF = unused
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::hidpp::{self, HidppRequest};
 use crate::transport::{hidpp_request, lookup_feature_index, HidTransport};
+use serde::{Deserialize, Serialize};
 
 /// Onboard profile mode.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum OnboardMode {
     /// Host controls settings (software manages device).
     Host = 1,
@@ -33,8 +43,19 @@ impl OnboardMode {
     }
 }
 
+/// Bytes returned by one memoryRead.
+pub const MEMORY_READ_LEN: usize = 16;
+
+/// Number of DPI stage slots in a profile sector.
+const DPI_STAGE_SLOTS: usize = 5;
+
+/// Reply too short to decode.
+const MALFORMED: Error = Error::MalformedReply {
+    feature: hidpp::features::ONBOARD_PROFILES,
+};
+
 /// Description of the device's onboard profile capabilities.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProfileDescription {
     /// Memory model (device-specific).
     pub memory_model: u8,
@@ -82,6 +103,21 @@ pub fn set_onboard_mode(
     Ok(())
 }
 
+/// Read whether the mouse runs from host settings or its onboard profile.
+pub fn get_onboard_mode(transport: &dyn HidTransport, device_index: u8) -> Result<OnboardMode> {
+    let feature_idx =
+        lookup_feature_index(transport, device_index, hidpp::features::ONBOARD_PROFILES)?;
+
+    // getOnboardMode: function 2
+    let req = HidppRequest::new(device_index, feature_idx, 0x02, vec![]);
+    let resp = hidpp_request(transport, &req)?;
+
+    resp.params
+        .first()
+        .and_then(|&b| OnboardMode::from_byte(b))
+        .ok_or(MALFORMED)
+}
+
 /// Read the currently active profile index.
 ///
 /// Returns (page, offset) identifying the active profile in device memory.
@@ -89,8 +125,8 @@ pub fn get_current_profile(transport: &dyn HidTransport, device_index: u8) -> Re
     let feature_idx =
         lookup_feature_index(transport, device_index, hidpp::features::ONBOARD_PROFILES)?;
 
-    // getCurrentProfile: function 2
-    let req = HidppRequest::new(device_index, feature_idx, 0x02, vec![]);
+    // getCurrentProfile: function 4
+    let req = HidppRequest::new(device_index, feature_idx, 0x04, vec![]);
     let resp = hidpp_request(transport, &req)?;
 
     Ok((resp.params[0], resp.params[1]))
@@ -113,6 +149,85 @@ pub fn set_current_profile(
     Ok(())
 }
 
+/// Read 16 bytes of onboard memory at `offset` within `sector`.
+pub fn read_memory(
+    transport: &dyn HidTransport,
+    device_index: u8,
+    sector: u16,
+    offset: u16,
+) -> Result<[u8; MEMORY_READ_LEN]> {
+    let feature_idx =
+        lookup_feature_index(transport, device_index, hidpp::features::ONBOARD_PROFILES)?;
+
+    // memoryRead: function 5, params = [sector_hi, sector_lo, offset_hi, offset_lo]
+    let [sector_hi, sector_lo] = sector.to_be_bytes();
+    let [offset_hi, offset_lo] = offset.to_be_bytes();
+    let req = HidppRequest::new(
+        device_index,
+        feature_idx,
+        0x05,
+        vec![sector_hi, sector_lo, offset_hi, offset_lo],
+    );
+    let resp = hidpp_request(transport, &req)?;
+
+    resp.params
+        .get(..MEMORY_READ_LEN)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(MALFORMED)
+}
+
+/// DPI stages stored in an onboard profile.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DpiStages {
+    /// DPI of each used stage, in cycling order.
+    pub stages: Vec<u16>,
+    /// Index into `stages` of the default stage.
+    pub default_stage: usize,
+}
+
+impl DpiStages {
+    /// Decode the start of a profile sector.
+    fn decode(sector: &[u8; MEMORY_READ_LEN]) -> Self {
+        let stages: Vec<u16> = sector[3..3 + 2 * DPI_STAGE_SLOTS]
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .take_while(|&dpi| dpi != 0 && dpi != 0xFFFF)
+            .collect();
+        let default_stage = (sector[1] as usize).min(stages.len().saturating_sub(1));
+        Self {
+            stages,
+            default_stage,
+        }
+    }
+}
+
+/// Read the DPI stages of the active onboard profile.
+pub fn read_dpi_stages(transport: &dyn HidTransport, device_index: u8) -> Result<DpiStages> {
+    let (page, offset) = get_current_profile(transport, device_index)?;
+    let sector = read_memory(
+        transport,
+        device_index,
+        u16::from_be_bytes([page, offset]),
+        0,
+    )?;
+    Ok(DpiStages::decode(&sector))
+}
+
+/// Decode a DPI stage change broadcast (ONBOARD_PROFILES event 1), sent
+/// when the DPI buttons switch stages in onboard mode.
+///
+/// Returns the new stage index, or `None` if the report is not such an
+/// event for `feature_idx`.
+pub fn parse_dpi_stage_event(report: &hidpp::HidppResponse, feature_idx: u8) -> Option<u8> {
+    if report.feature_index != feature_idx
+        || report.function() != 0x01
+        || report.function_sw & 0x0F != 0
+    {
+        return None;
+    }
+    report.params.first().copied()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -196,14 +311,44 @@ mod tests {
         mock.on_short_request(
             DEV_IDX,
             PROFILE_FEATURE_IDX,
-            0x21, // function=2 << 4 | sw_id=1
+            0x41, // function=4 << 4 | sw_id=1
             &[],
-            &[0x00, 0x00, 0x00], // page=0, offset=0
+            &[0x00, 0x01, 0x00], // page=0, offset=1
         );
 
         let (page, offset) = get_current_profile(&mock, DEV_IDX).unwrap();
         assert_eq!(page, 0);
-        assert_eq!(offset, 0);
+        assert_eq!(offset, 1);
+    }
+
+    #[test]
+    fn get_onboard_mode_decodes_mode() {
+        let mock = MockTransport::new();
+        setup_profile_feature_lookup(&mock);
+        mock.on_short_request(DEV_IDX, PROFILE_FEATURE_IDX, 0x21, &[], &[0x01, 0x00, 0x00]);
+        assert_eq!(get_onboard_mode(&mock, DEV_IDX).unwrap(), OnboardMode::Host);
+    }
+
+    #[test]
+    fn read_dpi_stages_decodes_profile_sector() {
+        let mock = MockTransport::new();
+        setup_profile_feature_lookup(&mock);
+        mock.on_short_request(DEV_IDX, PROFILE_FEATURE_IDX, 0x41, &[], &[0x00, 0x02, 0x00]);
+        // Sector 2: 1 ms, default stage 2, stages 400/800/1600 then unused.
+        mock.on_long_request(
+            DEV_IDX,
+            PROFILE_FEATURE_IDX,
+            0x51,
+            &[0x00, 0x02, 0x00, 0x00],
+            &[
+                0x01, 0x02, 0x00, 0x90, 0x01, 0x20, 0x03, 0x40, 0x06, 0xFF, 0xFF, 0x00, 0x00, 0xFF,
+                0x00, 0x00,
+            ],
+        );
+
+        let stages = read_dpi_stages(&mock, DEV_IDX).unwrap();
+        assert_eq!(stages.stages, [400, 800, 1600]);
+        assert_eq!(stages.default_stage, 2);
     }
 
     #[test]
@@ -222,6 +367,31 @@ mod tests {
         set_current_profile(&mock, DEV_IDX, 0, 0).unwrap();
     }
 
+    #[test]
+    fn dpi_stage_events_are_told_apart_from_replies() {
+        let event = |function_sw| hidpp::HidppResponse {
+            is_long: false,
+            device_index: DEV_IDX,
+            feature_index: PROFILE_FEATURE_IDX,
+            function_sw,
+            params: vec![0x02, 0x00, 0x00],
+        };
+        assert_eq!(
+            parse_dpi_stage_event(&event(0x10), PROFILE_FEATURE_IDX),
+            Some(2)
+        );
+        // setOnboardMode reply, profile change event, other feature.
+        assert_eq!(
+            parse_dpi_stage_event(&event(0x11), PROFILE_FEATURE_IDX),
+            None
+        );
+        assert_eq!(
+            parse_dpi_stage_event(&event(0x00), PROFILE_FEATURE_IDX),
+            None
+        );
+        assert_eq!(parse_dpi_stage_event(&event(0x10), 0x03), None);
+    }
+
     #[test]
     fn onboard_mode_from_byte() {
         assert_eq!(OnboardMode::from_byte(1), Some(OnboardMode::Host));
@@ -0,0 +1,320 @@
+//! Minimal apply plans: the writes needed to turn one profile into another.
+//!
+//! [`plan`] compares a target [`Profile`] with what the device has now
+//! (usually a [`crate::profile::snapshot`]) and lists only the settings that
+//! differ, in the order [`crate::profile::apply_profile`] writes them: DPI,
+//! further sensors, report rate, buttons, LEDs. Every target value goes
+//! through `safety` while planning, so a plan that builds can be executed
+//! without surprises, and a dry run shows exactly what would be sent.
+//!
+//! DPI stages and onboard mode are not host-mode settings and never appear
+//! in a plan.
+
+use crate::device::{ButtonAction, PollingRate};
+use crate::error::Result;
+use crate::led::{LedEffect, LedMode};
+use crate::profile::Profile;
+use crate::transport::HidTransport;
+use crate::{buttons, dpi, led, report_rate, safety};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// One device write, with the value it replaces.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "setting", rename_all = "snake_case")]
+pub enum Write {
+    Dpi {
+        from: u16,
+        to: u16,
+    },
+    /// DPI of sensor 1 onwards; sensor 0 is [`Write::Dpi`].
+    SensorDpi {
+        sensor: u8,
+        from: Option<u16>,
+        to: u16,
+    },
+    ReportRate {
+        from: PollingRate,
+        to: PollingRate,
+    },
+    Button {
+        index: usize,
+        from: Option<ButtonAction>,
+        to: ButtonAction,
+    },
+    Led {
+        zone: u8,
+        from: Option<LedEffect>,
+        to: LedEffect,
+    },
+}
+
+impl Write {
+    /// Send this write to the device.
+    pub fn execute(&self, transport: &dyn HidTransport, device_index: u8) -> Result<()> {
+        match self {
+            Self::Dpi { to, .. } => dpi::write_dpi(transport, device_index, *to).map(|_| ()),
+            Self::SensorDpi { sensor, to, .. } => {
+                dpi::write_sensor_dpi(transport, device_index, *sensor, *to).map(|_| ())
+            }
+            Self::ReportRate { to, .. } => {
+                report_rate::write_report_rate(transport, device_index, *to)
+            }
+            Self::Button { index, to, .. } => {
+                buttons::write_button_mapping(transport, device_index, *index, *to)
+            }
+            Self::Led { zone, to, .. } => led::write_led_effect(transport, device_index, *zone, to),
+        }
+    }
+}
+
+fn or_unknown<T: ToString>(value: Option<T>) -> String {
+    value.map_or("?".to_string(), |v| v.to_string())
+}
+
+fn describe_led(effect: &LedEffect) -> String {
+    let [r, g, b] = effect.color;
+    match effect.mode {
+        LedMode::Off => "off".to_string(),
+        LedMode::Solid => format!("solid #{r:02X}{g:02X}{b:02X}"),
+        LedMode::Cycle => format!("cycle {} ms, {}%", effect.duration_ms, effect.brightness),
+        LedMode::Breathing => format!(
+            "breathing #{r:02X}{g:02X}{b:02X} {} ms, {}%",
+            effect.duration_ms, effect.brightness
+        ),
+    }
+}
+
+impl fmt::Display for Write {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dpi { from, to } => write!(f, "DPI: {from} -> {to}"),
+            Self::SensorDpi { sensor, from, to } => {
+                write!(f, "Sensor {sensor} DPI: {} -> {to}", or_unknown(*from))
+            }
+            Self::ReportRate { from, to } => write!(f, "Polling rate: {from} -> {to}"),
+            Self::Button { index, from, to } => {
+                write!(f, "Button {index}: {} -> {to}", or_unknown(*from))
+            }
+            Self::Led { zone, from, to } => write!(
+                f,
+                "LED zone {zone}: {} -> {}",
+                or_unknown(from.as_ref().map(describe_led)),
+                describe_led(to)
+            ),
+        }
+    }
+}
+
+/// Ordered writes that bring a device from one profile to another.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    pub writes: Vec<Write>,
+}
+
+impl Plan {
+    /// Whether the device already matches the target.
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    /// Run every write in order, stopping at the first failure.
+    pub fn execute(&self, transport: &dyn HidTransport, device_index: u8) -> Result<()> {
+        for write in &self.writes {
+            write.execute(transport, device_index)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("Nothing to change.");
+        }
+        for (i, write) in self.writes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}. {write}", i + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Plan the writes that turn `current` into `target`.
+///
+/// Fails on the first target value `safety` rejects. Values `safety` adjusts
+/// (DPI rounding) are planned as adjusted, so a DPI that rounds to the
+/// current value is not rewritten.
+pub fn plan(current: &Profile, target: &Profile) -> Result<Plan> {
+    let mut writes = Vec::new();
+
+    let dpi = safety::validate_dpi(target.dpi)?;
+    if dpi != current.dpi {
+        writes.push(Write::Dpi {
+            from: current.dpi,
+            to: dpi,
+        });
+    }
+
+    for (i, &value) in target.extra_sensor_dpi.iter().enumerate() {
+        let to = safety::validate_dpi(value)?;
+        let from = current.extra_sensor_dpi.get(i).copied();
+        if from != Some(to) {
+            writes.push(Write::SensorDpi {
+                sensor: i as u8 + 1,
+                from,
+                to,
+            });
+        }
+    }
+
+    if target.polling_rate != current.polling_rate {
+        writes.push(Write::ReportRate {
+            from: current.polling_rate,
+            to: target.polling_rate,
+        });
+    }
+
+    // Controls past the remappable buttons may be recorded, not changed.
+    for (index, &to) in target.buttons.iter().enumerate() {
+        let from = current.buttons.get(index).copied();
+        if from != Some(to) {
+            safety::validate_button_index(index)?;
+            writes.push(Write::Button { index, from, to });
+        }
+    }
+
+    for (zone, effect) in target.leds.iter().enumerate() {
+        let zone = u8::try_from(zone).unwrap_or(u8::MAX);
+        safety::validate_led_zone(zone)?;
+        let to = safety::validate_led_effect(effect)?.normalized();
+        let from = current.leds.get(zone as usize).map(LedEffect::normalized);
+        if from != Some(to) {
+            writes.push(Write::Led { zone, from, to });
+        }
+    }
+
+    Ok(Plan { writes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::sim::SimulatedG502;
+
+    #[test]
+    fn identical_profiles_need_no_writes() {
+        let profile = Profile::default();
+        let plan = plan(&profile, &profile).unwrap();
+        assert!(plan.is_empty());
+        assert_eq!(plan.to_string(), "Nothing to change.");
+    }
+
+    #[test]
+    fn only_differences_are_planned_in_apply_order() {
+        let current = Profile::default();
+        let mut target = current.clone();
+        target.leds = vec![LedEffect {
+            mode: LedMode::Solid,
+            color: [0xFF, 0x00, 0x00],
+            ..Default::default()
+        }];
+        target.buttons[3] = ButtonAction::Forward;
+        target.dpi = 1612; // rounds to 1600
+        target.polling_rate = PollingRate::Hz500;
+
+        let plan = plan(&current, &target).unwrap();
+        assert_eq!(
+            plan.writes,
+            vec![
+                Write::Dpi {
+                    from: 800,
+                    to: 1600
+                },
+                Write::ReportRate {
+                    from: PollingRate::Hz1000,
+                    to: PollingRate::Hz500
+                },
+                Write::Button {
+                    index: 3,
+                    from: Some(ButtonAction::Back),
+                    to: ButtonAction::Forward
+                },
+                Write::Led {
+                    zone: 0,
+                    from: None,
+                    to: target.leds[0].normalized()
+                },
+            ]
+        );
+        assert_eq!(
+            plan.to_string().lines().nth(2),
+            Some("3. Button 3: Back -> Forward")
+        );
+    }
+
+    #[test]
+    fn unsafe_targets_fail_planning() {
+        let current = Profile::default();
+        let too_fast = Profile {
+            dpi: 50_000,
+            ..current.clone()
+        };
+        assert!(matches!(
+            plan(&current, &too_fast),
+            Err(Error::OutOfRange { field: "dpi", .. })
+        ));
+
+        let mut extra_button = current.clone();
+        extra_button.buttons.push(ButtonAction::NoAction);
+        assert!(plan(&current, &extra_button).is_err());
+
+        // A recorded control past the remappable buttons is fine unchanged.
+        let mut recorded = current.clone();
+        recorded.buttons.push(ButtonAction::DpiCycleUp);
+        assert!(plan(&recorded, &recorded).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unused_led_fields_do_not_cause_writes() {
+        let solid = LedEffect {
+            mode: LedMode::Solid,
+            color: [1, 2, 3],
+            duration_ms: 1234,
+            brightness: 7,
+        };
+        let current = Profile {
+            leds: vec![solid.normalized()],
+            ..Default::default()
+        };
+        let target = Profile {
+            leds: vec![solid],
+            ..Default::default()
+        };
+        assert!(plan(&current, &target).unwrap().is_empty());
+    }
+
+    #[test]
+    fn executing_a_plan_reaches_the_target() {
+        let sim = SimulatedG502::new(0x01);
+        let current = crate::profile::snapshot(&sim, 0x01).unwrap();
+        let mut target = current.clone();
+        target.dpi = 3200;
+        target.buttons[5] = ButtonAction::NoAction;
+
+        let before = sim.write_count();
+        let steps = plan(&current, &target).unwrap();
+        steps.execute(&sim, 0x01).unwrap();
+        assert_eq!(sim.write_count() - before, 2);
+
+        let after = crate::profile::snapshot(&sim, 0x01).unwrap();
+        assert!(plan(&after, &target).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+//! Battery-aware power policies.
+//!
+//! A profile's `power` rules trade responsiveness for battery life while a
+//! wireless mouse runs on battery:
+//!
+//! ```json
+//! "power": [
+//!   {"below": 20, "polling_rate": "Hz500"},
+//!   {"below": 10, "led_brightness": 0}
+//! ]
+//! ```
+//!
+//! A rule applies while the mouse is discharging and its level is under
+//! `below` percent. Rules only ever lower settings: the report rate drops to
+//! `polling_rate` if the profile's is higher, and the profile's LED effects
+//! are dimmed to `led_brightness` percent, `0` turning them off. When
+//! several rules apply, the lowest limit wins. Plugging in a charger, or the
+//! level rising again, restores the profile as written. Mice without a
+//! reading are treated as powered.
+//!
+//! [`PowerLimits::adjust`] gives the profile to write; the daemon applies
+//! it with [`crate::profile::apply_changes`] on every battery notification
+//! that changes the limits, so a switch writes the report rate
+//! ([`crate::report_rate::write_report_rate`]) and LEDs only when they move.
+
+use crate::battery::BatteryStatus;
+use crate::device::PollingRate;
+use crate::error::Result;
+use crate::led::{LedEffect, LedMode};
+use crate::profile::Profile;
+use crate::safety;
+use serde::{Deserialize, Serialize};
+
+/// One battery rule of a profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowerRule {
+    /// Applies while discharging below this level, in percent (1-100).
+    pub below: u8,
+    /// Highest report rate while the rule applies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub polling_rate: Option<PollingRate>,
+    /// Highest LED brightness in percent while the rule applies; 0 turns the
+    /// LEDs off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub led_brightness: Option<u8>,
+}
+
+impl PowerRule {
+    /// Whether the rule applies to `battery`.
+    pub fn applies(&self, battery: &BatteryStatus) -> bool {
+        !battery.state.is_charging() && battery.level < self.below
+    }
+}
+
+/// The limits in force for one battery reading.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PowerLimits {
+    pub polling_rate: Option<PollingRate>,
+    pub led_brightness: Option<u8>,
+}
+
+impl PowerLimits {
+    /// Combine the `rules` that apply to `battery`, the lowest limit of each
+    /// kind winning. No reading means no limits. Fails on a rule outside the
+    /// safe ranges.
+    pub fn for_battery(rules: &[PowerRule], battery: Option<&BatteryStatus>) -> Result<Self> {
+        let mut limits = Self::default();
+        for rule in rules {
+            safety::validate_power_rule(rule)?;
+            if !battery.is_some_and(|b| rule.applies(b)) {
+                continue;
+            }
+            if let Some(rate) = rule.polling_rate {
+                limits.polling_rate = Some(match limits.polling_rate {
+                    Some(limit) if limit.as_hz() < rate.as_hz() => limit,
+                    _ => rate,
+                });
+            }
+            if let Some(brightness) = rule.led_brightness {
+                limits.led_brightness = Some(
+                    limits
+                        .led_brightness
+                        .map_or(brightness, |l| l.min(brightness)),
+                );
+            }
+        }
+        Ok(limits)
+    }
+
+    /// Whether nothing is limited.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// `profile` with these limits applied.
+    pub fn adjust(&self, profile: &Profile) -> Profile {
+        let mut adjusted = profile.clone();
+        if let Some(limit) = self.polling_rate {
+            if limit.as_hz() < profile.polling_rate.as_hz() {
+                adjusted.polling_rate = limit;
+            }
+        }
+        if let Some(brightness) = self.led_brightness {
+            for effect in &mut adjusted.leds {
+                *effect = dim(effect, brightness);
+            }
+        }
+        adjusted
+    }
+}
+
+/// `effect` at no more than `percent` brightness. Solid colors have no
+/// brightness setting, so their color is scaled instead.
+fn dim(effect: &LedEffect, percent: u8) -> LedEffect {
+    if percent == 0 {
+        return LedEffect {
+            mode: LedMode::Off,
+            ..*effect
+        };
+    }
+    match effect.mode {
+        LedMode::Off => *effect,
+        LedMode::Solid => LedEffect {
+            color: effect
+                .color
+                .map(|c| (u16::from(c) * u16::from(percent.min(100)) / 100) as u8),
+            ..*effect
+        },
+        LedMode::Cycle | LedMode::Breathing => LedEffect {
+            brightness: effect.brightness.min(percent),
+            ..*effect
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::ChargingState;
+    use crate::error::Error;
+
+    fn battery(level: u8, state: ChargingState) -> BatteryStatus {
+        BatteryStatus {
+            level,
+            next_level: level.saturating_sub(5),
+            state,
+        }
+    }
+
+    fn rules() -> Vec<PowerRule> {
+        serde_json::from_str(
+            r#"[{"below": 20, "polling_rate": "Hz500"},
+                {"below": 10, "polling_rate": "Hz250", "led_brightness": 0},
+                {"below": 30, "led_brightness": 50}]"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn lowest_limit_of_the_applying_rules_wins() {
+        let rules = rules();
+        let at = |level, state| PowerLimits::for_battery(&rules, Some(&battery(level, state)));
+        assert!(at(80, ChargingState::Discharging).unwrap().is_empty());
+        assert_eq!(
+            at(25, ChargingState::Discharging).unwrap(),
+            PowerLimits {
+                polling_rate: None,
+                led_brightness: Some(50),
+            }
+        );
+        assert_eq!(
+            at(15, ChargingState::Discharging).unwrap().polling_rate,
+            Some(PollingRate::Hz500)
+        );
+        assert_eq!(
+            at(5, ChargingState::Discharging).unwrap(),
+            PowerLimits {
+                polling_rate: Some(PollingRate::Hz250),
+                led_brightness: Some(0),
+            }
+        );
+        assert!(at(5, ChargingState::Recharging).unwrap().is_empty());
+        assert!(PowerLimits::for_battery(&rules, None).unwrap().is_empty());
+
+        let bad = [PowerRule {
+            below: 120,
+            polling_rate: None,
+            led_brightness: None,
+        }];
+        assert!(matches!(
+            PowerLimits::for_battery(&bad, None),
+            Err(Error::OutOfRange {
+                field: "power_below",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn adjust_only_lowers_settings() {
+        let profile = Profile {
+            polling_rate: PollingRate::Hz1000,
+            leds: vec![
+                LedEffect {
+                    mode: LedMode::Solid,
+                    color: [200, 100, 0],
+                    ..Default::default()
+                },
+                LedEffect {
+                    mode: LedMode::Breathing,
+                    brightness: 30,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let limits = PowerLimits {
+            polling_rate: Some(PollingRate::Hz500),
+            led_brightness: Some(50),
+        };
+        let dimmed = limits.adjust(&profile);
+        assert_eq!(dimmed.polling_rate, PollingRate::Hz500);
+        assert_eq!(dimmed.leds[0].color, [100, 50, 0]);
+        assert_eq!(dimmed.leds[1].brightness, 30);
+
+        let slow = Profile {
+            polling_rate: PollingRate::Hz125,
+            ..profile.clone()
+        };
+        assert_eq!(limits.adjust(&slow).polling_rate, PollingRate::Hz125);
+
+        let off = PowerLimits {
+            led_brightness: Some(0),
+            ..Default::default()
+        };
+        assert!(off
+            .adjust(&profile)
+            .leds
+            .iter()
+            .all(|e| e.mode == LedMode::Off));
+        assert_eq!(PowerLimits::default().adjust(&profile).leds, profile.leds);
+    }
+}
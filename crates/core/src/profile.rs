@@ -1,8 +1,18 @@
 //! Logitech G Hub profile compatibility layer.
 
-use crate::device::{ButtonAction, PollingRate};
+use crate::debounce::DebounceSettings;
+use crate::device::{ButtonAction, PollingRate, G502_BUTTON_COUNT};
 use crate::error::{Error, Result};
+use crate::led::LedEffect;
+use crate::macros::Macro;
+use crate::onboard::OnboardMode;
+use crate::plan;
+use crate::power::PowerRule;
+use crate::transaction::{self, ApplyReport};
+use crate::transport::HidTransport;
+use crate::{buttons, dpi, ghub, led, onboard, report_rate, safety, wine};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 /// A saved mouse configuration profile.
@@ -10,12 +20,47 @@ use std::path::PathBuf;
 pub struct Profile {
     /// Profile display name.
     pub name: String,
-    /// DPI setting.
+    /// DPI setting (of the first sensor).
     pub dpi: u16,
+    /// DPI of further sensors on multi-sensor mice, sensor 1 first.
+    #[serde(default)]
+    pub extra_sensor_dpi: Vec<u16>,
+    /// DPI stages the mouse cycles through. Empty when unknown.
+    ///
+    /// Stages live in onboard memory or in G Hub, so they are recorded but
+    /// not written by [`apply_profile`].
+    #[serde(default)]
+    pub dpi_stages: Vec<u16>,
+    /// Index into `dpi_stages` of the default stage.
+    #[serde(default)]
+    pub default_dpi_stage: usize,
     /// Polling rate.
     pub polling_rate: PollingRate,
     /// Button mappings (index = physical button, value = action).
     pub buttons: Vec<ButtonAction>,
+    /// Raw HID++ control IDs by button index, for remaps no
+    /// [`ButtonAction`] names. [`apply_profile`] writes them in place of the
+    /// button's `buttons` entry, so they come back on every reconnect.
+    #[serde(default)]
+    pub button_cids: BTreeMap<usize, u16>,
+    /// Host-side click debounce settings.
+    #[serde(default)]
+    pub debounce: DebounceSettings,
+    /// Host-side macros by button index. open-g-hubd diverts these buttons
+    /// and plays the macro instead of the button's own action. Never written
+    /// to the mouse.
+    #[serde(default)]
+    pub button_macros: BTreeMap<usize, Macro>,
+    /// LED effects (index = zone). Empty leaves the LEDs as they are.
+    #[serde(default)]
+    pub leds: Vec<LedEffect>,
+    /// Battery rules lowering the report rate and LEDs (see [`crate::power`]).
+    #[serde(default)]
+    pub power: Vec<PowerRule>,
+    /// Onboard mode when the profile was captured. Not changed by
+    /// [`apply_profile`].
+    #[serde(default)]
+    pub onboard_mode: Option<OnboardMode>,
 }
 
 impl Default for Profile {
@@ -23,6 +68,9 @@ impl Default for Profile {
         Self {
             name: "Default".into(),
             dpi: 800,
+            extra_sensor_dpi: Vec::new(),
+            dpi_stages: Vec::new(),
+            default_dpi_stage: 0,
             polling_rate: PollingRate::Hz1000,
             buttons: vec![
                 ButtonAction::LeftClick,
@@ -32,19 +80,132 @@ impl Default for Profile {
                 ButtonAction::Forward,
                 ButtonAction::DpiCycleUp,
             ],
+            debounce: DebounceSettings::default(),
+            button_cids: BTreeMap::new(),
+            button_macros: BTreeMap::new(),
+            leds: Vec::new(),
+            power: Vec::new(),
+            onboard_mode: None,
         }
     }
 }
 
+/// Write a profile's host-mode settings (DPI, polling rate, buttons, LEDs) to a device.
+///
+/// Stops at the first failed write. Debounce settings are host-side and are
+/// not sent to the device.
+pub fn apply_profile(
+    transport: &dyn HidTransport,
+    device_index: u8,
+    profile: &Profile,
+) -> Result<()> {
+    dpi::write_dpi(transport, device_index, profile.dpi)?;
+    for (sensor, &value) in profile.extra_sensor_dpi.iter().enumerate() {
+        dpi::write_sensor_dpi(transport, device_index, sensor as u8 + 1, value)?;
+    }
+    report_rate::write_report_rate(transport, device_index, profile.polling_rate)?;
+    // Controls past the remappable buttons are recorded, never written.
+    for (index, action) in profile.buttons.iter().enumerate().take(G502_BUTTON_COUNT) {
+        match profile.button_cids.get(&index) {
+            Some(&cid) => buttons::write_button_mapping_cid(transport, device_index, index, cid)?,
+            None => buttons::write_button_mapping(transport, device_index, index, *action)?,
+        }
+    }
+    for (zone, effect) in profile.leds.iter().enumerate() {
+        led::write_led_effect(transport, device_index, zone as u8, effect)?;
+    }
+    Ok(())
+}
+
+/// Write only the settings where the device differs from `profile`.
+///
+/// Reads a [`snapshot`], plans the difference (see [`crate::plan`]) and runs
+/// it as a transaction: if a write fails, the earlier ones are rolled back.
+/// Errors reading the device or planning fail the call; how the writes went
+/// is in the returned report.
+pub fn apply_changes(
+    transport: &dyn HidTransport,
+    device_index: u8,
+    profile: &Profile,
+) -> Result<ApplyReport> {
+    let current = snapshot(transport, device_index)?;
+    let plan = plan::plan(&current, profile)?;
+    Ok(transaction::apply(transport, device_index, &plan))
+}
+
+/// Most sensors a profile records.
+const MAX_SENSORS: u8 = 4;
+
+/// `read` as `None` when the mouse lacks the feature it needs.
+fn if_supported<T>(read: Result<T>) -> Result<Option<T>> {
+    match read {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::UnsupportedFeature(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Capture everything a profile holds from a live device.
+///
+/// Reads every sensor's DPI, the report rate, the mapping of every control
+/// (including those past the remappable buttons), the LED zones, and, from
+/// the onboard profile feature, the onboard mode and the DPI stages of the
+/// active onboard profile. Mice without RGB zones get an empty LED list;
+/// mice without onboard profiles get no mode and no stages. Any other
+/// failure fails the snapshot.
+pub fn snapshot(transport: &dyn HidTransport, device_index: u8) -> Result<Profile> {
+    let dpi = dpi::read_dpi(transport, device_index)?;
+    let sensors = dpi::read_sensor_count(transport, device_index)?.min(MAX_SENSORS);
+    let extra_sensor_dpi = (1..sensors)
+        .map(|sensor| dpi::read_sensor_dpi(transport, device_index, sensor))
+        .collect::<Result<Vec<_>>>()?;
+    let polling_rate = report_rate::read_report_rate(transport, device_index)?;
+    let controls = buttons::read_control_count(transport, device_index)?;
+    let buttons = (0..controls)
+        .map(|index| buttons::read_control_mapping(transport, device_index, index))
+        .collect::<Result<Vec<_>>>()?;
+    let zones = if_supported(led::read_zone_count(transport, device_index))?
+        .unwrap_or(0)
+        .min(safety::G502_LED_ZONE_COUNT);
+    let leds = (0..zones)
+        .map(|zone| led::read_led_effect(transport, device_index, zone))
+        .collect::<Result<Vec<_>>>()?;
+    let onboard_mode = if_supported(onboard::get_onboard_mode(transport, device_index))?;
+    let stages = match onboard_mode {
+        Some(_) => onboard::read_dpi_stages(transport, device_index)?,
+        None => Default::default(),
+    };
+    Ok(Profile {
+        name: "Device".into(),
+        dpi,
+        extra_sensor_dpi,
+        dpi_stages: stages.stages,
+        default_dpi_stage: stages.default_stage,
+        polling_rate,
+        buttons,
+        leds,
+        onboard_mode,
+        ..Default::default()
+    })
+}
+
 /// Logitech G Hub profile storage location.
 ///
+/// `$OPEN_G_HUB_GHUB_SETTINGS` if set. Otherwise `%LOCALAPPDATA%\\LGHUB\\settings.db`
+/// on Windows, and the G Hub install found in a Wine prefix elsewhere (see
+/// [`wine::settings_path`]).
+///
 /// Open G Hub no longer supports a separate `open-g-hub/profile.json` fallback.
 /// Persistence must use Logitech G Hub's storage contract.
 pub fn profile_path() -> Result<PathBuf> {
+    if let Some(path) = std::env::var_os(wine::SETTINGS_ENV).filter(|p| !p.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+
     #[cfg(target_os = "windows")]
     {
         let local_app_data = std::env::var_os("LOCALAPPDATA")
-            .ok_or_else(|| Error::Profile("LOCALAPPDATA is not set".to_string()))?;
+            .ok_or_else(|| crate::error::Error::Profile("LOCALAPPDATA is not set".to_string()))?;
         Ok(PathBuf::from(local_app_data)
             .join("LGHUB")
             .join("settings.db"))
@@ -52,34 +213,25 @@ pub fn profile_path() -> Result<PathBuf> {
 
     #[cfg(not(target_os = "windows"))]
     {
-        Err(Error::Profile(
-            "Logitech G Hub-compatible profile storage is only available on Windows".to_string(),
-        ))
+        wine::settings_path()
     }
 }
 
-/// Save a profile to Logitech G Hub storage.
+/// Save a profile into Logitech G Hub storage.
 ///
-/// Legacy storage is removed; this now fails fast until direct G Hub format writes
-/// are implemented.
-pub fn save_profile(_profile: &Profile) -> Result<()> {
-    let path = profile_path()?;
-    Err(Error::Profile(format!(
-        "legacy open-g-hub profile storage has been removed; direct Logitech G Hub format write not implemented yet (expected location: {})",
-        path.display()
-    )))
+/// Merges into the G Hub profile of the same name after backing up the
+/// database (see [`ghub::save_profile`]).
+pub fn save_profile(profile: &Profile) -> Result<()> {
+    ghub::save_profile(&profile_path()?, profile, None)?;
+    Ok(())
 }
 
-/// Load a profile from Logitech G Hub storage.
+/// Load the active profile from Logitech G Hub storage.
 ///
-/// Legacy storage is removed; this now fails fast until direct G Hub format reads
-/// are implemented.
+/// See [`ghub::load_profile`] for reading a specific profile or a copied
+/// `settings.db`.
 pub fn load_profile() -> Result<Profile> {
-    let path = profile_path()?;
-    Err(Error::Profile(format!(
-        "legacy open-g-hub profile storage has been removed; direct Logitech G Hub format read not implemented yet (expected location: {})",
-        path.display()
-    )))
+    ghub::load_profile(&profile_path()?, None)
 }
 
 #[cfg(test)]
@@ -102,10 +254,73 @@ mod tests {
     }
 
     #[test]
-    fn profile_path_non_windows_is_rejected() {
-        #[cfg(not(target_os = "windows"))]
-        {
-            assert!(profile_path().is_err());
-        }
+    fn profile_without_debounce_field_still_parses() {
+        let json = r#"{"name":"Old","dpi":1600,"polling_rate":"Hz500","buttons":[]}"#;
+        let profile: Profile = serde_json::from_str(json).expect("deserialize profile");
+        assert_eq!(profile.debounce, DebounceSettings::default());
+    }
+
+    #[test]
+    fn apply_profile_writes_every_setting() {
+        let sim = crate::sim::SimulatedG502::new(0x01);
+        let profile = Profile {
+            dpi: 2400,
+            polling_rate: PollingRate::Hz250,
+            buttons: vec![ButtonAction::LeftClick, ButtonAction::MiddleClick],
+            leds: vec![LedEffect {
+                mode: crate::led::LedMode::Solid,
+                color: [0xFF, 0x00, 0x00],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        apply_profile(&sim, 0x01, &profile).unwrap();
+        assert_eq!(sim.dpi(), 2400);
+        assert_eq!(sim.report_interval(), 4);
+        assert_eq!(sim.remap(1), Some(buttons::cids::MIDDLE_CLICK));
+        assert_eq!(
+            led::read_led_effect(&sim, 0x01, 0).unwrap(),
+            profile.leds[0]
+        );
+    }
+
+    #[test]
+    fn snapshot_round_trips_apply() {
+        let sim = crate::sim::SimulatedG502::new(0x01);
+        let mut profile = snapshot(&sim, 0x01).unwrap();
+        assert_eq!(profile.dpi, 800);
+        assert!(profile.extra_sensor_dpi.is_empty());
+        assert_eq!(profile.buttons.len(), G502_BUTTON_COUNT);
+        assert_eq!(profile.leds.len(), 2);
+        assert_eq!(profile.onboard_mode, Some(OnboardMode::Onboard));
+        assert_eq!(profile.dpi_stages, [400, 800, 1600, 3200]);
+        assert_eq!(profile.default_dpi_stage, 1);
+
+        profile.dpi = 1800;
+        profile.buttons[4] = ButtonAction::NoAction;
+        apply_profile(&sim, 0x01, &profile).unwrap();
+        let read = snapshot(&sim, 0x01).unwrap();
+        assert_eq!(read.dpi, 1800);
+        assert_eq!(read.buttons, profile.buttons);
+        assert_eq!(read.leds, profile.leds);
+    }
+
+    #[test]
+    fn snapshot_fails_when_the_mouse_goes_away() {
+        use crate::transport::{Fault, FaultKind, FaultLayer, Faults, TransportStack};
+
+        let faults = Faults::new();
+        let device = TransportStack::new()
+            .layer(FaultLayer::new(faults.clone()))
+            .build(crate::sim::SimulatedG502::new(0x01));
+        faults.inject(
+            Fault::new(FaultKind::Disconnect)
+                .on(crate::hidpp::features::ONBOARD_PROFILES, 2)
+                .always(),
+        );
+        assert!(matches!(
+            snapshot(&device, 0x01),
+            Err(Error::Disconnected { .. })
+        ));
     }
 }
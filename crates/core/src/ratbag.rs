@@ -0,0 +1,638 @@
+//! libratbag / Piper data model, for moving settings in and out.
+//!
+//! [`RatbagDevice`] mirrors ratbagd's `org.freedesktop.ratbag1` object tree
+//! as JSON, using the D-Bus property names: a device has profiles, and each
+//! profile has `Resolutions`, `ReportRate`, `Buttons` and `Leds`. A dump of a
+//! device's properties from ratbagd has this shape, and [`export`] writes it.
+//!
+//! ```json
+//! {"Name": "Logitech G502", "Profiles": [{
+//!   "Index": 0, "IsActive": true, "ReportRate": 1000,
+//!   "Resolutions": [{"Index": 0, "IsActive": true, "IsDefault": true, "Resolution": 800}],
+//!   "Buttons": [{"Index": 0, "Mapping": [1, 1]}],
+//!   "Leds": [{"Index": 0, "Mode": 1, "Color": [255, 0, 0], "EffectDuration": 0, "Brightness": 255}]
+//! }]}
+//! ```
+//!
+//! [`import`] and [`export`] map one ratbag profile onto a [`Profile`] and
+//! back. Whatever a profile cannot hold (keys, macros, most special actions,
+//! separate X/Y resolutions, values outside `safety`'s bounds) is listed in
+//! the result instead of being dropped silently.
+
+use crate::device::{ButtonAction, PollingRate, G502_BUTTON_COUNT};
+use crate::error::{Error, Result};
+use crate::led::{LedEffect, LedMode};
+use crate::profile::Profile;
+use crate::safety;
+use serde::{Deserialize, Serialize};
+
+/// ratbag button action types and special action codes.
+pub mod action {
+    pub const NONE: u32 = 0;
+    pub const BUTTON: u32 = 1;
+    pub const SPECIAL: u32 = 2;
+    pub const KEY: u32 = 3;
+    pub const MACRO: u32 = 4;
+
+    const SPECIAL_BASE: u32 = 1 << 30;
+    pub const SPECIAL_RESOLUTION_CYCLE_UP: u32 = SPECIAL_BASE + 7;
+    pub const SPECIAL_RESOLUTION_CYCLE_DOWN: u32 = SPECIAL_BASE + 8;
+    pub const SPECIAL_RESOLUTION_UP: u32 = SPECIAL_BASE + 9;
+    pub const SPECIAL_RESOLUTION_DOWN: u32 = SPECIAL_BASE + 10;
+
+    /// libratbag's names for special actions, from `SPECIAL_BASE` on.
+    const SPECIAL_NAMES: [&str; 19] = [
+        "unknown",
+        "doubleclick",
+        "wheel-left",
+        "wheel-right",
+        "wheel-up",
+        "wheel-down",
+        "ratchet-mode-switch",
+        "resolution-cycle-up",
+        "resolution-cycle-down",
+        "resolution-up",
+        "resolution-down",
+        "resolution-alternate",
+        "resolution-default",
+        "profile-cycle-up",
+        "profile-cycle-down",
+        "profile-up",
+        "profile-down",
+        "second-mode",
+        "battery-level",
+    ];
+
+    /// Name of a special action code, if libratbag defines it.
+    pub fn special_name(value: u32) -> Option<&'static str> {
+        let offset = value.checked_sub(SPECIAL_BASE)?;
+        SPECIAL_NAMES.get(offset as usize).copied()
+    }
+}
+
+/// ratbag LED modes, in `LedMode` order.
+pub const LED_MODES: [(u32, LedMode); 4] = [
+    (0, LedMode::Off),
+    (1, LedMode::Solid),
+    (2, LedMode::Cycle),
+    (3, LedMode::Breathing),
+];
+
+/// ratbag `(type, value)` mapping for a button action.
+pub fn to_mapping(action: ButtonAction) -> (u32, u32) {
+    match action {
+        ButtonAction::LeftClick => (action::BUTTON, 1),
+        ButtonAction::RightClick => (action::BUTTON, 2),
+        ButtonAction::MiddleClick => (action::BUTTON, 3),
+        ButtonAction::Back => (action::BUTTON, 4),
+        ButtonAction::Forward => (action::BUTTON, 5),
+        ButtonAction::DpiCycleUp => (action::SPECIAL, action::SPECIAL_RESOLUTION_CYCLE_UP),
+        ButtonAction::DpiCycleDown => (action::SPECIAL, action::SPECIAL_RESOLUTION_CYCLE_DOWN),
+        ButtonAction::NoAction => (action::NONE, 0),
+    }
+}
+
+/// Button action for a ratbag mapping, if the G502 can do it.
+pub fn from_mapping(kind: u32, value: u32) -> Option<ButtonAction> {
+    match (kind, value) {
+        (action::NONE, _) => Some(ButtonAction::NoAction),
+        (action::BUTTON, 1) => Some(ButtonAction::LeftClick),
+        (action::BUTTON, 2) => Some(ButtonAction::RightClick),
+        (action::BUTTON, 3) => Some(ButtonAction::MiddleClick),
+        (action::BUTTON, 4) => Some(ButtonAction::Back),
+        (action::BUTTON, 5) => Some(ButtonAction::Forward),
+        (action::SPECIAL, action::SPECIAL_RESOLUTION_CYCLE_UP | action::SPECIAL_RESOLUTION_UP) => {
+            Some(ButtonAction::DpiCycleUp)
+        }
+        (
+            action::SPECIAL,
+            action::SPECIAL_RESOLUTION_CYCLE_DOWN | action::SPECIAL_RESOLUTION_DOWN,
+        ) => Some(ButtonAction::DpiCycleDown),
+        // Keys and macros need onboard memory writes, which we do not do.
+        _ => None,
+    }
+}
+
+/// Scale 0–100 % to ratbag's 0–255.
+pub fn brightness_to_ratbag(percent: u8) -> u32 {
+    (percent as u32 * 255 + 50) / 100
+}
+
+pub fn brightness_from_ratbag(value: u32) -> u8 {
+    ((value.min(255) * 100 + 127) / 255) as u8
+}
+
+/// A ratbag device and its profiles.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RatbagDevice {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub model: String,
+    pub profiles: Vec<RatbagProfile>,
+}
+
+/// One ratbag profile.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct RatbagProfile {
+    pub index: u32,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    pub is_active: bool,
+    pub disabled: bool,
+    /// Report rate in Hz.
+    pub report_rate: u32,
+    pub resolutions: Vec<RatbagResolution>,
+    pub buttons: Vec<RatbagButton>,
+    pub leds: Vec<RatbagLed>,
+    /// -1 when the device has no angle snapping.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub angle_snapping: Option<i32>,
+    /// Debounce time in ms; -1 when the device has no debounce setting.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debounce: Option<i32>,
+}
+
+/// A DPI value: one for both axes, or X and Y separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Dpi {
+    Both(u32),
+    Separate(u32, u32),
+}
+
+/// One entry of a profile's DPI list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RatbagResolution {
+    #[serde(default)]
+    pub index: u32,
+    #[serde(default)]
+    pub is_active: bool,
+    #[serde(default)]
+    pub is_default: bool,
+    #[serde(default)]
+    pub is_disabled: bool,
+    pub resolution: Dpi,
+}
+
+/// The value half of a button mapping.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MappingValue {
+    /// Button number, special action code or key code.
+    Code(u32),
+    /// Macro events as `(type, keycode)`.
+    Macro(Vec<(u32, u32)>),
+}
+
+/// A button and its `(action type, value)` mapping.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RatbagButton {
+    #[serde(default)]
+    pub index: u32,
+    pub mapping: (u32, MappingValue),
+}
+
+/// One LED and its effect.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RatbagLed {
+    #[serde(default)]
+    pub index: u32,
+    pub mode: u32,
+    #[serde(default)]
+    pub color: (u32, u32, u32),
+    /// Period of one cycle or breath in ms.
+    #[serde(default)]
+    pub effect_duration: u32,
+    /// 0–255.
+    #[serde(default)]
+    pub brightness: u32,
+}
+
+/// A profile converted from ratbag, and what did not fit.
+#[derive(Debug, Clone)]
+pub struct Imported {
+    pub profile: Profile,
+    pub unsupported: Vec<String>,
+}
+
+/// A ratbag device holding one exported profile, and what did not fit.
+#[derive(Debug, Clone)]
+pub struct Exported {
+    pub device: RatbagDevice,
+    pub unsupported: Vec<String>,
+}
+
+/// Human-readable form of a mapping, for reports.
+fn describe_mapping(kind: u32, value: &MappingValue) -> String {
+    match (kind, value) {
+        (action::BUTTON, MappingValue::Code(n)) => format!("button {n}"),
+        (action::SPECIAL, MappingValue::Code(code)) => action::special_name(*code)
+            .map_or(format!("special action {code}"), |name| name.to_string()),
+        (action::KEY, MappingValue::Code(key)) => format!("key code {key}"),
+        (action::MACRO, MappingValue::Macro(events)) => format!("macro of {} events", events.len()),
+        _ => format!("action type {kind}"),
+    }
+}
+
+/// Convert ratbag profile `index`, or the active one, into a [`Profile`].
+///
+/// Enabled resolutions become the DPI stages and the active one the DPI.
+/// Buttons and LEDs that cannot be represented keep the profile defaults.
+pub fn import(device: &RatbagDevice, index: Option<u32>) -> Result<Imported> {
+    let chosen = match index {
+        Some(i) => device.profiles.iter().find(|p| p.index == i),
+        None => device
+            .profiles
+            .iter()
+            .find(|p| p.is_active)
+            .or_else(|| device.profiles.iter().find(|p| !p.disabled)),
+    }
+    .ok_or_else(|| Error::Profile("no matching ratbag profile".to_string()))?;
+
+    let mut unsupported = Vec::new();
+    for other in &device.profiles {
+        if other.index != chosen.index && !other.disabled {
+            unsupported.push(format!(
+                "profile {}: only one profile is imported at a time",
+                other.index
+            ));
+        }
+    }
+
+    let name = if chosen.name.is_empty() {
+        format!("ratbag profile {}", chosen.index)
+    } else {
+        chosen.name.clone()
+    };
+    let mut profile = Profile {
+        name,
+        ..Default::default()
+    };
+
+    let mut active = None;
+    let mut default = None;
+    for res in chosen.resolutions.iter().filter(|r| !r.is_disabled) {
+        let dpi = match res.resolution {
+            Dpi::Both(dpi) => dpi,
+            Dpi::Separate(x, y) => {
+                if x != y {
+                    unsupported.push(format!(
+                        "resolution {}: separate X/Y DPI {x}x{y}, using {x}",
+                        res.index
+                    ));
+                }
+                x
+            }
+        };
+        let Ok(dpi) = safety::validate_dpi(u16::try_from(dpi).unwrap_or(u16::MAX)) else {
+            unsupported.push(format!(
+                "resolution {}: {dpi} DPI is outside the safe range",
+                res.index
+            ));
+            continue;
+        };
+        if res.is_active {
+            active = Some(dpi);
+        }
+        if res.is_default {
+            default = Some(profile.dpi_stages.len());
+        }
+        profile.dpi_stages.push(dpi);
+    }
+    profile.default_dpi_stage = default.unwrap_or(0);
+    if let Some(dpi) = active.or_else(|| profile.dpi_stages.get(profile.default_dpi_stage).copied())
+    {
+        profile.dpi = dpi;
+    }
+
+    match u16::try_from(chosen.report_rate)
+        .ok()
+        .and_then(PollingRate::from_hz)
+    {
+        Some(rate) => profile.polling_rate = rate,
+        None => unsupported.push(format!(
+            "report rate {} Hz (supported: 125, 250, 500, 1000)",
+            chosen.report_rate
+        )),
+    }
+
+    for button in &chosen.buttons {
+        let (kind, value) = &button.mapping;
+        let index = button.index as usize;
+        if index >= G502_BUTTON_COUNT {
+            unsupported.push(format!(
+                "button {index}: only buttons 0-{} can be remapped",
+                G502_BUTTON_COUNT - 1
+            ));
+            continue;
+        }
+        let mapped = match value {
+            MappingValue::Code(code) => from_mapping(*kind, *code),
+            MappingValue::Macro(_) => None,
+        };
+        match mapped {
+            Some(action) => profile.buttons[index] = action,
+            None => unsupported.push(format!(
+                "button {index}: {}",
+                describe_mapping(*kind, value)
+            )),
+        }
+    }
+
+    for led in &chosen.leds {
+        let Some(&(_, mode)) = LED_MODES.iter().find(|(id, _)| *id == led.mode) else {
+            unsupported.push(format!("LED {}: mode {}", led.index, led.mode));
+            continue;
+        };
+        let channel = |c: u32| c.min(255) as u8;
+        let effect = LedEffect {
+            mode,
+            color: [
+                channel(led.color.0),
+                channel(led.color.1),
+                channel(led.color.2),
+            ],
+            duration_ms: u16::try_from(led.effect_duration).unwrap_or(u16::MAX),
+            brightness: brightness_from_ratbag(led.brightness),
+        };
+        let zone = u8::try_from(led.index).unwrap_or(u8::MAX);
+        if let Err(e) =
+            safety::validate_led_zone(zone).and_then(|()| safety::validate_led_effect(&effect))
+        {
+            unsupported.push(format!("LED {}: {e}", led.index));
+            continue;
+        }
+        let zone = zone as usize;
+        if profile.leds.len() <= zone {
+            profile.leds.resize(zone + 1, LedEffect::default());
+        }
+        profile.leds[zone] = effect;
+    }
+
+    if chosen.angle_snapping.is_some_and(|v| v > 0) {
+        unsupported.push("angle snapping".to_string());
+    }
+    if let Some(ms) = chosen.debounce.filter(|&ms| ms > 0) {
+        unsupported.push(format!(
+            "hardware debounce of {ms} ms (the host-side debounce filter is separate)"
+        ));
+    }
+
+    Ok(Imported {
+        profile,
+        unsupported,
+    })
+}
+
+/// Convert a [`Profile`] into a ratbag device with a single active profile.
+pub fn export(profile: &Profile) -> Exported {
+    let mut unsupported = Vec::new();
+
+    let stages = if profile.dpi_stages.is_empty() {
+        vec![profile.dpi]
+    } else {
+        profile.dpi_stages.clone()
+    };
+    let default = if profile.dpi_stages.is_empty() {
+        0
+    } else {
+        profile.default_dpi_stage.min(stages.len() - 1)
+    };
+    let active = match stages.iter().position(|&dpi| dpi == profile.dpi) {
+        Some(i) => i,
+        None => {
+            unsupported.push(format!(
+                "DPI {} is not one of the DPI stages; the default stage is active",
+                profile.dpi
+            ));
+            default
+        }
+    };
+    let resolutions = stages
+        .iter()
+        .enumerate()
+        .map(|(i, &dpi)| RatbagResolution {
+            index: i as u32,
+            is_active: i == active,
+            is_default: i == default,
+            is_disabled: false,
+            resolution: Dpi::Both(dpi.into()),
+        })
+        .collect();
+
+    let buttons = profile
+        .buttons
+        .iter()
+        .enumerate()
+        .map(|(i, &action)| {
+            let (kind, value) = to_mapping(action);
+            RatbagButton {
+                index: i as u32,
+                mapping: (kind, MappingValue::Code(value)),
+            }
+        })
+        .collect();
+
+    let leds = profile
+        .leds
+        .iter()
+        .enumerate()
+        .map(|(i, effect)| {
+            let mode = LED_MODES
+                .iter()
+                .find(|(_, m)| *m == effect.mode)
+                .map_or(0, |(id, _)| *id);
+            let [r, g, b] = effect.color;
+            RatbagLed {
+                index: i as u32,
+                mode,
+                color: (r.into(), g.into(), b.into()),
+                effect_duration: effect.duration_ms.into(),
+                brightness: brightness_to_ratbag(effect.brightness),
+            }
+        })
+        .collect();
+
+    if !profile.extra_sensor_dpi.is_empty() {
+        unsupported.push("DPI of further sensors".to_string());
+    }
+    if profile.debounce.enabled {
+        unsupported.push("host-side click debounce".to_string());
+    }
+
+    Exported {
+        device: RatbagDevice {
+            profiles: vec![RatbagProfile {
+                index: 0,
+                name: profile.name.clone(),
+                is_active: true,
+                disabled: false,
+                report_rate: profile.polling_rate.as_hz().into(),
+                resolutions,
+                buttons,
+                leds,
+                angle_snapping: None,
+                debounce: None,
+            }],
+            ..Default::default()
+        },
+        unsupported,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIPER_DUMP: &str = r#"{
+        "Name": "Logitech G502 HERO Gaming Mouse",
+        "Model": "usb:046d:c08b:0",
+        "Profiles": [
+            {
+                "Index": 0, "Name": "Work", "IsActive": false, "Disabled": false,
+                "ReportRate": 500,
+                "Resolutions": [{"Index": 0, "IsActive": true, "IsDefault": true, "Resolution": 800}],
+                "Buttons": [], "Leds": []
+            },
+            {
+                "Index": 1, "Name": "Games", "IsActive": true, "Disabled": false,
+                "ReportRate": 1000, "AngleSnapping": -1, "Debounce": 8,
+                "Resolutions": [
+                    {"Index": 0, "IsActive": false, "IsDefault": false, "Resolution": 400},
+                    {"Index": 1, "IsActive": true, "IsDefault": true, "Resolution": [1600, 1600]},
+                    {"Index": 2, "IsActive": false, "IsDefault": false, "Resolution": [3200, 1600]},
+                    {"Index": 3, "IsDisabled": true, "Resolution": 6400},
+                    {"Index": 4, "Resolution": 99999}
+                ],
+                "Buttons": [
+                    {"Index": 3, "Mapping": [2, 1073741833]},
+                    {"Index": 4, "Mapping": [3, 30]},
+                    {"Index": 5, "Mapping": [4, [[0, 30], [1, 30]]]},
+                    {"Index": 6, "Mapping": [2, 1073741828]},
+                    {"Index": 7, "Mapping": [1, 1]}
+                ],
+                "Leds": [
+                    {"Index": 0, "Mode": 3, "Color": [0, 128, 255], "EffectDuration": 4000, "Brightness": 255},
+                    {"Index": 1, "Mode": 7, "Color": [0, 0, 0]}
+                ]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn button_mappings_round_trip() {
+        for &action in ButtonAction::ALL {
+            let (kind, value) = to_mapping(action);
+            assert_eq!(from_mapping(kind, value), Some(action));
+        }
+        assert_eq!(
+            from_mapping(action::SPECIAL, action::SPECIAL_RESOLUTION_UP),
+            Some(ButtonAction::DpiCycleUp)
+        );
+        assert_eq!(from_mapping(action::KEY, 30), None);
+        assert_eq!(from_mapping(action::MACRO, 0), None);
+        assert_eq!(
+            action::special_name(action::SPECIAL_RESOLUTION_DOWN),
+            Some("resolution-down")
+        );
+    }
+
+    #[test]
+    fn brightness_scales_both_ways() {
+        assert_eq!(brightness_to_ratbag(100), 255);
+        assert_eq!(brightness_from_ratbag(255), 100);
+        assert_eq!(brightness_from_ratbag(128), 50);
+        assert_eq!(brightness_from_ratbag(1000), 100);
+    }
+
+    #[test]
+    fn import_maps_the_active_profile_and_lists_the_rest() {
+        let device: RatbagDevice = serde_json::from_str(PIPER_DUMP).unwrap();
+        let Imported {
+            profile,
+            unsupported,
+        } = import(&device, None).unwrap();
+
+        assert_eq!(profile.name, "Games");
+        assert_eq!(profile.dpi_stages, [400, 1600, 3200]);
+        assert_eq!(profile.default_dpi_stage, 1);
+        assert_eq!(profile.dpi, 1600);
+        assert_eq!(profile.polling_rate, PollingRate::Hz1000);
+        assert_eq!(profile.buttons[3], ButtonAction::DpiCycleUp);
+        // Unrepresentable buttons keep their defaults.
+        assert_eq!(profile.buttons[4], ButtonAction::Forward);
+        assert_eq!(profile.leds.len(), 1);
+        assert_eq!(profile.leds[0].mode, LedMode::Breathing);
+        assert_eq!(profile.leds[0].brightness, 100);
+
+        assert_eq!(
+            unsupported,
+            [
+                "profile 0: only one profile is imported at a time",
+                "resolution 2: separate X/Y DPI 3200x1600, using 3200",
+                "resolution 4: 99999 DPI is outside the safe range",
+                "button 4: key code 30",
+                "button 5: macro of 2 events",
+                "button 6: only buttons 0-5 can be remapped",
+                "button 7: only buttons 0-5 can be remapped",
+                "LED 1: mode 7",
+                "hardware debounce of 8 ms (the host-side debounce filter is separate)",
+            ]
+        );
+
+        let work = import(&device, Some(0)).unwrap().profile;
+        assert_eq!(work.polling_rate, PollingRate::Hz500);
+        assert!(import(&device, Some(9)).is_err());
+    }
+
+    #[test]
+    fn export_then_import_round_trips() {
+        let mut profile = Profile {
+            name: "Desk".to_string(),
+            dpi: 1600,
+            dpi_stages: vec![800, 1600, 3200],
+            default_dpi_stage: 0,
+            leds: vec![LedEffect {
+                mode: LedMode::Solid,
+                color: [255, 0, 0],
+                duration_ms: 0,
+                brightness: 100,
+            }],
+            ..Default::default()
+        };
+        profile.buttons[4] = ButtonAction::DpiCycleDown;
+
+        let exported = export(&profile);
+        assert!(exported.unsupported.is_empty());
+        let json = serde_json::to_string(&exported.device).unwrap();
+        let device: RatbagDevice = serde_json::from_str(&json).unwrap();
+        let back = import(&device, None).unwrap();
+        assert!(back.unsupported.is_empty(), "{:?}", back.unsupported);
+        assert_eq!(back.profile.dpi, profile.dpi);
+        assert_eq!(back.profile.dpi_stages, profile.dpi_stages);
+        assert_eq!(back.profile.default_dpi_stage, 0);
+        assert_eq!(back.profile.buttons, profile.buttons);
+        assert_eq!(back.profile.leds, profile.leds);
+    }
+
+    #[test]
+    fn export_reports_what_ratbag_cannot_hold() {
+        let profile = Profile {
+            dpi: 1000,
+            dpi_stages: vec![800, 1600],
+            extra_sensor_dpi: vec![400],
+            ..Default::default()
+        };
+        let exported = export(&profile);
+        let resolutions = &exported.device.profiles[0].resolutions;
+        assert!(resolutions[0].is_active && resolutions[0].is_default);
+        assert_eq!(exported.unsupported.len(), 2);
+        assert!(exported.unsupported[0].starts_with("DPI 1000"));
+    }
+}
@@ -31,9 +31,8 @@ fn interval_to_rate(interval: u8) -> Result<PollingRate> {
         2 => Ok(PollingRate::Hz500),
         4 => Ok(PollingRate::Hz250),
         8 => Ok(PollingRate::Hz125),
-        other => Err(Error::HidppProtocol {
+        _ => Err(Error::MalformedReply {
             feature: hidpp::features::REPORT_RATE,
-            code: other,
         }),
     }
 }
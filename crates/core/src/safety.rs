@@ -29,9 +29,30 @@
 //! - **Note**: Additional G-shift buttons exist but are not exposed in this version
 //!
 //! ## Macros
-//! - **Not supported** in this version. Button remapping is CID-to-CID only.
-//! - Macro support would require ONBOARD_PROFILES memory write, which carries
-//!   higher bricking risk and is deferred to a future release.
+//! - **Onboard macros**: not supported. Button remapping is CID-to-CID only;
+//!   onboard macros would require ONBOARD_PROFILES memory writes, which carry
+//!   higher bricking risk.
+//! - **Host macros**: played on the host, never sent to the device
+//! - **Length**: at most 1,000 steps
+//! - **Delays**: at most 10,000 ms each
+//!
+//! ## Debounce Window
+//! - **Range**: 0 – 100 ms per button (0 disables filtering)
+//! - **Default**: 20 ms
+//! - **Note**: Host-side only (never sent to the device). Windows above 100 ms
+//!   start eating intentional double clicks.
+//!
+//! ## LEDs
+//! - **Zones**: 0–1 (0=logo, 1=DPI indicator)
+//! - **Brightness**: 1 – 100 % for animated effects
+//! - **Effect period**: 1,000 – 20,000 ms for cycle/breathing
+//! - **Reference**: HID++ 2.0 COLOR_LED_EFFECTS feature (0x8070)
+//!
+//! ## Power Rules
+//! - **Battery threshold**: 1 – 100 %
+//! - **LED brightness limit**: 0 – 100 % (0 turns the LEDs off)
+//! - **Note**: rules only lower the profile's own values, which are
+//!   validated as above when written
 //!
 //! ## Safety Invariants
 //! 1. All DPI values are clamped to [100, 25600] and rounded to nearest 50
@@ -43,13 +64,16 @@
 use crate::device::PollingRate;
 use crate::error::{Error, Result};
 use crate::hidpp::features;
+use crate::led::LedEffect;
+use crate::macros::{Macro, MacroStep};
+use crate::power::PowerRule;
 
 /// Bricking risk disclaimer — include in any user-facing output about device writes.
 pub const BRICKING_DISCLAIMER: &str = "\
 WARNING: This software writes directly to your mouse's hardware registers via HID++. \
 While all writes are bounds-checked against known-safe ranges, incorrect usage or \
 software bugs could theoretically render the device unresponsive. \
-Macro and firmware operations are intentionally not supported due to higher risk. \
+Onboard macro and firmware operations are intentionally not supported due to higher risk. \
 Use at your own risk. See TROUBLESHOOTING.md for recovery steps.";
 
 /// HID++ feature IDs that Open G Hub is allowed to communicate with.
@@ -58,14 +82,17 @@ Use at your own risk. See TROUBLESHOOTING.md for recovery steps.";
 /// This prevents accidental or malicious use of dangerous features like
 /// firmware update, DFU mode, or raw memory access.
 const ALLOWED_FEATURE_IDS: &[u16] = &[
-    features::ROOT,               // 0x0000 — feature index lookup (read-only)
-    features::FEATURE_SET,        // 0x0001 — enumerate features (read-only)
-    features::DEVICE_NAME,        // 0x0005 — device name (read-only)
-    features::BATTERY_STATUS,     // 0x1000 — battery level (read-only)
-    features::REPROG_CONTROLS_V4, // 0x1B04 — button remapping
-    features::ADJUSTABLE_DPI,     // 0x2201 — DPI configuration
-    features::REPORT_RATE,        // 0x8060 — polling rate
-    features::ONBOARD_PROFILES,   // 0x8100 — profile management
+    features::ROOT,                   // 0x0000 — feature index lookup (read-only)
+    features::FEATURE_SET,            // 0x0001 — enumerate features (read-only)
+    features::DEVICE_INFORMATION,     // 0x0003 — unit and model ID (read-only)
+    features::DEVICE_NAME,            // 0x0005 — device name (read-only)
+    features::BATTERY_STATUS,         // 0x1000 — battery level (read-only)
+    features::REPROG_CONTROLS_V4,     // 0x1B04 — button remapping
+    features::WIRELESS_DEVICE_STATUS, // 0x1D4B — reconnection events (read-only)
+    features::ADJUSTABLE_DPI,         // 0x2201 — DPI configuration
+    features::REPORT_RATE,            // 0x8060 — polling rate
+    features::COLOR_LED_EFFECTS,      // 0x8070 — LED effects
+    features::ONBOARD_PROFILES,       // 0x8100 — profile management
 ];
 
 /// Maximum allowed function ID within any feature.
@@ -81,12 +108,7 @@ pub fn validate_feature_id(feature_id: u16) -> Result<()> {
     if ALLOWED_FEATURE_IDS.contains(&feature_id) {
         Ok(())
     } else {
-        Err(Error::OutOfRange {
-            field: "feature_id",
-            value: feature_id as u32,
-            min: 0,
-            max: 0xFFFF,
-        })
+        Err(Error::FeatureNotAllowed(feature_id))
     }
 }
 
@@ -108,6 +130,37 @@ pub fn validate_hidpp_request(feature_id: u16, function_id: u8) -> Result<()> {
     Ok(())
 }
 
+/// `tracing` target of the audit log for whitelist bypasses.
+pub const AUDIT_TARGET: &str = "open_g_hub::audit";
+
+/// Permission to send requests outside the feature whitelist, through
+/// [`hidpp_request_expert`](crate::transport::hidpp_request_expert).
+///
+/// For expert tooling only, such as probing a feature Open G Hub does not
+/// support yet. Enabling it and every request sent with it are logged under
+/// [`AUDIT_TARGET`] with the reason given here.
+#[derive(Debug)]
+pub struct ExpertMode {
+    reason: String,
+}
+
+impl ExpertMode {
+    /// Turn on expert mode, saying why.
+    pub fn enable(reason: impl Into<String>) -> Self {
+        let reason = reason.into();
+        tracing::warn!(
+            target: AUDIT_TARGET,
+            reason = %reason,
+            "Expert mode enabled: feature whitelist can be bypassed"
+        );
+        Self { reason }
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
 /// G502 DPI constraints.
 pub const DPI_MIN: u16 = 100;
 pub const DPI_MAX: u16 = 25600;
@@ -152,6 +205,120 @@ pub fn validate_button_index(index: usize) -> Result<()> {
     Ok(())
 }
 
+/// Largest accepted click-debounce window in milliseconds.
+pub const DEBOUNCE_MAX_MS: u32 = 100;
+
+/// Validate a click-debounce window.
+pub fn validate_debounce_window(ms: u32) -> Result<u32> {
+    if ms > DEBOUNCE_MAX_MS {
+        return Err(Error::OutOfRange {
+            field: "debounce_ms",
+            value: ms,
+            min: 0,
+            max: DEBOUNCE_MAX_MS,
+        });
+    }
+    Ok(ms)
+}
+
+/// Most steps a host macro may have.
+pub const MACRO_MAX_STEPS: usize = 1000;
+/// Longest delay in a host macro, in milliseconds.
+pub const MACRO_MAX_DELAY_MS: u32 = 10_000;
+
+/// Validate a host macro's length and delays.
+pub fn validate_macro(m: &Macro) -> Result<()> {
+    if m.steps.len() > MACRO_MAX_STEPS {
+        return Err(Error::OutOfRange {
+            field: "macro_steps",
+            value: m.steps.len() as u32,
+            min: 0,
+            max: MACRO_MAX_STEPS as u32,
+        });
+    }
+    for step in &m.steps {
+        if let MacroStep::Delay(ms) = *step {
+            if ms > MACRO_MAX_DELAY_MS {
+                return Err(Error::OutOfRange {
+                    field: "macro_delay_ms",
+                    value: ms,
+                    min: 0,
+                    max: MACRO_MAX_DELAY_MS,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validate a battery power rule's threshold and brightness limit.
+pub fn validate_power_rule(rule: &PowerRule) -> Result<()> {
+    if !(1..=100).contains(&rule.below) {
+        return Err(Error::OutOfRange {
+            field: "power_below",
+            value: rule.below as u32,
+            min: 1,
+            max: 100,
+        });
+    }
+    if let Some(brightness) = rule.led_brightness.filter(|&b| b > LED_BRIGHTNESS_MAX) {
+        return Err(Error::OutOfRange {
+            field: "power_led_brightness",
+            value: brightness as u32,
+            min: 0,
+            max: LED_BRIGHTNESS_MAX as u32,
+        });
+    }
+    Ok(())
+}
+
+/// Number of LED zones on the G502 (logo, DPI indicator).
+pub const G502_LED_ZONE_COUNT: u8 = 2;
+/// LED effect period bounds in milliseconds.
+pub const LED_DURATION_MIN_MS: u16 = 1000;
+pub const LED_DURATION_MAX_MS: u16 = 20000;
+/// LED brightness bounds in percent.
+pub const LED_BRIGHTNESS_MIN: u8 = 1;
+pub const LED_BRIGHTNESS_MAX: u8 = 100;
+
+/// Validate an LED zone index (0-based).
+pub fn validate_led_zone(zone: u8) -> Result<()> {
+    if zone >= G502_LED_ZONE_COUNT {
+        return Err(Error::OutOfRange {
+            field: "led_zone",
+            value: zone as u32,
+            min: 0,
+            max: (G502_LED_ZONE_COUNT - 1) as u32,
+        });
+    }
+    Ok(())
+}
+
+/// Validate an LED effect. Period and brightness only matter for animated
+/// modes and are checked only there.
+pub fn validate_led_effect(effect: &LedEffect) -> Result<LedEffect> {
+    if !effect.mode.is_animated() {
+        return Ok(*effect);
+    }
+    if !(LED_DURATION_MIN_MS..=LED_DURATION_MAX_MS).contains(&effect.duration_ms) {
+        return Err(Error::OutOfRange {
+            field: "led_duration_ms",
+            value: effect.duration_ms as u32,
+            min: LED_DURATION_MIN_MS as u32,
+            max: LED_DURATION_MAX_MS as u32,
+        });
+    }
+    if !(LED_BRIGHTNESS_MIN..=LED_BRIGHTNESS_MAX).contains(&effect.brightness) {
+        return Err(Error::OutOfRange {
+            field: "led_brightness",
+            value: effect.brightness as u32,
+            min: LED_BRIGHTNESS_MIN as u32,
+            max: LED_BRIGHTNESS_MAX as u32,
+        });
+    }
+    Ok(*effect)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -238,6 +405,53 @@ mod tests {
         }
     }
 
+    #[test]
+    fn validate_debounce_window_bounds() {
+        assert_eq!(validate_debounce_window(0).unwrap(), 0);
+        assert_eq!(validate_debounce_window(100).unwrap(), 100);
+        assert!(validate_debounce_window(101).is_err());
+    }
+
+    #[test]
+    fn validate_led_bounds() {
+        use crate::led::LedMode;
+        assert!(validate_led_zone(1).is_ok());
+        assert!(validate_led_zone(2).is_err());
+
+        let fast = LedEffect {
+            mode: LedMode::Breathing,
+            duration_ms: 200,
+            ..LedEffect::default()
+        };
+        assert!(validate_led_effect(&fast).is_err());
+        let dark = LedEffect {
+            brightness: 0,
+            ..LedEffect::default()
+        };
+        assert!(validate_led_effect(&dark).is_err());
+        // A solid color ignores period and brightness.
+        let solid = LedEffect {
+            mode: LedMode::Solid,
+            ..fast
+        };
+        assert!(validate_led_effect(&solid).is_ok());
+    }
+
+    #[test]
+    fn validate_macro_bounds() {
+        let pause = |ms| Macro {
+            name: "Pause".into(),
+            steps: vec![MacroStep::Delay(ms)],
+        };
+        assert!(validate_macro(&pause(10_000)).is_ok());
+        assert!(validate_macro(&pause(10_001)).is_err());
+        let long = Macro {
+            name: "Long".into(),
+            steps: vec![MacroStep::KeyDown(30); MACRO_MAX_STEPS + 1],
+        };
+        assert!(validate_macro(&long).is_err());
+    }
+
     #[test]
     fn bricking_disclaimer_not_empty() {
         assert!(!BRICKING_DISCLAIMER.is_empty());
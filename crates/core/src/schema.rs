@@ -0,0 +1,232 @@
+//! Profile file schema: versions, migrations and parse modes.
+//!
+//! Profile JSON has had three shapes:
+//!
+//! - **0**: a bare [`Profile`] object, as printed by `snapshot` and read by
+//!   `apply` and `open-g-hubd --profile`. Fields added over time (debounce,
+//!   LEDs, further sensors, DPI stages, onboard mode) may be missing.
+//! - **1**: the profile library envelope, `{version, device, profile}`.
+//! - **2**: the same envelope, with the sensor, DPI stage and onboard mode
+//!   fields always present in `profile`.
+//!
+//! [`parse`] accepts all of them, runs the migrations from the file's version
+//! up to [`CURRENT_VERSION`] one step at a time, and then deserializes the
+//! result. Fields this build does not know are collected rather than
+//! silently dropped; [`ParseMode::Strict`] turns them into an error.
+//!
+//! [`Profile`]: crate::profile::Profile
+
+use crate::error::{Error, Result};
+use crate::store::StoredProfile;
+use serde_json::{json, Map, Value};
+
+/// Version written to new profile files.
+pub const CURRENT_VERSION: u32 = 2;
+
+/// Migration from version `i` to `i + 1` is `MIGRATIONS[i]`.
+const MIGRATIONS: [fn(Value) -> Result<Value>; CURRENT_VERSION as usize] =
+    [wrap_bare_profile, fill_capture_fields];
+
+/// How to treat fields this build does not know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Reject the file.
+    Strict,
+    /// Ignore them and list them in [`Parsed::unknown_fields`].
+    #[default]
+    Lenient,
+}
+
+/// A profile file brought up to the current schema.
+#[derive(Debug, Clone)]
+pub struct Parsed {
+    pub stored: StoredProfile,
+    /// Schema version the file was written in.
+    pub from_version: u32,
+    /// Dotted paths of ignored fields, e.g. `profile.leds.0.speed`.
+    pub unknown_fields: Vec<String>,
+}
+
+/// Parse a profile file of any known schema version.
+pub fn parse(text: &str, mode: ParseMode) -> Result<Parsed> {
+    let mut doc: Value = serde_json::from_str(text).map_err(invalid)?;
+    let from_version = version_of(&doc)?;
+    for migrate in &MIGRATIONS[from_version as usize..] {
+        doc = migrate(doc)?;
+    }
+
+    let mut unknown_fields = Vec::new();
+    let stored: StoredProfile =
+        serde_ignored::deserialize(doc, |path| unknown_fields.push(path.to_string()))
+            .map_err(invalid)?;
+    if mode == ParseMode::Strict && !unknown_fields.is_empty() {
+        return Err(Error::Profile(format!(
+            "unknown fields: {}",
+            unknown_fields.join(", ")
+        )));
+    }
+    Ok(Parsed {
+        stored,
+        from_version,
+        unknown_fields,
+    })
+}
+
+fn invalid(e: serde_json::Error) -> Error {
+    Error::Profile(format!("invalid profile: {e}"))
+}
+
+/// Schema version of a parsed document; a bare profile is version 0.
+fn version_of(doc: &Value) -> Result<u32> {
+    let obj = doc
+        .as_object()
+        .ok_or_else(|| Error::Profile("a profile must be a JSON object".to_string()))?;
+    let Some(version) = obj.get("version") else {
+        return if obj.contains_key("profile") {
+            Err(Error::Profile(
+                "profile envelope has no version".to_string(),
+            ))
+        } else {
+            Ok(0)
+        };
+    };
+    match version.as_u64() {
+        Some(v) if (1..=u64::from(CURRENT_VERSION)).contains(&v) => Ok(v as u32),
+        Some(v) if v > u64::from(CURRENT_VERSION) => Err(Error::Profile(format!(
+            "format version {v} is newer than this build supports ({CURRENT_VERSION})"
+        ))),
+        _ => Err(Error::Profile(format!("invalid format version {version}"))),
+    }
+}
+
+fn profile_object(doc: &mut Value) -> Result<&mut Map<String, Value>> {
+    doc.get_mut("profile")
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| Error::Profile("envelope has no profile object".to_string()))
+}
+
+/// 0 -> 1: put a bare profile into the library envelope, bound to no device.
+fn wrap_bare_profile(doc: Value) -> Result<Value> {
+    Ok(json!({ "version": 1, "device": null, "profile": doc }))
+}
+
+/// 1 -> 2: spell out the fields `snapshot` records, as "unknown".
+fn fill_capture_fields(mut doc: Value) -> Result<Value> {
+    let profile = profile_object(&mut doc)?;
+    for (key, empty) in [
+        ("extra_sensor_dpi", json!([])),
+        ("dpi_stages", json!([])),
+        ("default_dpi_stage", json!(0)),
+        ("onboard_mode", Value::Null),
+    ] {
+        profile.entry(key).or_insert(empty);
+    }
+    doc["version"] = json!(2);
+    Ok(doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{ButtonAction, MouseModel, PollingRate};
+    use crate::input::MouseButton;
+    use crate::led::LedMode;
+    use crate::store::DeviceBinding;
+
+    /// Profile files as earlier releases wrote them.
+    const CORPUS: &[(&str, &str, u32)] = &[
+        (
+            "v0-baseline",
+            include_str!("../tests/fixtures/profiles/v0-baseline.json"),
+            0,
+        ),
+        (
+            "v0-debounce-leds",
+            include_str!("../tests/fixtures/profiles/v0-debounce-leds.json"),
+            0,
+        ),
+        (
+            "v0-snapshot",
+            include_str!("../tests/fixtures/profiles/v0-snapshot.json"),
+            0,
+        ),
+        (
+            "v1-library",
+            include_str!("../tests/fixtures/profiles/v1-library.json"),
+            1,
+        ),
+        (
+            "v2-library",
+            include_str!("../tests/fixtures/profiles/v2-library.json"),
+            2,
+        ),
+    ];
+
+    #[test]
+    fn every_corpus_file_parses_strictly() {
+        for (name, text, version) in CORPUS {
+            let parsed = parse(text, ParseMode::Strict).unwrap_or_else(|e| panic!("{name}: {e}"));
+            assert_eq!(parsed.from_version, *version, "{name}");
+            assert_eq!(parsed.stored.version, CURRENT_VERSION, "{name}");
+            assert!(parsed.unknown_fields.is_empty(), "{name}");
+        }
+    }
+
+    #[test]
+    fn migrated_files_keep_their_settings() {
+        let baseline = parse(CORPUS[0].1, ParseMode::Strict).unwrap().stored;
+        assert!(baseline.device.is_none());
+        assert_eq!(baseline.profile.dpi, 1600);
+        assert_eq!(baseline.profile.polling_rate, PollingRate::Hz500);
+        assert_eq!(baseline.profile.buttons[5], ButtonAction::DpiCycleUp);
+        assert!(baseline.profile.leds.is_empty());
+
+        let library = parse(CORPUS[3].1, ParseMode::Strict).unwrap().stored;
+        assert_eq!(
+            library.device,
+            Some(DeviceBinding::Model(MouseModel::G502Lightspeed))
+        );
+        let profile = &library.profile;
+        assert_eq!(profile.debounce.per_button[&MouseButton::Left], 40);
+        assert_eq!(profile.leds[0].mode, LedMode::Breathing);
+        assert!(profile.dpi_stages.is_empty());
+        assert!(profile.onboard_mode.is_none());
+    }
+
+    #[test]
+    fn migration_steps_are_applied_in_order() {
+        let doc: Value = serde_json::from_str(CORPUS[0].1).unwrap();
+        let v1 = MIGRATIONS[0](doc).unwrap();
+        assert_eq!(v1["version"], 1);
+        let v2 = MIGRATIONS[1](v1).unwrap();
+        assert_eq!(v2["version"], 2);
+        assert_eq!(v2["profile"]["dpi_stages"], json!([]));
+        assert_eq!(v2["profile"]["name"], "Baseline");
+    }
+
+    #[test]
+    fn unknown_fields_are_reported_or_rejected() {
+        let text = include_str!("../tests/fixtures/profiles/v1-unknown-fields.json");
+        let parsed = parse(text, ParseMode::Lenient).unwrap();
+        assert_eq!(parsed.stored.profile.name, "Shooter");
+        assert_eq!(
+            parsed.unknown_fields,
+            ["exported_by", "profile.leds.0.speed", "profile.macros"]
+        );
+
+        let err = parse(text, ParseMode::Strict).unwrap_err().to_string();
+        assert!(err.contains("profile.leds.0.speed"), "{err}");
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        let newer = r#"{"version": 3, "device": null, "profile": {}}"#;
+        assert!(parse(newer, ParseMode::Lenient)
+            .unwrap_err()
+            .to_string()
+            .contains("newer than this build"));
+        let unversioned = r#"{"device": null, "profile": {}}"#;
+        assert!(parse(unversioned, ParseMode::Lenient).is_err());
+        assert!(parse("[1, 2]", ParseMode::Lenient).is_err());
+    }
+}
@@ -0,0 +1,741 @@
+//! Embedded Lua runtime compatible with the Logitech G Hub scripting API.
+//!
+//! Scripts define `OnEvent(event, arg, family)` exactly as in G Hub. The host
+//! drives it with `PROFILE_ACTIVATED`/`PROFILE_DEACTIVATED` and with
+//! `MOUSE_BUTTON_PRESSED`/`MOUSE_BUTTON_RELEASED` decoded from diverted HID++
+//! controls (see [`crate::buttons::set_button_divert`]). Output functions go to
+//! an [`InputBackend`]: uinput on Linux, or [`crate::input::RecordingBackend`]
+//! to run scripts headless in tests.
+//!
+//! Supported API:
+//! - Events: `OnEvent`, `EnablePrimaryMouseButtonEvents`
+//! - Keys: `PressKey`, `ReleaseKey`, `PressAndReleaseKey`, `IsModifierPressed`,
+//!   `IsKeyLockOn`
+//! - Mouse: `PressMouseButton`, `ReleaseMouseButton`, `PressAndReleaseMouseButton`,
+//!   `IsMouseButtonPressed`, `MoveMouseRelative`, `MoveMouseTo`,
+//!   `MoveMouseToVirtual`, `MoveMouseWheel`, `GetMousePosition`
+//! - Misc: `Sleep`, `GetRunningTime`, `GetDate`, `GetMKeyState`, `SetMKeyState`,
+//!   `OutputLogMessage`, `OutputDebugMessage`, `ClearLog`
+//!
+//! G Hub-managed functions with no host equivalent (`PlayMacro`,
+//! `SetMouseDPITable`, LCD output, ...) are accepted and logged as unsupported
+//! so existing scripts keep running.
+//!
+//! Note the G Hub numbering quirk: events report 1=left, 2=right, 3=middle,
+//! while `PressMouseButton`/`IsMouseButtonPressed` use 1=left, 2=middle, 3=right.
+
+use crate::error::{Error, Result};
+use crate::input::{self, InputBackend, InputEvent, MouseButton};
+use mlua::{Lua, Value, Variadic};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{debug, info, warn};
+
+/// Device family passed as the third `OnEvent` argument for mouse events.
+pub const FAMILY_MOUSE: &str = "mouse";
+
+/// G Hub API functions accepted but not implemented on the host.
+const UNSUPPORTED_FUNCTIONS: &[&str] = &[
+    "PlayMacro",
+    "PressMacro",
+    "ReleaseMacro",
+    "AbortMacro",
+    "SetMouseDPITable",
+    "SetMouseDPITableIndex",
+    "EnableMouseDPIShift",
+    "OutputLCDMessage",
+    "ClearLCD",
+    "SetBacklightColor",
+];
+
+/// Lua-side helpers that are simplest to express in Lua itself.
+const PRELUDE: &str = r#"
+function GetDate(format, time)
+    return os.date(format, time)
+end
+"#;
+
+/// Mutable state shared by the Lua API closures.
+struct ScriptState {
+    backend: Box<dyn InputBackend>,
+    started: Instant,
+    log: Vec<String>,
+    primary_events: bool,
+    m_state: u8,
+    physical_buttons: HashSet<MouseButton>,
+    synthetic_buttons: HashSet<MouseButton>,
+    held_keys: HashSet<u16>,
+    cursor: (i32, i32),
+}
+
+impl ScriptState {
+    fn emit(&mut self, event: InputEvent) -> mlua::Result<()> {
+        match event {
+            InputEvent::KeyDown(code) => {
+                self.held_keys.insert(code);
+            }
+            InputEvent::KeyUp(code) => {
+                self.held_keys.remove(&code);
+            }
+            InputEvent::ButtonDown(b) => {
+                self.synthetic_buttons.insert(b);
+            }
+            InputEvent::ButtonUp(b) => {
+                self.synthetic_buttons.remove(&b);
+            }
+            InputEvent::MoveAbsolute { x, y } => self.cursor = (x, y),
+            InputEvent::MoveRelative { .. } | InputEvent::Wheel(_) => {}
+        }
+        self.backend.emit(event).map_err(mlua::Error::external)
+    }
+
+    fn push_log(&mut self, message: String) {
+        info!(target: "open_g_hub::script", "{}", message.trim_end());
+        self.log.push(message);
+    }
+}
+
+type SharedState = Arc<Mutex<ScriptState>>;
+
+/// A loaded G Hub-compatible Lua script.
+pub struct ScriptEngine {
+    lua: Lua,
+    state: SharedState,
+    /// Diverted control CID → G Hub event button number.
+    controls: HashMap<u16, u8>,
+    held_cids: Vec<u16>,
+}
+
+impl ScriptEngine {
+    /// Create an engine that sends script output to `backend`.
+    pub fn new(backend: Box<dyn InputBackend>) -> Result<Self> {
+        let state = Arc::new(Mutex::new(ScriptState {
+            backend,
+            started: Instant::now(),
+            log: Vec::new(),
+            primary_events: false,
+            m_state: 1,
+            physical_buttons: HashSet::new(),
+            synthetic_buttons: HashSet::new(),
+            held_keys: HashSet::new(),
+            cursor: (0, 0),
+        }));
+
+        let lua = Lua::new();
+        register_api(&lua, &state).map_err(script_error)?;
+        lua.load(PRELUDE)
+            .set_name("prelude")
+            .exec()
+            .map_err(script_error)?;
+
+        Ok(Self {
+            lua,
+            state,
+            controls: HashMap::new(),
+            held_cids: Vec::new(),
+        })
+    }
+
+    /// Load and run a script's top-level chunk (defines `OnEvent`).
+    pub fn load(&self, name: &str, source: &str) -> Result<()> {
+        debug!(name, "Loading Lua script");
+        self.lua
+            .load(source)
+            .set_name(name)
+            .exec()
+            .map_err(script_error)
+    }
+
+    /// Call `OnEvent(event, arg, family)` if the script defines it.
+    pub fn fire(&self, event: &str, arg: i64, family: &str) -> Result<()> {
+        let handler: Value = self.lua.globals().get("OnEvent").map_err(script_error)?;
+        let Value::Function(handler) = handler else {
+            return Ok(());
+        };
+        debug!(event, arg, family, "Dispatching OnEvent");
+        handler
+            .call::<_, ()>((event, arg, family))
+            .map_err(script_error)
+    }
+
+    /// Fire `PROFILE_ACTIVATED`.
+    pub fn activate(&self) -> Result<()> {
+        self.fire("PROFILE_ACTIVATED", 0, "")
+    }
+
+    /// Fire `PROFILE_DEACTIVATED`.
+    pub fn deactivate(&self) -> Result<()> {
+        self.fire("PROFILE_DEACTIVATED", 0, "")
+    }
+
+    /// Associate a diverted control's CID with a G Hub event button number.
+    pub fn map_control(&mut self, cid: u16, button: u8) {
+        self.controls.insert(cid, button);
+    }
+
+    /// Report a physical mouse button press or release (G Hub event numbering).
+    ///
+    /// Button 1 is only delivered after the script calls
+    /// `EnablePrimaryMouseButtonEvents(true)`, as in G Hub.
+    pub fn handle_button(&self, button: u8, pressed: bool) -> Result<()> {
+        let primary_enabled = {
+            let mut state = self.state.lock().unwrap();
+            if let Some(b) = event_button(button) {
+                if pressed {
+                    state.physical_buttons.insert(b);
+                } else {
+                    state.physical_buttons.remove(&b);
+                }
+            }
+            state.primary_events
+        };
+
+        if button == 1 && !primary_enabled {
+            return Ok(());
+        }
+        let event = if pressed {
+            "MOUSE_BUTTON_PRESSED"
+        } else {
+            "MOUSE_BUTTON_RELEASED"
+        };
+        self.fire(event, button as i64, FAMILY_MOUSE)
+    }
+
+    /// Feed the held-CID list from a divertedButtonsEvent notification.
+    ///
+    /// Compares against the previous list and fires one event per change.
+    /// CIDs not registered with [`Self::map_control`] are ignored.
+    pub fn handle_diverted(&mut self, held: &[u16]) -> Result<()> {
+        let previous = std::mem::replace(&mut self.held_cids, held.to_vec());
+        for cid in previous.iter().filter(|c| !held.contains(c)) {
+            if let Some(&button) = self.controls.get(cid) {
+                self.handle_button(button, false)?;
+            }
+        }
+        for cid in held.iter().filter(|c| !previous.contains(c)) {
+            if let Some(&button) = self.controls.get(cid) {
+                self.handle_button(button, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Messages written with `OutputLogMessage` since the last `ClearLog`.
+    pub fn log(&self) -> Vec<String> {
+        self.state.lock().unwrap().log.clone()
+    }
+
+    /// Current M-key state (1..=3).
+    pub fn m_key_state(&self) -> u8 {
+        self.state.lock().unwrap().m_state
+    }
+
+    /// Release every key and button the script left pressed.
+    pub fn release_all(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let keys: Vec<u16> = state.held_keys.iter().copied().collect();
+        for code in keys {
+            state.emit(InputEvent::KeyUp(code)).map_err(script_error)?;
+        }
+        let buttons: Vec<MouseButton> = state.synthetic_buttons.iter().copied().collect();
+        for b in buttons {
+            state.emit(InputEvent::ButtonUp(b)).map_err(script_error)?;
+        }
+        Ok(())
+    }
+}
+
+fn script_error(e: mlua::Error) -> Error {
+    Error::Script(e.to_string())
+}
+
+/// Map a G Hub *event* button number (1=left, 2=right, 3=middle) to a button.
+fn event_button(number: u8) -> Option<MouseButton> {
+    match number {
+        1 => Some(MouseButton::Left),
+        2 => Some(MouseButton::Right),
+        3 => Some(MouseButton::Middle),
+        4 => Some(MouseButton::Back),
+        5 => Some(MouseButton::Forward),
+        _ => None,
+    }
+}
+
+/// Map a G Hub *output* button number (1=left, 2=middle, 3=right) to a button.
+fn output_button(number: i64) -> mlua::Result<MouseButton> {
+    match number {
+        1 => Ok(MouseButton::Left),
+        2 => Ok(MouseButton::Middle),
+        3 => Ok(MouseButton::Right),
+        4 => Ok(MouseButton::Back),
+        5 => Ok(MouseButton::Forward),
+        other => Err(mlua::Error::RuntimeError(format!(
+            "invalid mouse button {other} (expected 1-5)"
+        ))),
+    }
+}
+
+/// Resolve a `PressKey` argument: a key name or a set-1 scancode.
+fn key_arg(value: &Value) -> mlua::Result<u16> {
+    let resolved = match value {
+        Value::String(s) => {
+            let name = s.to_str()?;
+            input::key_from_name(name)
+                .ok_or_else(|| mlua::Error::RuntimeError(format!("unknown key '{name}'")))?
+        }
+        Value::Integer(n) => u16::try_from(*n)
+            .ok()
+            .and_then(input::key_from_scancode)
+            .ok_or_else(|| mlua::Error::RuntimeError(format!("unknown scancode {n}")))?,
+        Value::Number(n) => input::key_from_scancode(*n as u16)
+            .ok_or_else(|| mlua::Error::RuntimeError(format!("unknown scancode {n}")))?,
+        other => {
+            return Err(mlua::Error::RuntimeError(format!(
+                "expected key name or scancode, got {}",
+                other.type_name()
+            )))
+        }
+    };
+    Ok(resolved)
+}
+
+fn register_api(lua: &Lua, state: &SharedState) -> mlua::Result<()> {
+    let globals = lua.globals();
+
+    let s = state.clone();
+    globals.set(
+        "PressKey",
+        lua.create_function(move |_, keys: Variadic<Value>| {
+            let mut st = s.lock().unwrap();
+            for key in keys.iter() {
+                st.emit(InputEvent::KeyDown(key_arg(key)?))?;
+            }
+            Ok(())
+        })?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "ReleaseKey",
+        lua.create_function(move |_, keys: Variadic<Value>| {
+            let mut st = s.lock().unwrap();
+            for key in keys.iter() {
+                st.emit(InputEvent::KeyUp(key_arg(key)?))?;
+            }
+            Ok(())
+        })?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "PressAndReleaseKey",
+        lua.create_function(move |_, keys: Variadic<Value>| {
+            let codes = keys.iter().map(key_arg).collect::<mlua::Result<Vec<_>>>()?;
+            let mut st = s.lock().unwrap();
+            for &code in &codes {
+                st.emit(InputEvent::KeyDown(code))?;
+            }
+            for &code in codes.iter().rev() {
+                st.emit(InputEvent::KeyUp(code))?;
+            }
+            Ok(())
+        })?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "IsModifierPressed",
+        lua.create_function(move |_, name: String| {
+            let candidates: &[&str] = match name.to_lowercase().as_str() {
+                "shift" => &["lshift", "rshift"],
+                "ctrl" => &["lctrl", "rctrl"],
+                "alt" => &["lalt", "ralt"],
+                "lshift" => &["lshift"],
+                "rshift" => &["rshift"],
+                "lctrl" => &["lctrl"],
+                "rctrl" => &["rctrl"],
+                "lalt" => &["lalt"],
+                "ralt" => &["ralt"],
+                _ => &[],
+            };
+            let st = s.lock().unwrap();
+            Ok(candidates
+                .iter()
+                .filter_map(|n| input::key_from_name(n))
+                .any(|code| st.held_keys.contains(&code)))
+        })?,
+    )?;
+
+    // Lock-key state is owned by the compositor, not visible to the host.
+    globals.set(
+        "IsKeyLockOn",
+        lua.create_function(|_, _name: String| Ok(false))?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "PressMouseButton",
+        lua.create_function(move |_, button: i64| {
+            let b = output_button(button)?;
+            s.lock().unwrap().emit(InputEvent::ButtonDown(b))
+        })?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "ReleaseMouseButton",
+        lua.create_function(move |_, button: i64| {
+            let b = output_button(button)?;
+            s.lock().unwrap().emit(InputEvent::ButtonUp(b))
+        })?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "PressAndReleaseMouseButton",
+        lua.create_function(move |_, button: i64| {
+            let b = output_button(button)?;
+            let mut st = s.lock().unwrap();
+            st.emit(InputEvent::ButtonDown(b))?;
+            st.emit(InputEvent::ButtonUp(b))
+        })?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "IsMouseButtonPressed",
+        lua.create_function(move |_, button: i64| {
+            let b = output_button(button)?;
+            let st = s.lock().unwrap();
+            Ok(st.physical_buttons.contains(&b) || st.synthetic_buttons.contains(&b))
+        })?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "MoveMouseRelative",
+        lua.create_function(move |_, (dx, dy): (f64, f64)| {
+            s.lock().unwrap().emit(InputEvent::MoveRelative {
+                dx: dx as i32,
+                dy: dy as i32,
+            })
+        })?,
+    )?;
+
+    for name in ["MoveMouseTo", "MoveMouseToVirtual"] {
+        let s = state.clone();
+        globals.set(
+            name,
+            lua.create_function(move |_, (x, y): (f64, f64)| {
+                s.lock().unwrap().emit(InputEvent::MoveAbsolute {
+                    x: x as i32,
+                    y: y as i32,
+                })
+            })?,
+        )?;
+    }
+
+    let s = state.clone();
+    globals.set(
+        "MoveMouseWheel",
+        lua.create_function(move |_, clicks: f64| {
+            s.lock().unwrap().emit(InputEvent::Wheel(clicks as i32))
+        })?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "GetMousePosition",
+        lua.create_function(move |_, ()| Ok(s.lock().unwrap().cursor))?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "Sleep",
+        lua.create_function(move |_, ms: f64| {
+            s.lock().unwrap().backend.delay(ms.max(0.0) as u32);
+            Ok(())
+        })?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "GetRunningTime",
+        lua.create_function(move |_, ()| {
+            Ok(s.lock().unwrap().started.elapsed().as_millis() as i64)
+        })?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "EnablePrimaryMouseButtonEvents",
+        lua.create_function(move |_, enable: bool| {
+            s.lock().unwrap().primary_events = enable;
+            Ok(())
+        })?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "GetMKeyState",
+        lua.create_function(move |_, _family: Option<String>| Ok(s.lock().unwrap().m_state))?,
+    )?;
+
+    let s = state.clone();
+    globals.set(
+        "SetMKeyState",
+        lua.create_function(move |_, (m, _family): (u8, Option<String>)| {
+            if !(1..=3).contains(&m) {
+                return Err(mlua::Error::RuntimeError(format!(
+                    "invalid M-key state {m} (expected 1-3)"
+                )));
+            }
+            s.lock().unwrap().m_state = m;
+            Ok(())
+        })?,
+    )?;
+
+    for name in ["OutputLogMessage", "OutputDebugMessage"] {
+        let s = state.clone();
+        globals.set(
+            name,
+            lua.create_function(move |lua, args: Variadic<Value>| {
+                let format: mlua::Function = lua
+                    .globals()
+                    .get::<_, mlua::Table>("string")?
+                    .get("format")?;
+                let message: String = format.call(args)?;
+                s.lock().unwrap().push_log(message);
+                Ok(())
+            })?,
+        )?;
+    }
+
+    let s = state.clone();
+    globals.set(
+        "ClearLog",
+        lua.create_function(move |_, ()| {
+            s.lock().unwrap().log.clear();
+            Ok(())
+        })?,
+    )?;
+
+    for &name in UNSUPPORTED_FUNCTIONS {
+        globals.set(
+            name,
+            lua.create_function(move |_, _args: Variadic<Value>| {
+                warn!(
+                    function = name,
+                    "G Hub script function not supported on host"
+                );
+                Ok(())
+            })?,
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Recorded, RecordingBackend};
+
+    fn engine_with(source: &str) -> (ScriptEngine, RecordingBackend) {
+        let recorder = RecordingBackend::new();
+        let engine = ScriptEngine::new(Box::new(recorder.clone())).unwrap();
+        engine.load("test.lua", source).unwrap();
+        (engine, recorder)
+    }
+
+    #[test]
+    fn on_event_presses_keys_for_thumb_button() {
+        let (engine, recorder) = engine_with(
+            r#"
+            function OnEvent(event, arg, family)
+                if event == "MOUSE_BUTTON_PRESSED" and arg == 4 then
+                    PressAndReleaseKey("lctrl", "c")
+                end
+            end
+            "#,
+        );
+
+        engine.handle_button(4, true).unwrap();
+        assert_eq!(
+            recorder.events(),
+            vec![
+                InputEvent::KeyDown(29),
+                InputEvent::KeyDown(46),
+                InputEvent::KeyUp(46),
+                InputEvent::KeyUp(29),
+            ]
+        );
+    }
+
+    #[test]
+    fn primary_button_events_need_opt_in() {
+        let (engine, _recorder) = engine_with(
+            r#"
+            count = 0
+            function OnEvent(event, arg) if arg == 1 then count = count + 1 end end
+            "#,
+        );
+
+        engine.handle_button(1, true).unwrap();
+        let count: i64 = engine.lua.globals().get("count").unwrap();
+        assert_eq!(count, 0);
+
+        engine
+            .load("enable.lua", "EnablePrimaryMouseButtonEvents(true)")
+            .unwrap();
+        engine.handle_button(1, true).unwrap();
+        let count: i64 = engine.lua.globals().get("count").unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn sleep_and_motion_are_recorded_in_order() {
+        let (engine, recorder) = engine_with(
+            r#"
+            function OnEvent(event)
+                if event == "PROFILE_ACTIVATED" then
+                    MoveMouseRelative(5, -3)
+                    Sleep(20)
+                    MoveMouseWheel(-1)
+                    PressAndReleaseMouseButton(3)
+                end
+            end
+            "#,
+        );
+
+        engine.activate().unwrap();
+        assert_eq!(
+            recorder.recorded(),
+            vec![
+                Recorded::Event(InputEvent::MoveRelative { dx: 5, dy: -3 }),
+                Recorded::Delay(20),
+                Recorded::Event(InputEvent::Wheel(-1)),
+                Recorded::Event(InputEvent::ButtonDown(MouseButton::Right)),
+                Recorded::Event(InputEvent::ButtonUp(MouseButton::Right)),
+            ]
+        );
+    }
+
+    #[test]
+    fn press_key_accepts_scancodes() {
+        let (engine, recorder) = engine_with("PressKey(0x1E, 0x11D) ReleaseKey(0x1E)");
+        drop(engine);
+        assert_eq!(
+            recorder.events(),
+            vec![
+                InputEvent::KeyDown(30),
+                InputEvent::KeyDown(97),
+                InputEvent::KeyUp(30),
+            ]
+        );
+    }
+
+    #[test]
+    fn output_log_message_formats_arguments() {
+        let (engine, _recorder) =
+            engine_with(r#"OutputLogMessage("button %d pressed %s\n", 4, "twice")"#);
+        assert_eq!(engine.log(), vec!["button 4 pressed twice\n".to_string()]);
+
+        engine.load("clear.lua", "ClearLog()").unwrap();
+        assert!(engine.log().is_empty());
+    }
+
+    #[test]
+    fn m_key_state_roundtrip() {
+        let (engine, _recorder) = engine_with("SetMKeyState(2, 'kb') m = GetMKeyState('kb')");
+        assert_eq!(engine.m_key_state(), 2);
+        let m: i64 = engine.lua.globals().get("m").unwrap();
+        assert_eq!(m, 2);
+    }
+
+    #[test]
+    fn is_mouse_button_pressed_tracks_physical_state() {
+        let (engine, _recorder) = engine_with(
+            r#"
+            function OnEvent(event, arg)
+                held = IsMouseButtonPressed(3)
+            end
+            "#,
+        );
+        // Event numbering 2 = right; output numbering 3 = right.
+        engine.handle_button(2, true).unwrap();
+        assert!(engine.lua.globals().get::<_, bool>("held").unwrap());
+        engine.handle_button(2, false).unwrap();
+        assert!(!engine.lua.globals().get::<_, bool>("held").unwrap());
+    }
+
+    #[test]
+    fn diverted_cid_changes_become_press_and_release_events() {
+        let (mut engine, _recorder) = engine_with(
+            r#"
+            events = {}
+            function OnEvent(event, arg) table.insert(events, event .. ":" .. arg) end
+            "#,
+        );
+        engine.map_control(0x0053, 4);
+        engine.map_control(0x0056, 5);
+
+        engine.handle_diverted(&[0x0053]).unwrap();
+        engine.handle_diverted(&[0x0053, 0x0056]).unwrap();
+        engine.handle_diverted(&[]).unwrap();
+
+        let events: Vec<String> = engine.lua.load("return events").eval().unwrap();
+        assert_eq!(
+            events,
+            vec![
+                "MOUSE_BUTTON_PRESSED:4",
+                "MOUSE_BUTTON_PRESSED:5",
+                "MOUSE_BUTTON_RELEASED:4",
+                "MOUSE_BUTTON_RELEASED:5",
+            ]
+        );
+    }
+
+    #[test]
+    fn release_all_lifts_held_keys() {
+        let (engine, recorder) = engine_with("PressKey('lshift')");
+        engine.release_all().unwrap();
+        assert_eq!(
+            recorder.events(),
+            vec![InputEvent::KeyDown(42), InputEvent::KeyUp(42)]
+        );
+    }
+
+    #[test]
+    fn unsupported_functions_do_not_abort_script() {
+        let (engine, _recorder) = engine_with("PlayMacro('reload') ok = true");
+        assert!(engine.lua.globals().get::<_, bool>("ok").unwrap());
+    }
+
+    #[test]
+    fn unknown_key_is_a_script_error() {
+        let recorder = RecordingBackend::new();
+        let engine = ScriptEngine::new(Box::new(recorder)).unwrap();
+        let err = engine
+            .load("bad.lua", "PressKey('hyperspace')")
+            .unwrap_err();
+        assert!(matches!(err, Error::Script(msg) if msg.contains("hyperspace")));
+    }
+
+    #[test]
+    fn syntax_error_is_reported() {
+        let engine = ScriptEngine::new(Box::new(RecordingBackend::new())).unwrap();
+        assert!(matches!(
+            engine.load("broken.lua", "function OnEvent("),
+            Err(Error::Script(_))
+        ));
+    }
+
+    #[test]
+    fn get_date_uses_os_date() {
+        let (engine, _recorder) = engine_with("year = GetDate('!%Y', 0)");
+        let year: String = engine.lua.globals().get("year").unwrap();
+        assert_eq!(year, "1970");
+    }
+}
@@ -0,0 +1,585 @@
+//! Simulated G502 for headless testing of the daemon and its clients.
+//!
+//! Unlike `transport::mock::MockTransport`, which replays canned byte
+//! sequences, [`SimulatedG502`] keeps device state. Writes change what later
+//! reads return, and [`SimulatedG502::power_cycle`] drops host-mode settings
+//! the way a real wireless mouse does when it sleeps. It answers the same
+//! function numbers the feature modules send.
+
+use crate::buttons::cids;
+use crate::comm::RetryPolicy;
+use crate::error::{Error, Result};
+use crate::hidpp::{
+    features, HidppResponse, ERROR_REPORT_10, ERROR_REPORT_20, LONG_REPORT_ID, LONG_REPORT_LEN,
+    SHORT_REPORT_ID,
+};
+use crate::transport::{FeatureTable, HidTransport};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Feature table exposed by the simulated device (index = position).
+const FEATURE_TABLE: &[u16] = &[
+    features::ROOT,
+    features::FEATURE_SET,
+    features::DEVICE_NAME,
+    features::BATTERY_STATUS,
+    features::REPROG_CONTROLS_V4,
+    features::ADJUSTABLE_DPI,
+    features::REPORT_RATE,
+    features::ONBOARD_PROFILES,
+    features::WIRELESS_DEVICE_STATUS,
+    features::COLOR_LED_EFFECTS,
+    features::DEVICE_INFORMATION,
+];
+
+/// Controls in button index order, as the G502 reports them.
+const CONTROLS: &[u16] = &[
+    cids::LEFT_CLICK,
+    cids::RIGHT_CLICK,
+    cids::MIDDLE_CLICK,
+    cids::BACK,
+    cids::FORWARD,
+    cids::DPI_UP,
+];
+
+/// Factory DPI.
+const DEFAULT_DPI: u16 = 800;
+/// Factory report interval (1 ms = 1000 Hz).
+const DEFAULT_INTERVAL: u8 = 1;
+
+/// Factory LED effect: color cycle, 10 s period, full brightness.
+const DEFAULT_LED: (u8, [u8; 10]) = (0x03, [0, 0, 0, 0, 0, 0x27, 0x10, 100, 0, 0]);
+/// Onboard profile sector 1: 1 ms, default stage 1, stages 400/800/1600/3200.
+const PROFILE_SECTOR: [u8; 16] = [
+    0x01, 0x01, 0x00, 0x90, 0x01, 0x20, 0x03, 0x40, 0x06, 0x80, 0x0C, 0xFF, 0xFF, 0x00, 0x00, 0x00,
+];
+
+/// Unit ID reported by DEVICE_INFORMATION.
+pub const UNIT_ID: [u8; 4] = [0x1A, 0x2B, 0x3C, 0x4D];
+
+/// LED zones (logo, DPI indicator).
+const LED_ZONES: usize = 2;
+
+/// HID++ 2.0 error codes returned by the simulation.
+const ERR_INVALID_ARGUMENT: u8 = 0x02;
+const ERR_INVALID_FUNCTION: u8 = 0x07;
+const ERR_BUSY: u8 = 0x08;
+/// HID++ 1.0 code the receiver answers with while the mouse is unreachable.
+const ERR_RESOURCE: u8 = 0x09;
+
+#[derive(Debug, Clone)]
+struct Control {
+    cid: u16,
+    remap: u16,
+    diverted: bool,
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    connected: bool,
+    dpi: u16,
+    report_interval: u8,
+    controls: Vec<Control>,
+    onboard_mode: u8,
+    current_profile: (u8, u8),
+    battery: (u8, u8, u8),
+    leds: [(u8, [u8; 10]); LED_ZONES],
+}
+
+impl State {
+    fn factory() -> Self {
+        Self {
+            connected: true,
+            dpi: DEFAULT_DPI,
+            report_interval: DEFAULT_INTERVAL,
+            controls: CONTROLS
+                .iter()
+                .map(|&cid| Control {
+                    cid,
+                    remap: cid,
+                    diverted: false,
+                })
+                .collect(),
+            onboard_mode: 2,
+            current_profile: (0, 1),
+            battery: (80, 50, 0),
+            leds: [DEFAULT_LED; LED_ZONES],
+        }
+    }
+}
+
+/// A stateful in-memory G502 answering HID++ 2.0 requests.
+pub struct SimulatedG502 {
+    device_index: u8,
+    state: Mutex<State>,
+    notifications: Mutex<VecDeque<Vec<u8>>>,
+    writes: Mutex<usize>,
+    /// Requests still to be refused as busy.
+    busy: Mutex<u32>,
+    retry: Mutex<RetryPolicy>,
+    features: FeatureTable,
+}
+
+impl SimulatedG502 {
+    /// A G502 paired to a receiver at `device_index` (0xFF = wired).
+    pub fn new(device_index: u8) -> Self {
+        Self {
+            device_index,
+            state: Mutex::new(State::factory()),
+            notifications: Mutex::new(VecDeque::new()),
+            writes: Mutex::new(0),
+            busy: Mutex::new(0),
+            retry: Mutex::new(RetryPolicy::default()),
+            features: FeatureTable::new(),
+        }
+    }
+
+    /// HID++ device index the simulation answers on.
+    pub fn device_index(&self) -> u8 {
+        self.device_index
+    }
+
+    /// Feature index assigned to `feature_id`, if the device has it.
+    pub fn feature_index(feature_id: u16) -> Option<u8> {
+        FEATURE_TABLE
+            .iter()
+            .position(|&f| f == feature_id)
+            .map(|i| i as u8)
+    }
+
+    /// Current DPI.
+    pub fn dpi(&self) -> u16 {
+        self.state.lock().unwrap().dpi
+    }
+
+    /// Current report interval in ms.
+    pub fn report_interval(&self) -> u8 {
+        self.state.lock().unwrap().report_interval
+    }
+
+    /// CID that the control at `index` is remapped to.
+    pub fn remap(&self, index: usize) -> Option<u16> {
+        self.state
+            .lock()
+            .unwrap()
+            .controls
+            .get(index)
+            .map(|c| c.remap)
+    }
+
+    /// Whether the control at `index` is diverted to the host.
+    pub fn is_diverted(&self, index: usize) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .controls
+            .get(index)
+            .is_some_and(|c| c.diverted)
+    }
+
+    /// Current onboard mode byte (1 = host, 2 = onboard).
+    pub fn onboard_mode(&self) -> u8 {
+        self.state.lock().unwrap().onboard_mode
+    }
+
+    /// Set the battery reading: percentage and HID++ status byte (0 = discharging).
+    ///
+    /// Like the real device, this broadcasts a BATTERY_STATUS event.
+    pub fn set_battery(&self, level: u8, status: u8) {
+        let next = level.saturating_sub(30);
+        self.state.lock().unwrap().battery = (level, next, status);
+        let idx = Self::feature_index(features::BATTERY_STATUS).unwrap_or(0);
+        self.push_notification(vec![
+            0x10,
+            self.device_index,
+            idx,
+            0x00,
+            level,
+            next,
+            status,
+        ]);
+    }
+
+    /// Press the DPI button to reach `stage` of the onboard profile.
+    ///
+    /// Like the real device in onboard mode, the DPI follows the stage and
+    /// an ONBOARD_PROFILES event 1 announces it.
+    pub fn select_dpi_stage(&self, stage: u8) {
+        let slot = 3 + 2 * stage as usize;
+        if let Some(bytes) = PROFILE_SECTOR.get(slot..slot + 2) {
+            self.state.lock().unwrap().dpi = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        let idx = Self::feature_index(features::ONBOARD_PROFILES).unwrap_or(0);
+        self.push_notification(vec![0x10, self.device_index, idx, 0x10, stage, 0x00, 0x00]);
+    }
+
+    /// Refuse the next `requests` requests with a HID++ 2.0 busy error.
+    pub fn set_busy(&self, requests: u32) {
+        *self.busy.lock().unwrap() = requests;
+    }
+
+    /// Retry requests by `policy` instead of the default.
+    pub fn set_retry_policy(&self, policy: RetryPolicy) {
+        *self.retry.lock().unwrap() = policy;
+    }
+
+    /// Number of state-changing requests handled so far.
+    pub fn write_count(&self) -> usize {
+        *self.writes.lock().unwrap()
+    }
+
+    /// Whether the device currently answers requests.
+    pub fn is_connected(&self) -> bool {
+        self.state.lock().unwrap().connected
+    }
+
+    /// Drop the wireless link (device asleep or switched off).
+    ///
+    /// Requests fail until [`Self::power_cycle`] brings the device back.
+    pub fn disconnect(&self) {
+        self.state.lock().unwrap().connected = false;
+        self.push_notification(self.connection_report(false));
+    }
+
+    /// Simulate the mouse waking from sleep or being switched off and on.
+    ///
+    /// Host-mode settings revert to factory values, the receiver reports the
+    /// link, and the device broadcasts a WIRELESS_DEVICE_STATUS reconnection
+    /// asking for reconfiguration.
+    pub fn power_cycle(&self) {
+        {
+            let mut state = self.state.lock().unwrap();
+            let battery = state.battery;
+            *state = State::factory();
+            state.battery = battery;
+        }
+        self.push_notification(self.connection_report(true));
+
+        let idx = Self::feature_index(features::WIRELESS_DEVICE_STATUS).unwrap_or(0);
+        let mut report = vec![
+            LONG_REPORT_ID,
+            self.device_index,
+            idx,
+            0x00,
+            0x01,
+            0x01,
+            0x01,
+        ];
+        report.resize(LONG_REPORT_LEN, 0);
+        self.push_notification(report);
+    }
+
+    /// Queue an unsolicited report returned by `read_report`.
+    pub fn push_notification(&self, report: Vec<u8>) {
+        self.notifications.lock().unwrap().push_back(report);
+    }
+
+    fn connection_report(&self, linked: bool) -> Vec<u8> {
+        let r0 = if linked { 0x04 } else { 0x44 };
+        vec![0x10, self.device_index, 0x41, 0x04, r0, 0x8D, 0xC0]
+    }
+
+    fn count_write(&self) {
+        *self.writes.lock().unwrap() += 1;
+    }
+
+    /// Handle one request; `Err(code)` becomes a HID++ error report.
+    fn handle(&self, feature_id: u16, function: u8, p: &[u8]) -> std::result::Result<Vec<u8>, u8> {
+        let mut state = self.state.lock().unwrap();
+        let be16 = |p: &[u8]| ((p[0] as u16) << 8) | p[1] as u16;
+
+        match (feature_id, function) {
+            // ROOT getFeatureID(feature) → [index, type, version]
+            (features::ROOT, 0x00) => {
+                let index = Self::feature_index(be16(p)).unwrap_or(0);
+                Ok(vec![index, 0x00, 0x00])
+            }
+            // FEATURE_SET getCount
+            (features::FEATURE_SET, 0x00) => Ok(vec![FEATURE_TABLE.len() as u8 - 1]),
+            // DEVICE_INFORMATION getDeviceInfo → [entities, unitId[4], transport, modelId[6]]
+            (features::DEVICE_INFORMATION, 0x00) => {
+                let mut info = vec![0x03];
+                info.extend_from_slice(&UNIT_ID);
+                info.extend_from_slice(&[0x04, 0x40, 0x7F, 0xC0, 0x8D, 0x00, 0x00]);
+                Ok(info)
+            }
+            // BATTERY_STATUS getBatteryLevelStatus → [level, next level, status]
+            (features::BATTERY_STATUS, 0x00) => {
+                let (level, next, status) = state.battery;
+                Ok(vec![level, next, status])
+            }
+            // REPROG_CONTROLS_V4 getCount
+            (features::REPROG_CONTROLS_V4, 0x00) => Ok(vec![state.controls.len() as u8]),
+            // getControlInfo(index) → [cid, task, flags]
+            (features::REPROG_CONTROLS_V4, 0x01) => {
+                let control = state
+                    .controls
+                    .get(p[0] as usize)
+                    .ok_or(ERR_INVALID_ARGUMENT)?;
+                let cid = control.cid.to_be_bytes();
+                // Flags: mouse button (0x01) | reprogrammable (0x10) | divertable (0x20)
+                Ok(vec![cid[0], cid[1], cid[0], cid[1], 0x31])
+            }
+            // getControlReporting(cid) → [cid, flags, remap]
+            (features::REPROG_CONTROLS_V4, 0x02) => {
+                let cid = be16(p);
+                let control = state
+                    .controls
+                    .iter()
+                    .find(|c| c.cid == cid)
+                    .ok_or(ERR_INVALID_ARGUMENT)?;
+                let remap = control.remap.to_be_bytes();
+                Ok(vec![p[0], p[1], control.diverted as u8, remap[0], remap[1]])
+            }
+            // setControlReporting(cid, flags, remap)
+            (features::REPROG_CONTROLS_V4, 0x03) => {
+                let cid = be16(p);
+                let flags = p[2];
+                let control = state
+                    .controls
+                    .iter_mut()
+                    .find(|c| c.cid == cid)
+                    .ok_or(ERR_INVALID_ARGUMENT)?;
+                if flags & 0x02 != 0 {
+                    control.diverted = flags & 0x01 != 0;
+                }
+                if flags & 0x10 != 0 {
+                    control.remap = ((p[3] as u16) << 8) | p[4] as u16;
+                }
+                drop(state);
+                self.count_write();
+                Ok(p[..5.min(p.len())].to_vec())
+            }
+            // ADJUSTABLE_DPI getSensorCount
+            (features::ADJUSTABLE_DPI, 0x00) => Ok(vec![0x01]),
+            // getSensorDpi(sensor) → [dpi, default dpi]
+            (features::ADJUSTABLE_DPI, 0x01) => {
+                let dpi = state.dpi.to_be_bytes();
+                let default = DEFAULT_DPI.to_be_bytes();
+                Ok(vec![dpi[0], dpi[1], default[0], default[1]])
+            }
+            // setSensorDpi(sensor, dpi)
+            (features::ADJUSTABLE_DPI, 0x02) => {
+                state.dpi = be16(&p[1..]);
+                drop(state);
+                self.count_write();
+                Ok(p[..3].to_vec())
+            }
+            // REPORT_RATE getReportRateList → bitmask of 1/2/4/8 ms
+            (features::REPORT_RATE, 0x00) => Ok(vec![0x8B]),
+            (features::REPORT_RATE, 0x01) => Ok(vec![state.report_interval]),
+            (features::REPORT_RATE, 0x02) => {
+                if !matches!(p[0], 1 | 2 | 4 | 8) {
+                    return Err(ERR_INVALID_ARGUMENT);
+                }
+                state.report_interval = p[0];
+                drop(state);
+                self.count_write();
+                Ok(vec![p[0]])
+            }
+            // ONBOARD_PROFILES getDescription
+            (features::ONBOARD_PROFILES, 0x00) => Ok(vec![0x01, 0x05, 0x0B, 0x10]),
+            (features::ONBOARD_PROFILES, 0x01) => {
+                if !matches!(p[0], 1 | 2) {
+                    return Err(ERR_INVALID_ARGUMENT);
+                }
+                state.onboard_mode = p[0];
+                drop(state);
+                self.count_write();
+                Ok(vec![p[0]])
+            }
+            (features::ONBOARD_PROFILES, 0x02) => Ok(vec![state.onboard_mode]),
+            (features::ONBOARD_PROFILES, 0x03) => {
+                state.current_profile = (p[0], p[1]);
+                drop(state);
+                self.count_write();
+                Ok(vec![p[0], p[1]])
+            }
+            (features::ONBOARD_PROFILES, 0x04) => {
+                let (page, offset) = state.current_profile;
+                Ok(vec![page, offset])
+            }
+            // memoryRead(sector, offset): only profile sector 1 is populated
+            (features::ONBOARD_PROFILES, 0x05) => {
+                if be16(p) != 0x0001 || be16(&p[2..]) != 0 {
+                    return Err(ERR_INVALID_ARGUMENT);
+                }
+                Ok(PROFILE_SECTOR.to_vec())
+            }
+            // COLOR_LED_EFFECTS getInfo → zone count
+            (features::COLOR_LED_EFFECTS, 0x00) => Ok(vec![LED_ZONES as u8]),
+            // setZoneEffect(zone, effect, params[10], persist)
+            (features::COLOR_LED_EFFECTS, 0x03) => {
+                let zone = p[0] as usize;
+                if zone >= LED_ZONES {
+                    return Err(ERR_INVALID_ARGUMENT);
+                }
+                let mut params = [0u8; 10];
+                params.copy_from_slice(&p[2..12]);
+                state.leds[zone] = (p[1], params);
+                drop(state);
+                self.count_write();
+                Ok(p[..2].to_vec())
+            }
+            // getZoneEffect(zone) → [zone, effect, params[10]]
+            (features::COLOR_LED_EFFECTS, 0x0E) => {
+                let (effect, params) =
+                    *state.leds.get(p[0] as usize).ok_or(ERR_INVALID_ARGUMENT)?;
+                let mut reply = vec![p[0], effect];
+                reply.extend_from_slice(&params);
+                Ok(reply)
+            }
+            _ => Err(ERR_INVALID_FUNCTION),
+        }
+    }
+}
+
+impl HidTransport for SimulatedG502 {
+    fn send_report(&self, data: &[u8]) -> Result<Vec<u8>> {
+        // Decoding a request uses the same layout as a response.
+        let req = HidppResponse::decode(data)?;
+        if req.device_index != self.device_index {
+            return Err(Error::Timeout(format!(
+                "no device at index 0x{:02X}",
+                req.device_index
+            )));
+        }
+        // The receiver answers for a mouse it cannot reach.
+        if !self.is_connected() {
+            let mut report = vec![SHORT_REPORT_ID, self.device_index, ERROR_REPORT_10];
+            report.extend_from_slice(&[req.feature_index, req.function_sw, ERR_RESOURCE, 0x00]);
+            return Ok(report);
+        }
+
+        let feature_id = FEATURE_TABLE
+            .get(req.feature_index as usize)
+            .copied()
+            .unwrap_or(0xFFFF);
+        let mut params = req.params.clone();
+        params.resize(16, 0);
+
+        let mut report = vec![LONG_REPORT_ID, self.device_index];
+        let result = {
+            let mut busy = self.busy.lock().unwrap();
+            if *busy > 0 {
+                *busy -= 1;
+                Err(ERR_BUSY)
+            } else {
+                self.handle(feature_id, req.function(), &params)
+            }
+        };
+        match result {
+            Ok(payload) => {
+                report.extend_from_slice(&[req.feature_index, req.function_sw]);
+                report.extend_from_slice(&payload);
+            }
+            Err(code) => report.extend_from_slice(&[
+                ERROR_REPORT_20,
+                req.feature_index,
+                req.function_sw,
+                code,
+            ]),
+        }
+        report.resize(LONG_REPORT_LEN, 0);
+        Ok(report)
+    }
+
+    fn read_report(&self, _timeout_ms: i32) -> Result<Option<Vec<u8>>> {
+        Ok(self.notifications.lock().unwrap().pop_front())
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.retry.lock().unwrap().clone()
+    }
+
+    fn feature_table(&self) -> &FeatureTable {
+        &self.features
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{ButtonAction, PollingRate};
+    use crate::hidpp::{Hidpp10Error, Hidpp20Error, HidppError};
+    use crate::wireless::{self, ConnectionEvent};
+    use crate::{buttons, dpi, onboard, report_rate};
+
+    const DEV_IDX: u8 = 0x01;
+
+    #[test]
+    fn writes_are_visible_to_reads() {
+        let sim = SimulatedG502::new(DEV_IDX);
+        assert_eq!(dpi::read_dpi(&sim, DEV_IDX).unwrap(), DEFAULT_DPI);
+        dpi::write_dpi(&sim, DEV_IDX, 1600).unwrap();
+        assert_eq!(dpi::read_dpi(&sim, DEV_IDX).unwrap(), 1600);
+
+        report_rate::write_report_rate(&sim, DEV_IDX, PollingRate::Hz500).unwrap();
+        assert_eq!(
+            report_rate::read_report_rate(&sim, DEV_IDX).unwrap(),
+            PollingRate::Hz500
+        );
+
+        buttons::write_button_mapping(&sim, DEV_IDX, 3, ButtonAction::Forward).unwrap();
+        assert_eq!(
+            buttons::read_button_mapping(&sim, DEV_IDX, 3).unwrap(),
+            ButtonAction::Forward
+        );
+        assert_eq!(sim.write_count(), 3);
+    }
+
+    #[test]
+    fn unknown_function_returns_hidpp_error() {
+        let sim = SimulatedG502::new(DEV_IDX);
+        assert!(onboard::read_profile_description(&sim, DEV_IDX).is_ok());
+        let dpi_idx =
+            crate::transport::lookup_feature_index(&sim, DEV_IDX, features::ADJUSTABLE_DPI)
+                .unwrap();
+        let req = crate::hidpp::HidppRequest::new(DEV_IDX, dpi_idx, 0x0E, vec![]);
+        assert!(matches!(
+            crate::transport::hidpp_request_once(&sim, &req),
+            Err(Error::HidppProtocol {
+                code: HidppError::V20(Hidpp20Error::InvalidFunctionId),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn other_device_index_does_not_answer() {
+        let sim = SimulatedG502::new(DEV_IDX);
+        assert!(matches!(dpi::read_dpi(&sim, 0xFF), Err(Error::Timeout(_))));
+    }
+
+    #[test]
+    fn power_cycle_resets_settings_and_announces_reconnect() {
+        let sim = SimulatedG502::new(DEV_IDX);
+        dpi::write_dpi(&sim, DEV_IDX, 3200).unwrap();
+        sim.disconnect();
+        assert!(matches!(
+            dpi::read_dpi(&sim, DEV_IDX),
+            Err(Error::HidppProtocol {
+                code: HidppError::V10(Hidpp10Error::ResourceError),
+                ..
+            })
+        ));
+
+        sim.power_cycle();
+        assert_eq!(dpi::read_dpi(&sim, DEV_IDX).unwrap(), DEFAULT_DPI);
+
+        let wds = SimulatedG502::feature_index(features::WIRELESS_DEVICE_STATUS);
+        let events: Vec<_> = std::iter::from_fn(|| sim.read_report(0).unwrap())
+            .filter_map(|raw| HidppResponse::decode(&raw).ok())
+            .filter_map(|r| wireless::parse_connection_event(&r, wds))
+            .map(|(_, e)| e)
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ConnectionEvent::Disconnected,
+                ConnectionEvent::Connected,
+                ConnectionEvent::Reconnected { reconfigure: true },
+            ]
+        );
+    }
+}
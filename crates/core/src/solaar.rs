@@ -0,0 +1,308 @@
+//! Solaar configuration import.
+//!
+//! Solaar keeps per-device settings in `~/.config/solaar/config.yaml`: a
+//! YAML list whose first entry is the Solaar version, followed by one map
+//! per device. Keys starting with `_` identify the device (`_NAME`,
+//! `_serial`, `_unitId`, `_wpid`, `_modelId`) or are Solaar's bookkeeping
+//! (`_absent`, `_sensitive`); the rest are settings.
+//!
+//! ```yaml
+//! - 1.1.11
+//! - _NAME: G502 LIGHTSPEED Wireless Gaming Mouse
+//!   _serial: 1A2B3C4D
+//!   _unitId: 1A2B3C4D
+//!   dpi: 2400
+//!   report_rate: 2
+//!   reprogrammable-keys: {80: 80, 81: 81, 83: 86}
+//! ```
+//!
+//! [`SolaarConfig::find`] picks the entry for a connected mouse by serial
+//! number or unit ID, and [`SolaarDevice::to_profile`] turns `dpi`,
+//! `report_rate` and `reprogrammable-keys` into a [`Profile`]. Every other
+//! setting (smart shift, divert-keys, scroll modes, ...) is listed as
+//! skipped.
+
+use crate::buttons::{action_to_cid, cid_to_action};
+use crate::error::{Error, Result};
+use crate::profile::Profile;
+use crate::safety;
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// `$XDG_CONFIG_HOME/solaar/config.yaml`, else `~/.config/solaar/config.yaml`.
+pub fn config_path() -> Option<PathBuf> {
+    let non_empty = |var: &str| std::env::var_os(var).filter(|v| !v.is_empty());
+    let base = non_empty("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| non_empty("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("solaar").join("config.yaml"))
+}
+
+/// One device entry of a Solaar config.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SolaarDevice {
+    pub name: Option<String>,
+    pub serial: Option<String>,
+    pub unit_id: Option<String>,
+    /// Wireless product ID, e.g. `407F`.
+    pub wpid: Option<String>,
+    /// Settings by Solaar name, without the `_` keys.
+    pub settings: BTreeMap<String, Value>,
+}
+
+/// A parsed Solaar config file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SolaarConfig {
+    /// Solaar version that wrote the file, if recorded.
+    pub version: Option<String>,
+    pub devices: Vec<SolaarDevice>,
+}
+
+/// A profile converted from Solaar settings, and what was left out.
+#[derive(Debug, Clone)]
+pub struct SolaarImport {
+    pub profile: Profile,
+    pub skipped: Vec<String>,
+}
+
+fn invalid(message: impl std::fmt::Display) -> Error {
+    Error::Profile(format!("invalid Solaar config: {message}"))
+}
+
+/// A YAML scalar as text; Solaar leaves serials like `12345678` unquoted.
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Parse the contents of a Solaar `config.yaml`.
+pub fn parse(text: &str) -> Result<SolaarConfig> {
+    let doc: Value = serde_yaml::from_str(text).map_err(invalid)?;
+    let Value::Sequence(entries) = doc else {
+        return Err(invalid("expected a list of devices"));
+    };
+
+    let mut config = SolaarConfig::default();
+    let mut entries = entries.into_iter().peekable();
+    if let Some(version) = entries.peek().and_then(scalar) {
+        config.version = Some(version);
+        entries.next();
+    }
+    for entry in entries {
+        let Value::Mapping(map) = entry else {
+            return Err(invalid("device entries must be maps"));
+        };
+        let mut device = SolaarDevice::default();
+        for (key, value) in map {
+            let Some(key) = scalar(&key) else {
+                return Err(invalid("setting names must be strings"));
+            };
+            match key.as_str() {
+                "_NAME" => device.name = scalar(&value),
+                "_serial" => device.serial = scalar(&value),
+                "_unitId" => device.unit_id = scalar(&value),
+                "_wpid" => device.wpid = scalar(&value),
+                _ if key.starts_with('_') => {}
+                _ => {
+                    device.settings.insert(key, value);
+                }
+            }
+        }
+        config.devices.push(device);
+    }
+    Ok(config)
+}
+
+/// Read and parse a Solaar config file.
+pub fn load(path: &Path) -> Result<SolaarConfig> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| Error::Profile(format!("cannot read {}: {e}", path.display())))?;
+    parse(&text)
+}
+
+impl SolaarConfig {
+    /// The entry for a device with this serial number or unit ID.
+    pub fn find(&self, serial: Option<&str>, unit_id: Option<&str>) -> Option<&SolaarDevice> {
+        self.devices.iter().find(|d| d.matches(serial, unit_id))
+    }
+}
+
+impl SolaarDevice {
+    /// Whether `serial` or `unit_id` matches this entry's `_serial` or
+    /// `_unitId`. Solaar records the unit ID as the serial of wireless
+    /// devices, so either may match either; case is ignored.
+    pub fn matches(&self, serial: Option<&str>, unit_id: Option<&str>) -> bool {
+        let ours = [self.serial.as_deref(), self.unit_id.as_deref()];
+        [serial, unit_id].into_iter().flatten().any(|theirs| {
+            ours.into_iter()
+                .flatten()
+                .any(|ours| !ours.is_empty() && ours.eq_ignore_ascii_case(theirs))
+        })
+    }
+
+    /// Convert the supported settings into a [`Profile`].
+    ///
+    /// Settings Solaar has not recorded keep the profile defaults.
+    pub fn to_profile(&self) -> SolaarImport {
+        let mut profile = Profile {
+            name: self.name.clone().unwrap_or_else(|| "Solaar".to_string()),
+            ..Default::default()
+        };
+        let default_buttons = profile.buttons.clone();
+        let mut skipped = Vec::new();
+
+        for (key, value) in &self.settings {
+            match key.as_str() {
+                "dpi" => match value
+                    .as_u64()
+                    .and_then(|dpi| u16::try_from(dpi).ok())
+                    .map(safety::validate_dpi)
+                {
+                    Some(Ok(dpi)) => profile.dpi = dpi,
+                    _ => skipped.push(format!("dpi: {} is outside the safe range", show(value))),
+                },
+                // Solaar stores the report interval in milliseconds.
+                "report_rate" => match value
+                    .as_u64()
+                    .filter(|&ms| ms > 0)
+                    .and_then(|ms| u16::try_from(1000 / ms).ok())
+                    .map(safety::validate_polling_rate)
+                {
+                    Some(Ok(rate)) => profile.polling_rate = rate,
+                    _ => skipped.push(format!(
+                        "report_rate: {} ms (supported: 1, 2, 4, 8)",
+                        show(value)
+                    )),
+                },
+                "reprogrammable-keys" => {
+                    let Value::Mapping(keys) = value else {
+                        skipped.push(format!("{key}: expected a map of controls"));
+                        continue;
+                    };
+                    for (control, target) in keys {
+                        let (Some(control), Some(target)) = (control.as_u64(), target.as_u64())
+                        else {
+                            skipped.push(format!("{key}: {} -> {}", show(control), show(target)));
+                            continue;
+                        };
+                        let Some(index) = default_buttons
+                            .iter()
+                            .position(|&a| u64::from(action_to_cid(a)) == control)
+                        else {
+                            skipped.push(format!(
+                                "{key}: control {control:#06X} is not a remappable button"
+                            ));
+                            continue;
+                        };
+                        let action = u16::try_from(target).ok().map(cid_to_action);
+                        match action.filter(|&a| u64::from(action_to_cid(a)) == target) {
+                            Some(action) => profile.buttons[index] = action,
+                            None => skipped.push(format!(
+                                "{key}: control {control:#06X} -> {target:#06X} has no equivalent"
+                            )),
+                        }
+                    }
+                }
+                // Diverted controls are handled by Solaar's rule engine.
+                "divert-keys" => {
+                    if let Value::Mapping(keys) = value {
+                        for (control, mode) in keys {
+                            if mode.as_u64() != Some(0) {
+                                let control = control
+                                    .as_u64()
+                                    .map_or_else(|| show(control), |c| format!("{c:#06X}"));
+                                skipped.push(format!(
+                                    "{key}: control {control} is diverted to Solaar rules"
+                                ));
+                            }
+                        }
+                    }
+                }
+                _ => skipped.push(format!("{key}: not supported")),
+            }
+        }
+        SolaarImport { profile, skipped }
+    }
+}
+
+/// A setting value for reports: `2400`, `true`, `{80: 80}`.
+fn show(value: &Value) -> String {
+    match value {
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Null => "null".to_string(),
+        other => serde_yaml::to_string(other)
+            .map(|s| s.trim_end().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{ButtonAction, PollingRate};
+
+    const CONFIG: &str = include_str!("../tests/fixtures/solaar/config.yaml");
+
+    #[test]
+    fn devices_are_parsed_and_matched() {
+        let config = parse(CONFIG).unwrap();
+        assert_eq!(config.version.as_deref(), Some("1.1.11"));
+        assert_eq!(config.devices.len(), 2);
+        assert!(!config.devices[1].settings.contains_key("_absent"));
+
+        let g502 = config.find(None, Some("1a2b3c4d")).unwrap();
+        assert_eq!(g502.wpid.as_deref(), Some("407F"));
+        assert_eq!(
+            config.find(Some("9F8E7D6C"), None),
+            Some(&config.devices[0])
+        );
+        assert!(config.find(Some("00000000"), Some("FFFFFFFF")).is_none());
+        assert!(config.find(None, None).is_none());
+    }
+
+    #[test]
+    fn supported_settings_become_a_profile() {
+        let config = parse(CONFIG).unwrap();
+        let import = config.find(Some("1A2B3C4D"), None).unwrap().to_profile();
+        let profile = &import.profile;
+        assert_eq!(profile.name, "G502 LIGHTSPEED Wireless Gaming Mouse");
+        assert_eq!(profile.dpi, 2400);
+        assert_eq!(profile.polling_rate, PollingRate::Hz500);
+        assert_eq!(
+            profile.buttons,
+            [
+                ButtonAction::LeftClick,
+                ButtonAction::RightClick,
+                ButtonAction::MiddleClick,
+                ButtonAction::Forward,
+                ButtonAction::Back,
+                ButtonAction::NoAction,
+            ]
+        );
+        assert_eq!(
+            import.skipped,
+            [
+                "divert-keys: control 0x0053 is diverted to Solaar rules",
+                "onboard_profiles: not supported",
+                "reprogrammable-keys: control 0x00D7 is not a remappable button",
+                "scroll-ratchet: not supported",
+            ]
+        );
+    }
+
+    #[test]
+    fn unsafe_values_are_skipped() {
+        let text = "- _unitId: 01020304\n  dpi: 90000\n  report_rate: 3\n";
+        let import = parse(text).unwrap().devices[0].to_profile();
+        assert_eq!(import.profile.dpi, Profile::default().dpi);
+        assert_eq!(import.profile.polling_rate, Profile::default().polling_rate);
+        assert_eq!(import.skipped.len(), 2);
+        assert!(parse("dpi: 800").is_err());
+    }
+}
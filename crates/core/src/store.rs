@@ -0,0 +1,419 @@
+//! Native profile library, independent of Logitech G Hub.
+//!
+//! Each profile is one JSON file in `<config dir>/profiles/` (see
+//! [`config_dir`]), named after a slug of the profile name:
+//!
+//! ```json
+//! {"version": 2, "device": {"model": "G502Lightspeed"}, "profile": {...}}
+//! ```
+//!
+//! Files from older schemas, including bare profile JSON, are migrated when
+//! read (see [`crate::schema`]); fields this build does not know are logged
+//! and ignored.
+//!
+//! `device` binds the profile to a mouse model or serial number, or is `null`
+//! for any mouse. Names are matched by slug, so "Shooter" and "shooter" are
+//! the same profile. Files are written to a temporary sibling and renamed
+//! over the target, so a crash never leaves a half-written profile.
+
+use crate::device::{DeviceInfo, MouseModel};
+use crate::error::{Error, Result};
+use crate::profile::Profile;
+use crate::schema::{self, ParseMode, CURRENT_VERSION};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Directory under the config dir holding the profile files.
+const PROFILES_DIR: &str = "profiles";
+
+/// Open G Hub's configuration directory.
+///
+/// `$XDG_CONFIG_HOME/open-g-hub`, else `%APPDATA%\open-g-hub` on Windows,
+/// else `~/.config/open-g-hub`.
+pub fn config_dir() -> Option<PathBuf> {
+    let non_empty = |var: &str| std::env::var_os(var).filter(|v| !v.is_empty());
+    let base = non_empty("XDG_CONFIG_HOME").map(PathBuf::from);
+    #[cfg(target_os = "windows")]
+    let base = base.or_else(|| non_empty("APPDATA").map(PathBuf::from));
+    let base =
+        base.or_else(|| non_empty("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("open-g-hub"))
+}
+
+/// Which mice a stored profile is meant for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceBinding {
+    /// Any mouse of this model.
+    Model(MouseModel),
+    /// The one mouse with this serial number.
+    Serial(String),
+}
+
+impl DeviceBinding {
+    /// Whether `device` is one of the mice this binding names.
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        match self {
+            Self::Model(model) => device.model == *model,
+            Self::Serial(serial) => device.serial.as_deref() == Some(serial),
+        }
+    }
+}
+
+impl std::fmt::Display for DeviceBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Model(model) => f.write_str(model.name()),
+            Self::Serial(serial) => write!(f, "serial {serial}"),
+        }
+    }
+}
+
+/// A profile file in the library.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredProfile {
+    pub version: u32,
+    #[serde(default)]
+    pub device: Option<DeviceBinding>,
+    pub profile: Profile,
+}
+
+impl StoredProfile {
+    pub fn new(profile: Profile, device: Option<DeviceBinding>) -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            device,
+            profile,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.profile.name
+    }
+
+    /// Whether the profile applies to `device` (unbound profiles apply to all).
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        self.device.as_ref().is_none_or(|d| d.matches(device))
+    }
+}
+
+/// File-name stem for a profile name: lowercase ASCII letters and digits,
+/// with every other run of characters turned into one `-`.
+fn slug(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// A directory of profile files.
+#[derive(Debug, Clone)]
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+impl ProfileStore {
+    /// The store in `<config dir>/profiles`.
+    pub fn open_default() -> Result<Self> {
+        let config = config_dir().ok_or_else(|| {
+            Error::Profile("no config directory: set XDG_CONFIG_HOME or HOME".to_string())
+        })?;
+        Ok(Self::open(config.join(PROFILES_DIR)))
+    }
+
+    /// The store in `dir`, which is created on the first write.
+    pub fn open(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path_for(&self, name: &str) -> Result<PathBuf> {
+        let slug = slug(name);
+        if slug.is_empty() {
+            return Err(Error::Profile(format!(
+                "profile name '{name}' needs at least one letter or digit"
+            )));
+        }
+        Ok(self.dir.join(format!("{slug}.json")))
+    }
+
+    /// Every readable profile, sorted by name. Unreadable files are logged
+    /// and skipped.
+    pub fn list(&self) -> Result<Vec<StoredProfile>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(io_error(&self.dir, e)),
+        };
+        let mut profiles = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| io_error(&self.dir, e))?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            match read_file(&path) {
+                Ok(stored) => profiles.push(stored),
+                Err(e) => warn!("skipping {}: {e}", path.display()),
+            }
+        }
+        profiles.sort_by_key(|p| p.profile.name.to_lowercase());
+        Ok(profiles)
+    }
+
+    /// Profiles that apply to `device`: bound to it first, then unbound.
+    pub fn for_device(&self, device: &DeviceInfo) -> Result<Vec<StoredProfile>> {
+        let mut profiles: Vec<StoredProfile> = self
+            .list()?
+            .into_iter()
+            .filter(|p| p.matches(device))
+            .collect();
+        profiles.sort_by_key(|p| p.device.is_none());
+        Ok(profiles)
+    }
+
+    pub fn exists(&self, name: &str) -> Result<bool> {
+        Ok(self.path_for(name)?.is_file())
+    }
+
+    pub fn get(&self, name: &str) -> Result<StoredProfile> {
+        let path = self.path_for(name)?;
+        if !path.is_file() {
+            return Err(Error::Profile(format!("no profile named '{name}'")));
+        }
+        read_file(&path)
+    }
+
+    /// Add a new profile; fails if one with the same name exists.
+    pub fn create(&self, stored: &StoredProfile) -> Result<()> {
+        if self.exists(stored.name())? {
+            return Err(Error::Profile(format!(
+                "profile '{}' already exists",
+                stored.name()
+            )));
+        }
+        self.save(stored)
+    }
+
+    /// Write a profile, replacing any with the same name.
+    pub fn save(&self, stored: &StoredProfile) -> Result<()> {
+        let path = self.path_for(stored.name())?;
+        let json = serde_json::to_vec_pretty(stored)
+            .map_err(|e| Error::Profile(format!("serializing '{}': {e}", stored.name())))?;
+        write_atomic(&path, &json)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        let path = self.path_for(name)?;
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(Error::Profile(format!("no profile named '{name}'")))
+            }
+            Err(e) => Err(io_error(&path, e)),
+        }
+    }
+
+    /// Rename a profile. Changing only the case of its name is allowed.
+    pub fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let mut stored = self.get(from)?;
+        let old_path = self.path_for(from)?;
+        let new_path = self.path_for(to)?;
+        if new_path != old_path && new_path.is_file() {
+            return Err(Error::Profile(format!("profile '{to}' already exists")));
+        }
+        stored.profile.name = to.to_string();
+        self.save(&stored)?;
+        if new_path != old_path {
+            fs::remove_file(&old_path).map_err(|e| io_error(&old_path, e))?;
+        }
+        Ok(())
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> Error {
+    Error::Profile(format!("{}: {e}", path.display()))
+}
+
+fn read_file(path: &Path) -> Result<StoredProfile> {
+    let text = fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+    let parsed = schema::parse(&text, ParseMode::Lenient)
+        .map_err(|e| Error::Profile(format!("{}: {e}", path.display())))?;
+    if !parsed.unknown_fields.is_empty() {
+        warn!(
+            path = %path.display(),
+            fields = %parsed.unknown_fields.join(", "),
+            "Ignoring unknown profile fields"
+        );
+    }
+    Ok(parsed.stored)
+}
+
+/// Replace `path` with `data` via a synced temporary file and a rename.
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = dir.join(format!(".{file_name}.{}.tmp", std::process::id()));
+
+    let written = fs::File::create(&tmp).and_then(|mut file| {
+        file.write_all(data)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|()| fs::rename(&tmp, path)) {
+        let _ = fs::remove_file(&tmp);
+        return Err(io_error(path, e));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str, dpi: u16) -> StoredProfile {
+        StoredProfile::new(
+            Profile {
+                name: name.to_string(),
+                dpi,
+                ..Default::default()
+            },
+            None,
+        )
+    }
+
+    fn device(model: MouseModel, serial: Option<&str>) -> DeviceInfo {
+        DeviceInfo {
+            model,
+            vid: crate::LOGITECH_VID,
+            pid: model.pid(),
+            path: "/dev/hidraw0".to_string(),
+            serial: serial.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn slugs_are_file_safe() {
+        assert_eq!(slug("Shooter"), "shooter");
+        assert_eq!(slug("  CS2 / Low DPI! "), "cs2-low-dpi");
+        assert_eq!(slug("../../etc"), "etc");
+        assert_eq!(slug("---"), "");
+    }
+
+    #[test]
+    fn create_list_get_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(dir.path().join("profiles"));
+        assert!(store.list().unwrap().is_empty());
+
+        store.create(&profile("Shooter", 400)).unwrap();
+        store.create(&profile("desktop", 1600)).unwrap();
+        assert!(store.create(&profile("shooter", 800)).is_err());
+        assert!(store.create(&profile("!!!", 800)).is_err());
+
+        let names: Vec<String> = store
+            .list()
+            .unwrap()
+            .iter()
+            .map(|p| p.name().to_string())
+            .collect();
+        assert_eq!(names, ["desktop", "Shooter"]);
+        assert_eq!(store.get("SHOOTER").unwrap().profile.dpi, 400);
+
+        store.delete("shooter").unwrap();
+        assert!(store.get("Shooter").is_err());
+        assert!(store.delete("Shooter").is_err());
+        // No temporary files are left behind.
+        assert_eq!(fs::read_dir(store.dir()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn rename_moves_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(dir.path());
+        store.save(&profile("Old", 800)).unwrap();
+        store.save(&profile("Taken", 800)).unwrap();
+
+        assert!(store.rename("Old", "taken").is_err());
+        store.rename("Old", "New Name").unwrap();
+        assert!(!store.exists("Old").unwrap());
+        assert_eq!(store.get("new name").unwrap().name(), "New Name");
+
+        store.rename("New Name", "new name").unwrap();
+        assert_eq!(store.get("New Name").unwrap().name(), "new name");
+    }
+
+    #[test]
+    fn newer_format_versions_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(dir.path());
+        let mut stored = profile("Future", 800);
+        stored.version = CURRENT_VERSION + 1;
+        store.save(&stored).unwrap();
+        fs::write(dir.path().join("garbage.json"), "{").unwrap();
+
+        assert!(store.get("Future").is_err());
+        assert!(store.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn older_files_are_migrated_when_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(dir.path());
+        fs::write(
+            dir.path().join("baseline.json"),
+            include_str!("../tests/fixtures/profiles/v0-baseline.json"),
+        )
+        .unwrap();
+
+        let stored = store.get("Baseline").unwrap();
+        assert_eq!(stored.version, CURRENT_VERSION);
+        assert_eq!(stored.profile.dpi, 1600);
+        // Saving rewrites it in the current schema.
+        store.save(&stored).unwrap();
+        let text = fs::read_to_string(dir.path().join("baseline.json")).unwrap();
+        assert_eq!(
+            schema::parse(&text, ParseMode::Strict)
+                .unwrap()
+                .from_version,
+            CURRENT_VERSION
+        );
+    }
+
+    #[test]
+    fn bindings_select_profiles_for_a_device() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(dir.path());
+        store.save(&profile("Any", 800)).unwrap();
+        let mut hero = profile("Hero", 800);
+        hero.device = Some(DeviceBinding::Model(MouseModel::G502Hero));
+        store.save(&hero).unwrap();
+        let mut mine = profile("Mine", 800);
+        mine.device = Some(DeviceBinding::Serial("ABC123".to_string()));
+        store.save(&mine).unwrap();
+
+        let names = |d: &DeviceInfo| -> Vec<String> {
+            store
+                .for_device(d)
+                .unwrap()
+                .iter()
+                .map(|p| p.name().to_string())
+                .collect()
+        };
+        assert_eq!(names(&device(MouseModel::G502Hero, None)), ["Hero", "Any"]);
+        assert_eq!(
+            names(&device(MouseModel::G502Lightspeed, Some("ABC123"))),
+            ["Mine", "Any"]
+        );
+    }
+}
@@ -0,0 +1,341 @@
+//! Transactional apply: all of a plan's writes, or none of them.
+//!
+//! [`apply`] runs a [`Plan`] one write at a time. Before each write it reads
+//! the setting's current value from the device, so the value to restore is
+//! the live one rather than whatever the plan was built from. After each
+//! write it reads the setting back and treats a mismatch as a failure. When
+//! a step fails, every earlier write (and the failed one, if it reached the
+//! device) is undone in reverse order. The [`ApplyReport`] says what was
+//! applied, what was rolled back and what could not be restored.
+
+use crate::error::{Error, Result};
+use crate::plan::{Plan, Write};
+use crate::transport::HidTransport;
+use crate::{buttons, dpi, led, report_rate};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+impl Write {
+    /// The same write with `from` set to what the device holds now.
+    fn with_live_value(&self, transport: &dyn HidTransport, device_index: u8) -> Result<Self> {
+        let mut live = self.clone();
+        match &mut live {
+            Self::Dpi { from, .. } => *from = dpi::read_dpi(transport, device_index)?,
+            Self::SensorDpi { sensor, from, .. } => {
+                *from = Some(dpi::read_sensor_dpi(transport, device_index, *sensor)?)
+            }
+            Self::ReportRate { from, .. } => {
+                *from = report_rate::read_report_rate(transport, device_index)?
+            }
+            Self::Button { index, from, .. } => {
+                *from = Some(buttons::read_button_mapping(
+                    transport,
+                    device_index,
+                    *index,
+                )?)
+            }
+            Self::Led { zone, from, .. } => {
+                *from = Some(led::read_led_effect(transport, device_index, *zone)?.normalized())
+            }
+        }
+        Ok(live)
+    }
+
+    /// Whether `from` already equals `to`.
+    fn is_done(&self) -> bool {
+        match self {
+            Self::Dpi { from, to } => from == to,
+            Self::SensorDpi { from, to, .. } => *from == Some(*to),
+            Self::ReportRate { from, to } => from == to,
+            Self::Button { from, to, .. } => *from == Some(*to),
+            Self::Led { from, to, .. } => *from == Some(to.normalized()),
+        }
+    }
+
+    /// The write that puts `from` back, if `from` is known.
+    fn undo(&self) -> Option<Self> {
+        Some(match self.clone() {
+            Self::Dpi { from, to } => Self::Dpi { from: to, to: from },
+            Self::SensorDpi { sensor, from, to } => Self::SensorDpi {
+                sensor,
+                from: Some(to),
+                to: from?,
+            },
+            Self::ReportRate { from, to } => Self::ReportRate { from: to, to: from },
+            Self::Button { index, from, to } => Self::Button {
+                index,
+                from: Some(to),
+                to: from?,
+            },
+            Self::Led { zone, from, to } => Self::Led {
+                zone,
+                from: Some(to),
+                to: from?,
+            },
+        })
+    }
+}
+
+/// A write and why it did not go through.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepError {
+    pub write: Write,
+    pub error: String,
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.write, self.error)
+    }
+}
+
+/// Outcome of a transactional apply.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApplyReport {
+    /// Writes confirmed on the device, with the values they replaced.
+    pub applied: Vec<Write>,
+    /// The step that failed; nothing after it was attempted.
+    pub failed: Option<StepError>,
+    /// Writes undone after the failure, in the order they were undone.
+    pub rolled_back: Vec<Write>,
+    /// Writes that stay on the device because undoing them failed.
+    pub not_restored: Vec<StepError>,
+}
+
+impl ApplyReport {
+    /// Whether every write was applied.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_none()
+    }
+
+    /// `Ok` when complete, otherwise the whole report as the error.
+    pub fn into_result(self) -> Result<Self> {
+        if self.is_complete() {
+            Ok(self)
+        } else {
+            Err(Error::Profile(format!("apply failed\n{self}")))
+        }
+    }
+}
+
+impl fmt::Display for ApplyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines = Vec::new();
+        for write in &self.applied {
+            if !self.rolled_back.contains(write) {
+                lines.push(format!("Applied: {write}"));
+            }
+        }
+        if let Some(failed) = &self.failed {
+            lines.push(format!("Failed: {failed}"));
+        }
+        lines.extend(self.rolled_back.iter().map(|w| format!("Rolled back: {w}")));
+        lines.extend(
+            self.not_restored
+                .iter()
+                .map(|e| format!("Not restored: {e}")),
+        );
+        if lines.is_empty() {
+            return f.write_str("Nothing to change.");
+        }
+        f.write_str(&lines.join("\n"))
+    }
+}
+
+/// Run `plan` on the device, rolling everything back if a step fails.
+///
+/// Device errors end up in the report rather than the `Result`; use
+/// [`ApplyReport::into_result`] to turn an incomplete apply into an error.
+pub fn apply(transport: &dyn HidTransport, device_index: u8, plan: &Plan) -> ApplyReport {
+    let mut report = ApplyReport::default();
+    // Writes that may have reached the device, in the order they were made.
+    let mut written: Vec<Write> = Vec::new();
+
+    for write in &plan.writes {
+        // Without the live value there is nothing to roll back to.
+        let live = match write.with_live_value(transport, device_index) {
+            Ok(live) => live,
+            Err(e) => {
+                report.failed = Some(StepError {
+                    write: write.clone(),
+                    error: e.to_string(),
+                });
+                break;
+            }
+        };
+        if live.is_done() {
+            continue;
+        }
+        // From here on the write may have reached the device, even when
+        // its reply does not come back.
+        written.push(live.clone());
+        if let Err(e) = live.execute(transport, device_index) {
+            report.failed = Some(StepError {
+                write: live,
+                error: e.to_string(),
+            });
+            break;
+        }
+        let error = match live.with_live_value(transport, device_index) {
+            Ok(readback) if readback.is_done() => {
+                report.applied.push(live);
+                continue;
+            }
+            Ok(_) => "the device did not keep the new value".to_string(),
+            Err(e) => format!("could not read the value back: {e}"),
+        };
+        report.failed = Some(StepError { write: live, error });
+        break;
+    }
+
+    let Some(failed) = report.failed.clone() else {
+        return report;
+    };
+    for write in written.iter().rev() {
+        let Some(undo) = write.undo() else {
+            report.not_restored.push(StepError {
+                write: write.clone(),
+                error: "previous value unknown".to_string(),
+            });
+            continue;
+        };
+        // The failed write may never have reached the device.
+        if *write == failed.write
+            && undo
+                .with_live_value(transport, device_index)
+                .is_ok_and(|live| live.is_done())
+        {
+            continue;
+        }
+        match undo.execute(transport, device_index) {
+            Ok(()) => report.rolled_back.push(write.clone()),
+            Err(e) => report.not_restored.push(StepError {
+                write: write.clone(),
+                error: e.to_string(),
+            }),
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comm::RetryPolicy;
+    use crate::device::{ButtonAction, PollingRate};
+    use crate::hidpp::features;
+    use crate::profile::{snapshot, Profile};
+    use crate::sim::SimulatedG502;
+    use crate::transport::{BoxTransport, Fault, FaultKind, FaultLayer, Faults, TransportStack};
+    use std::sync::Arc;
+
+    /// The simulator behind a fault layer: each `(feature, function, n)`
+    /// setter times out after `n` successes.
+    fn faulty(failing: &[(u16, u8, usize)]) -> (Arc<SimulatedG502>, BoxTransport) {
+        let sim = Arc::new(SimulatedG502::new(0x01));
+        let faults = Faults::new();
+        for &(feature, function, successes) in failing {
+            faults.inject(
+                Fault::new(FaultKind::Timeout)
+                    .on(feature, function)
+                    .after(successes)
+                    .always(),
+            );
+        }
+        let device = TransportStack::new()
+            .layer(FaultLayer::new(faults))
+            .build(Arc::clone(&sim));
+        (sim, device)
+    }
+
+    fn target_from(current: &Profile) -> Profile {
+        let mut target = current.clone();
+        target.dpi = 3200;
+        target.polling_rate = PollingRate::Hz500;
+        target.buttons[4] = ButtonAction::NoAction;
+        target
+    }
+
+    #[test]
+    fn complete_apply_reaches_the_target() {
+        let (sim, device) = faulty(&[]);
+        let current = snapshot(&device, 0x01).unwrap();
+        let target = target_from(&current);
+        let plan = crate::plan::plan(&current, &target).unwrap();
+
+        let report = apply(&device, 0x01, &plan);
+        assert!(report.is_complete());
+        assert_eq!(report.applied, plan.writes);
+        assert!(report.rolled_back.is_empty());
+        assert_eq!(sim.dpi(), 3200);
+    }
+
+    #[test]
+    fn failed_step_rolls_back_earlier_writes_in_reverse() {
+        let (_sim, device) = faulty(&[(features::REPROG_CONTROLS_V4, 3, 0)]);
+        let current = snapshot(&device, 0x01).unwrap();
+        let plan = crate::plan::plan(&current, &target_from(&current)).unwrap();
+
+        let report = apply(&device, 0x01, &plan);
+        assert!(!report.is_complete());
+        assert!(matches!(
+            report.failed.as_ref().unwrap().write,
+            Write::Button { index: 4, .. }
+        ));
+        let undone: Vec<_> = plan.writes[..2].iter().rev().cloned().collect();
+        assert_eq!(report.rolled_back, undone);
+        assert!(report.not_restored.is_empty());
+        let after = snapshot(&device, 0x01).unwrap();
+        assert!(crate::plan::plan(&after, &current).unwrap().is_empty());
+
+        let message = report.into_result().unwrap_err().to_string();
+        assert!(message.contains("Failed: Button 4"), "{message}");
+        assert!(
+            message.contains("Rolled back: DPI: 800 -> 3200"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn failed_undo_is_reported_as_not_restored() {
+        let (sim, device) = faulty(&[
+            (features::ADJUSTABLE_DPI, 2, 1),
+            (features::REPROG_CONTROLS_V4, 3, 0),
+        ]);
+        let current = snapshot(&device, 0x01).unwrap();
+        let plan = crate::plan::plan(&current, &target_from(&current)).unwrap();
+
+        let report = apply(&device, 0x01, &plan);
+        assert_eq!(report.rolled_back, vec![plan.writes[1].clone()]);
+        assert_eq!(report.not_restored.len(), 1);
+        assert_eq!(report.not_restored[0].write, plan.writes[0]);
+        assert_eq!(sim.dpi(), 3200);
+        assert!(report
+            .to_string()
+            .contains("Not restored: DPI: 800 -> 3200"));
+    }
+
+    #[test]
+    fn write_whose_reply_is_lost_is_rolled_back() {
+        let sim = Arc::new(SimulatedG502::new(0x01));
+        sim.set_retry_policy(RetryPolicy::none());
+        let faults = Faults::new();
+        let device = TransportStack::new()
+            .layer(FaultLayer::new(faults.clone()))
+            .build(Arc::clone(&sim));
+        let current = snapshot(&device, 0x01).unwrap();
+        let plan = crate::plan::plan(&current, &target_from(&current)).unwrap();
+
+        // The DPI lands on the mouse, but the reply times out.
+        faults.inject(
+            Fault::new(FaultKind::Timeout)
+                .on(features::ADJUSTABLE_DPI, 2)
+                .delivered(),
+        );
+        let report = apply(&device, 0x01, &plan);
+        assert_eq!(report.failed.as_ref().unwrap().write, plan.writes[0]);
+        assert_eq!(report.rolled_back, vec![plan.writes[0].clone()]);
+        assert!(report.not_restored.is_empty());
+        assert_eq!(sim.dpi(), 800);
+    }
+}
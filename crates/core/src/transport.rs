@@ -2,9 +2,26 @@
 //!
 //! Provides a trait-based transport layer so that real HID devices and
 //! mock devices share the same interface.
+//!
+//! Every request is checked against the feature whitelist in
+//! [`safety`](crate::safety) before it is written. Each transport keeps a
+//! [`FeatureTable`] of the feature IDs behind the indices looked up through
+//! it; a request to an index that was never looked up, or to a feature off
+//! the whitelist, fails without touching the device. Expert tooling can
+//! bypass the check with [`hidpp_request_expert`], which logs every request
+//! it sends.
 
+use crate::comm::RetryPolicy;
 use crate::error::{Error, Result};
-use crate::hidpp::{HidppRequest, HidppResponse};
+use crate::hidpp::{
+    features, Hidpp20Error, HidppError, HidppRequest, HidppResponse, ERROR_REPORT_20,
+    SHORT_REPORT_ID,
+};
+use crate::safety::{self, ExpertMode};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
 use tracing::{debug, trace, warn};
 
 /// Abstraction over raw HID read/write.
@@ -13,10 +30,148 @@ use tracing::{debug, trace, warn};
 pub trait HidTransport: Send {
     /// Write a raw HID report and return the response.
     fn send_report(&self, data: &[u8]) -> Result<Vec<u8>>;
+
+    /// Read an unsolicited report (HID++ notification), waiting up to `timeout_ms`.
+    ///
+    /// Returns `Ok(None)` when nothing arrived in time. Transports that cannot
+    /// receive notifications keep the default, which never yields a report.
+    fn read_report(&self, _timeout_ms: i32) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// How [`hidpp_request`] retries failed requests on this transport.
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::default()
+    }
+
+    /// Feature IDs looked up on this transport, for the whitelist check.
+    fn feature_table(&self) -> &FeatureTable;
+}
+
+/// Which feature ID each looked-up feature index stands for, per device
+/// index. Filled by [`lookup_feature_index`]; index 0 is always ROOT.
+#[derive(Debug, Default)]
+pub struct FeatureTable {
+    ids: Mutex<HashMap<(u8, u8), u16>>,
+    /// Expert requests in flight, as (thread, device index, feature index,
+    /// function), which [`Self::check`] lets through.
+    expert: Mutex<Vec<(ThreadId, u8, u8, u8)>>,
+}
+
+impl FeatureTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember that `feature_index` on `device_index` is `feature_id`.
+    pub fn record(&self, device_index: u8, feature_index: u8, feature_id: u16) {
+        self.ids
+            .lock()
+            .unwrap()
+            .insert((device_index, feature_index), feature_id);
+    }
+
+    /// The feature behind `feature_index`, if it was looked up.
+    pub fn feature_id(&self, device_index: u8, feature_index: u8) -> Option<u16> {
+        if feature_index == 0 {
+            return Some(features::ROOT);
+        }
+        self.ids
+            .lock()
+            .unwrap()
+            .get(&(device_index, feature_index))
+            .copied()
+    }
+
+    /// Refuse a request to `function` of `feature_index` unless the index
+    /// was looked up and its feature is on the whitelist, or the request is
+    /// an expert request this thread has in flight.
+    pub fn check(&self, device_index: u8, feature_index: u8, function: u8) -> Result<()> {
+        let key = (
+            thread::current().id(),
+            device_index,
+            feature_index,
+            function,
+        );
+        if self.expert.lock().unwrap().contains(&key) {
+            return Ok(());
+        }
+        let feature_id =
+            self.feature_id(device_index, feature_index)
+                .ok_or(Error::UnknownFeatureIndex {
+                    device_index,
+                    feature_index,
+                })?;
+        safety::validate_hidpp_request(feature_id, function)
+    }
+
+    /// Admit `req` past [`Self::check`] on this thread while `send` runs.
+    fn admit_expert<T>(&self, req: &HidppRequest, send: impl FnOnce() -> T) -> T {
+        let key = (
+            thread::current().id(),
+            req.device_index,
+            req.feature_index,
+            req.function_sw >> 4,
+        );
+        self.expert.lock().unwrap().push(key);
+        let result = send();
+        let mut expert = self.expert.lock().unwrap();
+        if let Some(i) = expert.iter().position(|k| *k == key) {
+            expert.swap_remove(i);
+        }
+        result
+    }
 }
 
-/// Send a HID++ request and decode the response.
+/// Send a HID++ request and decode the response, retrying as the
+/// transport's [`RetryPolicy`] says.
 pub fn hidpp_request(transport: &dyn HidTransport, req: &HidppRequest) -> Result<HidppResponse> {
+    transport.retry_policy().send(transport, req)
+}
+
+/// Send a HID++ request once and decode the response.
+pub fn hidpp_request_once(
+    transport: &dyn HidTransport,
+    req: &HidppRequest,
+) -> Result<HidppResponse> {
+    transport
+        .feature_table()
+        .check(req.device_index, req.feature_index, req.function_sw >> 4)?;
+    exchange(transport, req)
+}
+
+/// Send a HID++ request without the whitelist check, retrying as
+/// [`hidpp_request`] does. Each request is logged at warn level with the
+/// reason given for `expert`.
+pub fn hidpp_request_expert(
+    transport: &dyn HidTransport,
+    req: &HidppRequest,
+    expert: &ExpertMode,
+) -> Result<HidppResponse> {
+    let feature_id = transport
+        .feature_table()
+        .feature_id(req.device_index, req.feature_index);
+    warn!(
+        target: safety::AUDIT_TARGET,
+        reason = expert.reason(),
+        device_index = req.device_index,
+        feature_index = req.feature_index,
+        feature_id = ?feature_id,
+        function_sw = format_args!("0x{:02X}", req.function_sw),
+        params_hex = format_args!("{:02X?}", req.params),
+        "Expert mode: sending HID++ request without whitelist check"
+    );
+    // Layers such as `SafetyLayer` check raw reports against the table too.
+    transport.feature_table().admit_expert(req, || {
+        transport
+            .retry_policy()
+            .run(req, || exchange(transport, req))
+    })
+}
+
+/// Write `req` and decode the reply, turning HID++ error reports into
+/// [`Error::HidppProtocol`].
+fn exchange(transport: &dyn HidTransport, req: &HidppRequest) -> Result<HidppResponse> {
     let encoded = req.encode()?;
     trace!(
         device_index = req.device_index,
@@ -37,26 +192,17 @@ pub fn hidpp_request(transport: &dyn HidTransport, req: &HidppRequest) -> Result
         "HID++ RX"
     );
 
-    // Check for HID++ error responses
-    if resp.is_error() {
-        let error_feature = if resp.params.len() >= 2 {
-            resp.params[0]
+    if let Some((feature_index, function, code)) = resp.error() {
+        // Receivers answer device index probes with HID++ 1.0 errors.
+        if matches!(code, HidppError::V10(_)) {
+            debug!(feature_index, function, error = %code, "HID++ 1.0 error response");
         } else {
-            0
-        };
-        let error_code = if resp.params.len() >= 3 {
-            resp.params[2]
-        } else {
-            0
-        };
-        warn!(
-            error_feature = error_feature,
-            error_code = error_code,
-            "HID++ error response"
-        );
+            warn!(feature_index, function, error = %code, "HID++ error response");
+        }
         return Err(Error::HidppProtocol {
-            feature: error_feature as u16,
-            code: error_code,
+            feature_index,
+            function,
+            code,
         });
     }
 
@@ -87,11 +233,11 @@ pub fn lookup_feature_index(
             feature_id = format_args!("0x{:04X}", feature_id),
             "Feature not supported by device"
         );
-        return Err(Error::HidppProtocol {
-            feature: feature_id,
-            code: 0x05, // NOT_FOUND
-        });
+        return Err(Error::UnsupportedFeature(feature_id));
     }
+    transport
+        .feature_table()
+        .record(device_index, feature_index, feature_id);
 
     debug!(
         feature_id = format_args!("0x{:04X}", feature_id),
@@ -101,6 +247,571 @@ pub fn lookup_feature_index(
     Ok(feature_index)
 }
 
+/// A transport shared between threads, as a stack of layers builds it.
+pub type BoxTransport = Box<dyn HidTransport + Sync>;
+
+impl<T: HidTransport + Sync + ?Sized> HidTransport for Arc<T> {
+    fn send_report(&self, data: &[u8]) -> Result<Vec<u8>> {
+        (**self).send_report(data)
+    }
+
+    fn read_report(&self, timeout_ms: i32) -> Result<Option<Vec<u8>>> {
+        (**self).read_report(timeout_ms)
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        (**self).retry_policy()
+    }
+
+    fn feature_table(&self) -> &FeatureTable {
+        (**self).feature_table()
+    }
+}
+
+impl HidTransport for BoxTransport {
+    fn send_report(&self, data: &[u8]) -> Result<Vec<u8>> {
+        (**self).send_report(data)
+    }
+
+    fn read_report(&self, timeout_ms: i32) -> Result<Option<Vec<u8>>> {
+        (**self).read_report(timeout_ms)
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        (**self).retry_policy()
+    }
+
+    fn feature_table(&self) -> &FeatureTable {
+        (**self).feature_table()
+    }
+}
+
+/// Wraps a transport in another that adds behavior around its reports.
+///
+/// Layers are stacked with [`TransportStack`]. A layered transport hands
+/// everything it does not change to the transport it wraps, the feature
+/// table included, so lookups through the stack land in the base transport.
+pub trait Layer: Send + Sync {
+    fn layer(&self, inner: BoxTransport) -> BoxTransport;
+}
+
+/// Layers to put around a base transport. The first layer added is the
+/// outermost: it sees each report first on the way out and last on the
+/// way back.
+#[derive(Default)]
+pub struct TransportStack {
+    layers: Vec<Box<dyn Layer>>,
+}
+
+impl TransportStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The stack the CLI, GUI and daemon open mice with: report logging,
+    /// `retry`, and the feature whitelist on raw reports.
+    pub fn standard(retry: RetryPolicy) -> Self {
+        Self::new()
+            .layer(LogLayer)
+            .layer(RetryLayer::new(retry))
+            .layer(SafetyLayer)
+    }
+
+    /// Add `layer` inside the ones added so far.
+    pub fn layer(mut self, layer: impl Layer + 'static) -> Self {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// Wrap `base` in the layers.
+    pub fn build(&self, base: impl HidTransport + Sync + 'static) -> BoxTransport {
+        self.layers
+            .iter()
+            .rev()
+            .fold(Box::new(base), |inner, layer| layer.layer(inner))
+    }
+}
+
+/// Logs every report written and read at debug level, with how long the
+/// reply took.
+pub struct LogLayer;
+
+impl Layer for LogLayer {
+    fn layer(&self, inner: BoxTransport) -> BoxTransport {
+        Box::new(Logged { inner })
+    }
+}
+
+struct Logged {
+    inner: BoxTransport,
+}
+
+impl HidTransport for Logged {
+    fn send_report(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let started = Instant::now();
+        let result = self.inner.send_report(data);
+        let elapsed_us = started.elapsed().as_micros() as u64;
+        match &result {
+            Ok(reply) => debug!(
+                request = format_args!("{data:02X?}"),
+                reply = format_args!("{reply:02X?}"),
+                elapsed_us,
+                "HID report"
+            ),
+            Err(e) => debug!(
+                request = format_args!("{data:02X?}"),
+                elapsed_us, "HID report failed: {e}"
+            ),
+        }
+        result
+    }
+
+    fn read_report(&self, timeout_ms: i32) -> Result<Option<Vec<u8>>> {
+        let report = self.inner.read_report(timeout_ms)?;
+        if let Some(report) = &report {
+            debug!(report = format_args!("{report:02X?}"), "HID notification");
+        }
+        Ok(report)
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.inner.retry_policy()
+    }
+
+    fn feature_table(&self) -> &FeatureTable {
+        self.inner.feature_table()
+    }
+}
+
+/// Sets the [`RetryPolicy`] that [`hidpp_request`] applies to requests on
+/// the stack. Retrying happens per HID++ request, where error replies are
+/// decoded, not per raw report.
+pub struct RetryLayer {
+    policy: RetryPolicy,
+}
+
+impl RetryLayer {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self { policy }
+    }
+}
+
+impl Layer for RetryLayer {
+    fn layer(&self, inner: BoxTransport) -> BoxTransport {
+        Box::new(WithRetry {
+            inner,
+            policy: self.policy.clone(),
+        })
+    }
+}
+
+struct WithRetry {
+    inner: BoxTransport,
+    policy: RetryPolicy,
+}
+
+impl HidTransport for WithRetry {
+    fn send_report(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.inner.send_report(data)
+    }
+
+    fn read_report(&self, timeout_ms: i32) -> Result<Option<Vec<u8>>> {
+        self.inner.read_report(timeout_ms)
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.policy.clone()
+    }
+
+    fn feature_table(&self) -> &FeatureTable {
+        self.inner.feature_table()
+    }
+}
+
+/// Keeps at least `min_interval` between reports written, for devices or
+/// receivers that drop requests sent back to back.
+pub struct RateLimitLayer {
+    min_interval: Duration,
+}
+
+impl RateLimitLayer {
+    pub fn new(min_interval: Duration) -> Self {
+        Self { min_interval }
+    }
+}
+
+impl Layer for RateLimitLayer {
+    fn layer(&self, inner: BoxTransport) -> BoxTransport {
+        Box::new(RateLimited {
+            inner,
+            min_interval: self.min_interval,
+            last: Mutex::new(None),
+        })
+    }
+}
+
+struct RateLimited {
+    inner: BoxTransport,
+    min_interval: Duration,
+    last: Mutex<Option<Instant>>,
+}
+
+impl HidTransport for RateLimited {
+    fn send_report(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut last = self.last.lock().unwrap();
+        if let Some(wait) = last.map(|at| self.min_interval.saturating_sub(at.elapsed())) {
+            if !wait.is_zero() {
+                trace!(
+                    wait_us = wait.as_micros() as u64,
+                    "Rate limiting HID report"
+                );
+                std::thread::sleep(wait);
+            }
+        }
+        *last = Some(Instant::now());
+        drop(last);
+        self.inner.send_report(data)
+    }
+
+    fn read_report(&self, timeout_ms: i32) -> Result<Option<Vec<u8>>> {
+        self.inner.read_report(timeout_ms)
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.inner.retry_policy()
+    }
+
+    fn feature_table(&self) -> &FeatureTable {
+        self.inner.feature_table()
+    }
+}
+
+/// Checks raw reports against the feature whitelist, so code writing
+/// reports without [`hidpp_request`] is held to it too. Requests sent with
+/// [`hidpp_request_expert`] pass, as the feature table admits them while
+/// they are in flight.
+pub struct SafetyLayer;
+
+impl Layer for SafetyLayer {
+    fn layer(&self, inner: BoxTransport) -> BoxTransport {
+        Box::new(Guarded { inner })
+    }
+}
+
+struct Guarded {
+    inner: BoxTransport,
+}
+
+impl HidTransport for Guarded {
+    fn send_report(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let req = HidppResponse::decode(data)?;
+        self.inner
+            .feature_table()
+            .check(req.device_index, req.feature_index, req.function())?;
+        self.inner.send_report(data)
+    }
+
+    fn read_report(&self, timeout_ms: i32) -> Result<Option<Vec<u8>>> {
+        self.inner.read_report(timeout_ms)
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.inner.retry_policy()
+    }
+
+    fn feature_table(&self) -> &FeatureTable {
+        self.inner.feature_table()
+    }
+}
+
+/// One report seen by a [`RecordLayer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    /// A report written and what came back: the reply, or the error.
+    Exchange {
+        request: Vec<u8>,
+        reply: std::result::Result<Vec<u8>, String>,
+    },
+    /// An unsolicited report.
+    Notification(Vec<u8>),
+}
+
+/// Handle to the reports a [`RecordLayer`] has seen.
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    records: Arc<Mutex<Vec<Record>>>,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything recorded so far, oldest first.
+    pub fn records(&self) -> Vec<Record> {
+        self.records.lock().unwrap().clone()
+    }
+
+    /// Forget what was recorded.
+    pub fn clear(&self) {
+        self.records.lock().unwrap().clear();
+    }
+
+    fn push(&self, record: Record) {
+        self.records.lock().unwrap().push(record);
+    }
+}
+
+/// Records every report written and read into a [`Recording`].
+pub struct RecordLayer {
+    recording: Recording,
+}
+
+impl RecordLayer {
+    pub fn new(recording: Recording) -> Self {
+        Self { recording }
+    }
+}
+
+impl Layer for RecordLayer {
+    fn layer(&self, inner: BoxTransport) -> BoxTransport {
+        Box::new(Recorded {
+            inner,
+            recording: self.recording.clone(),
+        })
+    }
+}
+
+struct Recorded {
+    inner: BoxTransport,
+    recording: Recording,
+}
+
+impl HidTransport for Recorded {
+    fn send_report(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let result = self.inner.send_report(data);
+        self.recording.push(Record::Exchange {
+            request: data.to_vec(),
+            reply: match &result {
+                Ok(reply) => Ok(reply.clone()),
+                Err(e) => Err(e.to_string()),
+            },
+        });
+        result
+    }
+
+    fn read_report(&self, timeout_ms: i32) -> Result<Option<Vec<u8>>> {
+        let report = self.inner.read_report(timeout_ms)?;
+        if let Some(report) = &report {
+            self.recording.push(Record::Notification(report.clone()));
+        }
+        Ok(report)
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.inner.retry_policy()
+    }
+
+    fn feature_table(&self) -> &FeatureTable {
+        self.inner.feature_table()
+    }
+}
+
+/// What an injected fault does to a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// The reply never arrives: [`Error::Timeout`].
+    Timeout,
+    /// The device is gone: [`Error::Disconnected`].
+    Disconnect,
+    /// The device answers with a HID++ 2.0 busy error.
+    Busy,
+}
+
+/// A fault to inject into matching reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    /// Only reports to this feature ID; any feature when unset.
+    pub feature: Option<u16>,
+    /// Only reports calling this function; any function when unset.
+    pub function: Option<u8>,
+    /// Matching reports let through before the fault hits.
+    pub after: usize,
+    /// Matching reports the fault hits, once it does.
+    pub times: usize,
+    /// Let hit reports reach the device and lose only the reply.
+    pub delivered: bool,
+}
+
+impl Fault {
+    /// `kind` on the next report.
+    pub fn new(kind: FaultKind) -> Self {
+        Self {
+            kind,
+            feature: None,
+            function: None,
+            after: 0,
+            times: 1,
+            delivered: false,
+        }
+    }
+
+    /// Only for `function` of `feature`.
+    pub fn on(self, feature: u16, function: u8) -> Self {
+        Self {
+            feature: Some(feature),
+            function: Some(function),
+            ..self
+        }
+    }
+
+    /// Let `reports` matching reports through first.
+    pub fn after(self, reports: usize) -> Self {
+        Self {
+            after: reports,
+            ..self
+        }
+    }
+
+    /// Hit every matching report from then on.
+    pub fn always(self) -> Self {
+        Self {
+            times: usize::MAX,
+            ..self
+        }
+    }
+
+    /// Deliver hit reports to the device before failing them, as when a
+    /// write lands but its reply is lost.
+    pub fn delivered(self) -> Self {
+        Self {
+            delivered: true,
+            ..self
+        }
+    }
+}
+
+/// Handle to the faults a [`FaultLayer`] injects, shared with the test
+/// driving it.
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    pending: Arc<Mutex<Vec<Fault>>>,
+}
+
+impl Faults {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `fault`. A fault that hits no reports (`times == 0`) is dropped.
+    pub fn inject(&self, fault: Fault) {
+        if fault.times > 0 {
+            self.pending.lock().unwrap().push(fault);
+        }
+    }
+
+    /// The fault hitting a report to `function` of `feature`, if any,
+    /// counting the report against every fault it matches. When several
+    /// could hit, the one injected first does.
+    fn hit(&self, feature: Option<u16>, function: u8) -> Option<Fault> {
+        let mut pending = self.pending.lock().unwrap();
+        let mut hit = None;
+        for fault in pending.iter_mut() {
+            let matches = fault.feature.is_none_or(|f| Some(f) == feature)
+                && fault.function.is_none_or(|f| f == function);
+            if !matches {
+                continue;
+            }
+            if fault.after > 0 {
+                fault.after -= 1;
+            } else if hit.is_none() {
+                fault.times -= 1;
+                hit = Some(fault.clone());
+            }
+        }
+        pending.retain(|f| f.times > 0);
+        hit
+    }
+}
+
+/// Injects the [`Faults`] it is given into reports on their way out, for
+/// tests. A faulted report never reaches the transport below, unless the
+/// fault is [delivered](Fault::delivered).
+pub struct FaultLayer {
+    faults: Faults,
+}
+
+impl FaultLayer {
+    pub fn new(faults: Faults) -> Self {
+        Self { faults }
+    }
+}
+
+impl Layer for FaultLayer {
+    fn layer(&self, inner: BoxTransport) -> BoxTransport {
+        Box::new(Faulted {
+            inner,
+            faults: self.faults.clone(),
+        })
+    }
+}
+
+struct Faulted {
+    inner: BoxTransport,
+    faults: Faults,
+}
+
+impl HidTransport for Faulted {
+    fn send_report(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let req = HidppResponse::decode(data)?;
+        let feature = self
+            .inner
+            .feature_table()
+            .feature_id(req.device_index, req.feature_index);
+        let Some(fault) = self.faults.hit(feature, req.function()) else {
+            return self.inner.send_report(data);
+        };
+        debug!(
+            kind = ?fault.kind,
+            delivered = fault.delivered,
+            request = format_args!("{data:02X?}"),
+            "Injecting fault"
+        );
+        if fault.delivered {
+            self.inner.send_report(data)?;
+        }
+        match fault.kind {
+            FaultKind::Timeout => Err(Error::Timeout("injected fault".into())),
+            FaultKind::Disconnect => Err(Error::Disconnected {
+                op: "write",
+                message: "injected fault".into(),
+            }),
+            FaultKind::Busy => Ok(vec![
+                SHORT_REPORT_ID,
+                req.device_index,
+                ERROR_REPORT_20,
+                req.feature_index,
+                req.function_sw,
+                Hidpp20Error::Busy.code(),
+                0x00,
+            ]),
+        }
+    }
+
+    fn read_report(&self, timeout_ms: i32) -> Result<Option<Vec<u8>>> {
+        self.inner.read_report(timeout_ms)
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.inner.retry_policy()
+    }
+
+    fn feature_table(&self) -> &FeatureTable {
+        self.inner.feature_table()
+    }
+}
+
 /// A mock HID transport for testing.
 ///
 /// Stores predefined request→response mappings.
@@ -108,19 +819,24 @@ pub fn lookup_feature_index(
 pub mod mock {
     use super::*;
     use crate::hidpp::{LONG_REPORT_LEN, SHORT_REPORT_LEN};
-    use std::collections::HashMap;
-    use std::sync::Mutex;
+    use std::collections::VecDeque;
 
     /// Mock transport that returns preconfigured responses.
+    #[derive(Default)]
     pub struct MockTransport {
         responses: Mutex<HashMap<Vec<u8>, Vec<u8>>>,
+        notifications: Mutex<VecDeque<Vec<u8>>>,
+        features: FeatureTable,
     }
 
     impl MockTransport {
         pub fn new() -> Self {
-            Self {
-                responses: Mutex::new(HashMap::new()),
-            }
+            Self::default()
+        }
+
+        /// Queue an unsolicited report returned by `read_report`.
+        pub fn push_notification(&self, report: Vec<u8>) {
+            self.notifications.lock().unwrap().push_back(report);
         }
 
         /// Register a response for a given request.
@@ -187,13 +903,19 @@ pub mod mock {
     impl HidTransport for MockTransport {
         fn send_report(&self, data: &[u8]) -> Result<Vec<u8>> {
             let responses = self.responses.lock().unwrap();
-            responses.get(data).cloned().ok_or_else(|| {
-                Error::Hid(format!(
-                    "mock: no response registered for request {:02X?}",
-                    data
-                ))
+            responses.get(data).cloned().ok_or_else(|| Error::Hid {
+                op: "write",
+                message: format!("mock: no response registered for request {data:02X?}"),
             })
         }
+
+        fn read_report(&self, _timeout_ms: i32) -> Result<Option<Vec<u8>>> {
+            Ok(self.notifications.lock().unwrap().pop_front())
+        }
+
+        fn feature_table(&self) -> &FeatureTable {
+            &self.features
+        }
     }
 }
 
@@ -223,6 +945,8 @@ mod tests {
     #[test]
     fn hidpp_request_detects_error_response() {
         let mock = mock::MockTransport::new();
+        mock.feature_table()
+            .record(0x01, 0x07, features::ADJUSTABLE_DPI);
         // Craft a request
         let req = HidppRequest::new(0x01, 0x07, 0x01, vec![0x00]);
         let encoded = req.encode().unwrap();
@@ -233,6 +957,177 @@ mod tests {
         mock.on_request(encoded, err_resp);
 
         let result = hidpp_request(&mock, &req);
-        assert!(result.is_err());
+        assert!(matches!(result, Err(Error::HidppProtocol { .. })));
+    }
+
+    #[test]
+    fn requests_outside_the_whitelist_are_never_written() {
+        let mock = mock::MockTransport::new();
+        // DFU (0x00D0) sits at index 0x03 on this device.
+        mock.on_short_request(0x01, 0x00, 0x01, &[0x00, 0xD0], &[0x03, 0x00, 0x00]);
+        mock.on_short_request(0x01, 0x03, 0x11, &[], &[0x01]);
+        mock.on_short_request(0x01, 0x05, 0x11, &[], &[0x01]);
+
+        // An index never looked up is refused.
+        let unknown = HidppRequest::new(0x01, 0x05, 0x01, vec![]);
+        assert!(matches!(
+            hidpp_request(&mock, &unknown),
+            Err(Error::UnknownFeatureIndex {
+                device_index: 0x01,
+                feature_index: 0x05
+            })
+        ));
+
+        // Looking up a feature is harmless, but using it is not allowed.
+        assert_eq!(lookup_feature_index(&mock, 0x01, 0x00D0).unwrap(), 0x03);
+        let dfu = HidppRequest::new(0x01, 0x03, 0x01, vec![]);
+        assert!(matches!(
+            hidpp_request(&mock, &dfu),
+            Err(Error::FeatureNotAllowed(0x00D0))
+        ));
+
+        // Expert mode gets through, by name.
+        let expert = ExpertMode::enable("probing DFU in a test");
+        assert_eq!(
+            hidpp_request_expert(&mock, &dfu, &expert).unwrap().params[0],
+            0x01
+        );
+    }
+
+    #[test]
+    fn layers_wrap_in_order_and_share_the_feature_table() {
+        let mock = Arc::new(mock::MockTransport::new());
+        mock.on_short_request(0x01, 0x00, 0x01, &[0x22, 0x01], &[0x07, 0x00, 0x00]);
+        mock.on_short_request(0x01, 0x07, 0x21, &[], &[0x00, 0x03, 0x20]);
+        let recording = Recording::new();
+        let faults = Faults::new();
+        faults.inject(Fault::new(FaultKind::Busy).on(features::ADJUSTABLE_DPI, 2));
+        let quick = RetryPolicy {
+            initial_backoff_ms: 1,
+            ..RetryPolicy::default()
+        };
+        let stack = TransportStack::new()
+            .layer(RecordLayer::new(recording.clone()))
+            .layer(RetryLayer::new(quick))
+            .layer(FaultLayer::new(faults.clone()))
+            .build(Arc::clone(&mock));
+
+        // The lookup through the stack lands in the mock's table.
+        assert_eq!(
+            lookup_feature_index(&stack, 0x01, features::ADJUSTABLE_DPI).unwrap(),
+            0x07
+        );
+        assert_eq!(
+            mock.feature_table().feature_id(0x01, 0x07),
+            Some(features::ADJUSTABLE_DPI)
+        );
+
+        // The fault sits inside the recorder: the busy reply is recorded,
+        // then the retry goes through.
+        let req = HidppRequest::new(0x01, 0x07, 0x02, vec![]);
+        assert_eq!(hidpp_request(&stack, &req).unwrap().params[1], 0x03);
+        let replies: Vec<u8> = recording
+            .records()
+            .iter()
+            .map(|r| match r {
+                Record::Exchange {
+                    reply: Ok(reply), ..
+                } => reply[2],
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect();
+        assert_eq!(replies, [0x00, ERROR_REPORT_20, 0x07]);
+
+        faults.inject(Fault::new(FaultKind::Disconnect));
+        assert!(matches!(
+            hidpp_request(&stack, &req),
+            Err(Error::Disconnected { .. })
+        ));
+    }
+
+    #[test]
+    fn faults_count_every_report_they_match() {
+        let faults = Faults::new();
+        faults.inject(Fault::new(FaultKind::Timeout).after(1));
+        faults.inject(Fault::new(FaultKind::Disconnect).after(2));
+        faults.inject(Fault {
+            times: 0,
+            ..Fault::new(FaultKind::Busy)
+        });
+        let kinds: Vec<Option<FaultKind>> = (0..4)
+            .map(|_| faults.hit(Some(features::ROOT), 0).map(|f| f.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                None,
+                Some(FaultKind::Timeout),
+                Some(FaultKind::Disconnect),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn safety_layer_checks_raw_reports() {
+        let mock = mock::MockTransport::new();
+        mock.on_short_request(0x01, 0x05, 0x11, &[], &[0x01]);
+        let stack = TransportStack::new().layer(SafetyLayer).build(mock);
+        let raw = HidppRequest::new(0x01, 0x05, 0x01, vec![])
+            .encode()
+            .unwrap();
+        assert!(matches!(
+            stack.send_report(&raw),
+            Err(Error::UnknownFeatureIndex { .. })
+        ));
+        let expert = ExpertMode::enable("test");
+        let req = HidppRequest::new(0x01, 0x05, 0x01, vec![]);
+        assert!(hidpp_request_expert(&stack, &req, &expert).is_ok());
+        // The admission ends with the expert request.
+        assert!(stack.send_report(&raw).is_err());
+
+        stack
+            .feature_table()
+            .record(0x01, 0x05, features::REPORT_RATE);
+        assert!(stack.send_report(&raw).is_ok());
+    }
+
+    #[test]
+    fn expert_requests_pass_the_standard_stack() {
+        let mock = mock::MockTransport::new();
+        mock.on_short_request(0x01, 0x00, 0x01, &[0x00, 0xD0], &[0x03, 0x00, 0x00]);
+        mock.on_short_request(0x01, 0x03, 0x11, &[], &[0x01]);
+        let stack = TransportStack::standard(RetryPolicy::none()).build(mock);
+        assert_eq!(lookup_feature_index(&stack, 0x01, 0x00D0).unwrap(), 0x03);
+
+        let dfu = HidppRequest::new(0x01, 0x03, 0x01, vec![]);
+        assert!(matches!(
+            hidpp_request(&stack, &dfu),
+            Err(Error::FeatureNotAllowed(0x00D0))
+        ));
+        let expert = ExpertMode::enable("probing DFU in a test");
+        assert_eq!(
+            hidpp_request_expert(&stack, &dfu, &expert).unwrap().params[0],
+            0x01
+        );
+        assert!(matches!(
+            stack.send_report(&dfu.encode().unwrap()),
+            Err(Error::FeatureNotAllowed(0x00D0))
+        ));
+    }
+
+    #[test]
+    fn rate_limit_spaces_reports() {
+        let mock = mock::MockTransport::new();
+        mock.on_short_request(0x01, 0x00, 0x01, &[], &[0x00]);
+        let stack = TransportStack::new()
+            .layer(RateLimitLayer::new(Duration::from_millis(20)))
+            .build(mock);
+        let req = HidppRequest::new(0x01, 0x00, 0x00, vec![]);
+        let started = Instant::now();
+        for _ in 0..3 {
+            hidpp_request(&stack, &req).unwrap();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(40));
     }
 }
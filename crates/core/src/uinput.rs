@@ -0,0 +1,251 @@
+//! Linux uinput backend: injects synthesized input through `/dev/uinput`.
+//!
+//! Two virtual devices are created: a keyboard/relative pointer for keys,
+//! buttons, motion and wheel, and a separate absolute pointer for
+//! `MoveAbsolute` (mixing both axis kinds on one device makes libinput treat
+//! it as a tablet).
+//!
+//! Requires write access to `/dev/uinput` (typically the `input` group or a
+//! udev rule).
+
+use crate::error::{Error, Result};
+use crate::input::{self, InputBackend, InputEvent, MouseButton};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use tracing::debug;
+
+const UINPUT_PATH: &str = "/dev/uinput";
+
+pub(crate) const EV_SYN: u16 = 0x00;
+pub(crate) const EV_KEY: u16 = 0x01;
+pub(crate) const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+pub(crate) const SYN_REPORT: u16 = 0x00;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+pub(crate) const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+pub(crate) const REL_WHEEL_HI_RES: u16 = 0x0B;
+pub(crate) const REL_HWHEEL_HI_RES: u16 = 0x0C;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const BUS_VIRTUAL: u16 = 0x06;
+
+/// Largest absolute coordinate (G Hub normalizes `MoveMouseTo` to 0..=65535).
+pub const ABS_MAX: i32 = 65535;
+
+/// Build an `_IOW('U', nr, size)` ioctl request number.
+const fn uinput_iow(nr: u32, size: usize) -> u32 {
+    (1 << 30) | ((size as u32) << 16) | ((b'U' as u32) << 8) | nr
+}
+
+/// Build an `_IO('U', nr)` ioctl request number.
+const fn uinput_io(nr: u32) -> u32 {
+    ((b'U' as u32) << 8) | nr
+}
+
+const UI_DEV_CREATE: u32 = uinput_io(1);
+const UI_DEV_DESTROY: u32 = uinput_io(2);
+const UI_DEV_SETUP: u32 = uinput_iow(3, std::mem::size_of::<libc::uinput_setup>());
+const UI_ABS_SETUP: u32 = uinput_iow(4, std::mem::size_of::<libc::uinput_abs_setup>());
+const UI_SET_EVBIT: u32 = uinput_iow(100, std::mem::size_of::<libc::c_int>());
+const UI_SET_KEYBIT: u32 = uinput_iow(101, std::mem::size_of::<libc::c_int>());
+const UI_SET_RELBIT: u32 = uinput_iow(102, std::mem::size_of::<libc::c_int>());
+const UI_SET_ABSBIT: u32 = uinput_iow(103, std::mem::size_of::<libc::c_int>());
+
+/// A single virtual input device.
+pub(crate) struct UinputDevice {
+    file: File,
+}
+
+impl UinputDevice {
+    fn open() -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .open(UINPUT_PATH)
+            .map_err(|e| Error::Input(format!("open {UINPUT_PATH}: {e}")))?;
+        Ok(Self { file })
+    }
+
+    fn ioctl_int(&self, request: u32, value: libc::c_int) -> Result<()> {
+        // SAFETY: the fd is valid for the lifetime of `self.file`, and every
+        // request used with this helper takes an int argument by value.
+        let rc = unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, value) };
+        if rc < 0 {
+            return Err(Error::Input(format!(
+                "uinput ioctl 0x{request:08X}: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+
+    fn ioctl_ptr<T>(&self, request: u32, arg: &T) -> Result<()> {
+        // SAFETY: `arg` points to a properly sized, initialized struct matching
+        // the request's encoded size.
+        let rc = unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, arg as *const T) };
+        if rc < 0 {
+            return Err(Error::Input(format!(
+                "uinput ioctl 0x{request:08X}: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+
+    fn setup(&self, name: &str, product: u16) -> Result<()> {
+        // SAFETY: uinput_setup is plain old data; all-zero is a valid value.
+        let mut setup: libc::uinput_setup = unsafe { std::mem::zeroed() };
+        setup.id.bustype = BUS_VIRTUAL;
+        setup.id.vendor = crate::LOGITECH_VID;
+        setup.id.product = product;
+        for (dst, src) in setup
+            .name
+            .iter_mut()
+            .zip(name.bytes().take(libc::UINPUT_MAX_NAME_SIZE - 1))
+        {
+            *dst = src as libc::c_char;
+        }
+        self.ioctl_ptr(UI_DEV_SETUP, &setup)?;
+        self.ioctl_int(UI_DEV_CREATE, 0)
+    }
+
+    /// Create a keyboard + relative pointer device.
+    ///
+    /// X, Y and the wheel are always present; `extra_rel` adds further axes
+    /// (e.g. hi-res wheel when mirroring a real mouse).
+    pub(crate) fn create_pointer_keyboard(name: &str, extra_rel: &[u16]) -> Result<Self> {
+        let dev = Self::open()?;
+        dev.ioctl_int(UI_SET_EVBIT, EV_KEY as libc::c_int)?;
+        dev.ioctl_int(UI_SET_EVBIT, EV_REL as libc::c_int)?;
+        for code in input::all_key_codes() {
+            dev.ioctl_int(UI_SET_KEYBIT, code as libc::c_int)?;
+        }
+        // BTN_LEFT..=BTN_TASK covers every button a G502 reports over evdev.
+        for code in MouseButton::Left.evdev_code()..=0x117 {
+            dev.ioctl_int(UI_SET_KEYBIT, code as libc::c_int)?;
+        }
+        for &rel in [REL_X, REL_Y, REL_WHEEL].iter().chain(extra_rel) {
+            dev.ioctl_int(UI_SET_RELBIT, rel as libc::c_int)?;
+        }
+        dev.setup(name, 0x0001)?;
+        debug!(name, "Created uinput pointer/keyboard device");
+        Ok(dev)
+    }
+
+    /// Create an absolute pointer device spanning 0..=ABS_MAX on both axes.
+    fn create_absolute_pointer(name: &str) -> Result<Self> {
+        let dev = Self::open()?;
+        dev.ioctl_int(UI_SET_EVBIT, EV_KEY as libc::c_int)?;
+        dev.ioctl_int(UI_SET_EVBIT, EV_ABS as libc::c_int)?;
+        dev.ioctl_int(UI_SET_KEYBIT, MouseButton::Left.evdev_code() as libc::c_int)?;
+        for axis in [ABS_X, ABS_Y] {
+            dev.ioctl_int(UI_SET_ABSBIT, axis as libc::c_int)?;
+            // SAFETY: uinput_abs_setup is plain old data; all-zero is valid.
+            let mut abs: libc::uinput_abs_setup = unsafe { std::mem::zeroed() };
+            abs.code = axis;
+            abs.absinfo.maximum = ABS_MAX;
+            dev.ioctl_ptr(UI_ABS_SETUP, &abs)?;
+        }
+        dev.setup(name, 0x0002)?;
+        debug!(name, "Created uinput absolute pointer device");
+        Ok(dev)
+    }
+
+    /// Write one raw evdev event.
+    pub(crate) fn write_event(&mut self, type_: u16, code: u16, value: i32) -> Result<()> {
+        // SAFETY: input_event is plain old data; the kernel fills in the time.
+        let mut ev: libc::input_event = unsafe { std::mem::zeroed() };
+        ev.type_ = type_;
+        ev.code = code;
+        ev.value = value;
+        // SAFETY: `ev` is a fully initialized repr(C) struct; we view its bytes.
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &ev as *const libc::input_event as *const u8,
+                std::mem::size_of::<libc::input_event>(),
+            )
+        };
+        self.file
+            .write_all(bytes)
+            .map_err(|e| Error::Input(format!("uinput write: {e}")))
+    }
+
+    /// Write a SYN_REPORT to flush a group of events.
+    pub(crate) fn sync(&mut self) -> Result<()> {
+        self.write_event(EV_SYN, SYN_REPORT, 0)
+    }
+}
+
+impl Drop for UinputDevice {
+    fn drop(&mut self) {
+        let _ = self.ioctl_int(UI_DEV_DESTROY, 0);
+    }
+}
+
+/// Input backend that injects events through Linux uinput.
+pub struct UinputBackend {
+    pointer: UinputDevice,
+    absolute: Option<UinputDevice>,
+}
+
+impl UinputBackend {
+    /// Create the virtual devices. The absolute pointer is created lazily.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            pointer: UinputDevice::create_pointer_keyboard("Open G Hub virtual input", &[])?,
+            absolute: None,
+        })
+    }
+}
+
+impl InputBackend for UinputBackend {
+    fn emit(&mut self, event: InputEvent) -> Result<()> {
+        match event {
+            InputEvent::KeyDown(code) => self.pointer.write_event(EV_KEY, code, 1)?,
+            InputEvent::KeyUp(code) => self.pointer.write_event(EV_KEY, code, 0)?,
+            InputEvent::ButtonDown(b) => self.pointer.write_event(EV_KEY, b.evdev_code(), 1)?,
+            InputEvent::ButtonUp(b) => self.pointer.write_event(EV_KEY, b.evdev_code(), 0)?,
+            InputEvent::MoveRelative { dx, dy } => {
+                if dx != 0 {
+                    self.pointer.write_event(EV_REL, REL_X, dx)?;
+                }
+                if dy != 0 {
+                    self.pointer.write_event(EV_REL, REL_Y, dy)?;
+                }
+            }
+            InputEvent::Wheel(clicks) => self.pointer.write_event(EV_REL, REL_WHEEL, clicks)?,
+            InputEvent::MoveAbsolute { x, y } => {
+                if self.absolute.is_none() {
+                    self.absolute = Some(UinputDevice::create_absolute_pointer(
+                        "Open G Hub virtual absolute pointer",
+                    )?);
+                }
+                if let Some(abs) = self.absolute.as_mut() {
+                    abs.write_event(EV_ABS, ABS_X, x.clamp(0, ABS_MAX))?;
+                    abs.write_event(EV_ABS, ABS_Y, y.clamp(0, ABS_MAX))?;
+                    abs.sync()?;
+                }
+                return Ok(());
+            }
+        }
+        self.pointer.sync()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ioctl_numbers_match_kernel_headers() {
+        // Values from <linux/uinput.h> on x86_64/aarch64.
+        assert_eq!(UI_DEV_CREATE, 0x5501);
+        assert_eq!(UI_DEV_DESTROY, 0x5502);
+        assert_eq!(UI_DEV_SETUP, 0x405C_5503);
+        assert_eq!(UI_ABS_SETUP, 0x401C_5504);
+        assert_eq!(UI_SET_EVBIT, 0x4004_5564);
+        assert_eq!(UI_SET_KEYBIT, 0x4004_5565);
+    }
+}
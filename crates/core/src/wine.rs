@@ -0,0 +1,477 @@
+//! Finding Logitech G Hub installs inside Wine prefixes.
+//!
+//! G Hub keeps `settings.db` in `%LOCALAPPDATA%\LGHUB`. In a Wine prefix that
+//! is `drive_c/users/<user>/AppData/Local/LGHUB/settings.db`, or
+//! `.../Local Settings/Application Data/LGHUB/settings.db` in prefixes made
+//! with the Windows XP layout. Prefixes are searched in this order:
+//!
+//! 1. `$WINEPREFIX`
+//! 2. `~/.wine`
+//! 3. Lutris: `prefix:` entries in `~/.config/lutris/games/*.yml` (and
+//!    `~/.local/share/lutris/games/*.yml`), then every directory in `~/Games`
+//! 4. Bottles: `~/.local/share/bottles/bottles/*` and the Flatpak data dir
+//! 5. Steam Proton: `steamapps/compatdata/*/pfx` in every Steam library,
+//!    including those listed in `libraryfolders.vdf`
+//!
+//! When several installs are found, the user picks one; the choice is stored
+//! as `ghub-settings` in [`crate::store::config_dir`] (see [`select`]).
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable naming a `settings.db` to use instead of searching.
+pub const SETTINGS_ENV: &str = "OPEN_G_HUB_GHUB_SETTINGS";
+
+/// File in the config directory holding the chosen `settings.db` path.
+const SELECTION_FILE: &str = "ghub-settings";
+
+/// `settings.db` locations relative to a Windows user profile.
+const USER_SETTINGS_DIRS: &[&str] = &[
+    "AppData/Local/LGHUB",
+    "Local Settings/Application Data/LGHUB",
+];
+
+/// Where a prefix was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrefixSource {
+    /// `$WINEPREFIX`.
+    WinePrefix,
+    /// The default `~/.wine`.
+    Wine,
+    Lutris,
+    Bottles,
+    Proton,
+}
+
+impl fmt::Display for PrefixSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::WinePrefix => "WINEPREFIX",
+            Self::Wine => "Wine",
+            Self::Lutris => "Lutris",
+            Self::Bottles => "Bottles",
+            Self::Proton => "Proton",
+        })
+    }
+}
+
+/// A G Hub install found in a Wine prefix.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GhubInstall {
+    pub source: PrefixSource,
+    /// The prefix directory (the one holding `drive_c`).
+    pub prefix: PathBuf,
+    /// G Hub's `settings.db` inside the prefix.
+    pub settings: PathBuf,
+}
+
+impl fmt::Display for GhubInstall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.source, self.prefix.display())
+    }
+}
+
+/// Directories the search starts from.
+#[derive(Debug, Clone)]
+pub struct SearchRoots {
+    pub home: PathBuf,
+    pub wineprefix: Option<PathBuf>,
+}
+
+impl SearchRoots {
+    /// Roots from `$HOME` and `$WINEPREFIX`; `None` without a home directory.
+    pub fn from_env() -> Option<Self> {
+        let home = std::env::var_os("HOME").filter(|h| !h.is_empty())?;
+        Some(Self {
+            home: PathBuf::from(home),
+            wineprefix: std::env::var_os("WINEPREFIX")
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from),
+        })
+    }
+}
+
+/// Find G Hub installs under the current user's prefixes.
+pub fn discover() -> Vec<GhubInstall> {
+    SearchRoots::from_env()
+        .map(|roots| discover_in(&roots))
+        .unwrap_or_default()
+}
+
+/// Find G Hub installs under `roots`, in search order.
+///
+/// A `settings.db` reachable from several prefixes (e.g. through the
+/// `~/.steam/steam` symlink) is listed once, under the first.
+pub fn discover_in(roots: &SearchRoots) -> Vec<GhubInstall> {
+    let mut installs: Vec<GhubInstall> = Vec::new();
+    let mut seen: Vec<PathBuf> = Vec::new();
+    for (source, prefix) in candidate_prefixes(roots) {
+        for settings in settings_in_prefix(&prefix) {
+            let key = fs::canonicalize(&settings).unwrap_or_else(|_| settings.clone());
+            if seen.contains(&key) {
+                continue;
+            }
+            seen.push(key);
+            installs.push(GhubInstall {
+                source,
+                prefix: prefix.clone(),
+                settings,
+            });
+        }
+    }
+    installs
+}
+
+/// Every prefix worth looking in, in search order. May contain duplicates.
+fn candidate_prefixes(roots: &SearchRoots) -> Vec<(PrefixSource, PathBuf)> {
+    let home = &roots.home;
+    let mut prefixes = Vec::new();
+
+    if let Some(prefix) = &roots.wineprefix {
+        prefixes.push((PrefixSource::WinePrefix, prefix.clone()));
+    }
+    prefixes.push((PrefixSource::Wine, home.join(".wine")));
+
+    for dir in [".config/lutris/games", ".local/share/lutris/games"] {
+        for config in dir_entries(&home.join(dir)) {
+            if config.extension().is_some_and(|ext| ext == "yml") {
+                if let Ok(text) = fs::read_to_string(&config) {
+                    prefixes.extend(
+                        lutris_prefixes(&text)
+                            .map(|p| (PrefixSource::Lutris, expand_home(&p, home))),
+                    );
+                }
+            }
+        }
+    }
+    prefixes.extend(dir_entries(&home.join("Games")).map(|p| (PrefixSource::Lutris, p)));
+
+    for dir in [
+        ".local/share/bottles/bottles",
+        ".var/app/com.usebottles.bottles/data/bottles/bottles",
+    ] {
+        prefixes.extend(dir_entries(&home.join(dir)).map(|p| (PrefixSource::Bottles, p)));
+    }
+
+    for steam in [
+        ".steam/steam",
+        ".local/share/Steam",
+        ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+    ] {
+        let steam = home.join(steam);
+        let mut libraries = vec![steam.clone()];
+        if let Ok(vdf) = fs::read_to_string(steam.join("steamapps/libraryfolders.vdf")) {
+            libraries.extend(steam_library_paths(&vdf));
+        }
+        for library in libraries {
+            prefixes.extend(
+                dir_entries(&library.join("steamapps/compatdata"))
+                    .map(|app| (PrefixSource::Proton, app.join("pfx"))),
+            );
+        }
+    }
+
+    prefixes
+}
+
+/// `settings.db` files under a prefix's user profiles.
+fn settings_in_prefix(prefix: &Path) -> Vec<PathBuf> {
+    dir_entries(&prefix.join("drive_c/users"))
+        .filter(|user| user.file_name().is_some_and(|name| name != "Public"))
+        .flat_map(|user| {
+            USER_SETTINGS_DIRS
+                .iter()
+                .map(move |dir| user.join(dir))
+                .collect::<Vec<_>>()
+        })
+        .map(|dir| dir.join(crate::ghub::SETTINGS_FILE))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// Entries of `dir`, sorted; nothing if it cannot be read.
+fn dir_entries(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|rd| rd.filter_map(|e| e.ok().map(|e| e.path())).collect())
+        .unwrap_or_default();
+    entries.sort();
+    entries.into_iter()
+}
+
+/// `prefix:` values in a Lutris game config.
+fn lutris_prefixes(yml: &str) -> impl Iterator<Item = String> + '_ {
+    yml.lines()
+        .filter_map(|line| line.trim().strip_prefix("prefix:"))
+        .map(|value| {
+            value
+                .trim()
+                .trim_matches(|c| c == '\'' || c == '"')
+                .to_string()
+        })
+        .filter(|value| !value.is_empty())
+}
+
+/// `"path"` values in Steam's `libraryfolders.vdf`.
+fn steam_library_paths(vdf: &str) -> impl Iterator<Item = PathBuf> + '_ {
+    vdf.lines().filter_map(|line| {
+        let rest = line.trim().strip_prefix("\"path\"")?;
+        let value = rest.trim().strip_prefix('"')?.strip_suffix('"')?;
+        Some(PathBuf::from(value.replace("\\\\", "\\")))
+    })
+}
+
+fn expand_home(path: &str, home: &Path) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => home.join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Pick the `settings.db` to use from `installs`.
+///
+/// An install from `$WINEPREFIX` wins, as does a lone install. Otherwise the
+/// user has to choose, and the error lists the candidates.
+pub fn choose(installs: &[GhubInstall]) -> Result<&GhubInstall> {
+    match installs {
+        [] => Err(Error::Profile(format!(
+            "no Logitech G Hub settings.db found in $WINEPREFIX, ~/.wine, Lutris, Bottles \
+             or Proton prefixes; set {SETTINGS_ENV} to its path"
+        ))),
+        [only] => Ok(only),
+        [first, ..] if first.source == PrefixSource::WinePrefix => Ok(first),
+        many => {
+            let list: Vec<String> = many
+                .iter()
+                .enumerate()
+                .map(|(i, install)| format!("  {}. {install}", i + 1))
+                .collect();
+            Err(Error::Profile(format!(
+                "found {} Logitech G Hub installs; pick one with \
+                 `open-g-hub ghub-prefixes --select N`:\n{}",
+                many.len(),
+                list.join("\n")
+            )))
+        }
+    }
+}
+
+/// The `settings.db` to use on this system.
+///
+/// The stored selection if its file still exists, else [`choose`] over
+/// [`discover`].
+pub fn settings_path() -> Result<PathBuf> {
+    if let Some(path) = selected().filter(|p| p.is_file()) {
+        return Ok(path);
+    }
+    choose(&discover()).map(|install| install.settings.clone())
+}
+
+fn selection_file() -> Option<PathBuf> {
+    Some(crate::store::config_dir()?.join(SELECTION_FILE))
+}
+
+/// The `settings.db` the user picked, if any.
+pub fn selected() -> Option<PathBuf> {
+    read_selection(&selection_file()?)
+}
+
+/// Remember `settings` as the `settings.db` to use; `None` forgets the choice.
+pub fn select(settings: Option<&Path>) -> Result<()> {
+    let file = selection_file().ok_or_else(|| {
+        Error::Profile("no config directory: set XDG_CONFIG_HOME or HOME".to_string())
+    })?;
+    write_selection(&file, settings)
+}
+
+fn read_selection(file: &Path) -> Option<PathBuf> {
+    let text = fs::read_to_string(file).ok()?;
+    let path = text.trim();
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+fn write_selection(file: &Path, settings: Option<&Path>) -> Result<()> {
+    let io_err = |e: std::io::Error| Error::Profile(format!("{}: {e}", file.display()));
+    match settings {
+        Some(settings) => {
+            if let Some(dir) = file.parent() {
+                fs::create_dir_all(dir).map_err(io_err)?;
+            }
+            fs::write(file, format!("{}\n", settings.display())).map_err(io_err)
+        }
+        None => match fs::remove_file(file) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(io_err(e)),
+            _ => Ok(()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create `<prefix>/drive_c/users/<user>/<dir>/settings.db`.
+    fn install(prefix: &Path, user: &str, dir: &str) -> PathBuf {
+        let dir = prefix.join("drive_c/users").join(user).join(dir);
+        fs::create_dir_all(&dir).unwrap();
+        let db = dir.join(crate::ghub::SETTINGS_FILE);
+        fs::write(&db, b"").unwrap();
+        db
+    }
+
+    fn roots(home: &Path) -> SearchRoots {
+        SearchRoots {
+            home: home.to_path_buf(),
+            wineprefix: None,
+        }
+    }
+
+    #[test]
+    fn finds_installs_in_every_launcher() {
+        let home = tempfile::tempdir().unwrap();
+        let home = home.path();
+        let wine = install(&home.join(".wine"), "alice", "AppData/Local/LGHUB");
+        let lutris = install(
+            &home.join("Games/logitech-g-hub"),
+            "alice",
+            "AppData/Local/LGHUB",
+        );
+        let bottles = install(
+            &home.join(".var/app/com.usebottles.bottles/data/bottles/bottles/GHub"),
+            "alice",
+            "Local Settings/Application Data/LGHUB",
+        );
+        let proton = install(
+            &home.join(".local/share/Steam/steamapps/compatdata/4242/pfx"),
+            "steamuser",
+            "AppData/Local/LGHUB",
+        );
+        // Not a user profile, and not a G Hub install.
+        install(&home.join(".wine"), "Public", "AppData/Local/LGHUB");
+        fs::create_dir_all(home.join("Games/other/drive_c/users/alice")).unwrap();
+
+        let found = discover_in(&roots(home));
+        let summary: Vec<(PrefixSource, &Path)> = found
+            .iter()
+            .map(|i| (i.source, i.settings.as_path()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (PrefixSource::Wine, wine.as_path()),
+                (PrefixSource::Lutris, lutris.as_path()),
+                (PrefixSource::Bottles, bottles.as_path()),
+                (PrefixSource::Proton, proton.as_path()),
+            ]
+        );
+        assert_eq!(found[3].prefix, proton.ancestors().nth(7).unwrap());
+    }
+
+    #[test]
+    fn follows_lutris_configs_and_steam_libraries() {
+        let home = tempfile::tempdir().unwrap();
+        let home = home.path();
+        let elsewhere = tempfile::tempdir().unwrap();
+
+        let lutris_prefix = elsewhere.path().join("lgh");
+        let lutris = install(&lutris_prefix, "bob", "AppData/Local/LGHUB");
+        let configs = home.join(".config/lutris/games");
+        fs::create_dir_all(&configs).unwrap();
+        fs::write(
+            configs.join("logitech-g-hub-1.yml"),
+            format!(
+                "game:\n  exe: lghub.exe\n  prefix: '{}'\n",
+                lutris_prefix.display()
+            ),
+        )
+        .unwrap();
+
+        let library = elsewhere.path().join("SteamLibrary");
+        let proton = install(
+            &library.join("steamapps/compatdata/99/pfx"),
+            "steamuser",
+            "AppData/Local/LGHUB",
+        );
+        let steamapps = home.join(".steam/steam/steamapps");
+        fs::create_dir_all(&steamapps).unwrap();
+        fs::write(
+            steamapps.join("libraryfolders.vdf"),
+            format!(
+                "\"libraryfolders\"\n{{\n\t\"1\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t}}\n}}\n",
+                library.display()
+            ),
+        )
+        .unwrap();
+
+        let found = discover_in(&roots(home));
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            (found[0].source, &found[0].settings),
+            (PrefixSource::Lutris, &lutris)
+        );
+        assert_eq!(
+            (found[1].source, &found[1].settings),
+            (PrefixSource::Proton, &proton)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_installs_are_listed_once() {
+        let home = tempfile::tempdir().unwrap();
+        let home = home.path();
+        install(
+            &home.join(".local/share/Steam/steamapps/compatdata/1/pfx"),
+            "steamuser",
+            "AppData/Local/LGHUB",
+        );
+        fs::create_dir_all(home.join(".steam")).unwrap();
+        std::os::unix::fs::symlink(home.join(".local/share/Steam"), home.join(".steam/steam"))
+            .unwrap();
+
+        assert_eq!(discover_in(&roots(home)).len(), 1);
+    }
+
+    #[test]
+    fn choose_needs_a_pick_between_installs() {
+        let home = tempfile::tempdir().unwrap();
+        let home = home.path();
+        assert!(choose(&discover_in(&roots(home))).is_err());
+
+        let wine = install(&home.join(".wine"), "alice", "AppData/Local/LGHUB");
+        let found = discover_in(&roots(home));
+        assert_eq!(choose(&found).unwrap().settings, wine);
+
+        let bottle = home.join(".local/share/bottles/bottles/GHub");
+        let bottled = install(&bottle, "alice", "AppData/Local/LGHUB");
+        let err = choose(&discover_in(&roots(home))).unwrap_err().to_string();
+        assert!(err.contains("2. Bottles"), "{err}");
+
+        // An explicit $WINEPREFIX is the user's choice already.
+        let with_env = SearchRoots {
+            wineprefix: Some(bottle),
+            ..roots(home)
+        };
+        let found = discover_in(&with_env);
+        assert_eq!(found.len(), 2);
+        assert_eq!(choose(&found).unwrap().settings, bottled);
+    }
+
+    #[test]
+    fn selection_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("open-g-hub").join(SELECTION_FILE);
+        assert_eq!(read_selection(&file), None);
+
+        let db = Path::new("/games/ghub/drive_c/users/a/AppData/Local/LGHUB/settings.db");
+        write_selection(&file, Some(db)).unwrap();
+        assert_eq!(read_selection(&file).as_deref(), Some(db));
+
+        write_selection(&file, None).unwrap();
+        assert_eq!(read_selection(&file), None);
+        write_selection(&file, None).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+//! Wireless connection notifications: receiver link changes and
+//! WIRELESS_DEVICE_STATUS (0x1D4B) reconnection events.
+//!
+//! Two notification kinds tell the host that a wireless mouse (re)appeared:
+//! - HID++ 1.0 device connection (sub ID 0x41), sent by the receiver when the
+//!   link to a paired device is established or lost.
+//! - HID++ 2.0 WIRELESS_DEVICE_STATUS event 0, broadcast by the device after it
+//!   wakes or power-cycles. `request = 1` asks software to reconfigure it,
+//!   because host-mode settings (DPI, rate, remaps) were lost.
+
+use crate::hidpp::HidppResponse;
+
+/// HID++ 1.0 sub ID of the receiver's device connection notification.
+pub const DEVICE_CONNECTION_SUB_ID: u8 = 0x41;
+
+/// A connection state change reported by the receiver or device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// Receiver established the wireless link.
+    Connected,
+    /// Receiver lost the wireless link (device asleep or switched off).
+    Disconnected,
+    /// Device reported a reconnection; `reconfigure` means settings were lost.
+    Reconnected { reconfigure: bool },
+}
+
+/// Decode a notification into a connection event for its device index.
+///
+/// `wireless_status_idx` is the device's WIRELESS_DEVICE_STATUS feature index,
+/// if it has one. Returns `None` for unrelated reports.
+pub fn parse_connection_event(
+    report: &HidppResponse,
+    wireless_status_idx: Option<u8>,
+) -> Option<(u8, ConnectionEvent)> {
+    if !report.is_long && report.feature_index == DEVICE_CONNECTION_SUB_ID {
+        // r0 bit 6 set = link not established.
+        let link_lost = report.params.first().is_some_and(|r0| r0 & 0x40 != 0);
+        let event = if link_lost {
+            ConnectionEvent::Disconnected
+        } else {
+            ConnectionEvent::Connected
+        };
+        return Some((report.device_index, event));
+    }
+
+    let idx = wireless_status_idx?;
+    if report.feature_index == idx && report.function() == 0x00 {
+        // params[0] = status (1 = reconnection), params[1] = request (1 = reconfigure)
+        if report.params.first() == Some(&0x01) {
+            let reconfigure = report.params.get(1) == Some(&0x01);
+            return Some((
+                report.device_index,
+                ConnectionEvent::Reconnected { reconfigure },
+            ));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hidpp::LONG_REPORT_LEN;
+
+    const WDS_IDX: u8 = 0x0C;
+
+    fn decode(data: &[u8]) -> HidppResponse {
+        HidppResponse::decode(data).unwrap()
+    }
+
+    #[test]
+    fn receiver_link_established() {
+        let report = decode(&[0x10, 0x01, 0x41, 0x04, 0x02, 0x8D, 0xC0]);
+        assert_eq!(
+            parse_connection_event(&report, None),
+            Some((0x01, ConnectionEvent::Connected))
+        );
+    }
+
+    #[test]
+    fn receiver_link_lost() {
+        let report = decode(&[0x10, 0x01, 0x41, 0x04, 0x42, 0x8D, 0xC0]);
+        assert_eq!(
+            parse_connection_event(&report, None),
+            Some((0x01, ConnectionEvent::Disconnected))
+        );
+    }
+
+    #[test]
+    fn wireless_status_reconnection_requests_reconfigure() {
+        let mut data = vec![0x11, 0x01, WDS_IDX, 0x00, 0x01, 0x01, 0x01];
+        data.resize(LONG_REPORT_LEN, 0);
+        assert_eq!(
+            parse_connection_event(&decode(&data), Some(WDS_IDX)),
+            Some((0x01, ConnectionEvent::Reconnected { reconfigure: true }))
+        );
+    }
+
+    #[test]
+    fn wireless_status_without_request() {
+        let mut data = vec![0x11, 0x01, WDS_IDX, 0x00, 0x01, 0x00];
+        data.resize(LONG_REPORT_LEN, 0);
+        assert_eq!(
+            parse_connection_event(&decode(&data), Some(WDS_IDX)),
+            Some((0x01, ConnectionEvent::Reconnected { reconfigure: false }))
+        );
+    }
+
+    #[test]
+    fn unrelated_reports_are_ignored() {
+        let mut data = vec![0x11, 0x01, 0x05, 0x00, 0x01, 0x01];
+        data.resize(LONG_REPORT_LEN, 0);
+        assert_eq!(parse_connection_event(&decode(&data), Some(WDS_IDX)), None);
+        // Wireless status event without a known feature index.
+        let mut data = vec![0x11, 0x01, WDS_IDX, 0x00, 0x01, 0x01];
+        data.resize(LONG_REPORT_LEN, 0);
+        assert_eq!(parse_connection_event(&decode(&data), None), None);
+    }
+}
@@ -0,0 +1,145 @@
+//! Automatic profile switching: follows the focused application and makes
+//! the library profile its rules pick the active one.
+
+use crate::manager::Manager;
+use open_g_hub_core::autoswitch::{AutoSwitcher, FocusProvider, Rules};
+use open_g_hub_core::store::ProfileStore;
+use tracing::{debug, info, warn};
+
+pub struct AutoSwitch {
+    provider: Box<dyn FocusProvider>,
+    switcher: AutoSwitcher,
+    store: ProfileStore,
+    /// Whether the last focus lookup failed, so a broken provider is logged
+    /// once rather than on every poll.
+    failing: bool,
+}
+
+impl AutoSwitch {
+    pub fn new(provider: Box<dyn FocusProvider>, rules: Rules, store: ProfileStore) -> Self {
+        Self {
+            provider,
+            switcher: AutoSwitcher::new(rules),
+            store,
+            failing: false,
+        }
+    }
+
+    pub fn provider_name(&self) -> &'static str {
+        self.provider.name()
+    }
+
+    /// Look at the focused application once and, when its profile differs
+    /// from the last one switched to, make that profile active. Only the
+    /// settings that differ are written (see `Manager::set_active_profile`).
+    /// A switch that fails is tried again on the next poll.
+    pub fn poll(&mut self, manager: &Manager) {
+        let focus = match self.provider.focused() {
+            Ok(focus) => {
+                self.failing = false;
+                focus
+            }
+            Err(e) => {
+                if !self.failing {
+                    warn!(provider = self.provider.name(), "Focus lookup failed: {e}");
+                }
+                self.failing = true;
+                return;
+            }
+        };
+        let Some(name) = self.switcher.update(focus.as_ref()) else {
+            return;
+        };
+        debug!(?focus, profile = %name, "Focused application changed profile");
+        let stored = match self.store.get(&name) {
+            Ok(stored) => stored,
+            Err(e) => {
+                warn!(profile = %name, "Cannot load profile for auto-switch: {e}");
+                return;
+            }
+        };
+        match manager.set_active_profile(stored.profile) {
+            Ok(()) => {
+                info!(profile = %name, "Switched profile for focused application");
+                self.switcher.confirm(&name);
+            }
+            Err(e) => warn!(profile = %name, "Switching profile failed: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager::tests::{gaming_profile, SimSource};
+    use open_g_hub_core::autoswitch::{FocusInfo, Rule, TestFocus};
+    use open_g_hub_core::store::StoredProfile;
+
+    #[test]
+    fn focus_changes_switch_the_active_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(dir.path());
+        let gaming = gaming_profile();
+        let desktop = open_g_hub_core::profile::Profile {
+            name: "Desktop".into(),
+            dpi: 1200,
+            ..gaming.clone()
+        };
+        store.create(&StoredProfile::new(gaming, None)).unwrap();
+        store.create(&StoredProfile::new(desktop, None)).unwrap();
+
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), None);
+        manager.scan();
+
+        let focus = TestFocus::new();
+        let rules = Rules {
+            rules: vec![Rule {
+                exe: Some("cs2".into()),
+                profile: "Gaming".into(),
+                ..Default::default()
+            }],
+            default: Some("Desktop".into()),
+        };
+        let mut auto = AutoSwitch::new(Box::new(focus.clone()), rules, store);
+
+        focus.set(Some(FocusInfo {
+            exe: Some("cs2".into()),
+            ..Default::default()
+        }));
+        auto.poll(&manager);
+        assert_eq!(manager.status().active_profile.as_deref(), Some("Gaming"));
+        assert_eq!(source.sim.dpi(), 3200);
+
+        let writes = source.sim.write_count();
+        auto.poll(&manager);
+        assert_eq!(source.sim.write_count(), writes);
+
+        focus.set(None);
+        auto.poll(&manager);
+        assert_eq!(manager.status().active_profile.as_deref(), Some("Desktop"));
+        assert_eq!(source.sim.dpi(), 1200);
+    }
+
+    #[test]
+    fn failed_switch_is_retried() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(dir.path());
+        let manager = Manager::new(Box::new(SimSource::new()), None);
+        manager.scan();
+
+        let rules = Rules {
+            default: Some("Gaming".into()),
+            ..Default::default()
+        };
+        let mut auto = AutoSwitch::new(Box::new(TestFocus::new()), rules, store.clone());
+        auto.poll(&manager);
+        assert_eq!(manager.status().active_profile, None);
+
+        store
+            .create(&StoredProfile::new(gaming_profile(), None))
+            .unwrap();
+        auto.poll(&manager);
+        assert_eq!(manager.status().active_profile.as_deref(), Some("Gaming"));
+    }
+}
@@ -0,0 +1,241 @@
+//! open-g-hubd: background daemon that owns Logitech G mice.
+//!
+//! Keeps the HID connections open, watches for connect/reconnect
+//! notifications, re-applies the active profile whenever a mouse comes back,
+//! optionally switches profiles by focused application and shows desktop
+//! notifications, and serves the GUI and CLI over a JSON-RPC Unix socket.
+
+mod autoswitch;
+mod manager;
+#[cfg(feature = "notify")]
+mod notify;
+#[cfg(feature = "ratbag")]
+mod ratbag;
+mod server;
+#[cfg(all(test, any(feature = "ratbag", feature = "notify")))]
+mod test_bus;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use manager::{HidapiSource, Manager};
+use open_g_hub_core::autoswitch as autoswitch_core;
+use open_g_hub_core::battery_history::BatteryHistory;
+use open_g_hub_core::comm::RetrySettings;
+#[cfg(target_os = "linux")]
+use open_g_hub_core::macros::MacroPlayer;
+use open_g_hub_core::schema::{self, ParseMode};
+use open_g_hub_core::store::ProfileStore;
+use open_g_hub_core::{ipc, profile};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Bus to publish the ratbag1 service on.
+#[cfg(feature = "ratbag")]
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Bus {
+    /// The system bus, where Piper looks (needs the D-Bus policy in crates/daemon/dbus).
+    System,
+    /// The user's session bus.
+    Session,
+}
+
+/// How long each loop iteration waits for device notifications.
+const NOTIFY_WAIT_MS: i32 = 100;
+
+/// How often the focused application is checked with --auto-switch.
+const FOCUS_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Parser)]
+#[command(
+    name = "open-g-hubd",
+    version,
+    about = "Open G Hub daemon: owns devices and restores settings on reconnect"
+)]
+struct Args {
+    /// Socket path (default: $OPEN_G_HUB_SOCKET or $XDG_RUNTIME_DIR/open-g-hub.sock).
+    #[arg(long)]
+    socket: Option<PathBuf>,
+    /// Profile (JSON) to apply at startup and after every reconnect.
+    #[arg(long)]
+    profile: Option<PathBuf>,
+    /// Seconds between scans for plugged/unplugged devices.
+    #[arg(long, default_value_t = 2)]
+    scan_interval: u64,
+    /// Switch library profiles by focused application, following the rules
+    /// in ~/.config/open-g-hub/autoswitch.json.
+    #[arg(long)]
+    auto_switch: bool,
+    /// Rules file for --auto-switch.
+    #[arg(long, requires = "auto_switch")]
+    rules: Option<PathBuf>,
+    /// Focus provider for --auto-switch (default: detected from the session).
+    #[arg(long, requires = "auto_switch", value_parser = clap::builder::PossibleValuesParser::new(autoswitch_core::PROVIDERS))]
+    focus_provider: Option<String>,
+    /// Retry policies for HID++ requests (default:
+    /// ~/.config/open-g-hub/retry.json).
+    #[arg(long)]
+    retry_config: Option<PathBuf>,
+    /// Show desktop notifications for DPI stage changes, profile switches,
+    /// low battery and disconnects.
+    #[cfg(feature = "notify")]
+    #[arg(long)]
+    notify: bool,
+    /// Notification settings (default: ~/.config/open-g-hub/notifications.json).
+    #[cfg(feature = "notify")]
+    #[arg(long, requires = "notify")]
+    notify_config: Option<PathBuf>,
+    /// Serve org.freedesktop.ratbag1 (Piper) on this bus.
+    #[cfg(feature = "ratbag")]
+    #[arg(long, value_enum)]
+    ratbag_bus: Option<Bus>,
+}
+
+fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+
+    let args = Args::parse();
+
+    let active = match &args.profile {
+        Some(path) => {
+            let json = std::fs::read_to_string(path)
+                .with_context(|| format!("read profile {}", path.display()))?;
+            let parsed = schema::parse(&json, ParseMode::Lenient)
+                .with_context(|| format!("parse profile {}", path.display()))?;
+            if !parsed.unknown_fields.is_empty() {
+                warn!(
+                    fields = %parsed.unknown_fields.join(", "),
+                    "Ignoring unknown profile fields"
+                );
+            }
+            Some(parsed.stored.profile)
+        }
+        None => profile::load_profile().ok(),
+    };
+
+    let retry = match &args.retry_config {
+        Some(path) => RetrySettings::load(path)?,
+        None => RetrySettings::load_default()?,
+    };
+    let mut manager = Manager::new(Box::new(HidapiSource { retry }), active);
+    match BatteryHistory::open_default() {
+        Ok(history) => manager = manager.with_battery_history(history),
+        Err(e) => warn!("Battery history disabled: {e}"),
+    }
+    #[cfg(target_os = "linux")]
+    match open_g_hub_core::uinput::UinputBackend::new() {
+        Ok(backend) => manager = manager.with_macro_player(MacroPlayer::spawn(Box::new(backend))),
+        Err(e) => warn!("Button macros disabled: {e}"),
+    }
+    let manager = Arc::new(manager);
+    let socket = args.socket.unwrap_or_else(ipc::socket_path);
+    let listener = server::bind(&socket)?;
+    {
+        let manager = Arc::clone(&manager);
+        std::thread::spawn(move || server::serve(listener, manager));
+    }
+
+    #[cfg(feature = "notify")]
+    if args.notify {
+        start_notifier(args.notify_config, &manager)?;
+    }
+
+    #[cfg(feature = "ratbag")]
+    let mut ratbag = match args.ratbag_bus {
+        Some(bus) => {
+            let builder = match bus {
+                Bus::System => zbus::blocking::connection::Builder::system(),
+                Bus::Session => zbus::blocking::connection::Builder::session(),
+            }?;
+            let service = ratbag::RatbagService::start(builder, Arc::clone(&manager))
+                .context("start ratbag1 service (is ratbagd running?)")?;
+            info!("Serving {}", ratbag::BUS_NAME);
+            Some(service)
+        }
+        None => None,
+    };
+
+    let mut auto_switch = if args.auto_switch {
+        Some(start_auto_switch(
+            args.rules,
+            args.focus_provider.as_deref(),
+        )?)
+    } else {
+        None
+    };
+
+    let stop = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&stop))?;
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&stop))?;
+
+    info!(socket = %socket.display(), "open-g-hubd listening");
+    let scan_interval = Duration::from_secs(args.scan_interval.max(1));
+    let mut last_scan: Option<Instant> = None;
+    let mut last_focus: Option<Instant> = None;
+    while !stop.load(Ordering::Relaxed) {
+        if last_scan.is_none_or(|t| t.elapsed() >= scan_interval) {
+            manager.scan();
+            last_scan = Some(Instant::now());
+            #[cfg(feature = "ratbag")]
+            if let Some(service) = ratbag.as_mut() {
+                if let Err(e) = service.sync() {
+                    tracing::warn!("ratbag device sync failed: {e}");
+                }
+            }
+        }
+        if let Some(auto) = auto_switch.as_mut() {
+            if last_focus.is_none_or(|t| t.elapsed() >= FOCUS_INTERVAL) {
+                auto.poll(&manager);
+                last_focus = Some(Instant::now());
+            }
+        }
+        if manager.poll_notifications(NOTIFY_WAIT_MS) == 0 {
+            std::thread::sleep(Duration::from_millis(NOTIFY_WAIT_MS as u64));
+        }
+    }
+
+    let _ = std::fs::remove_file(&socket);
+    info!("open-g-hubd stopped");
+    Ok(())
+}
+
+/// Load the switching rules and pick the focus provider for --auto-switch.
+fn start_auto_switch(
+    rules: Option<PathBuf>,
+    provider: Option<&str>,
+) -> Result<autoswitch::AutoSwitch> {
+    let path = rules
+        .or_else(autoswitch_core::rules_path)
+        .context("no config directory for the auto-switch rules (set HOME)")?;
+    let rules = autoswitch_core::Rules::load(&path)?;
+    let provider = match provider {
+        Some(name) => autoswitch_core::provider(name),
+        None => autoswitch_core::detect(),
+    }
+    .context("no focus provider for this session (use --focus-provider)")?;
+    let auto = autoswitch::AutoSwitch::new(provider, rules, ProfileStore::open_default()?);
+    info!(
+        rules = %path.display(),
+        provider = auto.provider_name(),
+        "Switching profiles by focused application"
+    );
+    Ok(auto)
+}
+
+/// Load the notification settings and start showing notifications for --notify.
+#[cfg(feature = "notify")]
+fn start_notifier(config: Option<PathBuf>, manager: &Arc<Manager>) -> Result<()> {
+    use open_g_hub_core::notify::{self as notify_core, NotifySettings};
+    let settings = match config.or_else(notify_core::settings_path) {
+        Some(path) => NotifySettings::load(&path)?,
+        None => NotifySettings::default(),
+    };
+    let connection = zbus::blocking::Connection::session().context("connect to the session bus")?;
+    notify::DesktopNotifier::new(connection, settings).spawn(Arc::clone(manager));
+    info!("Showing desktop notifications");
+    Ok(())
+}
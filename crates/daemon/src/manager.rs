@@ -0,0 +1,992 @@
+//! Device ownership: hotplug scanning, link tracking, and restoring the
+//! active profile whenever a mouse (re)connects.
+//!
+//! A mouse can come back in two ways:
+//! - Wired: it disappears from and reappears in the HID device list.
+//! - Wireless: the receiver stays enumerated and sends link notifications,
+//!   and the mouse broadcasts WIRELESS_DEVICE_STATUS when it wakes.
+//!
+//! Either way, host-mode settings are gone and the active profile is written again.
+//!
+//! The profile is written as its power rules want it for the mouse's last
+//! battery reading (see [`open_g_hub_core::power`]); a battery broadcast that
+//! changes which rules apply writes the difference. Battery readings also go
+//! to the battery history when one is attached (see
+//! [`Manager::with_battery_history`]).
+//!
+//! Buttons with a host macro in the active profile are diverted, and their
+//! presses play the macro when a player is attached (see
+//! [`Manager::with_macro_player`]).
+//!
+//! Link changes, battery broadcasts, diverted button presses, onboard DPI
+//! stage changes, and switches of the active profile are published as
+//! [`DaemonEvent`]s to every subscriber.
+
+use open_g_hub_core::battery::BatteryStatus;
+use open_g_hub_core::battery_history::{BatteryHistory, BatterySample, Usage};
+use open_g_hub_core::device::{self, DeviceInfo};
+use open_g_hub_core::error::{Error, Result};
+use open_g_hub_core::hid::HidapiTransport;
+use open_g_hub_core::hidpp::{features, HidppResponse};
+use open_g_hub_core::ipc::{DaemonEvent, DaemonStatus, DeviceState};
+use open_g_hub_core::macros::MacroPlayer;
+use open_g_hub_core::power::PowerLimits;
+use open_g_hub_core::profile::{self, Profile};
+use open_g_hub_core::transaction::ApplyReport;
+use open_g_hub_core::transport::{lookup_feature_index, HidTransport, TransportStack};
+use open_g_hub_core::wireless::{self, ConnectionEvent};
+use open_g_hub_core::{battery, buttons, comm, dpi, onboard, report_rate};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info, warn};
+
+/// A transport shared between the monitor loop and IPC handlers.
+pub type SharedTransport = Arc<dyn HidTransport + Sync>;
+
+/// Where devices come from: real HID in the daemon, simulations in tests.
+pub trait DeviceSource: Send + Sync {
+    /// List the supported mice currently present.
+    fn scan(&self) -> Result<Vec<DeviceInfo>>;
+    /// Open a transport to a listed mouse.
+    fn open(&self, info: &DeviceInfo) -> Result<SharedTransport>;
+}
+
+/// Devices found through hidapi, opened through the standard transport
+/// stack with their model's retry policy.
+#[derive(Default)]
+pub struct HidapiSource {
+    pub retry: comm::RetrySettings,
+}
+
+impl DeviceSource for HidapiSource {
+    fn scan(&self) -> Result<Vec<DeviceInfo>> {
+        device::discover_devices()
+    }
+
+    fn open(&self, info: &DeviceInfo) -> Result<SharedTransport> {
+        let retry = self.retry.policy_for(info).clone();
+        let stack = TransportStack::standard(retry);
+        Ok(Arc::from(stack.build(HidapiTransport::open(info)?)))
+    }
+}
+
+/// Identity of a mouse across scans. Each HID interface has its own path, so
+/// paths alone would count one mouse several times.
+type DeviceKey = (u16, u16, Option<String>);
+
+fn device_key(info: &DeviceInfo) -> DeviceKey {
+    (info.vid, info.pid, info.serial.clone())
+}
+
+/// `profile` with its power rules applied for `battery`. Rules outside the
+/// safe ranges are ignored.
+fn powered(profile: &Profile, battery: Option<&BatteryStatus>) -> Profile {
+    match PowerLimits::for_battery(&profile.power, battery) {
+        Ok(limits) => limits.adjust(profile),
+        Err(e) => {
+            warn!(profile = %profile.name, "Ignoring power rules: {e}");
+            profile.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Link {
+    device_index: Option<u8>,
+    wireless_status_idx: Option<u8>,
+    battery_idx: Option<u8>,
+    reprog_idx: Option<u8>,
+    onboard_idx: Option<u8>,
+    connected: bool,
+    /// Last battery reading, for the power rules.
+    battery: Option<BatteryStatus>,
+}
+
+struct Device {
+    info: DeviceInfo,
+    transport: SharedTransport,
+    link: Mutex<Link>,
+    /// Unit ID, else HID serial: the key of the mouse's battery history.
+    unit_id: Mutex<Option<String>>,
+    /// Buttons diverted to play a macro, as (CID, button index).
+    macro_buttons: Mutex<Vec<(u16, usize)>>,
+    /// CIDs held in the last diverted-buttons report.
+    held: Mutex<Vec<u16>>,
+}
+
+impl Device {
+    fn link(&self) -> Link {
+        *self.link.lock().unwrap()
+    }
+}
+
+/// Owns every connected mouse and the profile to restore on reconnect.
+pub struct Manager {
+    source: Box<dyn DeviceSource>,
+    devices: Mutex<Vec<Arc<Device>>>,
+    active: Mutex<Option<Profile>>,
+    subscribers: Mutex<Vec<mpsc::Sender<DaemonEvent>>>,
+    history: Option<Mutex<BatteryHistory>>,
+    macros: Option<MacroPlayer>,
+}
+
+impl Manager {
+    pub fn new(source: Box<dyn DeviceSource>, active: Option<Profile>) -> Self {
+        Self {
+            source,
+            devices: Mutex::new(Vec::new()),
+            active: Mutex::new(active),
+            subscribers: Mutex::new(Vec::new()),
+            history: None,
+            macros: None,
+        }
+    }
+
+    /// Record every battery reading in `history`.
+    pub fn with_battery_history(mut self, history: BatteryHistory) -> Self {
+        self.history = Some(Mutex::new(history));
+        self
+    }
+
+    /// Divert the buttons that have a host macro in the active profile and
+    /// play the macro through `player` when one is pressed.
+    pub fn with_macro_player(mut self, player: MacroPlayer) -> Self {
+        self.macros = Some(player);
+        self
+    }
+
+    /// Receive every event published from now on. Dropping the receiver
+    /// unsubscribes.
+    pub fn subscribe(&self) -> mpsc::Receiver<DaemonEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    fn publish(&self, event: DaemonEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    fn publish_link(&self, device: &Device, connected: bool) {
+        self.publish(DaemonEvent::Connection {
+            device: device.info.path.clone(),
+            connected,
+        });
+    }
+
+    /// Pick up newly plugged mice and forget removed ones.
+    pub fn scan(&self) {
+        let found = match self.source.scan() {
+            Ok(found) => found,
+            Err(e) => {
+                warn!("Device scan failed: {e}");
+                return;
+            }
+        };
+
+        let mut present: Vec<DeviceInfo> = Vec::new();
+        for info in found {
+            if !present.iter().any(|p| device_key(p) == device_key(&info)) {
+                present.push(info);
+            }
+        }
+
+        let mut removed = Vec::new();
+        let new: Vec<DeviceInfo> = {
+            let mut devices = self.devices.lock().unwrap();
+            devices.retain(|d| {
+                let keep = present.iter().any(|p| device_key(p) == device_key(&d.info));
+                if !keep {
+                    info!(model = d.info.model.name(), "Device removed");
+                    removed.push(Arc::clone(d));
+                }
+                keep
+            });
+            present
+                .into_iter()
+                .filter(|p| !devices.iter().any(|d| device_key(&d.info) == device_key(p)))
+                .collect()
+        };
+        for device in removed {
+            if device.link().connected {
+                self.publish_link(&device, false);
+            }
+        }
+
+        for info in new {
+            match self.source.open(&info) {
+                Ok(transport) => {
+                    info!(model = info.model.name(), path = %info.path, "Device added");
+                    let device = Arc::new(Device {
+                        info,
+                        transport,
+                        link: Mutex::new(Link::default()),
+                        unit_id: Mutex::new(None),
+                        macro_buttons: Mutex::new(Vec::new()),
+                        held: Mutex::new(Vec::new()),
+                    });
+                    self.bring_up(&device);
+                    self.devices.lock().unwrap().push(device);
+                }
+                Err(e) => warn!(path = %info.path, "Cannot open device: {e}"),
+            }
+        }
+    }
+
+    /// Drain notifications from every device, react to link changes, and
+    /// publish battery and button events.
+    ///
+    /// Waits up to `timeout_ms` on each device for the first report. Returns
+    /// the number of devices polled.
+    pub fn poll_notifications(&self, timeout_ms: i32) -> usize {
+        let devices: Vec<Arc<Device>> = self.devices.lock().unwrap().clone();
+        for device in &devices {
+            let mut reconnected = false;
+            let mut wait = timeout_ms;
+            loop {
+                let raw = match device.transport.read_report(wait) {
+                    Ok(Some(raw)) => raw,
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!(model = device.info.model.name(), "Lost device: {e}");
+                        self.forget(device);
+                        reconnected = false;
+                        break;
+                    }
+                };
+                wait = 0;
+
+                let Ok(report) = HidppResponse::decode(&raw) else {
+                    continue;
+                };
+                let link = device.link();
+                match wireless::parse_connection_event(&report, link.wireless_status_idx) {
+                    Some((_, ConnectionEvent::Disconnected)) => {
+                        info!(model = device.info.model.name(), "Wireless link lost");
+                        device.link.lock().unwrap().connected = false;
+                        reconnected = false;
+                        if link.connected {
+                            self.publish_link(device, false);
+                        }
+                    }
+                    Some((_, event)) => {
+                        debug!(?event, "Connection notification");
+                        reconnected = true;
+                    }
+                    None => self.publish_report(device, &link, &report),
+                }
+            }
+
+            // One burst (link up + WIRELESS_DEVICE_STATUS) restores once.
+            if reconnected {
+                info!(model = device.info.model.name(), "Device reconnected");
+                self.bring_up(device);
+            }
+        }
+        devices.len()
+    }
+
+    /// Publish a battery broadcast, diverted-button report, or DPI stage
+    /// change.
+    fn publish_report(&self, device: &Device, link: &Link, report: &HidppResponse) {
+        let path = || device.info.path.clone();
+        if let Some(battery) = link
+            .battery_idx
+            .and_then(|idx| battery::parse_battery_event(report, idx))
+        {
+            debug!(level = battery.level, "Battery notification");
+            self.battery_changed(device, battery);
+            if let Some(idx) = link.device_index {
+                self.record_battery(device, idx, &battery);
+            }
+            self.publish(DaemonEvent::Battery {
+                device: path(),
+                battery,
+            });
+        } else if let Some(pressed) = link
+            .reprog_idx
+            .and_then(|idx| buttons::parse_diverted_buttons(report, idx))
+        {
+            self.play_macros(device, &pressed);
+            self.publish(DaemonEvent::Button {
+                device: path(),
+                pressed,
+            });
+        } else if let Some(stage) = link
+            .onboard_idx
+            .and_then(|idx| onboard::parse_dpi_stage_event(report, idx))
+        {
+            let dpi = link
+                .device_index
+                .and_then(|idx| dpi::read_dpi(device.transport.as_ref(), idx).ok());
+            debug!(stage, ?dpi, "DPI stage notification");
+            self.publish(DaemonEvent::Dpi {
+                device: path(),
+                stage,
+                dpi,
+            });
+        }
+    }
+
+    /// Play the macros of the diverted buttons in `pressed` that were not
+    /// held in the previous report.
+    fn play_macros(&self, device: &Device, pressed: &[u16]) {
+        let previous = std::mem::replace(&mut *device.held.lock().unwrap(), pressed.to_vec());
+        let (Some(player), Some(profile)) = (&self.macros, self.active_profile()) else {
+            return;
+        };
+        let macro_buttons = device.macro_buttons.lock().unwrap().clone();
+        for cid in pressed.iter().filter(|cid| !previous.contains(cid)) {
+            let m = macro_buttons
+                .iter()
+                .find(|&&(c, _)| c == *cid)
+                .and_then(|(_, button)| profile.button_macros.get(button));
+            if let Some(m) = m {
+                debug!(name = %m.name, "Playing button macro");
+                player.play(m.clone());
+            }
+        }
+    }
+
+    /// Divert the buttons with a macro in `profile` and hand back the ones
+    /// diverted for a macro it no longer has. No-op without a macro player.
+    fn divert_macro_buttons(&self, device: &Device, idx: u8, profile: &Profile) {
+        if self.macros.is_none() {
+            return;
+        }
+        let transport = device.transport.as_ref();
+        let mut diverted = device.macro_buttons.lock().unwrap();
+        diverted.retain(|&(_, button)| {
+            if profile.button_macros.contains_key(&button) {
+                return true;
+            }
+            match buttons::set_button_divert(transport, idx, button, false) {
+                Ok(_) => false,
+                Err(e) => {
+                    warn!(button, "Restoring macro button failed: {e}");
+                    true
+                }
+            }
+        });
+        for &button in profile.button_macros.keys() {
+            if diverted.iter().any(|&(_, b)| b == button) {
+                continue;
+            }
+            match buttons::set_button_divert(transport, idx, button, true) {
+                Ok(cid) => diverted.push((cid, button)),
+                Err(e) => warn!(button, "Diverting macro button failed: {e}"),
+            }
+        }
+    }
+
+    /// Record a battery reading and, when it changes which power rules of
+    /// the active profile apply, write the difference to `device`.
+    fn battery_changed(&self, device: &Device, battery: BatteryStatus) {
+        let (previous, idx) = {
+            let mut link = device.link.lock().unwrap();
+            (link.battery.replace(battery), link.device_index)
+        };
+        let (Some(profile), Some(idx)) = (self.active_profile(), idx) else {
+            return;
+        };
+        let limits = |b: Option<&BatteryStatus>| PowerLimits::for_battery(&profile.power, b).ok();
+        if profile.power.is_empty() || limits(previous.as_ref()) == limits(Some(&battery)) {
+            return;
+        }
+        let target = powered(&profile, Some(&battery));
+        match profile::apply_changes(device.transport.as_ref(), idx, &target)
+            .and_then(ApplyReport::into_result)
+        {
+            Ok(report) => info!(
+                level = battery.level,
+                charging = battery.state.is_charging(),
+                writes = report.applied.len(),
+                "Applied battery power rules"
+            ),
+            Err(e) => warn!(
+                model = device.info.model.name(),
+                "Applying battery power rules failed: {e}"
+            ),
+        }
+    }
+
+    /// Add a reading of `device` to the battery history, with the settings
+    /// it runs: the active profile as its power rules leave it, else the
+    /// report rate read from the mouse.
+    fn record_battery(&self, device: &Device, idx: u8, battery: &BatteryStatus) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let Some(unit_id) = device.unit_id.lock().unwrap().clone() else {
+            return;
+        };
+        let usage = match self.active_profile() {
+            Some(profile) => Usage::of(&powered(&profile, Some(battery))),
+            None => Usage {
+                polling_rate: report_rate::read_report_rate(device.transport.as_ref(), idx).ok(),
+                lighting: None,
+            },
+        };
+        let sample = BatterySample::now(battery, usage);
+        if let Err(e) = history.lock().unwrap().record(&unit_id, &sample) {
+            warn!("Recording battery history failed: {e}");
+        }
+    }
+
+    fn forget(&self, device: &Arc<Device>) {
+        self.devices
+            .lock()
+            .unwrap()
+            .retain(|d| !Arc::ptr_eq(d, device));
+        if device.link().connected {
+            self.publish_link(device, false);
+        }
+    }
+
+    /// Find the device index, learn the notification features, and restore
+    /// the active profile.
+    fn bring_up(&self, device: &Device) {
+        let transport = device.transport.as_ref();
+        let idx = match comm::find_device_index(transport) {
+            Ok(idx) => idx,
+            Err(e) => {
+                // A receiver whose mouse is asleep; its link notification
+                // will bring us back here.
+                debug!(model = device.info.model.name(), "Mouse not answering: {e}");
+                device.link.lock().unwrap().connected = false;
+                return;
+            }
+        };
+        let feature = |id| lookup_feature_index(transport, idx, id).ok();
+        let battery_idx = feature(features::BATTERY_STATUS);
+        let battery = battery_idx.and_then(|_| battery::read_battery(transport, idx).ok());
+        *device.link.lock().unwrap() = Link {
+            device_index: Some(idx),
+            wireless_status_idx: feature(features::WIRELESS_DEVICE_STATUS),
+            battery_idx,
+            reprog_idx: feature(features::REPROG_CONTROLS_V4),
+            onboard_idx: feature(features::ONBOARD_PROFILES),
+            connected: true,
+            battery,
+        };
+        self.publish_link(device, true);
+
+        // The mouse forgot its diverted buttons along with the rest.
+        device.macro_buttons.lock().unwrap().clear();
+        device.held.lock().unwrap().clear();
+        if let Some(profile) = self.active_profile() {
+            match profile::apply_profile(transport, idx, &powered(&profile, battery.as_ref())) {
+                Ok(()) => info!(profile = %profile.name, "Restored active profile"),
+                Err(e) => warn!(profile = %profile.name, "Restoring profile failed: {e}"),
+            }
+            self.divert_macro_buttons(device, idx, &profile);
+        }
+
+        if let Some(battery) = battery {
+            let unit_id = device::read_unit_id(transport, idx)
+                .ok()
+                .or_else(|| device.info.serial.clone());
+            *device.unit_id.lock().unwrap() = unit_id;
+            self.record_battery(device, idx, &battery);
+        }
+    }
+
+    /// Devices and their link state.
+    pub fn devices(&self) -> Vec<DeviceState> {
+        self.devices
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|d| {
+                let link = d.link();
+                DeviceState {
+                    info: d.info.clone(),
+                    device_index: link.device_index,
+                    connected: link.connected,
+                }
+            })
+            .collect()
+    }
+
+    /// Devices, their link state, and the active profile.
+    pub fn status(&self) -> DaemonStatus {
+        DaemonStatus {
+            devices: self.devices(),
+            active_profile: self.active_profile().map(|p| p.name),
+        }
+    }
+
+    pub fn active_profile(&self) -> Option<Profile> {
+        self.active.lock().unwrap().clone()
+    }
+
+    /// Apply `profile` now, writing only the settings each device does not
+    /// have yet and rolling a device back if one of its writes fails, and
+    /// on success make it the one restored on reconnect.
+    ///
+    /// When a device fails, the previous active profile stays and is written
+    /// again to the devices that took the new one. Publishes a profile event
+    /// when a switch succeeds and the name differs from the previous active
+    /// profile's.
+    pub fn set_active_profile(&self, profile: Profile) -> Result<()> {
+        let apply_changes = |t: &dyn HidTransport, idx, p: &Profile| {
+            profile::apply_changes(t, idx, p)
+                .and_then(ApplyReport::into_result)
+                .map(|_| ())
+        };
+        if let Err(e) = self.apply_to_devices(&profile, apply_changes) {
+            if let Some(previous) = self.active_profile() {
+                if let Err(e) = self.apply_to_devices(&previous, apply_changes) {
+                    warn!(profile = %previous.name, "Restoring previous profile failed: {e}");
+                }
+            }
+            return Err(e);
+        }
+        let name = profile.name.clone();
+        let previous = self.active.lock().unwrap().replace(profile);
+        if previous.is_none_or(|p| p.name != name) {
+            self.publish(DaemonEvent::Profile { name });
+        }
+        Ok(())
+    }
+
+    /// Write the whole active profile to every connected device again.
+    pub fn reapply(&self) -> Result<()> {
+        match self.active_profile() {
+            Some(profile) => self.apply_to_devices(&profile, profile::apply_profile),
+            None => Ok(()),
+        }
+    }
+
+    fn apply_to_devices(
+        &self,
+        profile: &Profile,
+        apply: impl Fn(&dyn HidTransport, u8, &Profile) -> Result<()>,
+    ) -> Result<()> {
+        let devices: Vec<Arc<Device>> = self.devices.lock().unwrap().clone();
+        let mut first_error = None;
+        for device in devices {
+            let link = device.link();
+            let (Some(idx), true) = (link.device_index, link.connected) else {
+                continue;
+            };
+            let profile = powered(profile, link.battery.as_ref());
+            if let Err(e) = apply(device.transport.as_ref(), idx, &profile) {
+                warn!(
+                    model = device.info.model.name(),
+                    "Applying profile failed: {e}"
+                );
+                first_error.get_or_insert(e);
+            }
+            self.divert_macro_buttons(&device, idx, &profile);
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Fold a live settings change into the active profile, so the next
+    /// reconnect does not undo it. No-op when there is no active profile.
+    pub fn update_active(&self, change: impl FnOnce(&mut Profile)) {
+        if let Some(profile) = self.active.lock().unwrap().as_mut() {
+            change(profile);
+        }
+    }
+
+    /// Run `op` against the connected mouse with HID path `path`, or the
+    /// first connected one when `path` is `None`.
+    pub fn with_device<T>(
+        &self,
+        path: Option<&str>,
+        op: impl FnOnce(&dyn HidTransport, u8) -> Result<T>,
+    ) -> Result<T> {
+        let target = self.devices.lock().unwrap().iter().find_map(|d| {
+            if path.is_some_and(|p| p != d.info.path) {
+                return None;
+            }
+            let link = d.link();
+            match (link.device_index, link.connected) {
+                (Some(idx), true) => Some((Arc::clone(&d.transport), idx)),
+                _ => None,
+            }
+        });
+        let (transport, idx) = target.ok_or_else(|| match path {
+            Some(p) => Error::DeviceNotFound(format!("no connected mouse at {p}")),
+            None => Error::DeviceNotFound("no connected Logitech G mouse".to_string()),
+        })?;
+        op(transport.as_ref(), idx)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use open_g_hub_core::device::{ButtonAction, MouseModel, PollingRate};
+    use open_g_hub_core::input::{key_from_name, InputEvent, RecordingBackend};
+    use open_g_hub_core::macros::Macro;
+    use open_g_hub_core::sim::SimulatedG502;
+    use open_g_hub_core::transport::{Fault, FaultKind, FaultLayer, Faults};
+    use open_g_hub_core::{buttons, pids, LOGITECH_VID};
+
+    /// Source yielding one simulated Lightspeed receiver while `present`,
+    /// opened behind a fault layer injecting `faults`.
+    pub(crate) struct SimSource {
+        pub sim: Arc<SimulatedG502>,
+        pub present: Mutex<bool>,
+        pub faults: Faults,
+    }
+
+    impl SimSource {
+        pub(crate) fn new() -> Arc<Self> {
+            Arc::new(Self {
+                sim: Arc::new(SimulatedG502::new(0x01)),
+                present: Mutex::new(true),
+                faults: Faults::new(),
+            })
+        }
+    }
+
+    impl DeviceSource for Arc<SimSource> {
+        fn scan(&self) -> Result<Vec<DeviceInfo>> {
+            if !*self.present.lock().unwrap() {
+                return Ok(Vec::new());
+            }
+            let info = sim_info();
+            // Two HID interfaces of the same receiver.
+            Ok(vec![
+                info.clone(),
+                DeviceInfo {
+                    path: "sim-2".to_string(),
+                    ..info
+                },
+            ])
+        }
+
+        fn open(&self, _info: &DeviceInfo) -> Result<SharedTransport> {
+            let stack = TransportStack::new().layer(FaultLayer::new(self.faults.clone()));
+            Ok(Arc::from(stack.build(Arc::clone(&self.sim))))
+        }
+    }
+
+    pub(crate) fn sim_info() -> DeviceInfo {
+        DeviceInfo {
+            model: MouseModel::G502Lightspeed,
+            vid: LOGITECH_VID,
+            pid: pids::G502_LIGHTSPEED,
+            path: "sim".to_string(),
+            serial: None,
+        }
+    }
+
+    pub(crate) fn gaming_profile() -> Profile {
+        Profile {
+            name: "Gaming".into(),
+            dpi: 3200,
+            polling_rate: PollingRate::Hz500,
+            buttons: vec![
+                ButtonAction::LeftClick,
+                ButtonAction::RightClick,
+                ButtonAction::Back,
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scan_attaches_one_device_per_mouse_and_applies_profile() {
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), Some(gaming_profile()));
+        manager.scan();
+
+        let status = manager.status();
+        assert_eq!(status.devices.len(), 1);
+        assert_eq!(status.devices[0].device_index, Some(0x01));
+        assert!(status.devices[0].connected);
+        assert_eq!(status.active_profile.as_deref(), Some("Gaming"));
+        assert_eq!(source.sim.dpi(), 3200);
+        assert_eq!(source.sim.remap(2), Some(buttons::cids::BACK));
+    }
+
+    #[test]
+    fn wireless_reconnect_restores_profile_once() {
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), Some(gaming_profile()));
+        manager.scan();
+
+        source.sim.disconnect();
+        manager.poll_notifications(0);
+        assert!(!manager.status().devices[0].connected);
+
+        source.sim.power_cycle();
+        assert_eq!(source.sim.dpi(), 800);
+        let writes_before = source.sim.write_count();
+        manager.poll_notifications(0);
+
+        assert!(manager.status().devices[0].connected);
+        assert_eq!(source.sim.dpi(), 3200);
+        assert_eq!(source.sim.report_interval(), 2);
+        // DPI + rate + three buttons, written exactly once.
+        assert_eq!(source.sim.write_count() - writes_before, 5);
+    }
+
+    #[test]
+    fn unplug_and_replug_restores_profile() {
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), Some(gaming_profile()));
+        manager.scan();
+
+        *source.present.lock().unwrap() = false;
+        manager.scan();
+        assert!(manager.status().devices.is_empty());
+
+        source.sim.power_cycle();
+        while source.sim.read_report(0).unwrap().is_some() {}
+        *source.present.lock().unwrap() = true;
+        manager.scan();
+        assert_eq!(source.sim.dpi(), 3200);
+    }
+
+    #[test]
+    fn live_changes_are_kept_in_the_active_profile() {
+        let manager = Manager::new(Box::new(SimSource::new()), Some(gaming_profile()));
+        manager.update_active(|p| p.dpi = 1600);
+        assert_eq!(manager.active_profile().unwrap().dpi, 1600);
+
+        let without = Manager::new(Box::new(SimSource::new()), None);
+        without.update_active(|p| p.dpi = 1600);
+        assert!(without.active_profile().is_none());
+    }
+
+    #[test]
+    fn link_battery_and_button_events_are_published() {
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), None);
+        let events = manager.subscribe();
+        manager.scan();
+        assert_eq!(
+            events.try_recv().unwrap(),
+            DaemonEvent::Connection {
+                device: "sim".into(),
+                connected: true
+            }
+        );
+
+        source.sim.set_battery(20, 0);
+        let reprog = SimulatedG502::feature_index(features::REPROG_CONTROLS_V4).unwrap();
+        source
+            .sim
+            .push_notification(vec![0x10, 0x01, reprog, 0x00, 0x00, 0x53, 0x00]);
+        source.sim.disconnect();
+        manager.poll_notifications(0);
+
+        let received: Vec<DaemonEvent> = events.try_iter().collect();
+        assert!(matches!(
+            &received[0],
+            DaemonEvent::Battery { battery, .. } if battery.level == 20
+        ));
+        assert!(matches!(
+            &received[1],
+            DaemonEvent::Button { pressed, .. } if pressed == &[buttons::cids::BACK]
+        ));
+        assert!(matches!(
+            received[2],
+            DaemonEvent::Connection {
+                connected: false,
+                ..
+            }
+        ));
+        assert_eq!(received.len(), 3);
+    }
+
+    #[test]
+    fn macro_buttons_are_diverted_and_play_on_press() {
+        let source = SimSource::new();
+        let backend = RecordingBackend::new();
+        let ctrl = key_from_name("lctrl").unwrap();
+        let c = key_from_name("c").unwrap();
+        let mut profile = gaming_profile();
+        profile
+            .button_macros
+            .insert(3, Macro::keystroke("Copy", &[ctrl], c));
+        let manager = Manager::new(Box::new(source.clone()), Some(profile))
+            .with_macro_player(MacroPlayer::spawn(Box::new(backend.clone())));
+        manager.scan();
+        assert!(source.sim.is_diverted(3));
+        assert!(!source.sim.is_diverted(2));
+
+        let reprog = SimulatedG502::feature_index(features::REPROG_CONTROLS_V4).unwrap();
+        let report = |cid: u16| vec![0x10, 0x01, reprog, 0x00, (cid >> 8) as u8, cid as u8, 0x00];
+        // Press, keep holding, release, press again: two plays.
+        for cid in [
+            buttons::cids::BACK,
+            buttons::cids::BACK,
+            0,
+            buttons::cids::BACK,
+        ] {
+            source.sim.push_notification(report(cid));
+        }
+        manager.poll_notifications(0);
+        // Ctrl down, C down, C up, Ctrl up, twice.
+        let expected = 8;
+        for _ in 0..100 {
+            if backend.events().len() >= expected {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let events = backend.events();
+        assert_eq!(events.len(), expected);
+        assert_eq!(events[0], InputEvent::KeyDown(ctrl));
+        assert_eq!(events[3], InputEvent::KeyUp(ctrl));
+
+        manager.set_active_profile(gaming_profile()).unwrap();
+        assert!(!source.sim.is_diverted(3));
+    }
+
+    #[test]
+    fn dpi_stage_and_profile_switch_events_are_published() {
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), None);
+        manager.scan();
+        let events = manager.subscribe();
+
+        source.sim.select_dpi_stage(2);
+        manager.poll_notifications(0);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            DaemonEvent::Dpi {
+                device: "sim".into(),
+                stage: 2,
+                dpi: Some(1600),
+            }
+        );
+
+        manager.set_active_profile(gaming_profile()).unwrap();
+        manager.set_active_profile(gaming_profile()).unwrap();
+        let received: Vec<DaemonEvent> = events.try_iter().collect();
+        assert_eq!(
+            received,
+            [DaemonEvent::Profile {
+                name: "Gaming".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn failed_switch_keeps_the_previous_profile() {
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), Some(gaming_profile()));
+        manager.scan();
+        let events = manager.subscribe();
+
+        source.faults.inject(
+            Fault::new(FaultKind::Disconnect)
+                .on(features::REPORT_RATE, 2)
+                .always(),
+        );
+        let desktop = Profile {
+            name: "Desktop".into(),
+            dpi: 1200,
+            polling_rate: PollingRate::Hz125,
+            ..gaming_profile()
+        };
+        assert!(manager.set_active_profile(desktop).is_err());
+
+        assert_eq!(manager.status().active_profile.as_deref(), Some("Gaming"));
+        assert_eq!(source.sim.dpi(), 3200);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn battery_events_apply_the_profile_power_rules() {
+        use open_g_hub_core::led::{self, LedEffect, LedMode};
+        let solid = LedEffect {
+            mode: LedMode::Solid,
+            color: [0xFF, 0x40, 0x00],
+            ..Default::default()
+        };
+        let profile = Profile {
+            polling_rate: PollingRate::Hz1000,
+            leds: vec![solid],
+            power: serde_json::from_str(
+                r#"[{"below": 20, "polling_rate": "Hz500"}, {"below": 10, "led_brightness": 0}]"#,
+            )
+            .unwrap(),
+            ..gaming_profile()
+        };
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), Some(profile));
+        manager.scan();
+        let sim = &source.sim;
+        assert_eq!(sim.report_interval(), 1);
+
+        sim.set_battery(15, 0);
+        manager.poll_notifications(0);
+        assert_eq!(sim.report_interval(), 2);
+
+        // Same rules in force: nothing to write.
+        let writes = sim.write_count();
+        sim.set_battery(14, 0);
+        manager.poll_notifications(0);
+        assert_eq!(sim.write_count(), writes);
+
+        sim.set_battery(5, 0);
+        manager.poll_notifications(0);
+        assert_eq!(
+            led::read_led_effect(sim.as_ref(), 0x01, 0).unwrap().mode,
+            LedMode::Off
+        );
+
+        // A reconnect restores the profile as the battery allows.
+        sim.power_cycle();
+        manager.poll_notifications(0);
+        assert_eq!(sim.report_interval(), 2);
+
+        sim.set_battery(5, 1);
+        manager.poll_notifications(0);
+        assert_eq!(sim.report_interval(), 1);
+        assert_eq!(led::read_led_effect(sim.as_ref(), 0x01, 0).unwrap(), solid);
+    }
+
+    #[test]
+    fn battery_readings_are_recorded_with_the_running_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("battery.db");
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), Some(gaming_profile()))
+            .with_battery_history(BatteryHistory::open(&path).unwrap());
+        manager.scan();
+        source.sim.set_battery(70, 0);
+        source.sim.set_battery(70, 1);
+        manager.poll_notifications(0);
+
+        let history = BatteryHistory::open(&path).unwrap();
+        assert_eq!(history.devices().unwrap(), ["1A2B3C4D"]);
+        let samples = history.samples("1A2B3C4D").unwrap();
+        let levels: Vec<(u8, bool)> = samples
+            .iter()
+            .map(|s| (s.level, s.state.is_charging()))
+            .collect();
+        assert_eq!(levels, [(80, false), (70, false), (70, true)]);
+        assert_eq!(samples[0].usage.polling_rate, Some(PollingRate::Hz500));
+    }
+
+    #[test]
+    fn no_primary_without_connected_device() {
+        let manager = Manager::new(Box::new(SimSource::new()), None);
+        assert!(matches!(
+            manager.with_device(None, |_, idx| Ok(idx)),
+            Err(Error::DeviceNotFound(_))
+        ));
+        manager.scan();
+        assert_eq!(manager.with_device(None, |_, idx| Ok(idx)).unwrap(), 0x01);
+        assert_eq!(
+            manager.with_device(Some("sim"), |_, idx| Ok(idx)).unwrap(),
+            0x01
+        );
+        assert!(manager
+            .with_device(Some("elsewhere"), |_, idx| Ok(idx))
+            .is_err());
+    }
+}
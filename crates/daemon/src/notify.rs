@@ -0,0 +1,239 @@
+//! Desktop notifications over `org.freedesktop.Notifications` (feature
+//! "notify").
+//!
+//! A thread follows the manager's events and shows the ones the settings
+//! ask for (see [`open_g_hub_core::notify`]) on the user's session bus:
+//! DPI stage changes, profile switches, low battery, and disconnects. Each
+//! notification replaces the previous one of its kind still on screen.
+
+use crate::manager::Manager;
+use open_g_hub_core::ipc::DaemonEvent;
+use open_g_hub_core::notify::{Notification, Notifications, NotifySettings, Topic};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use tracing::{debug, warn};
+use zbus::blocking::Connection;
+use zbus::zvariant::Value;
+
+/// Well-known name and interface of the notification service.
+pub const BUS_NAME: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+const APP_NAME: &str = "Open G Hub";
+
+pub struct DesktopNotifier {
+    connection: Connection,
+    notifications: Notifications,
+    /// ID of the notification last shown per topic, for `replaces_id`.
+    shown: HashMap<Topic, u32>,
+    /// Model names by HID path, kept after a mouse is removed so its
+    /// disconnect can still name it.
+    names: HashMap<String, String>,
+}
+
+impl DesktopNotifier {
+    pub fn new(connection: Connection, settings: NotifySettings) -> Self {
+        Self {
+            connection,
+            notifications: Notifications::new(settings),
+            shown: HashMap::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// Show notifications for `manager`'s events from now on, on a thread
+    /// of their own so a slow notification server never holds up devices.
+    pub fn spawn(mut self, manager: Arc<Manager>) -> JoinHandle<()> {
+        let events = manager.subscribe();
+        std::thread::spawn(move || {
+            for event in events {
+                self.handle(&manager, &event);
+            }
+        })
+    }
+
+    /// Show the notification `event` calls for, if any.
+    pub fn handle(&mut self, manager: &Manager, event: &DaemonEvent) {
+        let name = self.device_name(manager, event);
+        let Some(note) = self.notifications.notification(event, &name) else {
+            return;
+        };
+        match self.send(&note) {
+            Ok(id) => {
+                debug!(id, summary = %note.summary, "Notification shown");
+                self.shown.insert(note.topic, id);
+            }
+            Err(e) => warn!(summary = %note.summary, "Showing notification failed: {e}"),
+        }
+    }
+
+    fn device_name(&mut self, manager: &Manager, event: &DaemonEvent) -> String {
+        let path = match event {
+            DaemonEvent::Connection { device, .. }
+            | DaemonEvent::Battery { device, .. }
+            | DaemonEvent::Button { device, .. }
+            | DaemonEvent::Dpi { device, .. } => device,
+            DaemonEvent::Profile { .. } => return String::new(),
+        };
+        if let Some(state) = manager.devices().into_iter().find(|d| &d.info.path == path) {
+            self.names
+                .insert(path.clone(), state.info.model.name().to_string());
+        }
+        self.names
+            .get(path)
+            .cloned()
+            .unwrap_or_else(|| "Mouse".into())
+    }
+
+    /// Call `Notify`, returning the ID the server gave the notification.
+    fn send(&self, note: &Notification) -> zbus::Result<u32> {
+        let replaces = self.shown.get(&note.topic).copied().unwrap_or(0);
+        let hints = HashMap::from([
+            ("urgency", Value::U8(note.urgency as u8)),
+            ("category", Value::from("device")),
+        ]);
+        let timeout = self
+            .notifications
+            .settings()
+            .timeout_ms
+            .map_or(-1, |ms| i32::try_from(ms).unwrap_or(i32::MAX));
+        let reply = self.connection.call_method(
+            Some(BUS_NAME),
+            PATH,
+            Some(BUS_NAME),
+            "Notify",
+            &(
+                APP_NAME,
+                replaces,
+                note.icon,
+                note.summary.as_str(),
+                note.body.as_str(),
+                Vec::<&str>::new(),
+                hints,
+                timeout,
+            ),
+        )?;
+        reply.body().deserialize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager::tests::{gaming_profile, SimSource};
+    use crate::test_bus::PrivateBus;
+    use std::sync::Mutex;
+    use zbus::blocking::connection;
+    use zbus::zvariant::OwnedValue;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Shown {
+        replaces: u32,
+        summary: String,
+        urgency: u8,
+        timeout: i32,
+    }
+
+    /// Stand-in notification server recording every `Notify` call.
+    struct FakeServer {
+        shown: Arc<Mutex<Vec<Shown>>>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl FakeServer {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            app_name: String,
+            replaces_id: u32,
+            _app_icon: String,
+            summary: String,
+            _body: String,
+            _actions: Vec<String>,
+            hints: HashMap<String, OwnedValue>,
+            expire_timeout: i32,
+        ) -> u32 {
+            assert_eq!(app_name, APP_NAME);
+            let mut shown = self.shown.lock().unwrap();
+            shown.push(Shown {
+                replaces: replaces_id,
+                summary,
+                urgency: hints
+                    .get("urgency")
+                    .and_then(|u| u8::try_from(u).ok())
+                    .unwrap_or(1),
+                timeout: expire_timeout,
+            });
+            if replaces_id != 0 {
+                replaces_id
+            } else {
+                shown.len() as u32
+            }
+        }
+    }
+
+    #[test]
+    fn events_become_notifications_on_the_session_bus() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not available; skipping");
+            return;
+        };
+        let shown = Arc::new(Mutex::new(Vec::new()));
+        let _server = connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .name(BUS_NAME)
+            .unwrap()
+            .serve_at(
+                PATH,
+                FakeServer {
+                    shown: Arc::clone(&shown),
+                },
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+        let client = connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        let settings = NotifySettings {
+            timeout_ms: Some(1500),
+            ..Default::default()
+        };
+        let mut notifier = DesktopNotifier::new(client, settings);
+
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), None);
+        manager.scan();
+        let events = manager.subscribe();
+        manager.set_active_profile(gaming_profile()).unwrap();
+        source.sim.select_dpi_stage(2);
+        manager.poll_notifications(0);
+        source.sim.select_dpi_stage(3);
+        source.sim.set_battery(4, 0);
+        manager.poll_notifications(0);
+        source.sim.disconnect();
+        manager.poll_notifications(0);
+        for event in events.try_iter() {
+            notifier.handle(&manager, &event);
+        }
+
+        let shown = shown.lock().unwrap();
+        let calls: Vec<(u32, &str, u8)> = shown
+            .iter()
+            .map(|s| (s.replaces, s.summary.as_str(), s.urgency))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                (0, "Profile: Gaming", 0),
+                (0, "1600 DPI", 0),
+                // The second stage change replaces the first.
+                (2, "3200 DPI", 0),
+                (0, "Logitech G502 Lightspeed battery low", 2),
+                (0, "Logitech G502 Lightspeed disconnected", 1),
+            ]
+        );
+        assert!(shown.iter().all(|s| s.timeout == 1500));
+    }
+}
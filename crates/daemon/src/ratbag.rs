@@ -0,0 +1,907 @@
+//! `org.freedesktop.ratbag1` D-Bus service (feature "ratbag").
+//!
+//! Exposes the daemon's mice with the ratbagd object model so Piper and other
+//! ratbag front-ends can configure them:
+//!
+//! ```text
+//! /org/freedesktop/ratbag1                              Manager
+//! /org/freedesktop/ratbag1/device/<sys>                 Device
+//! /org/freedesktop/ratbag1/profile/<sys>/p0             Profile
+//! /org/freedesktop/ratbag1/resolution/<sys>/p0/r0       Resolution
+//! /org/freedesktop/ratbag1/button/<sys>/p0/b<n>         Button
+//! /org/freedesktop/ratbag1/led/<sys>/p0/l<n>            Led
+//! ```
+//!
+//! The daemon keeps a single active profile, so every device shows that one
+//! profile with a single resolution. Property writes are staged until
+//! `Device.Commit()`, which makes the staged profile the daemon's active
+//! profile. That writes it through the core safety layer and restores it on
+//! every reconnect.
+
+use crate::manager::Manager;
+use async_io::block_on;
+use open_g_hub_core::device::{ButtonAction, DeviceInfo, PollingRate, G502_BUTTON_COUNT};
+use open_g_hub_core::led::LedEffect;
+use open_g_hub_core::profile::{self, Profile};
+use open_g_hub_core::ratbag::{
+    action, brightness_from_ratbag, brightness_to_ratbag, from_mapping, to_mapping, LED_MODES,
+};
+use open_g_hub_core::safety;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+use zbus::blocking::connection;
+use zbus::blocking::Connection;
+use zbus::fdo;
+use zbus::names::BusName;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+
+/// Well-known bus name claimed by ratbagd.
+pub const BUS_NAME: &str = "org.freedesktop.ratbag1";
+/// ratbag D-Bus API version implemented here.
+pub const API_VERSION: i32 = 2;
+
+const ROOT_PATH: &str = "/org/freedesktop/ratbag1";
+const PROFILE_INTERFACE: &str = "org.freedesktop.ratbag1.Profile";
+
+/// ratbag color depth: 8 bits per RGB channel.
+const COLORDEPTH_RGB_888: u32 = 1;
+/// ratbag device type: mouse.
+const DEVICE_TYPE_MOUSE: u32 = 2;
+
+/// D-Bus object path element for a device: its HID path with everything
+/// outside `[A-Za-z0-9_]` replaced.
+fn sysname(info: &DeviceInfo) -> String {
+    let name = info.path.rsplit('/').next().unwrap_or(&info.path);
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn object_path(path: String) -> OwnedObjectPath {
+    OwnedObjectPath::try_from(path).expect("object path built from sanitized parts")
+}
+
+fn device_path(sys: &str) -> OwnedObjectPath {
+    object_path(format!("{ROOT_PATH}/device/{sys}"))
+}
+
+fn profile_path(sys: &str) -> OwnedObjectPath {
+    object_path(format!("{ROOT_PATH}/profile/{sys}/p0"))
+}
+
+fn resolution_path(sys: &str) -> OwnedObjectPath {
+    object_path(format!("{ROOT_PATH}/resolution/{sys}/p0/r0"))
+}
+
+fn button_path(sys: &str, index: usize) -> OwnedObjectPath {
+    object_path(format!("{ROOT_PATH}/button/{sys}/p0/b{index}"))
+}
+
+fn led_path(sys: &str, index: usize) -> OwnedObjectPath {
+    object_path(format!("{ROOT_PATH}/led/{sys}/p0/l{index}"))
+}
+
+/// The value inside a variant, looking through nested variants.
+fn unwrap_variant<'a>(value: &'a Value<'a>) -> &'a Value<'a> {
+    match value {
+        Value::Value(inner) => unwrap_variant(inner),
+        other => other,
+    }
+}
+
+fn invalid(message: impl Into<String>) -> fdo::Error {
+    fdo::Error::InvalidArgs(message.into())
+}
+
+/// Profile edits waiting for `Device.Commit()`.
+struct Staged {
+    profile: Profile,
+    dirty: bool,
+}
+
+/// State shared by every exported object.
+struct Shared {
+    manager: Arc<Manager>,
+    /// Pending edits, or (when clean) the settings last read from the mouse.
+    staged: Mutex<Staged>,
+    /// Exported profile objects; they all show the same staged profile.
+    profiles: Mutex<Vec<OwnedObjectPath>>,
+}
+
+impl Shared {
+    /// The profile front-ends see: pending edits if any, otherwise the active
+    /// profile completed with what the mouse reported.
+    fn current(&self) -> Profile {
+        let staged = self.staged.lock().unwrap();
+        if staged.dirty {
+            return staged.profile.clone();
+        }
+        let device = &staged.profile;
+        let mut profile = self
+            .manager
+            .active_profile()
+            .unwrap_or_else(|| device.clone());
+        if profile.buttons.len() < device.buttons.len() {
+            let missing = &device.buttons[profile.buttons.len()..];
+            profile.buttons.extend_from_slice(missing);
+        }
+        if profile.leds.is_empty() {
+            profile.leds = device.leds.clone();
+        }
+        profile
+    }
+
+    async fn edit(
+        &self,
+        conn: &zbus::Connection,
+        change: impl FnOnce(&mut Profile),
+    ) -> fdo::Result<()> {
+        let mut profile = self.current();
+        change(&mut profile);
+        *self.staged.lock().unwrap() = Staged {
+            profile,
+            dirty: true,
+        };
+        self.announce_dirty(conn).await
+    }
+
+    /// Signal `IsDirty` on every profile. Sent as a raw signal because the
+    /// caller may be a setter holding the profile object itself.
+    async fn announce_dirty(&self, conn: &zbus::Connection) -> fdo::Result<()> {
+        let dirty = self.is_dirty();
+        let paths = self.profiles.lock().unwrap().clone();
+        for path in paths {
+            let changed = HashMap::from([("IsDirty", Value::from(dirty))]);
+            conn.emit_signal(
+                None::<BusName<'_>>,
+                &path,
+                "org.freedesktop.DBus.Properties",
+                "PropertiesChanged",
+                &(PROFILE_INTERFACE, changed, Vec::<String>::new()),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.staged.lock().unwrap().dirty
+    }
+
+    /// Re-read the mouse's settings as the base for unedited properties.
+    fn refresh(&self) {
+        match self.manager.with_device(None, profile::snapshot) {
+            Ok(profile) => {
+                let mut staged = self.staged.lock().unwrap();
+                if !staged.dirty {
+                    staged.profile = profile;
+                }
+            }
+            Err(e) => warn!("Reading device settings for ratbag failed: {e}"),
+        }
+    }
+
+    /// Make the staged edits the active profile. Edits are dropped either
+    /// way; on failure front-ends resync from the device.
+    fn commit(&self) -> open_g_hub_core::error::Result<()> {
+        if !self.is_dirty() {
+            return Ok(());
+        }
+        let profile = self.current();
+        self.staged.lock().unwrap().dirty = false;
+        let result = self.manager.set_active_profile(profile);
+        if result.is_err() {
+            self.refresh();
+        }
+        result
+    }
+}
+
+struct ManagerObject {
+    devices: Vec<OwnedObjectPath>,
+}
+
+#[zbus::interface(name = "org.freedesktop.ratbag1.Manager")]
+impl ManagerObject {
+    #[zbus(property, name = "APIVersion")]
+    fn api_version(&self) -> i32 {
+        API_VERSION
+    }
+
+    #[zbus(property)]
+    fn devices(&self) -> Vec<OwnedObjectPath> {
+        self.devices.clone()
+    }
+}
+
+struct DeviceObject {
+    shared: Arc<Shared>,
+    info: DeviceInfo,
+    sys: String,
+}
+
+#[zbus::interface(name = "org.freedesktop.ratbag1.Device")]
+impl DeviceObject {
+    #[zbus(property)]
+    fn model(&self) -> String {
+        format!("usb:{:04x}:{:04x}:0", self.info.vid, self.info.pid)
+    }
+
+    #[zbus(property)]
+    fn name(&self) -> String {
+        self.info.model.name().to_string()
+    }
+
+    #[zbus(property)]
+    fn device_type(&self) -> u32 {
+        DEVICE_TYPE_MOUSE
+    }
+
+    #[zbus(property)]
+    fn firmware_version(&self) -> String {
+        String::new()
+    }
+
+    #[zbus(property)]
+    fn profiles(&self) -> Vec<OwnedObjectPath> {
+        vec![profile_path(&self.sys)]
+    }
+
+    /// Write staged changes to the mouse.
+    async fn commit(&self, #[zbus(signal_emitter)] emitter: SignalEmitter<'_>) -> fdo::Result<()> {
+        let result = self.shared.commit();
+        self.shared.announce_dirty(emitter.connection()).await?;
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                warn!("ratbag commit failed: {e}");
+                Self::resync(&emitter).await?;
+                Err(fdo::Error::Failed(e.to_string()))
+            }
+        }
+    }
+
+    /// Sent when the device state changed and clients must re-read it.
+    #[zbus(signal)]
+    async fn resync(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+struct ProfileObject {
+    shared: Arc<Shared>,
+    sys: String,
+    leds: usize,
+}
+
+#[zbus::interface(name = "org.freedesktop.ratbag1.Profile")]
+impl ProfileObject {
+    #[zbus(property)]
+    fn index(&self) -> u32 {
+        0
+    }
+
+    #[zbus(property)]
+    fn name(&self) -> String {
+        self.shared.current().name
+    }
+
+    #[zbus(property)]
+    async fn set_name(
+        &mut self,
+        name: String,
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> fdo::Result<()> {
+        self.shared.edit(conn, |profile| profile.name = name).await
+    }
+
+    #[zbus(property)]
+    fn disabled(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn set_disabled(&mut self, disabled: bool) -> fdo::Result<()> {
+        if disabled {
+            return Err(invalid("the only profile cannot be disabled"));
+        }
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn is_active(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn is_dirty(&self) -> bool {
+        self.shared.is_dirty()
+    }
+
+    #[zbus(property)]
+    fn resolutions(&self) -> Vec<OwnedObjectPath> {
+        vec![resolution_path(&self.sys)]
+    }
+
+    #[zbus(property)]
+    fn buttons(&self) -> Vec<OwnedObjectPath> {
+        (0..G502_BUTTON_COUNT)
+            .map(|i| button_path(&self.sys, i))
+            .collect()
+    }
+
+    #[zbus(property)]
+    fn leds(&self) -> Vec<OwnedObjectPath> {
+        (0..self.leds).map(|i| led_path(&self.sys, i)).collect()
+    }
+
+    #[zbus(property)]
+    fn report_rate(&self) -> u32 {
+        self.shared.current().polling_rate.as_hz() as u32
+    }
+
+    #[zbus(property)]
+    async fn set_report_rate(
+        &mut self,
+        hz: u32,
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> fdo::Result<()> {
+        let hz = u16::try_from(hz).map_err(|_| invalid(format!("unsupported rate {hz}")))?;
+        let rate = safety::validate_polling_rate(hz).map_err(|e| invalid(e.to_string()))?;
+        self.shared
+            .edit(conn, |profile| profile.polling_rate = rate)
+            .await
+    }
+
+    #[zbus(property)]
+    fn report_rates(&self) -> Vec<u32> {
+        PollingRate::ALL.iter().map(|r| r.as_hz() as u32).collect()
+    }
+
+    /// Angle snapping is not supported (-1).
+    #[zbus(property)]
+    fn angle_snapping(&self) -> i32 {
+        -1
+    }
+
+    /// Hardware debounce is not supported (-1); see `open-g-hub-cli debounce`.
+    #[zbus(property)]
+    fn debounce(&self) -> i32 {
+        -1
+    }
+
+    #[zbus(property)]
+    fn debounces(&self) -> Vec<u32> {
+        Vec::new()
+    }
+
+    /// The only profile is always active.
+    fn set_active(&self) {}
+}
+
+struct ResolutionObject {
+    shared: Arc<Shared>,
+}
+
+#[zbus::interface(name = "org.freedesktop.ratbag1.Resolution")]
+impl ResolutionObject {
+    #[zbus(property)]
+    fn index(&self) -> u32 {
+        0
+    }
+
+    #[zbus(property)]
+    fn is_active(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn is_default(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn is_disabled(&self) -> bool {
+        false
+    }
+
+    /// Single-axis DPI as a `u`.
+    #[zbus(property)]
+    fn resolution(&self) -> OwnedValue {
+        OwnedValue::from(self.shared.current().dpi as u32)
+    }
+
+    /// Accepts `u` or `(uu)`; the G502 has no separate Y resolution, so Y is ignored.
+    #[zbus(property)]
+    async fn set_resolution(
+        &mut self,
+        value: OwnedValue,
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> fdo::Result<()> {
+        let dpi = match unwrap_variant(&value) {
+            Value::U32(x) => *x,
+            Value::Structure(s) => match s.fields() {
+                [x, y] => match (unwrap_variant(x), unwrap_variant(y)) {
+                    (Value::U32(x), Value::U32(_)) => *x,
+                    _ => return Err(invalid("resolution must be u or (uu)")),
+                },
+                _ => return Err(invalid("resolution must be u or (uu)")),
+            },
+            _ => return Err(invalid("resolution must be u or (uu)")),
+        };
+        let dpi = u16::try_from(dpi).unwrap_or(u16::MAX);
+        let dpi = safety::validate_dpi(dpi).map_err(|e| invalid(e.to_string()))?;
+        self.shared.edit(conn, |profile| profile.dpi = dpi).await
+    }
+
+    #[zbus(property)]
+    fn resolutions(&self) -> Vec<u32> {
+        (safety::DPI_MIN..=safety::DPI_MAX)
+            .step_by(safety::DPI_STEP as usize)
+            .map(u32::from)
+            .collect()
+    }
+
+    /// The only resolution is always active.
+    fn set_active(&self) {}
+
+    /// The only resolution is always the default.
+    fn set_default(&self) {}
+}
+
+struct ButtonObject {
+    shared: Arc<Shared>,
+    index: usize,
+}
+
+#[zbus::interface(name = "org.freedesktop.ratbag1.Button")]
+impl ButtonObject {
+    #[zbus(property)]
+    fn index(&self) -> u32 {
+        self.index as u32
+    }
+
+    #[zbus(property)]
+    fn mapping(&self) -> (u32, OwnedValue) {
+        let action = self
+            .shared
+            .current()
+            .buttons
+            .get(self.index)
+            .copied()
+            .unwrap_or(ButtonAction::NoAction);
+        let (kind, value) = to_mapping(action);
+        (kind, OwnedValue::from(value))
+    }
+
+    #[zbus(property)]
+    async fn set_mapping(
+        &mut self,
+        mapping: (u32, OwnedValue),
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> fdo::Result<()> {
+        let (kind, value) = mapping;
+        let value = match unwrap_variant(&value) {
+            Value::U32(v) => *v,
+            _ if kind == action::NONE => 0,
+            _ => {
+                return Err(invalid(
+                    "only button, special and none mappings are supported",
+                ))
+            }
+        };
+        let action = from_mapping(kind, value)
+            .ok_or_else(|| invalid(format!("unsupported mapping ({kind}, {value})")))?;
+        let index = self.index;
+        self.shared
+            .edit(conn, |profile| {
+                if profile.buttons.len() <= index {
+                    profile.buttons.resize(index + 1, ButtonAction::NoAction);
+                }
+                profile.buttons[index] = action;
+            })
+            .await
+    }
+
+    #[zbus(property)]
+    fn action_types(&self) -> Vec<u32> {
+        vec![action::NONE, action::BUTTON, action::SPECIAL]
+    }
+}
+
+struct LedObject {
+    shared: Arc<Shared>,
+    index: usize,
+}
+
+impl LedObject {
+    fn effect(&self) -> LedEffect {
+        self.shared
+            .current()
+            .leds
+            .get(self.index)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    async fn edit(
+        &self,
+        conn: &zbus::Connection,
+        change: impl FnOnce(&mut LedEffect),
+    ) -> fdo::Result<()> {
+        let index = self.index;
+        self.shared
+            .edit(conn, |profile| {
+                if profile.leds.len() <= index {
+                    profile.leds.resize(index + 1, LedEffect::default());
+                }
+                change(&mut profile.leds[index]);
+            })
+            .await
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.ratbag1.Led")]
+impl LedObject {
+    #[zbus(property)]
+    fn index(&self) -> u32 {
+        self.index as u32
+    }
+
+    #[zbus(property)]
+    fn mode(&self) -> u32 {
+        let mode = self.effect().mode;
+        LED_MODES
+            .iter()
+            .find(|(_, m)| *m == mode)
+            .map_or(0, |(id, _)| *id)
+    }
+
+    #[zbus(property)]
+    async fn set_mode(
+        &mut self,
+        mode: u32,
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> fdo::Result<()> {
+        let (_, mode) = LED_MODES
+            .iter()
+            .find(|(id, _)| *id == mode)
+            .ok_or_else(|| invalid(format!("unsupported LED mode {mode}")))?;
+        self.edit(conn, |effect| effect.mode = *mode).await
+    }
+
+    #[zbus(property)]
+    fn modes(&self) -> Vec<u32> {
+        LED_MODES.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[zbus(property)]
+    fn color(&self) -> (u32, u32, u32) {
+        let [r, g, b] = self.effect().color;
+        (r.into(), g.into(), b.into())
+    }
+
+    #[zbus(property)]
+    async fn set_color(
+        &mut self,
+        color: (u32, u32, u32),
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> fdo::Result<()> {
+        let channel = |c: u32| c.min(255) as u8;
+        let rgb = [channel(color.0), channel(color.1), channel(color.2)];
+        self.edit(conn, |effect| effect.color = rgb).await
+    }
+
+    #[zbus(property)]
+    fn color_depth(&self) -> u32 {
+        COLORDEPTH_RGB_888
+    }
+
+    #[zbus(property)]
+    fn effect_duration(&self) -> u32 {
+        self.effect().duration_ms.into()
+    }
+
+    /// Checked against the safe range on commit.
+    #[zbus(property)]
+    async fn set_effect_duration(
+        &mut self,
+        ms: u32,
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> fdo::Result<()> {
+        let ms = u16::try_from(ms).unwrap_or(u16::MAX);
+        self.edit(conn, |effect| effect.duration_ms = ms).await
+    }
+
+    #[zbus(property)]
+    fn brightness(&self) -> u32 {
+        brightness_to_ratbag(self.effect().brightness)
+    }
+
+    #[zbus(property)]
+    async fn set_brightness(
+        &mut self,
+        value: u32,
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> fdo::Result<()> {
+        let brightness = brightness_from_ratbag(value);
+        self.edit(conn, |effect| effect.brightness = brightness)
+            .await
+    }
+}
+
+/// The running ratbag service.
+pub struct RatbagService {
+    connection: Connection,
+    shared: Arc<Shared>,
+    exported: Vec<(String, usize)>,
+}
+
+impl RatbagService {
+    /// Claim [`BUS_NAME`] on the bus `builder` connects to and export the
+    /// current devices.
+    pub fn start(builder: connection::Builder<'_>, manager: Arc<Manager>) -> zbus::Result<Self> {
+        let connection = builder
+            .name(BUS_NAME)?
+            .serve_at(
+                ROOT_PATH,
+                ManagerObject {
+                    devices: Vec::new(),
+                },
+            )?
+            .build()?;
+        let shared = Arc::new(Shared {
+            manager,
+            staged: Mutex::new(Staged {
+                profile: Profile::default(),
+                dirty: false,
+            }),
+            profiles: Mutex::new(Vec::new()),
+        });
+        let mut service = Self {
+            connection,
+            shared,
+            exported: Vec::new(),
+        };
+        service.sync()?;
+        Ok(service)
+    }
+
+    /// Export objects for newly connected mice and drop those that went away.
+    pub fn sync(&mut self) -> zbus::Result<()> {
+        let connected: Vec<DeviceInfo> = self
+            .shared
+            .manager
+            .status()
+            .devices
+            .into_iter()
+            .filter(|d| d.connected)
+            .map(|d| d.info)
+            .collect();
+
+        let mut changed = false;
+        let server = self.connection.object_server();
+        let mut index = 0;
+        while index < self.exported.len() {
+            let (sys, leds) = self.exported[index].clone();
+            if connected.iter().any(|info| sysname(info) == sys) {
+                index += 1;
+                continue;
+            }
+            info!(device = %sys, "Removing ratbag device");
+            server.remove::<DeviceObject, _>(device_path(&sys))?;
+            server.remove::<ProfileObject, _>(profile_path(&sys))?;
+            server.remove::<ResolutionObject, _>(resolution_path(&sys))?;
+            for i in 0..G502_BUTTON_COUNT {
+                server.remove::<ButtonObject, _>(button_path(&sys, i))?;
+            }
+            for i in 0..leds {
+                server.remove::<LedObject, _>(led_path(&sys, i))?;
+            }
+            self.exported.remove(index);
+            changed = true;
+        }
+
+        for info in connected {
+            let sys = sysname(&info);
+            if self.exported.iter().any(|(s, _)| *s == sys) {
+                continue;
+            }
+            self.shared.refresh();
+            let leds = self.shared.current().leds.len();
+            info!(device = %sys, "Exporting ratbag device");
+            let shared = &self.shared;
+            server.at(
+                profile_path(&sys),
+                ProfileObject {
+                    shared: Arc::clone(shared),
+                    sys: sys.clone(),
+                    leds,
+                },
+            )?;
+            server.at(
+                resolution_path(&sys),
+                ResolutionObject {
+                    shared: Arc::clone(shared),
+                },
+            )?;
+            for index in 0..G502_BUTTON_COUNT {
+                let shared = Arc::clone(shared);
+                server.at(button_path(&sys, index), ButtonObject { shared, index })?;
+            }
+            for index in 0..leds {
+                let shared = Arc::clone(shared);
+                server.at(led_path(&sys, index), LedObject { shared, index })?;
+            }
+            server.at(
+                device_path(&sys),
+                DeviceObject {
+                    shared: Arc::clone(shared),
+                    info,
+                    sys: sys.clone(),
+                },
+            )?;
+            self.exported.push((sys, leds));
+            changed = true;
+        }
+
+        if changed {
+            *self.shared.profiles.lock().unwrap() =
+                self.exported.iter().map(|(s, _)| profile_path(s)).collect();
+            let manager = server.interface::<_, ManagerObject>(ROOT_PATH)?;
+            manager.get_mut().devices = self.exported.iter().map(|(s, _)| device_path(s)).collect();
+            block_on(manager.get().devices_changed(manager.signal_emitter()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager::tests::{gaming_profile, SimSource};
+    use crate::test_bus::PrivateBus;
+    use open_g_hub_core::led;
+
+    #[test]
+    fn sysname_is_a_valid_path_element() {
+        let info = DeviceInfo {
+            path: "/dev/hidraw-3.1".into(),
+            ..crate::manager::tests::sim_info()
+        };
+        assert_eq!(sysname(&info), "hidraw_3_1");
+    }
+
+    /// Uncached, so every read goes to the service.
+    fn proxy<'a>(conn: &Connection, path: &'a str, iface: &'a str) -> zbus::blocking::Proxy<'a> {
+        zbus::blocking::proxy::Builder::new(conn)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path(path)
+            .unwrap()
+            .interface(iface)
+            .unwrap()
+            .cache_properties(zbus::proxy::CacheProperties::No)
+            .build()
+            .unwrap()
+    }
+
+    /// Wait for the next `IsDirty` change announced on `changes`.
+    fn next_dirty(changes: &mut zbus::blocking::fdo::PropertiesChangedIterator) -> bool {
+        loop {
+            let signal = changes.next().unwrap();
+            let args = signal.args().unwrap();
+            if let Some(dirty) = args.changed_properties().get("IsDirty") {
+                return bool::try_from(dirty).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn piper_style_session_configures_simulated_device() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not available; skipping");
+            return;
+        };
+        let source = SimSource::new();
+        let manager = Arc::new(Manager::new(
+            Box::new(source.clone()),
+            Some(gaming_profile()),
+        ));
+        manager.scan();
+        let builder = connection::Builder::address(bus.address.as_str()).unwrap();
+        let _service = RatbagService::start(builder, Arc::clone(&manager)).unwrap();
+
+        let client = connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        let root = proxy(&client, ROOT_PATH, "org.freedesktop.ratbag1.Manager");
+        assert_eq!(root.get_property::<i32>("APIVersion").unwrap(), API_VERSION);
+        let devices: Vec<OwnedObjectPath> = root.get_property("Devices").unwrap();
+        assert_eq!(devices.len(), 1, "two HID interfaces, one mouse");
+
+        let device = proxy(
+            &client,
+            devices[0].as_str(),
+            "org.freedesktop.ratbag1.Device",
+        );
+        assert_eq!(
+            device.get_property::<String>("Name").unwrap(),
+            "Logitech G502 Lightspeed"
+        );
+        let profiles: Vec<OwnedObjectPath> = device.get_property("Profiles").unwrap();
+        let profile = proxy(
+            &client,
+            profiles[0].as_str(),
+            "org.freedesktop.ratbag1.Profile",
+        );
+        // Active profile, with buttons it leaves alone read from the mouse.
+        assert_eq!(profile.get_property::<u32>("ReportRate").unwrap(), 500);
+        let buttons: Vec<OwnedObjectPath> = profile.get_property("Buttons").unwrap();
+        assert_eq!(buttons.len(), G502_BUTTON_COUNT);
+
+        let properties = zbus::blocking::fdo::PropertiesProxy::builder(&client)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path(profiles[0].as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut changes = properties.receive_properties_changed().unwrap();
+
+        let resolutions: Vec<OwnedObjectPath> = profile.get_property("Resolutions").unwrap();
+        let resolution = proxy(
+            &client,
+            resolutions[0].as_str(),
+            "org.freedesktop.ratbag1.Resolution",
+        );
+        resolution
+            .set_property("Resolution", Value::U32(1600))
+            .unwrap();
+        profile.set_property("ReportRate", 1000u32).unwrap();
+        let button = proxy(
+            &client,
+            buttons[5].as_str(),
+            "org.freedesktop.ratbag1.Button",
+        );
+        button
+            .set_property("Mapping", (action::BUTTON, Value::U32(3)))
+            .unwrap();
+        let leds: Vec<OwnedObjectPath> = profile.get_property("Leds").unwrap();
+        let led_obj = proxy(&client, leds[0].as_str(), "org.freedesktop.ratbag1.Led");
+        led_obj.set_property("Mode", 1u32).unwrap();
+        led_obj
+            .set_property("Color", (255u32, 0u32, 64u32))
+            .unwrap();
+
+        // Nothing reaches the mouse before Commit.
+        assert!(profile.get_property::<bool>("IsDirty").unwrap());
+        assert_eq!(source.sim.dpi(), 3200);
+        device.call_method("Commit", &()).unwrap();
+        assert!(!profile.get_property::<bool>("IsDirty").unwrap());
+        // Caching clients (Piper) learn about both transitions.
+        assert!(next_dirty(&mut changes));
+        while next_dirty(&mut changes) {}
+
+        assert_eq!(source.sim.dpi(), 1600);
+        assert_eq!(source.sim.report_interval(), 1);
+        assert_eq!(
+            source.sim.remap(5),
+            Some(open_g_hub_core::buttons::cids::MIDDLE_CLICK)
+        );
+        let effect = led::read_led_effect(source.sim.as_ref(), 0x01, 0).unwrap();
+        assert_eq!(effect.mode, led::LedMode::Solid);
+        assert_eq!(effect.color, [255, 0, 64]);
+        // Committed settings are what the daemon restores on reconnect.
+        assert_eq!(manager.active_profile().unwrap().dpi, 1600);
+
+        // The safety layer still rejects what the device must not get.
+        assert!(resolution
+            .set_property("Resolution", Value::U32(50_000))
+            .is_err());
+        assert!(button
+            .set_property("Mapping", (action::KEY, Value::U32(30)))
+            .is_err());
+    }
+}
@@ -0,0 +1,764 @@
+//! JSON-RPC server on the daemon's Unix socket.
+//!
+//! One request per line, one response per line (see `open_g_hub_core::ipc`).
+//! Each client connection gets its own thread. Device access is serialized by
+//! the transports themselves.
+//!
+//! A client that calls `subscribe` also gets `event` notifications. They are
+//! written by a forwarder thread through the same locked writer as responses,
+//! so lines never interleave. Device methods take an optional `device` param
+//! (a HID path from `list_devices`) and default to the first connected mouse.
+
+use crate::manager::Manager;
+use open_g_hub_core::device::{self, ButtonAction};
+use open_g_hub_core::error::Error;
+use open_g_hub_core::ipc::{
+    codes, DaemonEvent, EventKind, RpcError, RpcRequest, RpcResponse, EVENT_METHOD, JSONRPC_VERSION,
+};
+use open_g_hub_core::led::LedEffect;
+use open_g_hub_core::onboard::{self, OnboardMode};
+use open_g_hub_core::profile::{self, Profile};
+use open_g_hub_core::{battery, buttons, dpi, led, report_rate, safety};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// How often an idle event forwarder checks whether its client is gone.
+const FORWARD_POLL: Duration = Duration::from_millis(250);
+
+/// Where a connection's replies and events go.
+pub type Sink = Arc<Mutex<dyn Write + Send>>;
+
+/// Bind the socket, replacing a stale one left by a crashed daemon.
+///
+/// Fails if another daemon is already answering on `path`.
+pub fn bind(path: &Path) -> anyhow::Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            anyhow::bail!(
+                "another open-g-hubd is already listening on {}",
+                path.display()
+            );
+        }
+        std::fs::remove_file(path)?;
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    // Only the owning user may drive the mouse.
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Accept clients forever.
+pub fn serve(listener: UnixListener, manager: Arc<Manager>) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let manager = Arc::clone(&manager);
+                std::thread::spawn(move || handle_connection(stream, &manager));
+            }
+            Err(e) => warn!("Accept failed: {e}"),
+        }
+    }
+}
+
+fn handle_connection(stream: UnixStream, manager: &Manager) {
+    let Ok(writer) = stream.try_clone() else {
+        return;
+    };
+    let sink: Sink = Arc::new(Mutex::new(writer));
+    let session = Session::new(Arc::clone(&sink));
+    debug!("Client connected");
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<RpcRequest>(&line) {
+            Ok(request) => dispatch(manager, &session, request),
+            Err(e) => Some(RpcResponse::failure(
+                Value::Null,
+                RpcError::new(codes::PARSE_ERROR, e.to_string()),
+            )),
+        };
+        let Some(reply) = reply else {
+            continue;
+        };
+        if write_line(&sink, &reply).is_err() {
+            break;
+        }
+    }
+    debug!("Client disconnected");
+}
+
+fn write_line(sink: &Sink, message: &impl Serialize) -> std::io::Result<()> {
+    let mut out = serde_json::to_string(message)?;
+    out.push('\n');
+    sink.lock().unwrap().write_all(out.as_bytes())
+}
+
+/// Per-connection state: which events the client wants and where they go.
+pub struct Session {
+    sink: Sink,
+    topics: Arc<Mutex<HashSet<EventKind>>>,
+    forwarding: AtomicBool,
+    closed: Arc<AtomicBool>,
+}
+
+impl Session {
+    pub fn new(sink: Sink) -> Self {
+        Self {
+            sink,
+            topics: Arc::new(Mutex::new(HashSet::new())),
+            forwarding: AtomicBool::new(false),
+            closed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Add `kinds` to the subscription, starting the forwarder on first use.
+    /// Returns the topics now subscribed.
+    fn subscribe(&self, manager: &Manager, kinds: &[EventKind]) -> Vec<EventKind> {
+        self.topics.lock().unwrap().extend(kinds.iter().copied());
+        if !self.forwarding.swap(true, Ordering::SeqCst) {
+            let events = manager.subscribe();
+            let sink = Arc::clone(&self.sink);
+            let topics = Arc::clone(&self.topics);
+            let closed = Arc::clone(&self.closed);
+            std::thread::spawn(move || forward(events, &sink, &topics, &closed));
+        }
+        self.topics()
+    }
+
+    /// Remove `kinds` from the subscription. Returns the topics left.
+    fn unsubscribe(&self, kinds: &[EventKind]) -> Vec<EventKind> {
+        let mut topics = self.topics.lock().unwrap();
+        for kind in kinds {
+            topics.remove(kind);
+        }
+        drop(topics);
+        self.topics()
+    }
+
+    fn topics(&self) -> Vec<EventKind> {
+        let topics = self.topics.lock().unwrap();
+        EventKind::ALL
+            .iter()
+            .copied()
+            .filter(|k| topics.contains(k))
+            .collect()
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Write subscribed events to the client until it goes away.
+fn forward(
+    events: Receiver<DaemonEvent>,
+    sink: &Sink,
+    topics: &Mutex<HashSet<EventKind>>,
+    closed: &AtomicBool,
+) {
+    while !closed.load(Ordering::SeqCst) {
+        let event = match events.recv_timeout(FORWARD_POLL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if !topics.lock().unwrap().contains(&event.kind()) {
+            continue;
+        }
+        let Ok(params) = serde_json::to_value(&event) else {
+            continue;
+        };
+        if write_line(sink, &RpcRequest::notification(EVENT_METHOD, params)).is_err() {
+            break;
+        }
+    }
+}
+
+/// Run one request. Notifications (no `id`) run but get no reply.
+pub fn dispatch(manager: &Manager, session: &Session, request: RpcRequest) -> Option<RpcResponse> {
+    let result = if request.jsonrpc != JSONRPC_VERSION {
+        Err(RpcError::new(
+            codes::INVALID_REQUEST,
+            format!("unsupported jsonrpc version '{}'", request.jsonrpc),
+        ))
+    } else {
+        call(manager, session, &request.method, request.params)
+    };
+    let id = request.id?;
+    Some(match result {
+        Ok(value) => RpcResponse::success(id, value),
+        Err(error) => RpcResponse::failure(id, error),
+    })
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(codes::INVALID_PARAMS, e.to_string()))
+}
+
+/// Like [`params`], but absent params mean `T::default()`.
+fn optional_params<T: DeserializeOwned + Default>(raw: Value) -> Result<T, RpcError> {
+    if raw.is_null() {
+        Ok(T::default())
+    } else {
+        params(raw)
+    }
+}
+
+fn device_error(e: Error) -> RpcError {
+    RpcError::from_error(&e)
+}
+
+fn to_value<T: serde::Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(codes::INTERNAL_ERROR, e.to_string()))
+}
+
+/// The `device` param shared by all device methods.
+fn target(raw: &Value) -> Option<String> {
+    raw.get("device")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+#[derive(Deserialize)]
+struct DpiParams {
+    dpi: u16,
+}
+
+#[derive(Deserialize)]
+struct RateParams {
+    hz: u16,
+}
+
+#[derive(Deserialize)]
+struct ButtonIndexParams {
+    index: usize,
+}
+
+#[derive(Deserialize)]
+struct ButtonParams {
+    index: usize,
+    action: ButtonAction,
+}
+
+#[derive(Deserialize)]
+struct ButtonCidParams {
+    index: usize,
+    cid: u16,
+}
+
+#[derive(Deserialize)]
+struct DivertParams {
+    index: usize,
+    divert: bool,
+}
+
+#[derive(Deserialize)]
+struct ZoneParams {
+    zone: u8,
+}
+
+#[derive(Deserialize)]
+struct LedParams {
+    zone: u8,
+    effect: LedEffect,
+}
+
+#[derive(Deserialize)]
+struct ProfileParams {
+    profile: Profile,
+}
+
+#[derive(Deserialize)]
+struct OnboardModeParams {
+    mode: OnboardMode,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OnboardSlot {
+    page: u8,
+    offset: u8,
+}
+
+#[derive(Default, Deserialize)]
+struct EventParams {
+    /// Omitted means every topic.
+    events: Option<Vec<EventKind>>,
+}
+
+fn call(manager: &Manager, session: &Session, method: &str, raw: Value) -> Result<Value, RpcError> {
+    let path = target(&raw);
+    let device = path.as_deref();
+    match method {
+        "status" => to_value(manager.status()),
+        "list_devices" => to_value(manager.devices()),
+        "scan" => {
+            manager.scan();
+            to_value(manager.devices())
+        }
+        "get_dpi" => to_value(
+            manager
+                .with_device(device, dpi::read_dpi)
+                .map_err(device_error)?,
+        ),
+        "set_dpi" => {
+            let p: DpiParams = params(raw)?;
+            let written = manager
+                .with_device(device, |t, idx| dpi::write_dpi(t, idx, p.dpi))
+                .map_err(device_error)?;
+            manager.update_active(|profile| profile.dpi = written);
+            to_value(written)
+        }
+        "get_report_rate" => {
+            let rate = manager
+                .with_device(device, report_rate::read_report_rate)
+                .map_err(device_error)?;
+            to_value(rate.as_hz())
+        }
+        "get_report_rates" => {
+            let rates = manager
+                .with_device(device, report_rate::read_supported_rates)
+                .map_err(device_error)?;
+            to_value(rates.iter().map(|r| r.as_hz()).collect::<Vec<_>>())
+        }
+        "set_report_rate" => {
+            let p: RateParams = params(raw)?;
+            let rate = safety::validate_polling_rate(p.hz).map_err(device_error)?;
+            manager
+                .with_device(device, |t, idx| {
+                    report_rate::write_report_rate(t, idx, rate)
+                })
+                .map_err(device_error)?;
+            manager.update_active(|profile| profile.polling_rate = rate);
+            to_value(rate.as_hz())
+        }
+        "get_button" => {
+            let p: ButtonIndexParams = params(raw)?;
+            to_value(
+                manager
+                    .with_device(device, |t, idx| {
+                        buttons::read_button_mapping(t, idx, p.index)
+                    })
+                    .map_err(device_error)?,
+            )
+        }
+        "get_buttons" => to_value(
+            manager
+                .with_device(device, |t, idx| {
+                    let count = buttons::read_control_count(t, idx)? as usize;
+                    (0..count.min(device::G502_BUTTON_COUNT))
+                        .map(|i| buttons::read_button_mapping(t, idx, i))
+                        .collect::<open_g_hub_core::error::Result<Vec<_>>>()
+                })
+                .map_err(device_error)?,
+        ),
+        "set_button" => {
+            let p: ButtonParams = params(raw)?;
+            manager
+                .with_device(device, |t, idx| {
+                    buttons::write_button_mapping(t, idx, p.index, p.action)
+                })
+                .map_err(device_error)?;
+            manager.update_active(|profile| {
+                if let Some(slot) = profile.buttons.get_mut(p.index) {
+                    *slot = p.action;
+                    profile.button_cids.remove(&p.index);
+                }
+            });
+            Ok(Value::Null)
+        }
+        "set_button_cid" => {
+            let p: ButtonCidParams = params(raw)?;
+            manager
+                .with_device(device, |t, idx| {
+                    buttons::write_button_mapping_cid(t, idx, p.index, p.cid)
+                })
+                .map_err(device_error)?;
+            manager.update_active(|profile| {
+                if let Some(slot) = profile.buttons.get_mut(p.index) {
+                    // What the mouse reads back, so planned writes keep it.
+                    *slot = buttons::cid_to_action(p.cid);
+                    profile.button_cids.insert(p.index, p.cid);
+                }
+            });
+            Ok(Value::Null)
+        }
+        "set_button_divert" => {
+            let p: DivertParams = params(raw)?;
+            to_value(
+                manager
+                    .with_device(device, |t, idx| {
+                        buttons::set_button_divert(t, idx, p.index, p.divert)
+                    })
+                    .map_err(device_error)?,
+            )
+        }
+        "get_led" => {
+            let p: ZoneParams = params(raw)?;
+            to_value(
+                manager
+                    .with_device(device, |t, idx| led::read_led_effect(t, idx, p.zone))
+                    .map_err(device_error)?,
+            )
+        }
+        "set_led" => {
+            let p: LedParams = params(raw)?;
+            manager
+                .with_device(device, |t, idx| {
+                    led::write_led_effect(t, idx, p.zone, &p.effect)
+                })
+                .map_err(device_error)?;
+            manager.update_active(|profile| {
+                if let Some(slot) = profile.leds.get_mut(p.zone as usize) {
+                    *slot = p.effect;
+                }
+            });
+            Ok(Value::Null)
+        }
+        "get_unit_id" => to_value(
+            manager
+                .with_device(device, device::read_unit_id)
+                .map_err(device_error)?,
+        ),
+        "get_battery" => to_value(
+            manager
+                .with_device(device, battery::read_battery)
+                .map_err(device_error)?,
+        ),
+        "read_profile" => to_value(
+            manager
+                .with_device(device, profile::snapshot)
+                .map_err(device_error)?,
+        ),
+        "apply_profile" => {
+            let p: ProfileParams = params(raw)?;
+            manager
+                .with_device(device, |t, idx| profile::apply_profile(t, idx, &p.profile))
+                .map_err(device_error)?;
+            Ok(Value::Null)
+        }
+        "get_active_profile" => to_value(manager.active_profile()),
+        "set_active_profile" => {
+            let p: ProfileParams = params(raw)?;
+            info!(profile = %p.profile.name, "Active profile changed");
+            manager
+                .set_active_profile(p.profile)
+                .map_err(device_error)?;
+            Ok(Value::Null)
+        }
+        "reapply" => {
+            manager.reapply().map_err(device_error)?;
+            Ok(Value::Null)
+        }
+        "set_onboard_mode" => {
+            let p: OnboardModeParams = params(raw)?;
+            manager
+                .with_device(device, |t, idx| onboard::set_onboard_mode(t, idx, p.mode))
+                .map_err(device_error)?;
+            Ok(Value::Null)
+        }
+        "get_onboard_description" => to_value(
+            manager
+                .with_device(device, onboard::read_profile_description)
+                .map_err(device_error)?,
+        ),
+        "get_onboard_profile" => {
+            let (page, offset) = manager
+                .with_device(device, onboard::get_current_profile)
+                .map_err(device_error)?;
+            to_value(OnboardSlot { page, offset })
+        }
+        "set_onboard_profile" => {
+            let p: OnboardSlot = params(raw)?;
+            manager
+                .with_device(device, |t, idx| {
+                    onboard::set_current_profile(t, idx, p.page, p.offset)
+                })
+                .map_err(device_error)?;
+            Ok(Value::Null)
+        }
+        "subscribe" => {
+            let p: EventParams = optional_params(raw)?;
+            let kinds = p.events.unwrap_or_else(|| EventKind::ALL.to_vec());
+            to_value(session.subscribe(manager, &kinds))
+        }
+        "unsubscribe" => {
+            let p: EventParams = optional_params(raw)?;
+            let kinds = p.events.unwrap_or_else(|| EventKind::ALL.to_vec());
+            to_value(session.unsubscribe(&kinds))
+        }
+        other => Err(RpcError::new(
+            codes::METHOD_NOT_FOUND,
+            format!("unknown method '{other}'"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager::tests::{gaming_profile, SimSource};
+    use open_g_hub_core::battery::ChargingState;
+    use open_g_hub_core::device::PollingRate;
+    use open_g_hub_core::ipc::DaemonClient;
+    use serde_json::json;
+
+    fn request(method: &str, params: Value) -> RpcRequest {
+        RpcRequest::new(1, method, params)
+    }
+
+    fn session() -> Session {
+        Session::new(Arc::new(Mutex::new(Vec::<u8>::new())))
+    }
+
+    #[test]
+    fn unknown_method_and_bad_params_are_reported() {
+        let manager = Manager::new(Box::new(SimSource::new()), None);
+        let reply = dispatch(&manager, &session(), request("explode", Value::Null)).unwrap();
+        assert_eq!(reply.error.unwrap().code, codes::METHOD_NOT_FOUND);
+
+        let reply = dispatch(
+            &manager,
+            &session(),
+            request("set_dpi", json!({ "dpi": "high" })),
+        )
+        .unwrap();
+        assert_eq!(reply.error.unwrap().code, codes::INVALID_PARAMS);
+    }
+
+    #[test]
+    fn device_errors_are_classified() {
+        let manager = Manager::new(Box::new(SimSource::new()), None);
+        let reply = dispatch(&manager, &session(), request("get_dpi", Value::Null)).unwrap();
+        let error = reply.error.unwrap();
+        assert_eq!(error.code, codes::DISCONNECTED);
+        assert!(error.message.contains("no connected"));
+        assert_eq!(error.data.unwrap()["class"], "disconnected");
+
+        manager.scan();
+        let reply = dispatch(
+            &manager,
+            &session(),
+            request(
+                "set_led",
+                json!({ "zone": 9, "effect": LedEffect::default() }),
+            ),
+        )
+        .unwrap();
+        let error = reply.error.unwrap();
+        assert_eq!(error.code, codes::INVALID_PARAMS);
+        assert_eq!(error.data.unwrap()["field"], "led_zone");
+    }
+
+    #[test]
+    fn battery_onboard_and_device_targeting() {
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), None);
+        manager.scan();
+        source.sim.set_battery(42, 1);
+
+        let reply = dispatch(&manager, &session(), request("get_battery", Value::Null)).unwrap();
+        let battery: battery::BatteryStatus =
+            serde_json::from_value(reply.result.unwrap()).unwrap();
+        assert_eq!(battery.level, 42);
+        assert_eq!(battery.state, ChargingState::Recharging);
+
+        let reply = dispatch(&manager, &session(), request("get_unit_id", Value::Null)).unwrap();
+        assert_eq!(reply.result.unwrap(), "1A2B3C4D");
+
+        let reply = dispatch(
+            &manager,
+            &session(),
+            request(
+                "set_onboard_mode",
+                json!({ "mode": "onboard", "device": "sim" }),
+            ),
+        )
+        .unwrap();
+        assert!(reply.error.is_none());
+        assert_eq!(source.sim.onboard_mode(), 2);
+
+        let reply = dispatch(
+            &manager,
+            &session(),
+            request("get_dpi", json!({ "device": "/dev/hidraw99" })),
+        )
+        .unwrap();
+        assert_eq!(reply.error.unwrap().code, codes::DISCONNECTED);
+    }
+
+    #[test]
+    fn subscribed_events_are_forwarded() {
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), None);
+        manager.scan();
+
+        let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let session = Session::new(buffer.clone());
+        let reply = dispatch(
+            &manager,
+            &session,
+            request("subscribe", json!({ "events": ["battery"] })),
+        )
+        .unwrap();
+        assert_eq!(reply.result, Some(json!(["battery"])));
+
+        source.sim.set_battery(33, 0);
+        source.sim.disconnect();
+        manager.poll_notifications(0);
+
+        let line = loop {
+            let text = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+            if let Some(line) = text.lines().next() {
+                break line.to_string();
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        let note: RpcRequest = serde_json::from_str(&line).unwrap();
+        assert_eq!(note.method, EVENT_METHOD);
+        assert!(note.id.is_none());
+        assert_eq!(note.params["type"], "battery");
+        assert_eq!(note.params["battery"]["level"], 33);
+
+        // The connection event was not subscribed to.
+        std::thread::sleep(FORWARD_POLL);
+        let text = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        assert_eq!(text.lines().count(), 1);
+
+        let reply = dispatch(&manager, &session, request("unsubscribe", Value::Null)).unwrap();
+        assert_eq!(reply.result, Some(json!([])));
+    }
+
+    #[test]
+    fn notifications_get_no_reply() {
+        let manager = Manager::new(Box::new(SimSource::new()), None);
+        let mut req = request("status", Value::Null);
+        req.id = None;
+        assert!(dispatch(&manager, &session(), req).is_none());
+    }
+
+    #[test]
+    fn live_set_updates_active_profile() {
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), Some(gaming_profile()));
+        manager.scan();
+        let reply = dispatch(
+            &manager,
+            &session(),
+            request("set_dpi", json!({ "dpi": 1234 })),
+        )
+        .unwrap();
+        assert_eq!(reply.result, Some(json!(1250)));
+        assert_eq!(source.sim.dpi(), 1250);
+        assert_eq!(manager.active_profile().unwrap().dpi, 1250);
+    }
+
+    #[test]
+    fn custom_cid_survives_a_reconnect() {
+        let source = SimSource::new();
+        let manager = Manager::new(Box::new(source.clone()), Some(gaming_profile()));
+        manager.scan();
+        let reply = dispatch(
+            &manager,
+            &session(),
+            request("set_button_cid", json!({ "index": 2, "cid": 0x00C3 })),
+        )
+        .unwrap();
+        assert!(reply.error.is_none());
+        assert_eq!(source.sim.remap(2), Some(0x00C3));
+
+        source.sim.disconnect();
+        manager.poll_notifications(0);
+        source.sim.power_cycle();
+        manager.poll_notifications(0);
+        assert_eq!(source.sim.remap(2), Some(0x00C3));
+    }
+
+    #[test]
+    fn client_round_trip_over_socket() {
+        let source = SimSource::new();
+        let manager = Arc::new(Manager::new(Box::new(source.clone()), None));
+        manager.scan();
+
+        let path =
+            std::env::temp_dir().join(format!("open-g-hubd-test-{}.sock", std::process::id()));
+        let listener = bind(&path).unwrap();
+        {
+            let manager = Arc::clone(&manager);
+            std::thread::spawn(move || serve(listener, manager));
+        }
+        assert!(
+            bind(&path).is_err(),
+            "second daemon must not steal the socket"
+        );
+
+        let mut client = DaemonClient::connect(&path).unwrap();
+        assert_eq!(client.write_dpi(2000).unwrap(), 2000);
+        assert_eq!(client.read_dpi().unwrap(), 2000);
+        client.write_report_rate(PollingRate::Hz250).unwrap();
+        assert_eq!(client.read_report_rate().unwrap(), PollingRate::Hz250);
+
+        client.set_active_profile(&gaming_profile()).unwrap();
+        assert_eq!(source.sim.dpi(), 3200);
+        let status = client.status().unwrap();
+        assert_eq!(status.active_profile.as_deref(), Some("Gaming"));
+        assert!(status.devices[0].connected);
+
+        assert!(matches!(
+            client.write_dpi(50_000),
+            Err(Error::OutOfRange {
+                field: "dpi",
+                value: 50_000,
+                ..
+            })
+        ));
+
+        client
+            .subscribe(&[EventKind::Connection, EventKind::Battery])
+            .unwrap();
+        source.sim.set_battery(64, 0);
+        manager.poll_notifications(0);
+        // Events read while waiting for a reply are kept for `next_event`.
+        assert_eq!(client.list_devices().unwrap().len(), 1);
+        assert!(matches!(
+            client.next_event(Duration::from_secs(5)).unwrap(),
+            Some(DaemonEvent::Battery { battery, .. }) if battery.level == 64
+        ));
+        assert!(client
+            .next_event(Duration::from_millis(50))
+            .unwrap()
+            .is_none());
+
+        source.sim.disconnect();
+        manager.poll_notifications(0);
+        assert!(matches!(
+            client.next_event(Duration::from_secs(5)).unwrap(),
+            Some(DaemonEvent::Connection {
+                connected: false,
+                ..
+            })
+        ));
+        assert!(matches!(client.read_dpi(), Err(Error::Disconnected { .. })));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
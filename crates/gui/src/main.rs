@@ -7,13 +7,21 @@ use iced::{Element, Length, Subscription, Task as IcedTask, Theme};
 use std::array;
 use std::time::{Duration, Instant};
 
+use open_g_hub_core::battery_history::{self, BatteryHistory, BatterySample, Usage};
+use open_g_hub_core::client::DeviceClient;
 use open_g_hub_core::device::{ButtonAction, PollingRate, G502_BUTTON_COUNT};
+#[cfg(unix)]
+use open_g_hub_core::ipc::{DaemonClient, DaemonStatus};
+use open_g_hub_core::plan;
+use open_g_hub_core::profile::Profile;
 use open_g_hub_core::safety;
-use open_g_hub_core::transport::HidTransport;
+use open_g_hub_core::store::{ProfileStore, StoredProfile};
+use open_g_hub_core::wine::{self, GhubInstall};
 
 /// Device polling interval.
 const POLL_INTERVAL: Duration = Duration::from_secs(2);
-const DEVICE_INDEX_CANDIDATES: [u8; 2] = [0xFF, 0x01];
+/// How often the battery reading and estimate are refreshed.
+const BATTERY_INTERVAL: Duration = Duration::from_secs(60);
 
 fn main() -> iced::Result {
     tracing_subscriber::fmt()
@@ -26,77 +34,31 @@ fn main() -> iced::Result {
         .run_with(|| (App::new(), IcedTask::none()))
 }
 
-struct GuiHidTransport {
-    device: hidapi::HidDevice,
-}
-
-impl GuiHidTransport {
-    fn open_first_supported() -> Result<Self, String> {
-        let devices = open_g_hub_core::device::discover_devices().map_err(|e| e.to_string())?;
-        let first = devices
-            .first()
-            .ok_or_else(|| "No supported Logitech G device found".to_string())?;
-
-        let api = hidapi::HidApi::new().map_err(|e| format!("hidapi init: {e}"))?;
-        let device = api.open(first.vid, first.pid).map_err(|e| {
-            format!(
-                "open HID device (VID=0x{:04X} PID=0x{:04X}): {e}",
-                first.vid, first.pid
-            )
-        })?;
-
-        Ok(Self { device })
-    }
-}
-
-impl HidTransport for GuiHidTransport {
-    fn send_report(&self, data: &[u8]) -> open_g_hub_core::error::Result<Vec<u8>> {
-        self.device
-            .write(data)
-            .map_err(|e| open_g_hub_core::error::Error::Hid(format!("write: {e}")))?;
-
-        let mut response = [0u8; 64];
-        let n = self
-            .device
-            .read_timeout(&mut response, 1000)
-            .map_err(|e| open_g_hub_core::error::Error::Hid(format!("read_timeout: {e}")))?;
-
-        if n == 0 {
-            return Err(open_g_hub_core::error::Error::Timeout(
-                "hid_read timed out after 1000ms".to_string(),
-            ));
-        }
-
-        Ok(response[..n].to_vec())
-    }
-}
-
-fn with_device_index<T>(
-    mut op: impl FnMut(u8) -> open_g_hub_core::error::Result<T>,
-) -> open_g_hub_core::error::Result<T> {
-    let mut last_error = None;
-    for idx in DEVICE_INDEX_CANDIDATES {
-        match op(idx) {
-            Ok(v) => return Ok(v),
-            Err(e) => last_error = Some(e),
-        }
-    }
-
-    Err(last_error.unwrap_or_else(|| {
-        open_g_hub_core::error::Error::DeviceNotFound("no usable device index".to_string())
-    }))
-}
-
 /// Application state.
 struct App {
+    /// Profile being edited; supplies the settings the editor does not show.
+    base: Profile,
     dpi: u16,
     polling_rate: PollingRate,
     buttons: [ButtonAction; G502_BUTTON_COUNT],
+    /// What the mouse has now, as of the last read or apply; the pending
+    /// changes preview is planned against it.
+    device_state: Option<Profile>,
     custom_cids: [String; G502_BUTTON_COUNT],
     connected: bool,
     status: String,
     last_poll: Instant,
     auto_poll: bool,
+    /// Battery level, runtime estimate and measured drain per setting.
+    battery: Vec<String>,
+    last_battery: Option<Instant>,
+    /// G Hub installs found in Wine prefixes; a picker shows when there are several.
+    ghub_installs: Vec<GhubInstall>,
+    ghub_install: Option<GhubInstall>,
+    /// Names in the native profile library.
+    library: Vec<String>,
+    library_choice: Option<String>,
+    library_name: String,
 }
 
 #[derive(Debug, Clone)]
@@ -110,31 +72,65 @@ enum Message {
     RefreshDevice,
     PollTick,
     SaveProfile,
+    GhubInstallSelected(GhubInstall),
+    LibraryProfileSelected(String),
+    LibraryNameChanged(String),
+    SaveToLibrary,
 }
 
 impl App {
     fn new() -> Self {
         let profile = open_g_hub_core::profile::load_profile().unwrap_or_default();
-        let buttons: [ButtonAction; G502_BUTTON_COUNT] = {
-            let mut arr = [ButtonAction::NoAction; G502_BUTTON_COUNT];
-            for (i, btn) in profile.buttons.iter().enumerate().take(G502_BUTTON_COUNT) {
-                arr[i] = *btn;
-            }
-            arr
-        };
 
-        Self {
+        let ghub_installs = wine::discover();
+        let in_use = open_g_hub_core::profile::profile_path().ok();
+        let ghub_install = ghub_installs
+            .iter()
+            .find(|install| Some(&install.settings) == in_use.as_ref())
+            .cloned();
+
+        let mut app = Self {
+            base: profile.clone(),
             dpi: profile.dpi,
             polling_rate: profile.polling_rate,
-            buttons,
+            buttons: [ButtonAction::NoAction; G502_BUTTON_COUNT],
+            device_state: None,
             custom_cids: array::from_fn(|_| String::new()),
             connected: false,
             status: "Scanning for devices...".into(),
             last_poll: Instant::now(),
             auto_poll: true,
+            battery: Vec::new(),
+            last_battery: None,
+            ghub_installs,
+            ghub_install,
+            library: Vec::new(),
+            library_choice: None,
+            library_name: String::new(),
+        };
+        app.show_profile(&profile);
+        app.refresh_library();
+        app
+    }
+
+    /// Put a profile's settings into the editor.
+    fn show_profile(&mut self, profile: &Profile) {
+        self.base = profile.clone();
+        self.dpi = profile.dpi;
+        self.polling_rate = profile.polling_rate;
+        self.buttons = [ButtonAction::NoAction; G502_BUTTON_COUNT];
+        for (slot, btn) in self.buttons.iter_mut().zip(&profile.buttons) {
+            *slot = *btn;
         }
     }
 
+    fn refresh_library(&mut self) {
+        self.library = ProfileStore::open_default()
+            .and_then(|store| store.list())
+            .map(|profiles| profiles.iter().map(|p| p.name().to_string()).collect())
+            .unwrap_or_default();
+    }
+
     fn subscription(&self) -> Subscription<Message> {
         if self.auto_poll {
             iced::time::every(POLL_INTERVAL).map(|_| Message::PollTick)
@@ -143,7 +139,92 @@ impl App {
         }
     }
 
+    /// The settings shown in the UI as a profile.
+    fn current_profile(&self) -> Profile {
+        Profile {
+            name: "Default".into(),
+            dpi: self.dpi,
+            polling_rate: self.polling_rate,
+            buttons: self.buttons.to_vec(),
+            ..self.base.clone()
+        }
+    }
+
     fn poll_device(&mut self) {
+        let was_connected = self.connected;
+        self.check_connection();
+        if self.connected && !was_connected {
+            self.load_from_device();
+        } else if !self.connected {
+            self.device_state = None;
+            self.battery.clear();
+        }
+        if self.connected
+            && self
+                .last_battery
+                .is_none_or(|t| t.elapsed() >= BATTERY_INTERVAL)
+        {
+            self.refresh_battery();
+        }
+    }
+
+    /// Read the battery and estimate the runtime left from the history at
+    /// the settings on the mouse. Wired mice without a battery show nothing.
+    fn refresh_battery(&mut self) {
+        self.last_battery = Some(Instant::now());
+        let usage = Usage::of(self.device_state.as_ref().unwrap_or(&self.base));
+        let reading = DeviceClient::connect().and_then(|mut client| {
+            let battery = client.read_battery()?;
+            let unit_id = client.read_unit_id()?;
+            Ok((battery, unit_id, client.is_daemon()))
+        });
+        let Ok((battery, unit_id, via_daemon)) = reading else {
+            self.battery.clear();
+            return;
+        };
+        let mut lines = vec![format!("Battery: {}% ({:?})", battery.level, battery.state)];
+        if let Ok(history) = BatteryHistory::open_default() {
+            // The daemon records readings itself.
+            if !via_daemon {
+                let _ = history.record(&unit_id, &BatterySample::now(&battery, usage));
+            }
+            let samples = history.samples(&unit_id).unwrap_or_default();
+            if let Some(estimate) =
+                battery_history::estimate_runtime(&samples, battery.level, usage)
+            {
+                lines.push(format!("Runtime left at {usage}: {estimate}"));
+            }
+            for rate in battery_history::drain_rates(&samples) {
+                lines.push(format!(
+                    "  {}: {:.1} %/h, ~{:.0} h per charge",
+                    rate.usage,
+                    rate.percent_per_hour,
+                    rate.full_charge_hours()
+                ));
+            }
+        }
+        self.battery = lines;
+    }
+
+    /// Fill the editor with what is on the mouse right now.
+    fn load_from_device(&mut self) {
+        match DeviceClient::connect().and_then(|mut client| client.snapshot()) {
+            Ok(snapshot) => {
+                self.show_profile(&snapshot);
+                self.device_state = Some(snapshot);
+            }
+            Err(e) => self.status = format!("Could not read the mouse: {e}"),
+        }
+    }
+
+    fn check_connection(&mut self) {
+        #[cfg(unix)]
+        if let Ok(status) = DaemonClient::connect_default().and_then(|mut c| c.status()) {
+            self.show_daemon_status(&status);
+            self.last_poll = Instant::now();
+            return;
+        }
+
         match open_g_hub_core::device::discover_devices() {
             Ok(devices) if !devices.is_empty() => {
                 let was_disconnected = !self.connected;
@@ -170,6 +251,30 @@ impl App {
         self.last_poll = Instant::now();
     }
 
+    /// Connection state as tracked by open-g-hubd, which also sees a
+    /// wireless mouse go to sleep while its receiver stays plugged in.
+    #[cfg(unix)]
+    fn show_daemon_status(&mut self, status: &DaemonStatus) {
+        let was_connected = self.connected;
+        match status.devices.iter().find(|d| d.connected) {
+            Some(device) => {
+                self.connected = true;
+                if !was_connected {
+                    self.status =
+                        format!("Connected: {} (via open-g-hubd)", device.info.model.name());
+                }
+            }
+            None => {
+                self.connected = false;
+                self.status = if status.devices.is_empty() {
+                    "No Logitech G mice found.".into()
+                } else {
+                    "Receiver found; mouse is asleep or switched off.".into()
+                };
+            }
+        }
+    }
+
     fn update(&mut self, message: Message) -> IcedTask<Message> {
         match message {
             Message::DpiChanged(val) => {
@@ -204,76 +309,97 @@ impl App {
                     return IcedTask::none();
                 };
 
-                match GuiHidTransport::open_first_supported() {
-                    Ok(transport) => {
-                        match with_device_index(|dev_idx| {
-                            open_g_hub_core::buttons::write_button_mapping_cid(
-                                &transport, dev_idx, idx, cid,
-                            )
-                        }) {
-                            Ok(()) => {
-                                self.status =
-                                    format!("Applied custom CID 0x{cid:04X} to button {}", idx);
-                            }
-                            Err(e) => self.status = format!("Custom keybind error: {e}"),
+                match DeviceClient::connect() {
+                    Ok(mut client) => match client.write_button_mapping_cid(idx, cid) {
+                        Ok(()) => {
+                            self.status =
+                                format!("Applied custom CID 0x{cid:04X} to button {}", idx);
                         }
-                    }
+                        Err(e) => self.status = format!("Custom keybind error: {e}"),
+                    },
                     Err(e) => {
                         self.status = format!("Connection error: {e}");
                     }
                 }
             }
-            Message::ApplySettings => match GuiHidTransport::open_first_supported() {
-                Ok(transport) => {
-                    let result = with_device_index(|dev_idx| {
-                        open_g_hub_core::dpi::write_dpi(&transport, dev_idx, self.dpi)?;
-                        open_g_hub_core::report_rate::write_report_rate(
-                            &transport,
-                            dev_idx,
-                            self.polling_rate,
-                        )?;
-                        for (idx, action) in self.buttons.iter().enumerate() {
-                            open_g_hub_core::buttons::write_button_mapping(
-                                &transport, dev_idx, idx, *action,
-                            )?;
-                        }
-                        Ok(())
-                    });
-
-                    match result {
-                        Ok(()) => {
-                            self.status = format!(
-                                "Applied: DPI {}, {}Hz, {} button mappings",
-                                self.dpi,
-                                self.polling_rate.as_hz(),
-                                G502_BUTTON_COUNT
-                            );
-                        }
-                        Err(e) => self.status = format!("Apply error: {e}"),
+            Message::ApplySettings => match DeviceClient::connect() {
+                Ok(mut client) => match client.apply_profile(&self.current_profile()) {
+                    Ok(()) => {
+                        self.device_state = Some(self.current_profile());
+                        self.status = format!(
+                            "Applied: DPI {}, {}Hz, {} button mappings{}",
+                            self.dpi,
+                            self.polling_rate.as_hz(),
+                            G502_BUTTON_COUNT,
+                            if client.is_daemon() {
+                                " (restored on reconnect)"
+                            } else {
+                                ""
+                            }
+                        );
                     }
-                }
+                    Err(e) => {
+                        // A failed apply is rolled back; show what is left.
+                        self.device_state = client.snapshot().ok();
+                        self.status = format!("Apply error: {e}");
+                    }
+                },
                 Err(e) => {
                     self.status = format!("Connection error: {e}");
                 }
             },
             Message::RefreshDevice => {
+                self.last_battery = None;
                 self.poll_device();
             }
             Message::PollTick => {
                 self.poll_device();
             }
             Message::SaveProfile => {
-                let profile = open_g_hub_core::profile::Profile {
-                    name: "Default".into(),
-                    dpi: self.dpi,
-                    polling_rate: self.polling_rate,
-                    buttons: self.buttons.to_vec(),
-                };
-                match open_g_hub_core::profile::save_profile(&profile) {
+                match open_g_hub_core::profile::save_profile(&self.current_profile()) {
                     Ok(()) => self.status = "Profile saved.".into(),
                     Err(e) => self.status = format!("Save error: {e}"),
                 }
             }
+            Message::LibraryProfileSelected(name) => {
+                match ProfileStore::open_default().and_then(|store| store.get(&name)) {
+                    Ok(stored) => {
+                        self.show_profile(&stored.profile);
+                        self.status = format!("Loaded '{name}'; press Apply Settings to use it.");
+                        self.library_name = name.clone();
+                        self.library_choice = Some(name);
+                    }
+                    Err(e) => self.status = format!("Load error: {e}"),
+                }
+            }
+            Message::LibraryNameChanged(name) => self.library_name = name,
+            Message::SaveToLibrary => {
+                let name = self.library_name.trim().to_string();
+                let saved = ProfileStore::open_default().and_then(|store| {
+                    // Keep the device binding of the profile being replaced.
+                    let device = store.get(&name).ok().and_then(|old| old.device);
+                    let profile = Profile {
+                        name: name.clone(),
+                        ..self.current_profile()
+                    };
+                    store.save(&StoredProfile::new(profile, device))
+                });
+                match saved {
+                    Ok(()) => {
+                        self.status = format!("Saved '{name}' to the profile library.");
+                        self.refresh_library();
+                        self.library_choice = Some(name);
+                    }
+                    Err(e) => self.status = format!("Save error: {e}"),
+                }
+            }
+            Message::GhubInstallSelected(install) => match wine::select(Some(&install.settings)) {
+                Ok(()) => {
+                    self.status = format!("Profiles now saved to {}", install.settings.display());
+                    self.ghub_install = Some(install);
+                }
+                Err(e) => self.status = format!("Could not select G Hub install: {e}"),
+            },
         }
         IcedTask::none()
     }
@@ -302,12 +428,41 @@ impl App {
                 .spacing(14),
                 text(&self.status).size(14),
             ]
+            .extend(self.battery.iter().map(|line| text(line).size(14).into()))
+            .push_maybe((self.ghub_installs.len() > 1).then(|| {
+                row![
+                    text("G Hub install").size(15),
+                    pick_list(
+                        self.ghub_installs.as_slice(),
+                        self.ghub_install.clone(),
+                        Message::GhubInstallSelected
+                    )
+                    .width(Length::Fill),
+                ]
+                .spacing(10)
+            }))
             .spacing(8),
         )
         .padding(14)
         .width(Length::Fill);
 
         let rate_options: Vec<PollingRate> = PollingRate::ALL.to_vec();
+        let stages = (!self.base.dpi_stages.is_empty()).then(|| {
+            let stages: Vec<String> = self
+                .base
+                .dpi_stages
+                .iter()
+                .enumerate()
+                .map(|(i, dpi)| {
+                    if i == self.base.default_dpi_stage {
+                        format!("[{dpi}]")
+                    } else {
+                        dpi.to_string()
+                    }
+                })
+                .collect();
+            text(format!("DPI stages: {}", stages.join(" / "))).size(14)
+        });
         let performance_card = container(
             column![
                 text("Performance").size(20),
@@ -318,6 +473,9 @@ impl App {
                     |val| Message::DpiChanged(val as u16),
                 )
                 .step(safety::DPI_STEP as f64),
+            ]
+            .push_maybe(stages)
+            .push(
                 row![
                     text("Polling Rate").size(16),
                     pick_list(
@@ -327,7 +485,7 @@ impl App {
                     ),
                 ]
                 .spacing(10),
-            ]
+            )
             .spacing(10),
         )
         .padding(14)
@@ -382,6 +540,49 @@ impl App {
             .padding(14)
             .width(Length::Fill);
 
+        let library_card = container(
+            column![
+                text("Profile Library").size(20),
+                row![
+                    pick_list(
+                        self.library.as_slice(),
+                        self.library_choice.clone(),
+                        Message::LibraryProfileSelected
+                    )
+                    .placeholder("Stored profiles")
+                    .width(Length::Fill),
+                    text_input("Profile name", &self.library_name)
+                        .on_input(Message::LibraryNameChanged)
+                        .width(Length::Fill),
+                    button("Save to Library").on_press_maybe(
+                        (!self.library_name.trim().is_empty()).then_some(Message::SaveToLibrary)
+                    ),
+                ]
+                .spacing(10),
+            ]
+            .spacing(8),
+        )
+        .padding(14)
+        .width(Length::Fill);
+
+        let pending = self.device_state.as_ref().map(|state| {
+            let lines: Vec<String> = match plan::plan(state, &self.current_profile()) {
+                Ok(plan) if plan.is_empty() => vec![plan.to_string()],
+                Ok(plan) => plan.writes.iter().map(ToString::to_string).collect(),
+                Err(e) => vec![format!("Cannot apply: {e}")],
+            };
+            container(
+                lines
+                    .into_iter()
+                    .fold(column![text("Pending Changes").size(20)], |col, line| {
+                        col.push(text(line).size(14))
+                    })
+                    .spacing(6),
+            )
+            .padding(14)
+            .width(Length::Fill)
+        });
+
         let actions = row![
             button("Apply Settings").on_press(Message::ApplySettings),
             button("Save Profile").on_press(Message::SaveProfile),
@@ -392,10 +593,18 @@ impl App {
         ]
         .spacing(12);
 
-        let content = column![header, device_card, performance_card, button_card, actions]
-            .spacing(14)
-            .padding(20)
-            .max_width(980);
+        let content = column![
+            header,
+            device_card,
+            performance_card,
+            button_card,
+            library_card,
+        ]
+        .push_maybe(pending)
+        .push(actions)
+        .spacing(14)
+        .padding(20)
+        .max_width(980);
 
         container(content)
             .width(Length::Fill)